@@ -33,7 +33,7 @@ struct TokenAuth {
 }
 
 fn validator(token_auth: Data<TokenAuth>, credentials: BearerAuth) -> bool {
-    return token_auth.token.is_empty() || token_auth.token == credentials.token();
+    token_auth.token.is_empty() || token_auth.token == credentials.token()
 }
 
 #[post("/v1/observer_ward")]
@@ -50,7 +50,7 @@ async fn what_web_api(
         let vec_results = observer_ward_ins
             .read()
             .await
-            .scan(config.targets.clone())
+            .scan(futures::stream::iter(config.targets.clone()))
             .await;
         HttpResponse::Ok().json(vec_results)
     } else {
@@ -58,7 +58,7 @@ async fn what_web_api(
             observer_ward_ins
                 .read()
                 .await
-                .scan(config.targets.clone())
+                .scan(futures::stream::iter(config.targets.clone()))
                 .await
         });
         let mut data: HashMap<String, String> = HashMap::new();
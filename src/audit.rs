@@ -0,0 +1,351 @@
+use crate::cli::ObserverWardConfig;
+use fnv::FnvHasher;
+use observer_ward_what_web::{HttpRequest, HttpResponse, ScanObserver};
+use reqwest::header::{
+    HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE,
+};
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::hash::Hasher;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+// 正文不是UTF-8时用base64保存，和HAR的`encoding`一样
+#[derive(Serialize)]
+struct AuditBody {
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+    size: usize,
+}
+
+impl AuditBody {
+    fn new(body: &[u8]) -> Self {
+        let (text, encoding) = match std::str::from_utf8(body) {
+            Ok(text) => (text.to_string(), None),
+            Err(_) => (base64::encode(body), Some("base64")),
+        };
+        Self {
+            text,
+            encoding,
+            size: body.len(),
+        }
+    }
+}
+
+// 一行一条记录，同一个请求和它的响应写在同一个文件里
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AuditRecord {
+    Request {
+        target: String,
+        sequence: u64,
+        time: u128,
+        method: String,
+        url: String,
+        headers: Vec<(String, String)>,
+        body: AuditBody,
+    },
+    Response {
+        target: String,
+        sequence: u64,
+        time: u128,
+        url: String,
+        status: u16,
+        version: String,
+        headers: Vec<(String, String)>,
+        body: AuditBody,
+        // 扫描时超过`max_body_size`或者超过审计保存的大小
+        truncated: bool,
+    },
+}
+
+impl AuditRecord {
+    fn target(&self) -> &str {
+        match self {
+            AuditRecord::Request { target, .. } | AuditRecord::Response { target, .. } => target,
+        }
+    }
+    fn sequence(&self) -> u64 {
+        match self {
+            AuditRecord::Request { sequence, .. } | AuditRecord::Response { sequence, .. } => {
+                *sequence
+            }
+        }
+    }
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+// 认证信息和会话，审计文件经常被转给别人看，默认不保存原值
+const SECRET_HEADERS: [HeaderName; 4] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+const REDACTED: &str = "[REDACTED]";
+
+// 不是UTF-8的请求头按字节替换，审计只关心内容
+fn header_pairs(headers: &HeaderMap, keep_secrets: bool) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if !keep_secrets && SECRET_HEADERS.contains(name) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+// 文件名用目标的哈希，不用处理目标里不能出现在文件名里的字符；
+// FNV的结果不随Rust版本和进程变化，两次扫描同一个目标的文件名一样
+fn target_hash(target: &str) -> String {
+    let mut hasher = FnvHasher::default();
+    hasher.write(target.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+/// 把每个实际发送的HTTP请求和收到的响应写到每次扫描单独的目录下，
+/// 文件名是`目标哈希-编号.jsonl`，第一行是请求，收到响应时第二行是响应。
+/// 记录通过通道交给单独的线程写文件，不阻塞请求。认证和Cookie请求头默认打码
+pub struct AuditObserver {
+    body_size: usize,
+    keep_secrets: bool,
+    sender: Mutex<Option<UnboundedSender<AuditRecord>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AuditObserver {
+    // 在`dir`下按开始扫描的时间创建这次扫描的目录，响应正文最多保存`body_size`字节
+    pub fn create<P: AsRef<Path>>(
+        dir: P,
+        body_size: usize,
+        keep_secrets: bool,
+    ) -> io::Result<(Self, PathBuf)> {
+        let scan_dir = dir.as_ref().join(now_millis().to_string());
+        fs::create_dir_all(&scan_dir)?;
+        let (sender, receiver) = unbounded_channel();
+        let writer_dir = scan_dir.clone();
+        let handle = tokio::task::spawn_blocking(move || write_records(&writer_dir, receiver));
+        let observer = Self {
+            body_size,
+            keep_secrets,
+            sender: Mutex::new(Some(sender)),
+            handle: Mutex::new(Some(handle)),
+        };
+        Ok((observer, scan_dir))
+    }
+    fn send(&self, record: AuditRecord) {
+        if let Some(sender) = self
+            .sender
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .as_ref()
+        {
+            sender.send(record).unwrap_or_default();
+        }
+    }
+    // 扫描结束时调用，等还没写的记录写完
+    pub async fn finish(&self) {
+        self.sender
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        let handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        if let Some(handle) = handle {
+            handle.await.unwrap_or_default();
+        }
+    }
+}
+
+impl ScanObserver for AuditObserver {
+    fn http_request(&self, target: &str, sequence: u64, request: &HttpRequest) {
+        self.send(AuditRecord::Request {
+            target: target.to_string(),
+            sequence,
+            time: now_millis(),
+            method: request.method.to_string(),
+            url: request.url.to_string(),
+            headers: header_pairs(request.headers, self.keep_secrets),
+            body: AuditBody::new(request.body),
+        });
+    }
+    fn http_response(&self, target: &str, sequence: u64, response: &HttpResponse) {
+        let body = &response.body[..response.body.len().min(self.body_size)];
+        self.send(AuditRecord::Response {
+            target: target.to_string(),
+            sequence,
+            time: now_millis(),
+            url: response.url.to_string(),
+            status: response.status_code.as_u16(),
+            version: format!("{:?}", response.version),
+            headers: header_pairs(response.headers, self.keep_secrets),
+            body: AuditBody::new(body),
+            truncated: response.truncated || body.len() < response.body.len(),
+        });
+    }
+}
+
+// 每条记录追加到自己的文件，写失败的记录打印错误后跳过
+fn write_records(dir: &Path, mut receiver: UnboundedReceiver<AuditRecord>) {
+    while let Some(record) = receiver.blocking_recv() {
+        let name = format!(
+            "{}-{:04}.jsonl",
+            target_hash(record.target()),
+            record.sequence()
+        );
+        let path = dir.join(name);
+        let written = serde_json::to_vec(&record)
+            .map_err(io::Error::other)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)?
+                    .write_all(&line)
+            });
+        if let Err(err) = written {
+            log::error!("Failed to write audit record {:?}: {}", path, err);
+        }
+    }
+}
+
+pub fn open_audit(config: &ObserverWardConfig) -> Option<AuditObserver> {
+    if config.audit_dir.is_empty() {
+        return None;
+    }
+    match AuditObserver::create(
+        &config.audit_dir,
+        config.audit_body_size * 1024,
+        config.audit_secrets,
+    ) {
+        Ok((observer, scan_dir)) => {
+            eprintln!("Writing audit records to {}", scan_dir.display());
+            Some(observer)
+        }
+        Err(err) => {
+            println!("Failed to create {}: {}", config.audit_dir, err);
+            std::process::exit(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Method, StatusCode, Version};
+    use serde_json::Value;
+    use url::Url;
+
+    fn read_records(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_audit_records() {
+        let dir = std::env::temp_dir().join(format!("observer_ward_audit_{}", now_millis()));
+        let (observer, scan_dir) = AuditObserver::create(&dir, 4, false).unwrap();
+        assert!(scan_dir.starts_with(&dir));
+        let url = Url::parse("http://example.com/login").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Basic YWRtaW46YWRtaW4=".parse().unwrap());
+        headers.insert(COOKIE, "session=secret".parse().unwrap());
+        headers.insert("x-scan", "observer_ward".parse().unwrap());
+        let request = HttpRequest {
+            method: &Method::POST,
+            url: &url,
+            headers: &headers,
+            body: b"user=admin",
+        };
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert(SET_COOKIE, "session=next".parse().unwrap());
+        let response = HttpResponse {
+            url: &url,
+            status_code: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: &response_headers,
+            body: b"<html>",
+            truncated: false,
+        };
+        observer.http_request("example.com", 1, &request);
+        observer.http_response("example.com", 1, &response);
+        // 没有响应的请求只有一行
+        observer.http_request("example.com", 2, &request);
+        observer.finish().await;
+
+        let hash = target_hash("example.com");
+        assert_eq!(hash, "576846634e2714c6");
+        let mut names: Vec<String> = fs::read_dir(&scan_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                format!("{}-0001.jsonl", hash),
+                format!("{}-0002.jsonl", hash)
+            ]
+        );
+        let records = read_records(&scan_dir.join(&names[0]));
+        assert_eq!(records.len(), 2);
+        let (sent, received) = (&records[0], &records[1]);
+        assert_eq!(sent["type"], "request");
+        assert_eq!(received["type"], "response");
+        assert_eq!(sent["sequence"], 1);
+        assert_eq!(received["sequence"], 1);
+        assert_eq!(sent["method"], "POST");
+        assert_eq!(sent["body"]["text"], "user=admin");
+        let sent_headers = sent["headers"].as_array().unwrap();
+        assert!(sent_headers.contains(&serde_json::json!(["authorization", REDACTED])));
+        assert!(sent_headers.contains(&serde_json::json!(["cookie", REDACTED])));
+        assert!(sent_headers.contains(&serde_json::json!(["x-scan", "observer_ward"])));
+        assert_eq!(
+            received["headers"],
+            serde_json::json!([["set-cookie", REDACTED]])
+        );
+        // 超过保存大小的正文截断
+        assert_eq!(received["body"]["text"], "<htm");
+        assert_eq!(received["body"]["size"], 4);
+        assert_eq!(received["truncated"], true);
+        let records = read_records(&scan_dir.join(&names[1]));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["sequence"], 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_header_pairs() {
+        let mut headers = HeaderMap::new();
+        headers.insert(PROXY_AUTHORIZATION, "Basic a2FsaTpwQHNz".parse().unwrap());
+        assert_eq!(
+            header_pairs(&headers, false),
+            [(String::from("proxy-authorization"), REDACTED.to_string())]
+        );
+        assert_eq!(
+            header_pairs(&headers, true),
+            [(
+                String::from("proxy-authorization"),
+                String::from("Basic a2FsaTpwQHNz")
+            )]
+        );
+    }
+}
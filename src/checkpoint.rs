@@ -0,0 +1,150 @@
+use crate::cli::ObserverWardConfig;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+// 每完成多少个目标同步一次到磁盘
+const SYNC_INTERVAL: usize = 100;
+
+struct CheckpointWriter {
+    file: File,
+    unsynced: usize,
+}
+
+/// 断点续扫记录，每行一个已经完成的目标，只追加
+pub struct Checkpoint {
+    completed: HashSet<String>,
+    writer: Mutex<CheckpointWriter>,
+}
+
+impl Checkpoint {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+        // 进程被杀时最后一行可能没写完，没写完的目标重新扫
+        let finished = match content.iter().rposition(|b| *b == b'\n') {
+            Some(index) => &content[..index],
+            None => &[][..],
+        };
+        let completed = finished
+            .split(|b| *b == b'\n')
+            .filter_map(|line| std::str::from_utf8(line).ok())
+            .filter(|target| !target.is_empty())
+            .map(String::from)
+            .collect();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // 先结束没写完的行，不和下一个目标连在一起
+        if !content.is_empty() && !content.ends_with(b"\n") {
+            file.write_all(b"\n")?;
+        }
+        Ok(Self {
+            completed,
+            writer: Mutex::new(CheckpointWriter { file, unsynced: 0 }),
+        })
+    }
+    pub fn is_completed(&self, target: &str) -> bool {
+        self.completed.contains(target)
+    }
+    pub fn completed_count(&self) -> usize {
+        self.completed.len()
+    }
+    // 写完立即交给系统，进程退出也不会丢，断电最多丢最近一批
+    pub fn complete(&self, target: &str) -> io::Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| io::Error::other("checkpoint writer poisoned"))?;
+        writer.file.write_all(format!("{}\n", target).as_bytes())?;
+        writer.unsynced += 1;
+        if writer.unsynced >= SYNC_INTERVAL {
+            writer.file.sync_data()?;
+            writer.unsynced = 0;
+        }
+        Ok(())
+    }
+    pub fn sync(&self) -> io::Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| io::Error::other("checkpoint writer poisoned"))?;
+        writer.file.sync_data()?;
+        writer.unsynced = 0;
+        Ok(())
+    }
+}
+
+impl Drop for Checkpoint {
+    fn drop(&mut self) {
+        self.sync().unwrap_or_default();
+    }
+}
+
+pub fn open_checkpoint(config: &ObserverWardConfig) -> Option<Checkpoint> {
+    if config.resume.is_empty() {
+        return None;
+    }
+    match Checkpoint::open(&config.resume) {
+        Ok(checkpoint) => {
+            if checkpoint.completed_count() > 0 && !config.silent {
+                eprintln!(
+                    "Resuming from {}, skipping {} completed targets",
+                    config.resume,
+                    checkpoint.completed_count()
+                );
+            }
+            Some(checkpoint)
+        }
+        Err(err) => {
+            println!("Failed to open {}: {}", config.resume, err);
+            std::process::exit(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "observer_ward_checkpoint_{}.txt",
+            std::process::id()
+        ));
+        fs::remove_file(&path).unwrap_or_default();
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.completed_count(), 0);
+        checkpoint.complete("http://10.0.0.1").unwrap();
+        checkpoint.complete("https://10.0.0.2:8443").unwrap();
+        drop(checkpoint);
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.completed_count(), 2);
+        assert!(checkpoint.is_completed("http://10.0.0.1"));
+        assert!(checkpoint.is_completed("https://10.0.0.2:8443"));
+        assert!(!checkpoint.is_completed("http://10.0.0.3"));
+        drop(checkpoint);
+        // 进程被杀时没写完的最后一行不算完成，下一个目标另起一行
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"http://10.0.0.3")
+            .unwrap();
+        let checkpoint = Checkpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.completed_count(), 2);
+        assert!(!checkpoint.is_completed("http://10.0.0.3"));
+        checkpoint.complete("http://10.0.0.4").unwrap();
+        drop(checkpoint);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "http://10.0.0.1\nhttps://10.0.0.2:8443\nhttp://10.0.0.3\nhttp://10.0.0.4\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
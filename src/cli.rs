@@ -2,11 +2,16 @@ extern crate clap;
 
 use crate::OBSERVER_WARD_PATH;
 use clap::Arg;
+use observer_ward_what_web::{
+    AuthScheme, FaviconDbCategory, HttpVersion, RedirectPolicy, RequestOption,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::ffi::OsString;
 use std::path::Path;
 use std::process;
 use std::process::{Command, Stdio};
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ObserverWardConfig {
@@ -18,18 +23,93 @@ pub struct ObserverWardConfig {
     pub stdin: bool,
     #[serde(skip)]
     pub verify: String,
+    // `lint`子命令检查的指纹文件或者目录
+    #[serde(skip)]
+    pub lint: String,
+    // `diff`子命令比较的旧结果和新结果文件
+    #[serde(skip)]
+    pub diff: Vec<String>,
+    #[serde(skip)]
+    pub diff_json: bool,
     #[serde(skip)]
     pub file: String,
+    // nmap或者masscan的扫描结果
+    #[serde(skip)]
+    pub input_scan: String,
+    #[serde(skip)]
+    pub all_open_ports: bool,
+    // 离线识别的HAR文件
+    #[serde(skip)]
+    pub har: String,
     #[serde(default)]
     pub update_fingerprint: bool,
+    // 本地指纹库路径
+    #[serde(default)]
+    pub local: String,
+    // 更新指纹库的地址和可选的SHA-256校验值
+    #[serde(default)]
+    pub fingerprint_url: String,
+    #[serde(default)]
+    pub fingerprint_sha256: String,
     #[serde(skip)]
     pub csv: String,
     #[serde(skip)]
     pub json: String,
+    // 扫描结束后生成的HTML报告
+    #[serde(skip)]
+    pub html: String,
+    // 结果写到SQLite文件，多次扫描按目标和组件更新
+    #[serde(skip)]
+    pub sqlite: String,
+    // 扫描结束后导出nmap格式的XML
+    #[serde(skip)]
+    pub nmap: String,
+    #[serde(skip)]
+    pub output: String,
+    #[serde(skip)]
+    pub append: bool,
+    // 断点续扫记录文件
+    #[serde(skip)]
+    pub resume: String,
+    // 输出nuclei标签，cmd或者json
+    #[serde(skip)]
+    pub nuclei_tags: String,
+    // 按组件列出目标，text或者json
+    #[serde(skip)]
+    pub group_by_component: String,
+    // 没有识别出组件的目标按首页相似度分组，text或者json
+    #[serde(skip)]
+    pub cluster: String,
+    // 分到一组最多允许SimHash不同的位数
+    #[serde(skip)]
+    pub cluster_distance: u32,
     #[serde(default)]
     pub proxy: String,
+    // 只发给http(s)代理的请求头，`NAME: VALUE`
+    #[serde(default)]
+    pub proxy_headers: Vec<String>,
+    // 连续这么多个目标连不上而且代理也连不上时停止扫描，0表示不检查
+    #[serde(default = "default_proxy_failure_threshold")]
+    pub proxy_failure_threshold: u32,
+    // 代理断开后剩下的目标直接连接，不停止扫描
+    #[serde(default)]
+    pub proxy_fallback: bool,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
+    // 单个目标所有请求的总时间，秒
+    #[serde(default)]
+    pub target_deadline: Option<u64>,
+    #[serde(default = "default_retries")]
+    pub retries: u8,
+    #[serde(default = "default_retry_interval")]
+    pub retry_interval: u64,
+    #[serde(default)]
+    pub redirect: RedirectPolicy,
+    #[serde(default)]
+    pub http_version: HttpVersion,
+    // 没有写端口的目标展开到这些端口
+    #[serde(default)]
+    pub ports: Vec<u16>,
     #[serde(default)]
     pub plugins: String,
     #[serde(default)]
@@ -40,6 +120,22 @@ pub struct ObserverWardConfig {
     pub thread: u32,
     #[serde(default)]
     pub webhook: String,
+    // 推送到webhook时附加的请求头，`Name: value`
+    #[serde(default)]
+    pub webhook_header: String,
+    // 最多攒多少个结果或者等多少秒推送一次
+    #[serde(default = "default_webhook_batch")]
+    pub webhook_batch: usize,
+    #[serde(default = "default_webhook_interval")]
+    pub webhook_interval: u64,
+    // 保存每个HTTP请求和响应的目录，响应正文最多保存多少KB
+    #[serde(skip)]
+    pub audit_dir: String,
+    #[serde(default = "default_audit_body_size")]
+    pub audit_body_size: usize,
+    // 审计记录里原样保存认证和Cookie请求头，默认打码
+    #[serde(skip)]
+    pub audit_secrets: bool,
     #[serde(default)]
     pub service: bool,
     #[serde(skip)]
@@ -50,8 +146,70 @@ pub struct ObserverWardConfig {
     pub daemon: bool,
     #[serde(skip)]
     pub silent: bool,
+    // 日志详细程度，`-v`的个数
+    #[serde(skip)]
+    pub verbose: u8,
     #[serde(skip)]
     pub filter: bool,
+    // 扫描结束后打印组件、状态码、标题、错误和耗时的统计
+    #[serde(skip)]
+    pub stats: bool,
+    // 只列出每个目标会发送的请求，不连接目标
+    #[serde(skip)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub shiro_detect: bool,
+    #[serde(default)]
+    pub cross_host_favicon: bool,
+    // 另外按系统的根证书校验证书，记录校验结果
+    #[serde(default)]
+    pub verify_tls: bool,
+    #[serde(default)]
+    pub all_schemes: bool,
+    #[serde(default)]
+    pub verbose_matches: bool,
+    #[serde(default)]
+    pub min_confidence: u32,
+    // 每个主机每秒的请求数和总请求数
+    #[serde(default)]
+    pub rate_limit: Option<f32>,
+    #[serde(default)]
+    pub max_requests_per_host: Option<u32>,
+    // 打乱特殊请求的顺序，`MIN-MAX`毫秒的随机延时
+    #[serde(default)]
+    pub shuffle_probes: bool,
+    #[serde(default)]
+    pub jitter: String,
+    // 遵守robots.txt，不发送被禁止路径的特殊请求
+    #[serde(default)]
+    pub polite: bool,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // 按别名表统一组件名称，默认原样输出指纹库里的名称；指定了别名表时也会开启
+    #[serde(default)]
+    pub normalize_names: bool,
+    #[serde(default)]
+    pub name_aliases: String,
+    // 外部的favicon哈希库，CSV或者JSON
+    #[serde(default)]
+    pub favicon_db: String,
+    #[serde(default)]
+    pub favicon_db_category: FaviconDbCategory,
+    #[serde(default)]
+    pub user_agent: String,
+    #[serde(default)]
+    pub headers: Vec<String>,
+    // `HOST:IP`，请求这个域名时连接到指定的IP
+    #[serde(default)]
+    pub resolve: Vec<String>,
+    // 解析目标域名的DNS服务器，`IP[:PORT]`或者DoH地址
+    #[serde(default)]
+    pub dns: String,
+    // 所有目标共用的认证信息，`user:pass`和令牌只接收不返回
+    #[serde(default, skip_serializing)]
+    pub basic_auth: String,
+    #[serde(default, skip_serializing)]
+    pub bearer_token: String,
 }
 
 fn default_thread() -> u32 {
@@ -62,6 +220,30 @@ fn default_timeout() -> u64 {
     10
 }
 
+fn default_proxy_failure_threshold() -> u32 {
+    20
+}
+
+fn default_retries() -> u8 {
+    1
+}
+
+fn default_webhook_batch() -> usize {
+    1
+}
+
+fn default_webhook_interval() -> u64 {
+    5
+}
+
+fn default_audit_body_size() -> usize {
+    64
+}
+
+fn default_retry_interval() -> u64 {
+    500
+}
+
 impl Default for ObserverWardConfig {
     fn default() -> Self {
         Self {
@@ -69,28 +251,210 @@ impl Default for ObserverWardConfig {
             targets: Default::default(),
             stdin: false,
             verify: String::new(),
+            lint: String::new(),
+            diff: vec![],
+            diff_json: false,
             file: String::new(),
+            input_scan: String::new(),
+            all_open_ports: false,
+            har: String::new(),
             update_fingerprint: false,
+            local: String::new(),
+            fingerprint_url: String::new(),
+            fingerprint_sha256: String::new(),
             csv: String::new(),
             json: String::new(),
+            html: String::new(),
+            sqlite: String::new(),
+            nmap: String::new(),
+            output: String::new(),
+            append: false,
+            resume: String::new(),
+            nuclei_tags: String::new(),
+            group_by_component: String::new(),
+            cluster: String::new(),
+            cluster_distance: 3,
             proxy: String::new(),
+            proxy_headers: vec![],
+            proxy_failure_threshold: default_proxy_failure_threshold(),
+            proxy_fallback: false,
             timeout: 10,
+            target_deadline: None,
+            retries: 1,
+            retry_interval: 500,
+            redirect: RedirectPolicy::default(),
+            http_version: HttpVersion::default(),
+            ports: vec![],
             plugins: String::new(),
             update_plugins: false,
             update_self: false,
             thread: 100,
             webhook: String::new(),
+            webhook_header: String::new(),
+            webhook_batch: default_webhook_batch(),
+            webhook_interval: default_webhook_interval(),
+            audit_dir: String::new(),
+            audit_body_size: default_audit_body_size(),
+            audit_secrets: false,
             service: false,
             api_server: String::new(),
             daemon: false,
             token: String::new(),
             silent: false,
+            verbose: 0,
             filter: false,
+            stats: false,
+            dry_run: false,
+            shiro_detect: false,
+            cross_host_favicon: false,
+            verify_tls: false,
+            all_schemes: false,
+            verbose_matches: false,
+            min_confidence: 0,
+            rate_limit: None,
+            max_requests_per_host: None,
+            shuffle_probes: false,
+            polite: false,
+            jitter: String::new(),
+            seed: None,
+            normalize_names: false,
+            name_aliases: String::new(),
+            favicon_db: String::new(),
+            favicon_db_category: FaviconDbCategory::default(),
+            user_agent: String::new(),
+            headers: vec![],
+            resolve: vec![],
+            dns: String::new(),
+            basic_auth: String::new(),
+            bearer_token: String::new(),
         }
     }
 }
 
 impl ObserverWardConfig {
+    // `-o -`把结果逐行输出到标准输出
+    pub fn is_stdout_output(&self) -> bool {
+        self.output == "-"
+    }
+    // 标准输出留给结果、nuclei标签、组件分组或者聚类时，进度和汇总不打印到标准输出
+    pub fn is_quiet_stdout(&self) -> bool {
+        self.is_stdout_output()
+            || !self.nuclei_tags.is_empty()
+            || !self.group_by_component.is_empty()
+            || !self.cluster.is_empty()
+    }
+    // 默认只输出错误，`--verify`调试指纹时至少输出响应内容和匹配结果
+    pub fn log_level(&self) -> log::LevelFilter {
+        let level = match self.verbose {
+            0 => log::LevelFilter::Error,
+            1 => log::LevelFilter::Warn,
+            2 => log::LevelFilter::Info,
+            3 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        };
+        if self.verify.is_empty() {
+            level
+        } else {
+            level.max(log::LevelFilter::Debug)
+        }
+    }
+    pub fn request_option(&self) -> RequestOption {
+        let mut builder = RequestOption::builder()
+            .timeout(self.timeout)
+            .proxy(&self.proxy)
+            .proxy_failure_threshold(self.proxy_failure_threshold)
+            .proxy_fallback(self.proxy_fallback)
+            .shiro_detect(self.shiro_detect)
+            .cross_host_favicon(self.cross_host_favicon)
+            .verify_tls(self.verify_tls)
+            .all_schemes(self.all_schemes)
+            .verbose_matches(self.verbose_matches)
+            .min_confidence(self.min_confidence)
+            .shuffle_probes(self.shuffle_probes)
+            .polite(self.polite)
+            .retries(self.retries)
+            .retry_interval_ms(self.retry_interval)
+            .redirect_policy(self.redirect)
+            .ports(self.ports.clone())
+            .http_version(self.http_version);
+        if let Some(rps) = self.rate_limit {
+            builder = builder.rate_limit(rps);
+        }
+        if let Some(max_requests) = self.max_requests_per_host {
+            builder = builder.max_requests_per_host(max_requests);
+        }
+        if let Some(seed) = self.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(deadline) = self.target_deadline {
+            builder = builder.target_deadline(Duration::from_secs(deadline));
+        }
+        if !self.jitter.is_empty() {
+            let jitter = self.jitter.split_once('-').map_or_else(
+                || self.jitter.trim().parse().ok().map(|ms| (ms, ms)),
+                |(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)),
+            );
+            match jitter {
+                Some((min, max)) => builder = builder.jitter_ms(min, max),
+                None => {
+                    println!(
+                        "Invalid jitter {}, expected MIN-MAX milliseconds",
+                        self.jitter
+                    );
+                    process::exit(0);
+                }
+            }
+        }
+        if !self.user_agent.is_empty() {
+            builder = builder.user_agent(&self.user_agent);
+        }
+        if !self.basic_auth.is_empty() {
+            builder = builder.auth(AuthScheme::basic(&self.basic_auth));
+        } else if !self.bearer_token.is_empty() {
+            builder = builder.auth(AuthScheme::bearer(&self.bearer_token));
+        }
+        for header in self.headers.iter() {
+            match header.split_once(':') {
+                Some((name, value)) => builder = builder.header(name.trim(), value.trim()),
+                None => {
+                    println!("Invalid Header {}", header);
+                    process::exit(0);
+                }
+            }
+        }
+        for header in self.proxy_headers.iter() {
+            match header.split_once(':') {
+                Some((name, value)) => builder = builder.proxy_header(name.trim(), value.trim()),
+                None => {
+                    println!("Invalid Proxy Header {}", header);
+                    process::exit(0);
+                }
+            }
+        }
+        for resolve in self.resolve.iter() {
+            let parsed = resolve.split_once(':').and_then(|(host, ip)| {
+                let ip = ip.trim().trim_start_matches('[').trim_end_matches(']');
+                Some((host, ip.parse().ok()?))
+            });
+            match parsed {
+                Some((host, ip)) => builder = builder.resolve(host, ip),
+                None => {
+                    println!("Invalid resolve {}, expected HOST:IP", resolve);
+                    process::exit(0);
+                }
+            }
+        }
+        if !self.dns.is_empty() {
+            builder = builder.dns_server(&self.dns);
+        }
+        match builder.build() {
+            Ok(request_option) => request_option,
+            Err(err) => {
+                println!("{}", err);
+                process::exit(0);
+            }
+        }
+    }
     pub fn new() -> Self {
         let app = clap::Command::new("observer_ward")
             .version(env!("CARGO_PKG_VERSION"))
@@ -122,12 +486,62 @@ impl ObserverWardConfig {
                     .takes_value(false)
                     .help("Silent mode"),
             )
+            .arg(
+                Arg::new("verbose")
+                    .short('v')
+                    .long("verbose")
+                    .takes_value(false)
+                    .multiple_occurrences(true)
+                    .help("Log to stderr, repeat for more detail (-v warn, -vv info, -vvv debug, -vvvv trace)"),
+            )
             .arg(
                 Arg::new("filter")
                     .long("filter")
                     .takes_value(false)
                     .help("Filter mode,Display only the fingerprint that is not empty"),
             )
+            .arg(
+                Arg::new("stats")
+                    .long("stats")
+                    .takes_value(false)
+                    .help("Print component, status code, title, error and timing statistics after the scan"),
+            )
+            .arg(
+                Arg::new("dry_run")
+                    .long("dry_run")
+                    .takes_value(false)
+                    .help("List the requests each target would receive without sending them"),
+            )
+            .arg(
+                Arg::new("shiro_detect")
+                    .long("shiro_detect")
+                    .takes_value(false)
+                    .help("Send an extra rememberMe cookie request to identify Apache Shiro"),
+            )
+            .arg(
+                Arg::new("cross_host_favicon")
+                    .long("cross_host_favicon")
+                    .takes_value(false)
+                    .help("Also hash favicon links hosted on other hosts, such as a CDN"),
+            )
+            .arg(
+                Arg::new("verify_tls")
+                    .long("verify_tls")
+                    .takes_value(false)
+                    .help("Record whether each certificate validates against the system roots"),
+            )
+            .arg(
+                Arg::new("all_schemes")
+                    .long("all_schemes")
+                    .takes_value(false)
+                    .help("Request both https and http for targets without a scheme, even if the first one works"),
+            )
+            .arg(
+                Arg::new("verbose_matches")
+                    .long("verbose_matches")
+                    .takes_value(false)
+                    .help("Include the rules each fingerprint matched in the JSON output"),
+            )
             .arg(
                 Arg::new("file")
                     .short('f')
@@ -135,6 +549,24 @@ impl ObserverWardConfig {
                     .value_name("FILE")
                     .help("Read the target from the file"),
             )
+            .arg(
+                Arg::new("input_scan")
+                    .long("iN")
+                    .value_name("FILE")
+                    .help("Read open ports from nmap XML or masscan JSON/list output (ex: -iN scan.xml)"),
+            )
+            .arg(
+                Arg::new("all_open_ports")
+                    .long("all_open_ports")
+                    .takes_value(false)
+                    .help("Scan every open port from --iN, not only the HTTP-like ones"),
+            )
+            .arg(
+                Arg::new("har")
+                    .long("har")
+                    .value_name("FILE")
+                    .help("Identify the responses saved in a HAR file without sending requests"),
+            )
             .arg(
                 Arg::new("daemon")
                     .long("daemon")
@@ -155,6 +587,71 @@ impl ObserverWardConfig {
                     .value_name("JSON")
                     .help("Export to the json file or Import form the json file"),
             )
+            .arg(
+                Arg::new("html")
+                    .long("html")
+                    .value_name("HTML")
+                    .help("Export a self-contained html report (ex: report.html)"),
+            )
+            .arg(
+                Arg::new("sqlite")
+                    .long("sqlite")
+                    .value_name("DB")
+                    .help("Upsert results into a SQLite database (ex: results.db)"),
+            )
+            .arg(
+                Arg::new("nmap")
+                    .long("nmap")
+                    .value_name("XML")
+                    .help("Export results as nmap-compatible XML (ex: result.xml)"),
+            )
+            .arg(
+                Arg::new("output")
+                    .short('o')
+                    .long("output")
+                    .value_name("OUTPUT")
+                    .help("Stream results to a .csv/.jsonl file or - for stdout (ex: result.csv)"),
+            )
+            .arg(
+                Arg::new("append")
+                    .long("append")
+                    .takes_value(false)
+                    .help("Append to the output file instead of overwriting it"),
+            )
+            .arg(
+                Arg::new("resume")
+                    .long("resume")
+                    .value_name("FILE")
+                    .help("Record completed targets to this file and skip them when the scan is restarted"),
+            )
+            .arg(
+                Arg::new("nuclei_tags")
+                    .long("nuclei_tags")
+                    .value_name("FORMAT")
+                    .possible_values(["cmd", "json"])
+                    .help("Print matched components as nuclei tags, one command per target or a JSON mapping"),
+            )
+            .arg(
+                Arg::new("group_by_component")
+                    .long("group_by_component")
+                    .value_name("FORMAT")
+                    .possible_values(["text", "json"])
+                    .help("Print the targets of each matched component instead of the components of each target"),
+            )
+            .arg(
+                Arg::new("cluster")
+                    .long("cluster")
+                    .value_name("FORMAT")
+                    .possible_values(["text", "json"])
+                    .help("Group unidentified targets whose index pages look alike"),
+            )
+            .arg(
+                Arg::new("cluster_distance")
+                    .long("cluster_distance")
+                    .takes_value(true)
+                    .value_name("BITS")
+                    .help("Maximum SimHash distance within a --cluster group (default: 3)"),
+            )
             .arg(
                 Arg::new("proxy")
                     .long("proxy")
@@ -162,13 +659,120 @@ impl ObserverWardConfig {
                     .value_name("PROXY")
                     .help("Proxy to use for requests (ex: [http(s)|socks5(h)]://host:port)"),
             )
+            .arg(
+                Arg::new("proxy_header")
+                    .long("proxy_header")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .value_name("HEADER")
+                    .help("Header sent to the http(s) proxy (ex: 'Proxy-Authorization: Basic a2FsaTpwYXNz')"),
+            )
+            .arg(
+                Arg::new("proxy_failure_threshold")
+                    .long("proxy_failure_threshold")
+                    .takes_value(true)
+                    .value_name("COUNT")
+                    .help("Stop the scan when this many targets in a row fail to connect and the proxy is unreachable, 0 to disable (default: 20)"),
+            )
+            .arg(
+                Arg::new("proxy_fallback")
+                    .long("proxy_fallback")
+                    .takes_value(false)
+                    .help("Connect directly instead of stopping when the proxy is unreachable"),
+            )
+            .arg(
+                Arg::new("user_agent")
+                    .long("ua")
+                    .takes_value(true)
+                    .value_name("USER_AGENT")
+                    .help("Custom User-Agent for requests"),
+            )
+            .arg(
+                Arg::new("header")
+                    .short('H')
+                    .long("header")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .value_name("HEADER")
+                    .help("Extra default request header (ex: 'X-Forwarded-For: 127.0.0.1')"),
+            )
+            .arg(
+                Arg::new("resolve")
+                    .long("resolve")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .value_name("HOST:IP")
+                    .help("Send requests for HOST to IP, keeping the Host header and SNI (ex: example.com:10.0.0.1)"),
+            )
+            .arg(
+                Arg::new("dns")
+                    .long("dns")
+                    .takes_value(true)
+                    .value_name("SERVER")
+                    .help("Resolve targets with this DNS server, IP[:PORT] over UDP/TCP or a DoH url (ex: 10.0.0.53, https://1.1.1.1/dns-query)"),
+            )
+            .arg(
+                Arg::new("basic_auth")
+                    .long("basic_auth")
+                    .takes_value(true)
+                    .value_name("USER:PASS")
+                    .conflicts_with("bearer_token")
+                    .help("HTTP basic auth for every target, credentials in the target URL take precedence"),
+            )
+            .arg(
+                Arg::new("bearer_token")
+                    .long("bearer_token")
+                    .takes_value(true)
+                    .value_name("TOKEN")
+                    .help("Send 'Authorization: Bearer TOKEN' to every target"),
+            )
             .arg(
                 Arg::new("webhook")
                     .long("webhook")
+                    .aliases(&["webhook_url", "webhook-url"])
                     .takes_value(true)
                     .value_name("WEBHOOK")
                     .help("Send results to webhook server (ex: https://host:port/webhook)"),
             )
+            .arg(
+                Arg::new("webhook_header")
+                    .long("webhook_header")
+                    .takes_value(true)
+                    .value_name("HEADER")
+                    .help("Extra header sent to the webhook server (ex: 'Authorization: Bearer TOKEN')"),
+            )
+            .arg(
+                Arg::new("webhook_batch")
+                    .long("webhook_batch")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help("Push up to N results per webhook request as a JSON array (default 1)"),
+            )
+            .arg(
+                Arg::new("audit_dir")
+                    .long("audit_dir")
+                    .value_name("DIR")
+                    .help("Save every HTTP request and response under a new directory in DIR"),
+            )
+            .arg(
+                Arg::new("audit_body_size")
+                    .long("audit_body_size")
+                    .value_name("KB")
+                    .help("Keep the first KB of each response body in audit records (default 64)"),
+            )
+            .arg(
+                Arg::new("audit_secrets")
+                    .long("audit_secrets")
+                    .takes_value(false)
+                    .help("Keep Authorization, Cookie and Set-Cookie headers verbatim in audit records"),
+            )
+            .arg(
+                Arg::new("webhook_interval")
+                    .long("webhook_interval")
+                    .takes_value(true)
+                    .value_name("SECONDS")
+                    .help("Push a partial webhook batch after waiting this long (default 5)"),
+            )
             .arg(
                 Arg::new("timeout")
                     .long("timeout")
@@ -177,6 +781,60 @@ impl ObserverWardConfig {
                     .value_name("TIMEOUT")
                     .help("Set request timeout."),
             )
+            .arg(
+                Arg::new("target_deadline")
+                    .long("target_deadline")
+                    .takes_value(true)
+                    .value_name("SECONDS")
+                    .help("Stop scanning a target after this long and keep the partial results"),
+            )
+            .arg(
+                Arg::new("rate_limit")
+                    .long("rate_limit")
+                    .takes_value(true)
+                    .value_name("RPS")
+                    .help("Maximum requests per second to each host"),
+            )
+            .arg(
+                Arg::new("max_requests_per_host")
+                    .long("max_requests_per_host")
+                    .takes_value(true)
+                    .value_name("N")
+                    .help("Stop sending special probes to a host after N requests"),
+            )
+            .arg(
+                Arg::new("shuffle_probes")
+                    .long("shuffle_probes")
+                    .takes_value(false)
+                    .help("Send special probes in random order"),
+            )
+            .arg(
+                Arg::new("polite")
+                    .long("polite")
+                    .takes_value(false)
+                    .help("Fetch robots.txt and skip special probes on paths it disallows"),
+            )
+            .arg(
+                Arg::new("jitter")
+                    .long("jitter")
+                    .takes_value(true)
+                    .value_name("MIN-MAX")
+                    .help("Random delay in milliseconds between requests to the same host (ex: 200-800)"),
+            )
+            .arg(
+                Arg::new("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .value_name("SEED")
+                    .help("Random seed for --shuffle_probes and --jitter, for reproducible runs"),
+            )
+            .arg(
+                Arg::new("min_confidence")
+                    .long("min_confidence")
+                    .takes_value(true)
+                    .value_name("SCORE")
+                    .help("Drop matches with a confidence score (0-100) below this value"),
+            )
             .arg(
                 Arg::new("thread")
                     .long("thread")
@@ -185,6 +843,47 @@ impl ObserverWardConfig {
                     .value_name("THREAD")
                     .help("Number of concurrent threads."),
             )
+            .arg(
+                Arg::new("retries")
+                    .long("retries")
+                    .takes_value(true)
+                    .default_value("1")
+                    .value_name("RETRIES")
+                    .help("Retry connect errors and timeouts."),
+            )
+            .arg(
+                Arg::new("retry_interval")
+                    .long("retry_interval")
+                    .takes_value(true)
+                    .default_value("500")
+                    .value_name("MS")
+                    .help("Retry interval in ms, doubled each retry."),
+            )
+            .arg(
+                Arg::new("redirect")
+                    .long("redirect")
+                    .takes_value(true)
+                    .default_value("same-host")
+                    .value_name("POLICY")
+                    .possible_values(["none", "same-host", "same-domain", "always"])
+                    .help("Which redirects of the index page to follow."),
+            )
+            .arg(
+                Arg::new("http_version")
+                    .long("http_version")
+                    .takes_value(true)
+                    .default_value("auto")
+                    .value_name("VERSION")
+                    .possible_values(["auto", "http1-only", "http2-prior-knowledge"])
+                    .help("HTTP version to use, auto negotiates h2 over https."),
+            )
+            .arg(
+                Arg::new("ports")
+                    .long("ports")
+                    .takes_value(true)
+                    .value_name("PORTS")
+                    .help("Probe targets without a port on these ports (ex: 80,443,8080,8443)"),
+            )
             .arg(
                 Arg::new("verify")
                     .long("verify")
@@ -220,24 +919,140 @@ impl ObserverWardConfig {
                     .takes_value(false)
                     .help("Update self"),
             )
+            .arg(
+                Arg::new("local")
+                    .long("local")
+                    .value_name("PATH")
+                    .help("Use the local web fingerprint library file or directory"),
+            )
+            .arg(
+                Arg::new("normalize_names")
+                    .long("normalize_names")
+                    .takes_value(false)
+                    .help("Merge component names that differ in case or match the built-in alias table"),
+            )
+            .arg(
+                Arg::new("name_aliases")
+                    .long("name_aliases")
+                    .value_name("FILE")
+                    .help("JSON file mapping component name aliases to one name (ex: {\"nginx-waf\":\"nginx\"})"),
+            )
+            .arg(
+                Arg::new("favicon_db")
+                    .long("favicon_db")
+                    .value_name("FILE")
+                    .help("CSV or JSON file of favicon hashes (hash,name,priority) loaded as favicon fingerprints"),
+            )
+            .arg(
+                Arg::new("favicon_db_category")
+                    .long("favicon_db_category")
+                    .takes_value(true)
+                    .default_value("favicon")
+                    .value_name("CATEGORY")
+                    .possible_values(["favicon", "waf"])
+                    .help("Report --favicon_db matches as components (favicon) or as the WAF/CDN (waf)"),
+            )
+            .arg(
+                Arg::new("fingerprint_url")
+                    .long("fingerprint_url")
+                    .value_name("URL")
+                    .help("Update web fingerprint from this url instead of FingerprintHub"),
+            )
+            .arg(
+                Arg::new("fingerprint_sha256")
+                    .long("fingerprint_sha256")
+                    .value_name("SHA256")
+                    .help("Verify the downloaded web fingerprint with SHA-256"),
+            )
             .arg(
                 Arg::new("update_fingerprint")
                     .short('u')
                     .long("update_fingerprint")
                     .takes_value(false)
                     .help("Update web fingerprint"),
+            )
+            .subcommand(
+                clap::Command::new("lint")
+                    .about("Check a web fingerprint library for problems, exit with 1 on errors")
+                    .arg(
+                        Arg::new("library")
+                            .value_name("PATH")
+                            .required(true)
+                            .help("Fingerprint file or directory of fingerprint files"),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("diff")
+                    .about("Compare two .json/.jsonl result files, exit with 1 when they differ")
+                    .arg(
+                        Arg::new("old")
+                            .value_name("OLD")
+                            .required(true)
+                            .help("Results of the earlier scan"),
+                    )
+                    .arg(
+                        Arg::new("new")
+                            .value_name("NEW")
+                            .required(true)
+                            .help("Results of the later scan"),
+                    )
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .takes_value(false)
+                            .help("Print the difference as JSON"),
+                    ),
             );
-        let args = app.get_matches();
+        // 和nmap一样写成`-iN`，clap的短参数只能是一个字符
+        let args = app.get_matches_from(std::env::args_os().map(|arg| match arg == "-iN" {
+            true => OsString::from("--iN"),
+            false => arg,
+        }));
         let mut default = ObserverWardConfig::default();
+        if let Some(lint) = args.subcommand_matches("lint") {
+            default.lint = lint.value_of("library").unwrap_or_default().to_string();
+        }
+        if let Some(diff) = args.subcommand_matches("diff") {
+            default.diff = ["old", "new"]
+                .iter()
+                .map(|name| diff.value_of(name).unwrap_or_default().to_string())
+                .collect();
+            default.diff_json = diff.is_present("json");
+        }
         if args.is_present("stdin") {
             default.stdin = true;
         }
         if args.is_present("silent") {
             default.silent = true;
         }
+        default.verbose = args.occurrences_of("verbose").min(u8::MAX as u64) as u8;
         if args.is_present("filter") {
             default.filter = true;
         }
+        if args.is_present("stats") {
+            default.stats = true;
+        }
+        if args.is_present("dry_run") {
+            default.dry_run = true;
+        }
+        if args.is_present("append") {
+            default.append = true;
+        }
+        if args.is_present("shiro_detect") {
+            default.shiro_detect = true;
+        }
+        if args.is_present("cross_host_favicon") {
+            default.cross_host_favicon = true;
+        }
+        if args.is_present("verify_tls") {
+            default.verify_tls = true;
+        }
+        if args.is_present("all_schemes") {
+            default.all_schemes = true;
+        }
+        if args.is_present("verbose_matches") {
+            default.verbose_matches = true;
+        }
         if args.is_present("service") {
             default.service = true;
         }
@@ -277,12 +1092,39 @@ impl ObserverWardConfig {
         if let Some(webhook) = args.value_of("webhook") {
             default.webhook = webhook.to_string();
         };
+        if let Some(header) = args.value_of("webhook_header") {
+            default.webhook_header = header.to_string();
+        };
+        if let Some(batch) = args.value_of("webhook_batch") {
+            default.webhook_batch = batch.parse().unwrap_or(default.webhook_batch);
+        };
+        if let Some(interval) = args.value_of("webhook_interval") {
+            default.webhook_interval = interval.parse().unwrap_or(default.webhook_interval);
+        };
+        if let Some(dir) = args.value_of("audit_dir") {
+            default.audit_dir = dir.to_string();
+        }
+        if let Some(size) = args.value_of("audit_body_size") {
+            default.audit_body_size = size.parse().unwrap_or(default.audit_body_size);
+        }
+        if args.is_present("audit_secrets") {
+            default.audit_secrets = true;
+        }
         if let Some(server) = args.value_of("rest_api") {
             default.api_server = server.to_string();
         };
+        if let Some(file) = args.value_of("input_scan") {
+            default.input_scan = file.to_string();
+        }
+        if args.is_present("all_open_ports") {
+            default.all_open_ports = true;
+        }
         if let Some(file) = args.value_of("file") {
             default.file = file.to_string();
         };
+        if let Some(har) = args.value_of("har") {
+            default.har = har.to_string();
+        };
         if let Some(token) = args.value_of("token") {
             default.token = token.to_string();
         };
@@ -295,21 +1137,147 @@ impl ObserverWardConfig {
         if let Some(file) = args.value_of("json") {
             default.json = file.to_string();
         };
+        if let Some(file) = args.value_of("sqlite") {
+            default.sqlite = file.to_string();
+        }
+        if let Some(file) = args.value_of("nmap") {
+            default.nmap = file.to_string();
+        }
+        if let Some(file) = args.value_of("html") {
+            default.html = file.to_string();
+        };
+        if let Some(local) = args.value_of("local") {
+            default.local = local.to_string();
+        };
+        if args.is_present("normalize_names") {
+            default.normalize_names = true;
+        }
+        if let Some(name_aliases) = args.value_of("name_aliases") {
+            default.name_aliases = name_aliases.to_string();
+        };
+        if let Some(favicon_db) = args.value_of("favicon_db") {
+            default.favicon_db = favicon_db.to_string();
+        };
+        if let Some(category) = args.value_of("favicon_db_category") {
+            default.favicon_db_category = category.parse().unwrap_or_default();
+        }
+        if let Some(fingerprint_url) = args.value_of("fingerprint_url") {
+            default.fingerprint_url = fingerprint_url.to_string();
+        };
+        if let Some(sha256) = args.value_of("fingerprint_sha256") {
+            default.fingerprint_sha256 = sha256.to_lowercase();
+        };
+        if let Some(file) = args.value_of("output") {
+            default.output = file.to_string();
+        };
+        if let Some(resume) = args.value_of("resume") {
+            // 续扫的结果追加到上次的输出后面
+            default.resume = resume.to_string();
+            default.append = true;
+        };
+        if let Some(format) = args.value_of("nuclei_tags") {
+            default.nuclei_tags = format.to_string();
+        };
+        if let Some(format) = args.value_of("group_by_component") {
+            default.group_by_component = format.to_string();
+        };
+        if let Some(format) = args.value_of("cluster") {
+            default.cluster = format.to_string();
+        };
+        if let Some(distance) = args.value_of("cluster_distance") {
+            default.cluster_distance = distance.parse().unwrap_or(3);
+        };
+        if let Some(user_agent) = args.value_of("user_agent") {
+            default.user_agent = user_agent.to_string();
+        };
+        if let Some(basic_auth) = args.value_of("basic_auth") {
+            default.basic_auth = basic_auth.to_string();
+        };
+        if let Some(token) = args.value_of("bearer_token") {
+            default.bearer_token = token.to_string();
+        };
+        if let Some(headers) = args.values_of("header") {
+            default.headers = headers.map(String::from).collect();
+        }
+        if let Some(resolve) = args.values_of("resolve") {
+            default.resolve = resolve.map(String::from).collect();
+        };
+        if let Some(dns) = args.value_of("dns") {
+            default.dns = dns.to_string();
+        };
         if let Some(proxy) = args.value_of("proxy") {
             default.proxy = proxy.to_string();
         };
+        if let Some(headers) = args.values_of("proxy_header") {
+            default.proxy_headers = headers.map(String::from).collect();
+        }
+        if let Some(threshold) = args.value_of("proxy_failure_threshold") {
+            default.proxy_failure_threshold = threshold
+                .parse()
+                .unwrap_or_else(|_| default_proxy_failure_threshold());
+        };
+        if args.is_present("proxy_fallback") {
+            default.proxy_fallback = true;
+        }
         if let Some(timeout) = args.value_of("timeout") {
             default.timeout = timeout.parse().unwrap_or(10);
         };
+        if let Some(deadline) = args.value_of("target_deadline") {
+            default.target_deadline = deadline.parse().ok().filter(|secs| *secs > 0);
+        }
+        if let Some(min_confidence) = args.value_of("min_confidence") {
+            default.min_confidence = min_confidence.parse().unwrap_or(0);
+        }
+        if let Some(rate_limit) = args.value_of("rate_limit") {
+            default.rate_limit = rate_limit.parse().ok().filter(|rps: &f32| *rps > 0.0);
+        }
+        if let Some(max_requests) = args.value_of("max_requests_per_host") {
+            default.max_requests_per_host = max_requests.parse().ok();
+        }
+        if args.is_present("shuffle_probes") {
+            default.shuffle_probes = true;
+        }
+        if args.is_present("polite") {
+            default.polite = true;
+        }
+        if let Some(jitter) = args.value_of("jitter") {
+            default.jitter = jitter.to_string();
+        }
+        if let Some(seed) = args.value_of("seed") {
+            default.seed = seed.parse().ok();
+        };
         if let Some(thread) = args.value_of("thread") {
             default.thread = thread.parse().unwrap_or(100);
         };
+        if let Some(retries) = args.value_of("retries") {
+            default.retries = retries.parse().unwrap_or(1);
+        };
+        if let Some(retry_interval) = args.value_of("retry_interval") {
+            default.retry_interval = retry_interval.parse().unwrap_or(500);
+        };
+        if let Some(redirect) = args.value_of("redirect") {
+            default.redirect = redirect.parse().unwrap_or_default();
+        };
+        if let Some(http_version) = args.value_of("http_version") {
+            default.http_version = http_version.parse().unwrap_or_default();
+        };
+        if let Some(ports) = args.value_of("ports") {
+            for port in ports.split(',') {
+                match port.trim().parse::<u16>() {
+                    Ok(port) => default.ports.push(port),
+                    Err(_) => {
+                        println!("Invalid port {}", port);
+                        process::exit(0);
+                    }
+                }
+            }
+        };
         default
     }
 }
 
 pub fn has_nuclei_app() -> bool {
-    return if cfg!(target_os = "windows") {
+    if cfg!(target_os = "windows") {
         Command::new("nuclei.exe")
             .args(["-version"])
             .stdin(Stdio::null())
@@ -321,5 +1289,5 @@ pub fn has_nuclei_app() -> bool {
             .stdin(Stdio::null())
             .output()
             .is_ok()
-    };
+    }
 }
@@ -0,0 +1,235 @@
+use crate::error::Error;
+use observer_ward_what_web::WhatWebResult;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use url::Url;
+
+/// 读取`--json`保存的数组或者`--output`写的每行一个结果的文件
+pub fn read_results(path: &Path) -> Result<Vec<WhatWebResult>, Error> {
+    let data = std::fs::read_to_string(path)?;
+    let invalid = |err: serde_json::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), err),
+        )
+    };
+    if data.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&data).map_err(invalid)?);
+    }
+    let mut results = Vec::new();
+    for line in data.lines().filter(|line| !line.trim().is_empty()) {
+        results.push(serde_json::from_str(line).map_err(invalid)?);
+    }
+    Ok(results)
+}
+
+/// 比较两次扫描时用的目标地址，默认端口和结尾的`/`不影响比较，
+/// `http://a/`和`http://a:80`是同一个目标
+pub fn normalize_target(url: &str) -> String {
+    match Url::parse(url) {
+        // 解析时已经去掉了默认端口，主机名也转成了小写
+        Ok(url) => url.as_str().trim_end_matches('/').to_string(),
+        Err(_) => url.trim().trim_end_matches('/').to_string(),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TargetChange {
+    pub url: String,
+    pub components_added: Vec<String>,
+    pub components_removed: Vec<String>,
+    // 旧的和新的值，没有变化时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_code: Option<(u16, u16)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<(String, String)>,
+}
+
+impl TargetChange {
+    fn is_empty(&self) -> bool {
+        self.components_added.is_empty()
+            && self.components_removed.is_empty()
+            && self.status_code.is_none()
+            && self.title.is_none()
+    }
+}
+
+/// 两次扫描结果的差异，地址都是新结果里的写法，消失的目标是旧结果里的
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResultDiff {
+    pub appeared: Vec<String>,
+    pub disappeared: Vec<String>,
+    pub changed: Vec<TargetChange>,
+}
+
+impl ResultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.disappeared.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn sorted(names: HashSet<&String>) -> Vec<String> {
+    let mut names: Vec<String> = names.into_iter().cloned().collect();
+    names.sort();
+    names
+}
+
+pub fn diff_results(old: &[WhatWebResult], new: &[WhatWebResult]) -> ResultDiff {
+    // 同一个目标出现多次时用最后一个
+    let old: BTreeMap<String, &WhatWebResult> = old
+        .iter()
+        .map(|result| (normalize_target(&result.url), result))
+        .collect();
+    let new: BTreeMap<String, &WhatWebResult> = new
+        .iter()
+        .map(|result| (normalize_target(&result.url), result))
+        .collect();
+    let mut diff = ResultDiff::default();
+    for (key, result) in new.iter() {
+        let previous = match old.get(key) {
+            Some(previous) => previous,
+            None => {
+                diff.appeared.push(result.url.clone());
+                continue;
+            }
+        };
+        let mut change = TargetChange {
+            url: result.url.clone(),
+            components_added: sorted(result.name.difference(&previous.name).collect()),
+            components_removed: sorted(previous.name.difference(&result.name).collect()),
+            ..TargetChange::default()
+        };
+        if previous.status_code != result.status_code {
+            change.status_code = Some((previous.status_code, result.status_code));
+        }
+        if previous.title != result.title {
+            change.title = Some((previous.title.clone(), result.title.clone()));
+        }
+        if !change.is_empty() {
+            diff.changed.push(change);
+        }
+    }
+    for (key, result) in old.iter() {
+        if !new.contains_key(key) {
+            diff.disappeared.push(result.url.clone());
+        }
+    }
+    diff
+}
+
+impl fmt::Display for ResultDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for url in &self.appeared {
+            writeln!(f, "+ {}", url)?;
+        }
+        for url in &self.disappeared {
+            writeln!(f, "- {}", url)?;
+        }
+        for change in &self.changed {
+            writeln!(f, "~ {}", change.url)?;
+            for name in &change.components_added {
+                writeln!(f, "    + {}", name)?;
+            }
+            for name in &change.components_removed {
+                writeln!(f, "    - {}", name)?;
+            }
+            if let Some((old, new)) = change.status_code {
+                writeln!(f, "    status: {} -> {}", old, new)?;
+            }
+            if let Some((old, new)) = &change.title {
+                writeln!(f, "    title: {:?} -> {:?}", old, new)?;
+            }
+        }
+        write!(
+            f,
+            "{} appeared, {} disappeared, {} changed",
+            self.appeared.len(),
+            self.disappeared.len(),
+            self.changed.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str, names: &[&str], status_code: u16, title: &str) -> WhatWebResult {
+        let mut result = WhatWebResult::new(url.to_string());
+        result.name = names.iter().map(|name| name.to_string()).collect();
+        result.status_code = status_code;
+        result.title = title.to_string();
+        result
+    }
+
+    #[test]
+    fn test_normalize_target() {
+        assert_eq!(
+            normalize_target("http://A.example.com:80/"),
+            "http://a.example.com"
+        );
+        assert_eq!(
+            normalize_target("https://a.example.com:443"),
+            "https://a.example.com"
+        );
+        assert_eq!(
+            normalize_target("https://a.example.com:8443/"),
+            "https://a.example.com:8443"
+        );
+        assert_eq!(normalize_target(" 10.0.0.1:8080/ "), "10.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_diff_results() {
+        let old = vec![
+            result("http://a.example.com/", &["nginx", "php"], 200, "Home"),
+            result("http://b.example.com", &["iis"], 200, "IIS"),
+            result("http://c.example.com", &["tomcat"], 200, "Tomcat"),
+        ];
+        let new = vec![
+            result(
+                "http://a.example.com:80",
+                &["nginx", "wordpress"],
+                200,
+                "Home",
+            ),
+            result("http://c.example.com", &["tomcat"], 404, "Not Found"),
+            result("http://d.example.com", &["jenkins"], 200, "Jenkins"),
+        ];
+        let diff = diff_results(&old, &new);
+        assert_eq!(diff.appeared, ["http://d.example.com"]);
+        assert_eq!(diff.disappeared, ["http://b.example.com"]);
+        assert_eq!(
+            diff.changed,
+            [
+                TargetChange {
+                    url: String::from("http://a.example.com:80"),
+                    components_added: vec![String::from("wordpress")],
+                    components_removed: vec![String::from("php")],
+                    ..TargetChange::default()
+                },
+                TargetChange {
+                    url: String::from("http://c.example.com"),
+                    status_code: Some((200, 404)),
+                    title: Some((String::from("Tomcat"), String::from("Not Found"))),
+                    ..TargetChange::default()
+                },
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "+ http://d.example.com\n\
+             - http://b.example.com\n\
+             ~ http://a.example.com:80\n    + wordpress\n    - php\n\
+             ~ http://c.example.com\n    status: 200 -> 404\n    title: \"Tomcat\" -> \"Not Found\"\n\
+             1 appeared, 1 disappeared, 2 changed"
+        );
+        // 没有变化
+        let diff = diff_results(&old, &old);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "0 appeared, 0 disappeared, 0 changed");
+    }
+}
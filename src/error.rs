@@ -3,6 +3,8 @@ pub enum Error {
     IoError(std::io::Error),
     OpensslError(openssl::error::ErrorStack),
     ZipError(zip::result::ZipError),
+    ReqwestError(reqwest::Error),
+    FingerprintError(String),
 }
 
 impl From<std::io::Error> for Error {
@@ -23,12 +25,20 @@ impl From<zip::result::ZipError> for Error {
     }
 }
 
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::ReqwestError(e)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::IoError(e) => write!(f, "Io error: {}", e),
             Error::OpensslError(e) => write!(f, "Openssl error: {}", e),
             Error::ZipError(e) => write!(f, "Zip error: {}", e),
+            Error::ReqwestError(e) => write!(f, "Request error: {}", e),
+            Error::FingerprintError(e) => write!(f, "Fingerprint error: {}", e),
         }
     }
 }
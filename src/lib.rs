@@ -1,4 +1,13 @@
+use crate::audit::open_audit;
+use crate::checkpoint::open_checkpoint;
 use crate::cli::ObserverWardConfig;
+use crate::nmap::write_nmap_xml;
+use crate::output::open_output;
+use crate::report::write_html;
+use crate::shutdown::Shutdown;
+use crate::sqlite::open_sqlite;
+use crate::table::{render_table, ScanSummary};
+use crate::webhook::open_webhook;
 use crossterm::{
     style::{Color, Print, ResetColor, SetForegroundColor},
     ExecutableCommand,
@@ -6,29 +15,48 @@ use crossterm::{
 use error::Error;
 use futures::channel::mpsc::unbounded;
 use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use futures::{future, stream, SinkExt, Stream, StreamExt};
 use observer_ward_what_server::{NmapFingerPrint, WhatServer};
-use observer_ward_what_web::fingerprint::WebFingerPrint;
-use observer_ward_what_web::{RequestOption, TemplateResult, WhatWeb, WhatWebResult};
+use observer_ward_what_web::fingerprint::{
+    default_name_aliases, load_request_data_files, parse_web_fingerprint, FingerprintIssue,
+    WebFingerPrint, WebFingerPrintLib,
+};
+use observer_ward_what_web::{
+    cluster_by_simhash, group_by_component, read_favicon_db, read_har, read_scan_output,
+    read_targets, to_nuclei_tags, NoopObserver, RequestOption, RequestPreview, ScanObserver,
+    ScanStats, ScanTarget, TemplateResult, WardError, WhatWeb, WhatWebResult,
+};
 use once_cell::sync::Lazy;
 use prettytable::csv::Reader;
 use prettytable::{color, Attr, Cell, Row, Table};
-use reqwest::redirect::Policy;
-use reqwest::{header, Proxy};
-use serde_json::json;
-use std::collections::{HashMap, HashSet};
+use reqwest::Proxy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::io::IsTerminal;
 use std::io::{stdout, Cursor};
 use std::io::{BufRead, Read};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::process::Command;
 
 pub mod api;
+pub mod audit;
+pub mod checkpoint;
 pub mod cli;
+pub mod diff;
 pub mod error;
+pub mod nmap;
+pub mod output;
+pub mod report;
+pub mod shutdown;
+pub mod sqlite;
+pub mod table;
+pub mod webhook;
 
 use serde::{Deserialize, Serialize};
 
@@ -56,12 +84,45 @@ pub fn print_color(mut string: String, color: Color, nl: bool) {
     };
 }
 
+// 带上版本号的组件名称
+fn web_name_with_version(what_web_result: &WhatWebResult) -> Vec<String> {
+    what_web_result
+        .name
+        .iter()
+        .map(|name| match what_web_result.version.get(name) {
+            Some(versions) if !versions.is_empty() => {
+                let mut versions: Vec<&String> = versions.iter().collect();
+                versions.sort();
+                let versions: Vec<&str> = versions.into_iter().map(String::as_str).collect();
+                format!("{}/{}", name, versions.join(","))
+            }
+            _ => name.to_string(),
+        })
+        .collect()
+}
+
 pub fn print_what_web(what_web_result: &WhatWebResult) {
-    let color_web_name: Vec<String> = what_web_result.name.iter().map(String::from).collect();
+    let color_web_name: Vec<String> = web_name_with_version(what_web_result);
+    if let Some(err) = &what_web_result.error {
+        // 不是HTTP但是banner识别出了服务
+        if !color_web_name.is_empty() {
+            print!("[ {} |", what_web_result.display_url());
+            print_color(format!("{:?}", color_web_name), Color::Green, false);
+            let banner = what_web_result.banner.as_deref().unwrap_or_default();
+            println!(" | {} ]", banner.lines().next().unwrap_or_default());
+            return;
+        }
+        print_color(
+            format!("[ {} | {} ]", what_web_result.display_url(), err),
+            Color::Red,
+            true,
+        );
+        return;
+    }
     let status_code =
         reqwest::StatusCode::from_u16(what_web_result.status_code).unwrap_or_default();
     if !what_web_result.name.is_empty() {
-        print!("[ {} |", what_web_result.url);
+        print!("[ {} |", what_web_result.display_url());
         print_color(format!("{:?}", color_web_name), Color::Green, false);
         print!(" | {} | ", what_web_result.length);
         if status_code.is_success() {
@@ -69,11 +130,15 @@ pub fn print_what_web(what_web_result: &WhatWebResult) {
         } else {
             print_color(format!("{:?}", status_code), Color::Red, false);
         }
-        println!(" | {} ]", what_web_result.title);
+        print!(" | {}", what_web_result.title);
+        if let Some(waf) = &what_web_result.waf {
+            print_color(format!(" | WAF: {}", waf), Color::Yellow, false);
+        }
+        println!(" ]");
     } else {
         println!(
             "[ {} | {:?} | {} | {} | {} ]",
-            what_web_result.url,
+            what_web_result.display_url(),
             color_web_name,
             what_web_result.length,
             what_web_result.status_code,
@@ -98,32 +163,70 @@ pub fn print_nuclei(what_web_result: &WhatWebResult) {
     }
 }
 
-pub async fn webhook_results(what_web_result: WhatWebResult, webhook_url: &str) -> WhatWebResult {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        header::HeaderValue::from_static("application/json"),
-    );
-    let ua = "Mozilla/5.0 (X11; Linux x86_64; rv:94.0) Gecko/20100101 Firefox/94.0";
-    headers.insert(header::USER_AGENT, header::HeaderValue::from_static(ua));
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .pool_max_idle_per_host(0)
-        .danger_accept_invalid_certs(true)
-        .redirect(Policy::none())
-        .timeout(Duration::new(10, 0));
-    let what_web_result_json = json!(what_web_result)
-        .as_object()
-        .unwrap_or(&serde_json::Map::new())
-        .clone();
-    let _: Result<_, _> = client
-        .build()
-        .unwrap_or_default()
-        .post(webhook_url)
-        .json(&what_web_result_json)
-        .send()
-        .await;
-    what_web_result.clone()
+// 每个目标输出一条nuclei命令或者输出目标到标签的JSON
+pub fn print_nuclei_tags(format: &str, results: &[WhatWebResult]) {
+    let mut nuclei_tags: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for wwr in results {
+        let tags = to_nuclei_tags(wwr);
+        if !tags.is_empty() {
+            nuclei_tags.insert(&wwr.url, tags);
+        }
+    }
+    if format == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&nuclei_tags).unwrap_or_default()
+        );
+    } else {
+        for (url, tags) in nuclei_tags {
+            println!("nuclei -u {} -tags {}", url, tags.join(","));
+        }
+    }
+}
+
+pub fn print_group_by_component(format: &str, results: &[WhatWebResult]) {
+    let groups = group_by_component(results);
+    if format == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&groups).unwrap_or_default()
+        );
+    } else {
+        for (name, urls) in groups {
+            println!("{} ({})", name, urls.len());
+            for url in urls {
+                println!("    {}", url);
+            }
+        }
+    }
+}
+
+// 只看没有识别出组件的目标，多个目标的组在前，剩下的单独一个的只打印数量
+pub fn print_cluster(format: &str, distance: u32, results: &[WhatWebResult]) {
+    let unknown = results.iter().filter(|result| result.name.is_empty());
+    let clusters = cluster_by_simhash(unknown, distance);
+    if format == "json" {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&clusters).unwrap_or_default()
+        );
+    } else {
+        let (groups, single): (Vec<_>, Vec<_>) = clusters
+            .into_iter()
+            .partition(|cluster| cluster.urls.len() > 1);
+        for (index, cluster) in groups.iter().enumerate() {
+            println!(
+                "cluster {} ({}) {}",
+                index + 1,
+                cluster.urls.len(),
+                cluster.simhash
+            );
+            for url in cluster.urls.iter() {
+                println!("    {}", url);
+            }
+        }
+        println!("unclustered ({})", single.len());
+    }
 }
 
 pub fn print_opening() {
@@ -148,6 +251,9 @@ pub struct Helper<'a> {
     msg: HashMap<String, String>,
 }
 
+const DEFAULT_FINGERPRINT_URL: &str =
+    "https://0x727.github.io/FingerprintHub/web_fingerprint_v3.json";
+
 static OBSERVER_WARD_PATH: Lazy<PathBuf> = Lazy::new(|| -> PathBuf {
     let mut config_path = PathBuf::new();
     if let Some(cp) = dirs::config_dir() {
@@ -165,7 +271,7 @@ static OBSERVER_WARD_PATH: Lazy<PathBuf> = Lazy::new(|| -> PathBuf {
 
 impl<'a> Helper<'a> {
     pub fn new(config: &'a ObserverWardConfig) -> Self {
-        let ro = RequestOption::new(&config.timeout, &config.proxy);
+        let ro = config.request_option();
         Self {
             request_option: ro,
             config_path: &OBSERVER_WARD_PATH,
@@ -175,13 +281,58 @@ impl<'a> Helper<'a> {
     }
     async fn update_fingerprint(&mut self) {
         let fingerprint_path = self.config_path.join("web_fingerprint_v3.json");
-        self.download_file_from_github(
-            "https://0x727.github.io/FingerprintHub/web_fingerprint_v3.json",
-            fingerprint_path
-                .to_str()
-                .unwrap_or("web_fingerprint_v3.json"),
-        )
-        .await;
+        let mut update_url = self.config.fingerprint_url.as_str();
+        if update_url.is_empty() {
+            update_url = DEFAULT_FINGERPRINT_URL;
+        }
+        // 校验通过并且能正常解析才覆盖原来的指纹库
+        let content = match self.download(update_url).await {
+            Ok(content) => content,
+            Err(err) => {
+                self.msg.insert(
+                    String::from("err"),
+                    format!("Update failed, {}: {}", update_url, err),
+                );
+                return;
+            }
+        };
+        if !self.config.fingerprint_sha256.is_empty() {
+            let sha256 = openssl::sha::sha256(&content)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            if sha256 != self.config.fingerprint_sha256 {
+                self.msg.insert(
+                    String::from("err"),
+                    format!(
+                        "Update failed, SHA-256 mismatch: expected {} got {}",
+                        self.config.fingerprint_sha256, sha256
+                    ),
+                );
+                return;
+            }
+        }
+        if let Err(err) = parse_web_fingerprint(&content[..]) {
+            self.msg
+                .insert(String::from("err"), format!("Update failed, {}", err));
+            return;
+        }
+        match std::fs::write(&fingerprint_path, &content) {
+            Ok(_) => {
+                self.msg.insert(
+                    String::from("info"),
+                    format!(
+                        "Update: '{}' file size => {:?}",
+                        fingerprint_path.to_string_lossy(),
+                        content.len()
+                    ),
+                );
+            }
+            Err(err) => {
+                self.msg
+                    .insert(String::from("err"), format!("Update failed, {}", err));
+            }
+        }
         // self.download_file_from_github(
         //     "https://0x727.github.io/FingerprintHub/nmap_service_probes.json",
         //     "nmap_service_probes.json",
@@ -264,39 +415,98 @@ impl<'a> Helper<'_> {
         Vec::new()
     }
 
-    pub fn read_web_fingerprint(&mut self, verify: &str) -> Vec<WebFingerPrint> {
+    pub fn read_web_fingerprint(&mut self, verify: &str) -> Result<WebFingerPrintLib, Error> {
+        let mut fingerprint_lib = self.load_web_fingerprint(verify)?;
+        // 外部的favicon哈希库生成只有图标规则的指纹
+        if !self.config.favicon_db.is_empty() {
+            let favicon_db = |err: String| {
+                Error::FingerprintError(format!("{}: {}", self.config.favicon_db, err))
+            };
+            let entries = File::open(&self.config.favicon_db)
+                .map_err(|err| favicon_db(err.to_string()))
+                .and_then(|file| {
+                    read_favicon_db(io::BufReader::new(file))
+                        .map_err(|err| favicon_db(err.to_string()))
+                })?;
+            fingerprint_lib
+                .add_favicon_db(entries, self.config.favicon_db_category)
+                .map_err(|err| favicon_db(err.to_string()))?;
+        }
+        // 内置的别名表加上用户的，JSON对象，别名到统一后的名称
+        if self.config.normalize_names || !self.config.name_aliases.is_empty() {
+            let mut aliases = default_name_aliases();
+            if !self.config.name_aliases.is_empty() {
+                aliases.extend(self.read_name_aliases()?);
+            }
+            fingerprint_lib.normalize_names(&aliases);
+        }
+        Ok(fingerprint_lib)
+    }
+    fn read_name_aliases(&self) -> Result<HashMap<String, String>, Error> {
+        File::open(&self.config.name_aliases)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                serde_json::from_reader(io::BufReader::new(file)).map_err(|err| err.to_string())
+            })
+            .map_err(|err| {
+                Error::FingerprintError(format!("{}: {}", self.config.name_aliases, err))
+            })
+    }
+    fn load_web_fingerprint(&mut self, verify: &str) -> Result<WebFingerPrintLib, Error> {
+        let into_lib = |web_fingerprint: Vec<WebFingerPrint>| {
+            WebFingerPrintLib::new(web_fingerprint)
+                .map_err(|err| Error::FingerprintError(err.to_string()))
+        };
         if !verify.is_empty() {
             if let Ok(file) = File::open(verify) {
-                let mut web_fingerprint: Vec<WebFingerPrint> = vec![];
-                if let Ok(verify_fingerprints) =
-                    serde_yaml::from_reader::<_, VerifyWebFingerPrint>(&file)
-                {
-                    for mut verify_fingerprint in verify_fingerprints.fingerprint {
-                        verify_fingerprint.name = verify_fingerprints.name.clone();
-                        verify_fingerprint.priority = verify_fingerprints.priority;
-                        web_fingerprint.push(verify_fingerprint);
-                    }
-                };
-                return web_fingerprint;
+                let mut web_fingerprint = read_verify_fingerprint(&file, Path::new(verify))?;
+                resolve_request_data_files(&mut web_fingerprint, Path::new(verify))?;
+                return into_lib(web_fingerprint);
             } else {
                 println!("The verification file cannot be found in the current directory!");
             }
         }
         let mut web_fingerprint_path = PathBuf::from("web_fingerprint_v3.json");
-        if !web_fingerprint_path.exists() {
+        if !self.config.local.is_empty() {
+            web_fingerprint_path = PathBuf::from(&self.config.local);
+        } else if !web_fingerprint_path.exists() {
             web_fingerprint_path = self.config_path.join("web_fingerprint_v3.json");
         }
-        if let Ok(file) = File::open(web_fingerprint_path) {
-            if let Ok(web_fingerprint) = serde_json::from_reader::<_, Vec<WebFingerPrint>>(&file) {
-                return web_fingerprint;
-            } else {
-                println!("The fingerprint format is incorrect. Please update the fingerprint library again");
+        // 目录下的指纹文件按文件名排序后合并
+        if web_fingerprint_path.is_dir() {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(&web_fingerprint_path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.is_file()
+                        && matches!(
+                            path.extension().and_then(|ext| ext.to_str()),
+                            Some("json" | "yaml" | "yml")
+                        )
+                })
+                .collect();
+            paths.sort();
+            let mut fingerprint_lib = into_lib(vec![])?;
+            for path in paths {
+                fingerprint_lib.merge(into_lib(read_fingerprint_file(&path)?)?);
+            }
+            return Ok(fingerprint_lib);
+        }
+        if let Ok(file) = File::open(&web_fingerprint_path) {
+            match parse_web_fingerprint(io::BufReader::new(file)) {
+                Ok(mut web_fingerprint) => {
+                    resolve_request_data_files(&mut web_fingerprint, &web_fingerprint_path)?;
+                    return into_lib(web_fingerprint);
+                }
+                Err(err) => {
+                    println!("{:?}: {}", web_fingerprint_path, err);
+                    println!("The fingerprint format is incorrect. Please update the fingerprint library again");
+                }
             };
         } else {
             println!("The fingerprint library cannot be found in the current directory!");
             println!("Update fingerprint library with `-u` parameter!");
         }
-        Vec::new()
+        into_lib(vec![])
     }
 
     pub fn read_results_file(&self) -> Vec<WhatWebResult> {
@@ -326,8 +536,15 @@ impl<'a> Helper<'_> {
         }
         results
     }
+    async fn download(&self, update_url: &str) -> Result<Vec<u8>, Error> {
+        let proxy = self.request_option.proxy().cloned();
+        let proxy_obj = Proxy::custom(move |_url| proxy.clone());
+        let client = reqwest::Client::builder().proxy(proxy_obj).build()?;
+        let response = client.get(update_url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
     async fn download_file_from_github(&mut self, update_url: &'a str, filename: &'a str) {
-        let proxy = self.request_option.proxy.as_ref().cloned();
+        let proxy = self.request_option.proxy().cloned();
         let proxy_obj = Proxy::custom(move |_url| proxy.clone());
         let client = reqwest::Client::builder().proxy(proxy_obj);
         if let Ok(downloading_client) = client.build() {
@@ -356,14 +573,84 @@ impl<'a> Helper<'_> {
     }
 }
 
+// 检查指纹文件，目录下的所有指纹文件一起检查，不同文件里重复的指纹也会报告
+pub fn lint_web_fingerprint(path: &Path) -> Result<Vec<FingerprintIssue>, Error> {
+    let mut paths = vec![path.to_path_buf()];
+    if path.is_dir() {
+        paths = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.is_file()
+                    && matches!(
+                        path.extension().and_then(|ext| ext.to_str()),
+                        Some("json" | "yaml" | "yml")
+                    )
+            })
+            .collect();
+        paths.sort();
+    }
+    let mut web_fingerprint = vec![];
+    for path in paths {
+        web_fingerprint.extend(read_fingerprint_file(&path)?);
+    }
+    let fingerprint_lib = WebFingerPrintLib::new(web_fingerprint)
+        .map_err(|err| Error::FingerprintError(format!("{:?}: {}", path, err)))?;
+    Ok(fingerprint_lib.validate())
+}
+
 pub fn read_file_to_target(file_path: &str) -> HashSet<String> {
     if let Ok(lines) = read_lines(file_path) {
-        let target_list: Vec<String> = lines.filter_map(Result::ok).collect();
+        let target_list: Vec<String> = lines.map_while(Result::ok).collect();
         return HashSet::from_iter(target_list);
     }
     HashSet::from_iter([])
 }
 
+// 按行读取目标，边读边扫，支持JSON和CSV格式带上每个目标的请求头，只保存目标的哈希用来去重
+pub fn read_targets_stream<R>(reader: R) -> impl Stream<Item = ScanTarget>
+where
+    R: BufRead + Send + 'static,
+{
+    targets_stream(move || read_targets(reader))
+}
+
+// 从nmap或者masscan的结果里读取开放的端口，和目标文件一样去重
+pub fn read_scan_output_stream<R>(reader: R, all_ports: bool) -> impl Stream<Item = ScanTarget>
+where
+    R: BufRead + Send + 'static,
+{
+    targets_stream(move || read_scan_output(reader, all_ports))
+}
+
+fn targets_stream<F>(read: F) -> impl Stream<Item = ScanTarget>
+where
+    F: FnOnce() -> Box<dyn Iterator<Item = Result<ScanTarget, WardError>>> + Send + 'static,
+{
+    let (mut sender, receiver) = futures::channel::mpsc::channel::<ScanTarget>(1024);
+    std::thread::spawn(move || {
+        let mut seen: HashSet<u64> = HashSet::new();
+        for target in read() {
+            let target = match target {
+                Ok(target) => target,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    continue;
+                }
+            };
+            let mut hasher = DefaultHasher::new();
+            target.hash(&mut hasher);
+            if !seen.insert(hasher.finish()) {
+                continue;
+            }
+            // 扫描跟不上时阻塞读取，内存不会随输入增长
+            if futures::executor::block_on(sender.send(target)).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where
     P: AsRef<Path>,
@@ -373,17 +660,30 @@ where
 }
 
 pub fn print_results_and_save(
-    json: &str,
-    csv: &str,
-    silent: bool,
-    filter: bool,
+    config: &ObserverWardConfig,
     results: Vec<WhatWebResult>,
-    has_plugins: bool,
+    elapsed: Duration,
 ) {
+    let (json, csv) = (&config.json, &config.csv);
+    let (silent, filter) = (config.silent, config.filter);
+    let has_plugins = !config.plugins.is_empty();
+    // 结果输出到标准输出时，表格和汇总信息打印到标准错误
+    let summary_to_stderr = config.is_quiet_stdout();
     if !json.is_empty() {
-        let out = File::create(&json).expect("Failed to create file");
+        let out = File::create(json).expect("Failed to create file");
         serde_json::to_writer(out, &results).expect("Failed to save file")
     }
+    // 报告写失败时照样打印结果，不丢掉这次扫描
+    if !config.html.is_empty() {
+        if let Err(err) = write_html(&config.html, &results, elapsed) {
+            println!("Failed to write {}: {}", config.html, err);
+        }
+    }
+    if !config.nmap.is_empty() {
+        if let Err(err) = write_nmap_xml(&config.nmap, &results, elapsed) {
+            println!("Failed to write {}: {}", config.nmap, err);
+        }
+    }
     let mut table = Table::new();
     let mut headers = vec![
         Cell::new("url"),
@@ -398,14 +698,14 @@ pub fn print_results_and_save(
     }
     table.set_titles(Row::new(headers.clone()));
     for res in &results {
-        let wwn: Vec<String> = res.name.iter().map(String::from).collect();
+        let wwn: Vec<String> = web_name_with_version(res);
         let status_code = reqwest::StatusCode::from_u16(res.status_code).unwrap_or_default();
         let mut status_code_color = Attr::ForegroundColor(color::RED);
         if status_code.is_success() {
             status_code_color = Attr::ForegroundColor(color::GREEN);
         }
         let mut rows = vec![
-            Cell::new(res.url.as_str()),
+            Cell::new(&res.display_url()),
             Cell::new(&wwn.join("\n")).with_style(Attr::ForegroundColor(color::GREEN)),
             Cell::new(&res.length.to_string()),
             Cell::new(&res.status_code.to_string()).with_style(status_code_color),
@@ -419,38 +719,42 @@ pub fn print_results_and_save(
         table.add_row(Row::new(rows));
     }
     if !csv.is_empty() {
-        let out = File::create(&csv).expect("Failed to create file");
+        let out = File::create(csv).expect("Failed to create file");
         table.to_csv(out).expect("Failed to save file");
     }
-    let mut table = Table::new();
-    table.set_titles(Row::new(headers.clone()));
-    for res in &results {
-        if filter && res.name.is_empty() {
-            continue;
-        }
-        let wwn: Vec<String> = res.name.iter().map(String::from).collect();
-        let status_code = reqwest::StatusCode::from_u16(res.status_code).unwrap_or_default();
-        let mut status_code_color = Attr::ForegroundColor(color::RED);
-        if status_code.is_success() {
-            status_code_color = Attr::ForegroundColor(color::GREEN);
-        }
-        let mut rows = vec![
-            Cell::new(res.url.as_str()),
-            Cell::new(&wwn.join("\n")).with_style(Attr::ForegroundColor(color::GREEN)),
-            Cell::new(&res.length.to_string()),
-            Cell::new(&res.status_code.to_string()).with_style(status_code_color),
-            Cell::new(&textwrap::fill(res.title.as_str(), 40)),
-            Cell::new(&res.priority.to_string()),
-        ];
-        if has_plugins {
-            let wp: Vec<String> = res.plugins.iter().map(String::from).collect();
-            rows.push(Cell::new(&wp.join("\n")).with_style(Attr::ForegroundColor(color::RED)))
-        }
-        table.add_row(Row::new(rows));
+    let shown: Vec<&WhatWebResult> = results
+        .iter()
+        .filter(|res| !(filter && res.name.is_empty()))
+        .collect();
+    if silent {
+        return;
     }
-    if !table.is_empty() && !silent {
-        print_color(String::from("Important technology:\n"), Color::Yellow, true);
-        table.printstd();
+    let is_terminal = if summary_to_stderr {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    };
+    // 不是终端时输出纯文本，不截断也不带颜色
+    let width = if is_terminal {
+        crossterm::terminal::size()
+            .ok()
+            .map(|(columns, _)| columns as usize)
+    } else {
+        None
+    };
+    let summary = ScanSummary::new(&results, elapsed);
+    let mut output = String::new();
+    if !shown.is_empty() {
+        output.push_str(&render_table(&shown, has_plugins, width, is_terminal));
+    }
+    output.push_str(&summary.to_string());
+    if summary_to_stderr {
+        eprintln!("{}", output);
+    } else {
+        if !shown.is_empty() && is_terminal {
+            print_color(String::from("Important technology:\n"), Color::Yellow, true);
+        }
+        println!("{}", output);
     }
 }
 
@@ -528,7 +832,13 @@ impl Default for ObserverWard {
     fn default() -> Self {
         let config = ObserverWardConfig::new();
         let mut helper = Helper::new(&config);
-        let web_fingerprint = helper.read_web_fingerprint(&config.verify);
+        let web_fingerprint = match helper.read_web_fingerprint(&config.verify) {
+            Ok(w) => w,
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(0);
+            }
+        };
         let mut nmap_fingerprint = vec![];
         if config.service {
             nmap_fingerprint = helper.read_nmap_fingerprint();
@@ -540,20 +850,61 @@ impl Default for ObserverWard {
 impl ObserverWard {
     pub fn new(
         config: ObserverWardConfig,
-        web_fingerprint: Vec<WebFingerPrint>,
+        web_fingerprint: WebFingerPrintLib,
         nmap_fingerprint: Vec<NmapFingerPrint>,
     ) -> Self {
-        let request_option = RequestOption::new(&config.timeout, &config.proxy);
+        let request_option = config.request_option();
         let what_server_ins = WhatServer::new(300, nmap_fingerprint);
-        let what_web_ins = WhatWeb::new(request_option, web_fingerprint);
+        let what_web_ins = WhatWeb::from_fingerprint_lib(request_option, web_fingerprint);
         Self {
             what_server_ins,
             what_web_ins,
             config,
         }
     }
-    pub async fn scan(&self, targets: HashSet<String>) -> Vec<WhatWebResult> {
+    pub async fn scan<S, T>(&self, targets: S) -> Vec<WhatWebResult>
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Into<ScanTarget> + Send + 'static,
+    {
+        self.scan_until(targets, None).await
+    }
+    // 离线识别HAR文件里的响应，不请求目标，也不运行插件
+    pub async fn scan_har(&self, path: &str) -> Result<Vec<WhatWebResult>, Error> {
+        let file = File::open(path)?;
+        let raw_data = read_har(io::BufReader::new(file)).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, err))
+        })?;
+        let results = self.what_web_ins.scan_raw_data(raw_data).await;
+        if let Some(output) = open_output(&self.config) {
+            for wwr in results.iter() {
+                if let Err(err) = output.write(wwr) {
+                    eprintln!("Failed to write result: {}", err);
+                }
+            }
+        }
+        if let Some(webhook) = open_webhook(&self.config) {
+            for wwr in results.iter() {
+                webhook.target_finished(wwr);
+            }
+            webhook.finish().await;
+        }
+        Ok(results)
+    }
+    /// 收到退出信号后不再分发新目标，已经在扫描的目标继续完成
+    pub async fn scan_until<S, T>(
+        &self,
+        targets: S,
+        shutdown: Option<Arc<Shutdown>>,
+    ) -> Vec<WhatWebResult>
+    where
+        S: Stream<Item = T> + Send + 'static,
+        T: Into<ScanTarget> + Send + 'static,
+    {
         let config = self.config.clone();
+        // 服务模式下同一个实例多次扫描，每次扫描的请求数上限和汇总里的请求数、字节数单独计算
+        self.what_web_ins.reset_request_budget();
+        self.what_web_ins.counters().reset();
         let what_web_ins = self.what_web_ins.clone();
         let what_server_ins = self.what_server_ins.clone();
         let (what_web_sender, mut what_web_receiver) = unbounded();
@@ -562,26 +913,72 @@ impl ObserverWard {
         let (mut results_sender, mut results_receiver) = unbounded();
         let mut vec_results: Vec<WhatWebResult> = vec![];
         let config_thread = config.thread;
-        let is_debug = !config.verify.is_empty();
-        let webhook = config.webhook.clone();
+        let proxy = config.proxy.clone();
+        // 结果输出到标准输出时不打印扫描进度
+        let print_progress = !config.silent && !config.is_quiet_stdout();
+        let checkpoint = open_checkpoint(&self.config).map(Arc::new);
+        let resume = checkpoint.clone();
+        let audit = open_audit(&self.config).map(Arc::new);
+        let observer: Arc<dyn ScanObserver> = match audit.clone() {
+            Some(audit) => audit,
+            None => Arc::new(NoopObserver),
+        };
         let what_web_handle = tokio::task::spawn(async move {
-            let mut worker = FuturesUnordered::new();
-            let mut targets_iter = targets.iter();
-            for _ in 0..config_thread {
-                match targets_iter.next() {
-                    Some(target) => worker.push(what_web_ins.scan(target.to_string(), is_debug)),
-                    None => {
-                        break;
-                    }
+            let mut targets = targets.map(Into::<ScanTarget>::into).boxed();
+            // 续扫时按端口展开后跳过已经完成的目标
+            if let Some(resume) = resume {
+                let what_web_ins = what_web_ins.clone();
+                targets = targets
+                    .flat_map(move |target| {
+                        let probes = what_web_ins.expand_target(&target.url);
+                        stream::iter(probes.into_iter().map(move |url| ScanTarget {
+                            url,
+                            headers: target.headers.clone(),
+                        }))
+                    })
+                    .filter(move |target| future::ready(!resume.is_completed(&target.url)))
+                    .boxed();
+            }
+            let mut completed = 0;
+            let stop = shutdown.clone();
+            let stop = async move {
+                match stop {
+                    Some(stop) => stop.wait().await,
+                    None => futures::future::pending().await,
+                }
+            };
+            {
+                let dispatch = (&mut targets).take_until(stop);
+                let worker =
+                    what_web_ins.scan_target_stream(dispatch, config_thread as usize, observer);
+                futures::pin_mut!(worker);
+                while let Some((_target, result)) = worker.next().await {
+                    completed += 1;
+                    what_web_sender.unbounded_send(result).unwrap_or_default();
                 }
             }
-            while let Some(result) = worker.next().await {
-                if let Some(target) = targets_iter.next() {
-                    worker.push(what_web_ins.scan(target.to_string(), is_debug));
+            if what_web_ins.is_proxy_aborted() {
+                eprintln!(
+                    "Proxy `{}` is unreachable, stopped after {} targets. Fix the proxy and rerun, or use --proxy_fallback to connect directly",
+                    proxy, completed
+                );
+            }
+            match shutdown {
+                Some(shutdown) if shutdown.is_requested() => {
+                    // 统计没有扫描的目标，标准输入可能一直不结束，最多等一秒
+                    let mut remaining = 0;
+                    let count = async {
+                        while targets.next().await.is_some() {
+                            remaining += 1;
+                        }
+                    };
+                    let finished = tokio::time::timeout(Duration::from_secs(1), count)
+                        .await
+                        .is_ok();
+                    Some((completed, remaining, finished))
                 }
-                what_web_sender.unbounded_send(result).unwrap_or_default();
+                _ => None,
             }
-            true
         });
         let what_server_handle = tokio::task::spawn(async move {
             let mut worker = FuturesUnordered::new();
@@ -597,7 +994,7 @@ impl ObserverWard {
                 if let Some(v_wwr) = what_web_receiver.next().await {
                     worker.push(what_server_ins.scan(v_wwr));
                 }
-                if !config.silent {
+                if print_progress {
                     print_what_web(&wwr);
                 }
                 what_server_sender.start_send(wwr).unwrap_or_default();
@@ -622,7 +1019,7 @@ impl ObserverWard {
                     if let Some(v_wwr) = what_server_receiver.next().await {
                         worker.push(get_plugins_by_nuclei(v_wwr, &config));
                     }
-                    if !config.silent {
+                    if print_progress {
                         print_nuclei(&wwr);
                     }
                     verify_sender.start_send(wwr).unwrap_or_default();
@@ -635,62 +1032,142 @@ impl ObserverWard {
             true
         });
 
-        let results_handle = tokio::task::spawn(async move {
-            let mut worker = FuturesUnordered::new();
-            if !webhook.is_empty() {
-                for _ in 0..3 {
-                    match verify_receiver.next().await {
-                        Some(w) => {
-                            worker.push(webhook_results(w, &webhook));
-                        }
-                        None => {
-                            break;
-                        }
-                    }
+        let output = open_output(&self.config).map(Arc::new);
+        let sqlite = open_sqlite(&self.config);
+        let webhook = open_webhook(&self.config).map(Arc::new);
+        let webhook_observer = webhook.clone();
+        let write_output = move |wwr: &WhatWebResult| {
+            if let Some(output) = output.as_ref() {
+                if let Err(err) = output.write(wwr) {
+                    eprintln!("Failed to write result: {}", err);
                 }
-                while let Some(wwr) = worker.next().await {
-                    if let Some(w) = verify_receiver.next().await {
-                        worker.push(webhook_results(w, &webhook));
-                    }
-                    results_sender.start_send(wwr).unwrap_or_default();
+            }
+            if let Some(sqlite) = sqlite.as_ref() {
+                if let Err(err) = sqlite.write(wwr) {
+                    eprintln!("Failed to write result to SQLite: {}", err);
                 }
-            } else {
-                while let Some(wwr) = verify_receiver.next().await {
-                    results_sender.start_send(wwr).unwrap_or_default();
+            }
+            if let Some(webhook) = webhook_observer.as_ref() {
+                webhook.target_finished(wwr);
+            }
+            // 结果写完再记录完成，中途退出的目标下次重新扫
+            if let Some(checkpoint) = checkpoint.as_ref() {
+                if let Err(err) = checkpoint.complete(&wwr.target) {
+                    eprintln!("Failed to write checkpoint: {}", err);
                 }
             }
+        };
+        let results_handle = tokio::task::spawn(async move {
+            while let Some(wwr) = verify_receiver.next().await {
+                write_output(&wwr);
+                results_sender.start_send(wwr).unwrap_or_default();
+            }
             true
         });
-        let (_r1, _r2, _r3, _r4) = tokio::join!(
+        let (r1, _r2, _r3, _r4) = tokio::join!(
             what_web_handle,
             what_server_handle,
             verify_handle,
             results_handle
         );
+        // 等还没推送的结果发完
+        if let Some(webhook) = webhook {
+            webhook.finish().await;
+        }
+        if let Some(audit) = audit {
+            audit.finish().await;
+        }
+        if let Ok(Some((completed, remaining, finished))) = r1 {
+            let at_least = if finished { "" } else { "at least " };
+            eprintln!(
+                "Interrupted: {} targets completed, {}{} targets remaining",
+                completed, at_least, remaining
+            );
+        }
         while let Some(wwr) = results_receiver.next().await {
             vec_results.push(wwr);
         }
         if vec_results.len() < 2000 {
-            vec_results.sort_by(|a, b| b.priority.cmp(&a.priority));
+            vec_results.sort_by_key(|b| std::cmp::Reverse(b.priority));
         }
         vec_results
     }
+    // 代理断开，扫描提前停止
+    pub fn is_proxy_aborted(&self) -> bool {
+        self.what_web_ins.is_proxy_aborted()
+    }
+    // 不发送请求，列出这个目标会收到的请求
+    pub fn preview(&self, target: ScanTarget) -> Result<Vec<RequestPreview>, WardError> {
+        self.what_web_ins.preview(target)
+    }
+    // 结果的统计加上扫描期间发送的请求数和下载的字节数，不包括插件的请求
+    pub fn scan_stats(&self, results: &[WhatWebResult], elapsed: Duration) -> ScanStats {
+        ScanStats::from_results(results)
+            .with_counters(self.what_web_ins.counters())
+            .with_elapsed(elapsed)
+    }
     pub fn reload(&mut self, config: &ObserverWardConfig) {
         let mut helper = Helper::new(config);
-        let web_fingerprint = helper.read_web_fingerprint(&config.verify);
+        // 指纹库加载失败时保留原来的实例
+        let web_fingerprint = match helper.read_web_fingerprint(&config.verify) {
+            Ok(w) => w,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
         let mut nmap_fingerprint = vec![];
         if config.service {
             nmap_fingerprint = helper.read_nmap_fingerprint();
         }
-        let request_option = RequestOption::new(&config.timeout, &config.proxy);
+        let request_option = config.request_option();
         let what_server_ins = WhatServer::new(300, nmap_fingerprint);
-        let what_web_ins = WhatWeb::new(request_option, web_fingerprint);
+        let what_web_ins = WhatWeb::from_fingerprint_lib(request_option, web_fingerprint);
         self.config = config.clone();
         self.what_web_ins = what_web_ins;
         self.what_server_ins = what_server_ins;
     }
 }
 
+// 读取一个指纹文件，yaml按验证模式的格式，其他按JSON
+fn read_fingerprint_file(path: &Path) -> Result<Vec<WebFingerPrint>, Error> {
+    let file = File::open(path)?;
+    let mut web_fingerprint = if matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("yaml" | "yml")
+    ) {
+        read_verify_fingerprint(&file, path)?
+    } else {
+        parse_web_fingerprint(io::BufReader::new(file))
+            .map_err(|err| Error::FingerprintError(format!("{:?}: {}", path, err)))?
+    };
+    resolve_request_data_files(&mut web_fingerprint, path)?;
+    Ok(web_fingerprint)
+}
+
+// 请求体文件相对于指纹文件所在的目录
+fn resolve_request_data_files(
+    web_fingerprint: &mut [WebFingerPrint],
+    fingerprint_path: &Path,
+) -> Result<(), Error> {
+    let base_dir = fingerprint_path.parent().unwrap_or_else(|| Path::new(""));
+    load_request_data_files(web_fingerprint, base_dir)
+        .map_err(|err| Error::FingerprintError(format!("{:?}: {}", fingerprint_path, err)))
+}
+
+// 读取验证模式的yaml指纹，格式错误时报错，不能当成没有指纹
+fn read_verify_fingerprint(file: &File, path: &Path) -> Result<Vec<WebFingerPrint>, Error> {
+    let verify_fingerprints: VerifyWebFingerPrint = serde_yaml::from_reader(file)
+        .map_err(|err| Error::FingerprintError(format!("{:?}: {}", path, err)))?;
+    let mut web_fingerprint: Vec<WebFingerPrint> = vec![];
+    for mut verify_fingerprint in verify_fingerprints.fingerprint {
+        verify_fingerprint.name = verify_fingerprints.name.clone();
+        verify_fingerprint.priority = verify_fingerprints.priority;
+        web_fingerprint.push(verify_fingerprint);
+    }
+    Ok(web_fingerprint)
+}
+
 // 去重
 pub fn strings_to_urls(domains: String) -> HashSet<String> {
     let target_list = domains
@@ -699,3 +1176,26 @@ pub fn strings_to_urls(domains: String) -> HashSet<String> {
         .collect::<Vec<_>>();
     HashSet::from_iter(target_list)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_malformed_yaml() {
+        let dir = std::env::temp_dir().join(format!("observer_ward_lint_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("broken.yaml");
+        std::fs::write(
+            &path,
+            "name: nginx\npriority: [3\nfingerprint:\n  - path: /\n",
+        )
+        .unwrap();
+        let err = lint_web_fingerprint(&path).unwrap_err().to_string();
+        assert!(err.contains("broken.yaml"), "{}", err);
+        // 目录里有一个格式错误的文件也报错
+        let err = lint_web_fingerprint(&dir).unwrap_err().to_string();
+        assert!(err.contains("broken.yaml"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
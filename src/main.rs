@@ -1,13 +1,93 @@
-use std::collections::HashSet;
-use std::io::{self, Read};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
 
 use observer_ward::api::run_server;
 use observer_ward::cli::ObserverWardConfig;
+use observer_ward::diff::{diff_results, read_results};
 use observer_ward::error::Error;
+use observer_ward::shutdown::Shutdown;
 use observer_ward::{
-    print_opening, print_results_and_save, read_file_to_target, strings_to_urls, Helper,
+    lint_web_fingerprint, print_cluster, print_group_by_component, print_nuclei_tags,
+    print_opening, print_results_and_save, read_scan_output_stream, read_targets_stream, Helper,
     ObserverWard,
 };
+use observer_ward_what_web::fingerprint::IssueSeverity;
+use observer_ward_what_web::ScanTarget;
+
+// 只控制自己的日志，依赖库的日志只输出错误，`RUST_LOG`可以覆盖
+fn init_logger(config: &ObserverWardConfig) {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Error)
+        .filter_module("observer_ward", config.log_level())
+        .filter_module("what_web", config.log_level())
+        .parse_env("RUST_LOG")
+        .init();
+}
+
+// 给指纹库的CI用，有错误时退出码是1
+fn lint(path: &str) -> ! {
+    let issues = match lint_web_fingerprint(Path::new(path)) {
+        Ok(issues) => issues,
+        Err(err) => {
+            println!("{}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    for issue in issues.iter() {
+        println!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == IssueSeverity::Error)
+        .count();
+    println!(
+        "{}: {} errors, {} warnings",
+        path,
+        errors,
+        issues.len() - errors
+    );
+    process::exit(if errors > 0 { 1 } else { 0 });
+}
+
+// 有差异时退出码是1
+fn diff(old: &str, new: &str, json: bool) -> ! {
+    let read = |path: &str| match read_results(Path::new(path)) {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}: {}", path, err);
+            process::exit(2);
+        }
+    };
+    let result_diff = diff_results(&read(old), &read(new));
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&result_diff).unwrap_or_default()
+        );
+    } else {
+        println!("{}", result_diff);
+    }
+    process::exit(if result_diff.is_empty() { 0 } else { 1 });
+}
+
+// 只列出请求，不连接目标
+async fn dry_run(observer_ward: &ObserverWard, mut targets: BoxStream<'static, ScanTarget>) {
+    while let Some(target) = targets.next().await {
+        let url = target.url.clone();
+        match observer_ward.preview(target) {
+            Ok(previews) => {
+                for preview in previews {
+                    print!("{}", preview);
+                }
+            }
+            Err(err) => println!("{}: {}", url, err),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
@@ -19,39 +99,77 @@ async fn main() {
 
 async fn start() -> Result<(), Error> {
     let config = ObserverWardConfig::new();
-    if !(config.stdin || config.silent) {
+    if !config.lint.is_empty() {
+        lint(&config.lint);
+    }
+    if let [old, new] = config.diff.as_slice() {
+        diff(old, new, config.diff_json);
+    }
+    if !(config.stdin || config.silent || config.is_quiet_stdout()) {
         print_opening();
     }
     if !config.api_server.is_empty() {
         run_server();
     }
-    let mut targets = HashSet::new();
-    if config.stdin {
-        let mut buffer = String::new();
-        io::stdin().read_to_string(&mut buffer)?;
-        targets.extend(strings_to_urls(buffer));
+    init_logger(&config);
+    // 标准输入和文件边读边扫
+    let targets: BoxStream<'static, ScanTarget> = if config.stdin {
+        read_targets_stream(io::BufReader::new(io::stdin())).boxed()
     } else if !config.target.is_empty() {
-        targets.insert(String::from(&config.target));
+        stream::iter([ScanTarget::new(&config.target)]).boxed()
     } else if !config.file.is_empty() {
-        targets.extend(read_file_to_target(&config.file));
-    }
+        let file = File::open(&config.file)?;
+        read_targets_stream(io::BufReader::new(file)).boxed()
+    } else if !config.input_scan.is_empty() {
+        let file = File::open(&config.input_scan)?;
+        read_scan_output_stream(io::BufReader::new(file), config.all_open_ports).boxed()
+    } else {
+        stream::empty().boxed()
+    };
     let mut helper = Helper::new(&config);
-    let web_fingerprint = helper.read_web_fingerprint(&config.verify);
+    let web_fingerprint = helper.read_web_fingerprint(&config.verify)?;
     let mut nmap_fingerprint = vec![];
     if config.service {
         nmap_fingerprint = helper.read_nmap_fingerprint();
     }
     helper.run().await;
     let observer_ward_ins = ObserverWard::new(config.clone(), web_fingerprint, nmap_fingerprint);
-    let vec_results = observer_ward_ins.scan(targets).await;
-    let is_enable_plugin = !config.plugins.is_empty();
-    print_results_and_save(
-        &config.json,
-        &config.csv,
-        config.silent,
-        config.filter,
-        vec_results,
-        is_enable_plugin,
-    );
+    if config.dry_run {
+        dry_run(&observer_ward_ins, targets).await;
+        return Ok(());
+    }
+    let start_time = Instant::now();
+    let vec_results = if !config.har.is_empty() {
+        observer_ward_ins.scan_har(&config.har).await?
+    } else {
+        // Ctrl+C停止分发新目标，已经扫完的结果照常输出
+        let shutdown = Shutdown::install();
+        observer_ward_ins.scan_until(targets, Some(shutdown)).await
+    };
+    if !config.nuclei_tags.is_empty() {
+        print_nuclei_tags(&config.nuclei_tags, &vec_results);
+    }
+    if !config.group_by_component.is_empty() {
+        print_group_by_component(&config.group_by_component, &vec_results);
+    }
+    if !config.cluster.is_empty() {
+        print_cluster(&config.cluster, config.cluster_distance, &vec_results);
+    }
+    let elapsed = start_time.elapsed();
+    let stats = config
+        .stats
+        .then(|| observer_ward_ins.scan_stats(&vec_results, elapsed));
+    print_results_and_save(&config, vec_results, elapsed);
+    if let Some(stats) = stats {
+        // 结果输出到标准输出时打印到标准错误
+        if config.is_quiet_stdout() {
+            eprint!("{}", stats);
+        } else {
+            print!("{}", stats);
+        }
+    }
+    if observer_ward_ins.is_proxy_aborted() {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,306 @@
+use observer_ward_what_web::WhatWebResult;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// 转义XML的属性值，XML 1.0不允许的控制字符直接去掉，换行写成字符引用，解析后不会变成空格
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 连接的IP优先，经过代理时用`--resolve`指定的IP，都没有时只知道域名，不写`address`
+fn host_address(result: &WhatWebResult, host: &str) -> Option<(String, &'static str)> {
+    let ip = result
+        .peer_addr
+        .map(|addr| addr.ip())
+        .or(result.resolved_ip)
+        .or_else(|| host.parse().ok())?;
+    match ip {
+        IpAddr::V4(ip) => Some((ip.to_string(), "ipv4")),
+        IpAddr::V6(ip) => Some((ip.to_string(), "ipv6")),
+    }
+}
+
+// 首页能访问或者识别出banner时端口是open
+fn is_up(result: &WhatWebResult) -> bool {
+    result.status_code != 0 || result.banner.is_some()
+}
+
+// 一个目标一个host
+fn render_host(result: &WhatWebResult) -> String {
+    let url = Url::parse(&result.url).ok();
+    let host = url
+        .as_ref()
+        .and_then(|url| url.host_str())
+        .unwrap_or(&result.url);
+    let port = url
+        .as_ref()
+        .and_then(|url| url.port_or_known_default())
+        .unwrap_or_default();
+    // IPv6的主机名带方括号，和地址比较前去掉
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let address = host_address(result, host);
+    let is_open = is_up(result);
+    let mut xml = String::from("<host>");
+    if is_open {
+        xml.push_str(r#"<status state="up" reason="syn-ack" reason_ttl="0"/>"#);
+    } else {
+        xml.push_str(r#"<status state="down" reason="no-response" reason_ttl="0"/>"#);
+    }
+    if let Some((addr, addrtype)) = &address {
+        let _ = write!(
+            xml,
+            r#"<address addr="{}" addrtype="{}"/>"#,
+            escape_xml(addr),
+            addrtype
+        );
+    }
+    if address.as_ref().is_none_or(|(addr, _)| addr != host) {
+        let _ = write!(
+            xml,
+            r#"<hostnames><hostname name="{}" type="user"/></hostnames>"#,
+            escape_xml(host)
+        );
+    }
+    if is_open && port != 0 {
+        let _ = write!(
+            xml,
+            r#"<ports><port protocol="tcp" portid="{}"><state state="open" reason="syn-ack" reason_ttl="0"/>"#,
+            port
+        );
+        xml.push_str(&render_service(result, url.as_ref()));
+        if !result.title.is_empty() {
+            let title = escape_xml(&result.title);
+            let _ = write!(
+                xml,
+                r#"<script id="http-title" output="{0}"><elem key="title">{0}</elem></script>"#,
+                title
+            );
+        }
+        xml.push_str("</port></ports>");
+    }
+    xml.push_str("</host>\n");
+    xml
+}
+
+// 组件名称写在product里，带版本号的组件写在extrainfo里
+fn render_service(result: &WhatWebResult, url: Option<&Url>) -> String {
+    let scheme = url.map(Url::scheme).unwrap_or_default();
+    let name = match scheme {
+        "http" | "https" if result.status_code != 0 => "http",
+        _ => "unknown",
+    };
+    let mut names: Vec<&String> = result.name.iter().collect();
+    names.sort();
+    let mut service = format!(r#"<service name="{}""#, name);
+    if !names.is_empty() {
+        let product: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+        let _ = write!(service, r#" product="{}""#, escape_xml(&product.join(", ")));
+    }
+    let versions: Vec<String> = names
+        .iter()
+        .filter_map(|name| {
+            let mut versions: Vec<&String> = result.version.get(*name)?.iter().collect();
+            versions.sort();
+            let versions: Vec<&str> = versions.into_iter().map(String::as_str).collect();
+            Some(format!("{} {}", name, versions.join(",")))
+        })
+        .collect();
+    if !versions.is_empty() {
+        let _ = write!(
+            service,
+            r#" extrainfo="{}""#,
+            escape_xml(&versions.join(", "))
+        );
+    }
+    if scheme == "https" {
+        service.push_str(r#" tunnel="ssl""#);
+    }
+    service.push_str(r#" method="probed" conf="10"/>"#);
+    service
+}
+
+/// 生成nmap格式的XML，只包含DTD要求的元素和属性，给只认nmap结果的工具使用
+pub fn render_nmap_xml(results: &[WhatWebResult], elapsed: Duration) -> String {
+    let finished = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let start = finished.saturating_sub(elapsed);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n");
+    let _ = writeln!(
+        xml,
+        r#"<nmaprun scanner="nmap" args="observer_ward" start="{}" version="{}" xmloutputversion="1.05">"#,
+        start.as_secs(),
+        env!("CARGO_PKG_VERSION")
+    );
+    xml.push_str("<verbose level=\"0\"/>\n<debugging level=\"0\"/>\n");
+    for result in results {
+        xml.push_str(&render_host(result));
+    }
+    let up = results.iter().filter(|result| is_up(result)).count();
+    let _ = writeln!(
+        xml,
+        r#"<runstats><finished time="{}" elapsed="{:.2}" exit="success"/><hosts up="{}" down="{}" total="{}"/></runstats>"#,
+        finished.as_secs(),
+        elapsed.as_secs_f64(),
+        up,
+        results.len() - up,
+        results.len()
+    );
+    xml.push_str("</nmaprun>\n");
+    xml
+}
+
+pub fn write_nmap_xml<P: AsRef<Path>>(
+    path: P,
+    results: &[WhatWebResult],
+    elapsed: Duration,
+) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(render_nmap_xml(results, elapsed).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+    use std::collections::HashMap;
+
+    // 解析整个文档，返回每个元素的名字和属性，格式不对时解析失败
+    fn parse(xml: &str) -> Vec<(String, HashMap<String, String>)> {
+        let mut reader = Reader::from_str(xml);
+        let mut elements = vec![];
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(element) | Event::Empty(element) => {
+                    let attributes = element
+                        .attributes()
+                        .map(|attribute| {
+                            let attribute = attribute.unwrap();
+                            (
+                                String::from_utf8(attribute.key.as_ref().to_vec()).unwrap(),
+                                attribute.unescape_value().unwrap().to_string(),
+                            )
+                        })
+                        .collect();
+                    let name = String::from_utf8(element.name().as_ref().to_vec()).unwrap();
+                    elements.push((name, attributes));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        elements
+    }
+
+    fn attributes<'a>(
+        elements: &'a [(String, HashMap<String, String>)],
+        name: &str,
+    ) -> Vec<&'a HashMap<String, String>> {
+        elements
+            .iter()
+            .filter(|(element, _)| element == name)
+            .map(|(_, attributes)| attributes)
+            .collect()
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        let title = "<script>alert(\"x\" & 'y')</script>\u{1}\u{1b}[0m\ttab\r\nline";
+        let xml = format!(r#"<a title="{0}">{0}</a>"#, escape_xml(title));
+        let elements = parse(&xml);
+        // 控制字符去掉，其他字符原样解析回来，换行和制表符不会变成空格
+        assert_eq!(
+            elements[0].1["title"],
+            "<script>alert(\"x\" & 'y')</script>[0m\ttab\r\nline"
+        );
+        let banner = "SSH-2.0-OpenSSH_8.9\r\n\u{0}\u{7f}<&>";
+        let escaped = escape_xml(banner);
+        assert!(!escaped.contains('\u{0}'));
+        assert_eq!(
+            parse(&format!(r#"<a b="{}"/>"#, escaped))[0].1["b"],
+            "SSH-2.0-OpenSSH_8.9\r\n\u{7f}<&>"
+        );
+    }
+
+    #[test]
+    fn test_render_nmap_xml() {
+        let mut up = WhatWebResult::new(String::from("https://example.com:8443/"));
+        up.status_code = 200;
+        up.title = String::from("<Admin & \"Login\">\u{8}");
+        up.name.insert(String::from("nginx"));
+        up.version
+            .entry(String::from("nginx"))
+            .or_default()
+            .insert(String::from("1.22"));
+        up.peer_addr = Some("93.184.216.34:8443".parse().unwrap());
+        // 经过代理，不知道IP
+        let mut proxied = WhatWebResult::new(String::from("http://proxied.example.com/"));
+        proxied.status_code = 200;
+        let mut ipv6 = WhatWebResult::new(String::from("http://[::1]:8080/"));
+        ipv6.status_code = 200;
+        let down = WhatWebResult::new(String::from("http://10.0.0.1/"));
+        let xml = render_nmap_xml(&[up, proxied, ipv6, down], Duration::from_secs(3));
+        let elements = parse(&xml);
+        assert_eq!(elements[0].0, "nmaprun");
+        let hosts = xml
+            .lines()
+            .filter(|line| line.starts_with("<host>"))
+            .count();
+        assert_eq!(hosts, 4);
+        // DTD要求address有addr和addrtype，域名不能当成地址
+        let addresses = attributes(&elements, "address");
+        let addresses: Vec<(&str, &str)> = addresses
+            .iter()
+            .map(|a| (a["addr"].as_str(), a["addrtype"].as_str()))
+            .collect();
+        assert_eq!(
+            addresses,
+            [
+                ("93.184.216.34", "ipv4"),
+                ("::1", "ipv6"),
+                ("10.0.0.1", "ipv4")
+            ]
+        );
+        let hostnames: Vec<&str> = attributes(&elements, "hostname")
+            .iter()
+            .map(|h| h["name"].as_str())
+            .collect();
+        assert_eq!(hostnames, ["example.com", "proxied.example.com"]);
+        // port必须有protocol和portid，down的主机没有端口
+        let ports: Vec<(&str, &str)> = attributes(&elements, "port")
+            .iter()
+            .map(|p| (p["protocol"].as_str(), p["portid"].as_str()))
+            .collect();
+        assert_eq!(ports, [("tcp", "8443"), ("tcp", "80"), ("tcp", "8080")]);
+        let service = attributes(&elements, "service")[0];
+        assert_eq!(service["product"], "nginx");
+        assert_eq!(service["extrainfo"], "nginx 1.22");
+        assert_eq!(service["tunnel"], "ssl");
+        let script = attributes(&elements, "script")[0];
+        assert_eq!(script["output"], "<Admin & \"Login\">");
+        let hosts = attributes(&elements, "hosts")[0];
+        assert_eq!((hosts["up"].as_str(), hosts["down"].as_str()), ("3", "1"));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::cli::ObserverWardConfig;
+use observer_ward_what_web::WhatWebResult;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const CSV_HEADERS: [&str; 9] = [
+    "url",
+    "matched_names",
+    "priority",
+    "title",
+    "status_code",
+    "length",
+    "favicon_md5",
+    "body_md5",
+    "body_sha256",
+];
+
+enum OutputWriter {
+    Csv(Box<csv::Writer<Box<dyn Write + Send>>>),
+    // 每个结果一行JSON
+    JsonLines(BufWriter<Box<dyn Write + Send>>),
+}
+
+// 扫完一个目标写一次并立即刷新，长时间扫描不用把结果都放在内存里，进程被杀也不会丢
+pub struct ResultOutput {
+    writer: Mutex<OutputWriter>,
+}
+
+impl ResultOutput {
+    pub fn csv<P: AsRef<Path>>(path: P, append: bool) -> io::Result<Self> {
+        let (file, has_content) = open_file(path, append)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        // 追加到已有内容的文件时不再写表头
+        if !has_content {
+            writer.write_record(CSV_HEADERS)?;
+            writer.flush()?;
+        }
+        Ok(Self::new(OutputWriter::Csv(Box::new(writer))))
+    }
+    pub fn json_lines<P: AsRef<Path>>(path: P, append: bool) -> io::Result<Self> {
+        let (file, _) = open_file(path, append)?;
+        Ok(Self::new(OutputWriter::JsonLines(BufWriter::new(file))))
+    }
+    pub fn stdout() -> Self {
+        Self::new(OutputWriter::JsonLines(BufWriter::new(Box::new(
+            io::stdout(),
+        ))))
+    }
+    fn new(writer: OutputWriter) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+    pub fn write(&self, result: &WhatWebResult) -> io::Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| io::Error::other("output writer poisoned"))?;
+        match &mut *writer {
+            OutputWriter::Csv(writer) => {
+                let mut names: Vec<&str> = result.name.iter().map(String::as_str).collect();
+                names.sort_unstable();
+                let mut favicon_md5: Vec<&str> =
+                    result.favicon.values().map(|f| f.md5.as_str()).collect();
+                favicon_md5.sort_unstable();
+                favicon_md5.dedup();
+                writer.write_record([
+                    result.url.as_str(),
+                    &names.join("|"),
+                    &result.priority.to_string(),
+                    result.title.as_str(),
+                    &result.status_code.to_string(),
+                    &result.length.to_string(),
+                    &favicon_md5.join("|"),
+                    result.body_hash.as_ref().map_or("", |h| h.md5.as_str()),
+                    result.body_hash.as_ref().map_or("", |h| h.sha256.as_str()),
+                ])?;
+                writer.flush()
+            }
+            OutputWriter::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, result)?;
+                writer.write_all(b"\n")?;
+                writer.flush()
+            }
+        }
+    }
+}
+
+fn open_file<P: AsRef<Path>>(path: P, append: bool) -> io::Result<(Box<dyn Write + Send>, bool)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    let has_content = append && file.metadata()?.len() > 0;
+    Ok((Box::new(file), has_content))
+}
+
+// 根据输出文件的扩展名选择格式，`-`输出到标准输出
+pub fn open_output(config: &ObserverWardConfig) -> Option<ResultOutput> {
+    if config.output.is_empty() {
+        return None;
+    }
+    if config.is_stdout_output() {
+        return Some(ResultOutput::stdout());
+    }
+    let output = config.output.to_lowercase();
+    let result_output = if output.ends_with(".csv") {
+        ResultOutput::csv(&config.output, config.append)
+    } else if output.ends_with(".jsonl") {
+        ResultOutput::json_lines(&config.output, config.append)
+    } else {
+        println!(
+            "Unsupported output format {}, expected .csv or .jsonl",
+            config.output
+        );
+        std::process::exit(0);
+    };
+    match result_output {
+        Ok(output) => Some(output),
+        Err(err) => {
+            println!("Failed to create {}: {}", config.output, err);
+            std::process::exit(0);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::table::ScanSummary;
+use observer_ward_what_web::{MatchedRule, WhatWebResult};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+// 页面模板，样式和脚本都内联在里面，不依赖外部资源
+const TEMPLATE: &str = include_str!("report.html");
+
+/// 转义HTML文本和属性值，标题、跳转地址这些都是目标可以控制的，写进报告前都要经过这里
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 只有http和https的地址生成链接，其他协议（比如`javascript:`）当作普通文本
+fn link(url: &str) -> String {
+    let lower = url.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        format!(
+            r#"<a href="{0}" target="_blank" rel="noopener noreferrer">{0}</a>"#,
+            escape_html(url)
+        )
+    } else {
+        escape_html(url)
+    }
+}
+
+fn describe_rule(rule: &MatchedRule) -> String {
+    match rule {
+        MatchedRule::Keyword {
+            keyword,
+            offset,
+            snippet,
+        } => format!(
+            "keyword <code>{}</code> at {}: {}",
+            escape_html(keyword),
+            offset,
+            escape_html(snippet)
+        ),
+        MatchedRule::Regex {
+            pattern,
+            offset,
+            snippet,
+        } => format!(
+            "regex <code>{}</code> at {}: {}",
+            escape_html(pattern),
+            offset,
+            escape_html(snippet)
+        ),
+        MatchedRule::HexKeyword { hex, offset } => {
+            format!("hex <code>{}</code> at {}", escape_html(hex), offset)
+        }
+        MatchedRule::Header { name, value } => format!(
+            "header <code>{}: {}</code>",
+            escape_html(name),
+            escape_html(value)
+        ),
+        MatchedRule::Favicon { hash, url } => {
+            format!("favicon <code>{}</code> {}", escape_html(hash), link(url))
+        }
+        MatchedRule::StatusCode { status_code } => format!("status code {}", status_code),
+        MatchedRule::Length { length } => format!("length {} bytes", length),
+        MatchedRule::Realm { realm } => format!("realm <code>{}</code>", escape_html(realm)),
+        MatchedRule::ReasonPhrase { reason_phrase } => {
+            format!("reason phrase <code>{}</code>", escape_html(reason_phrase))
+        }
+        MatchedRule::MetaGenerator { generator } => {
+            format!("generator <code>{}</code>", escape_html(generator))
+        }
+        MatchedRule::ScriptSrc { path, .. } => {
+            format!("script src <code>{}</code>", escape_html(path))
+        }
+        MatchedRule::Cookie { name, .. } => format!("cookie <code>{}</code>", escape_html(name)),
+        MatchedRule::BodyHash { hash } => format!("body hash <code>{}</code>", escape_html(hash)),
+        MatchedRule::Robots { line, .. } => {
+            format!("robots.txt <code>{}</code>", escape_html(line))
+        }
+        MatchedRule::Cert { subject, issuer } => format!(
+            "certificate subject <code>{}</code> issuer <code>{}</code>",
+            escape_html(subject),
+            escape_html(issuer)
+        ),
+    }
+}
+
+fn priority_class(priority: u32) -> &'static str {
+    match priority {
+        4.. => "high",
+        2..=3 => "medium",
+        _ => "",
+    }
+}
+
+fn render_detail(result: &WhatWebResult) -> String {
+    let mut detail = String::new();
+    let _ = write!(
+        detail,
+        "<h3>Title</h3><div>{}</div>",
+        escape_html(&result.title)
+    );
+    if let Some(error) = &result.error {
+        let _ = write!(
+            detail,
+            r#"<h3>Error</h3><div class="bad">{}</div>"#,
+            escape_html(error)
+        );
+    }
+    if let Some(banner) = &result.banner {
+        let _ = write!(detail, "<h3>Banner</h3><pre>{}</pre>", escape_html(banner));
+    }
+    let mut names: Vec<&String> = result.name.iter().collect();
+    names.sort();
+    if !names.is_empty() {
+        detail.push_str("<h3>Matched fingerprints</h3><ul>");
+        for name in names {
+            let priority = result.matched.get(name).copied().unwrap_or_default();
+            let _ = write!(
+                detail,
+                r#"<li><span class="{}">{}</span> priority {}"#,
+                priority_class(priority),
+                escape_html(name),
+                priority
+            );
+            if let Some(confidence) = result.confidence.get(name) {
+                let _ = write!(detail, ", confidence {}", confidence);
+            }
+            if let Some(evidence) = result.evidence.get(name) {
+                detail.push_str("<ul>");
+                for item in evidence {
+                    for rule in &item.rules {
+                        let _ = write!(
+                            detail,
+                            "<li>{} ({})</li>",
+                            describe_rule(rule),
+                            link(&item.url)
+                        );
+                    }
+                }
+                detail.push_str("</ul>");
+            }
+            detail.push_str("</li>");
+        }
+        detail.push_str("</ul>");
+    }
+    if !result.redirect_chain.is_empty() {
+        detail.push_str("<h3>Redirect chain</h3><ul>");
+        for jump in &result.redirect_chain {
+            let _ = write!(
+                detail,
+                "<li>{} &rarr; {} ({:?})</li>",
+                link(jump.from.as_str()),
+                link(jump.to.as_str()),
+                jump.source
+            );
+        }
+        detail.push_str("</ul>");
+    }
+    if let Some(hsts) = &result.hsts {
+        let _ = write!(detail, "<h3>HSTS</h3><div>max-age={}", hsts.max_age);
+        if hsts.include_subdomains {
+            detail.push_str("; includeSubDomains");
+        }
+        if hsts.preload {
+            detail.push_str("; preload");
+        }
+        detail.push_str("</div>");
+    }
+    if let Some(body_hash) = &result.body_hash {
+        let _ = write!(
+            detail,
+            "<h3>Body hash</h3><div>md5 <code>{}</code> sha256 <code>{}</code></div>",
+            escape_html(&body_hash.md5),
+            escape_html(&body_hash.sha256)
+        );
+    }
+    if !result.favicon.is_empty() {
+        let mut favicon: Vec<_> = result.favicon.iter().collect();
+        favicon.sort_by(|a, b| a.0.cmp(b.0));
+        detail.push_str("<h3>Favicon hashes</h3><ul>");
+        for (url, hash) in favicon {
+            let _ = write!(
+                detail,
+                "<li>{} md5 <code>{}</code> mmh3 <code>{}</code>",
+                link(url),
+                escape_html(&hash.md5),
+                hash.mmh3
+            );
+            if let Some(dhash) = hash.dhash {
+                let _ = write!(detail, " dhash <code>{:016x}</code>", dhash);
+            }
+            detail.push_str("</li>");
+        }
+        detail.push_str("</ul>");
+    }
+    detail
+}
+
+fn render_row(result: &WhatWebResult) -> String {
+    let mut names: Vec<&String> = result.name.iter().collect();
+    names.sort();
+    let components: Vec<String> = names
+        .iter()
+        .map(|name| {
+            let priority = result.matched.get(*name).copied().unwrap_or_default();
+            format!(
+                r#"<span class="{}">{}</span>"#,
+                priority_class(priority),
+                escape_html(name)
+            )
+        })
+        .collect();
+    let sort_names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    let status_class = match result.status_code {
+        200..=299 => "ok",
+        _ => "bad",
+    };
+    format!(
+        concat!(
+            r#"<tbody class="result"><tr class="summary">"#,
+            r#"<td data-sort="{url}">{url}</td>"#,
+            r#"<td data-sort="{status}" class="{status_class}">{status}</td>"#,
+            r#"<td data-sort="{title}">{title}</td>"#,
+            r#"<td data-sort="{names}">{components}</td>"#,
+            r#"<td data-sort="{priority}">{priority}</td>"#,
+            r#"<td data-sort="{length}">{length}</td>"#,
+            r#"</tr><tr class="detail"><td colspan="6">{detail}</td></tr></tbody>"#,
+            "\n"
+        ),
+        url = escape_html(&result.display_url()),
+        status = result.status_code,
+        status_class = status_class,
+        title = escape_html(&result.title),
+        names = escape_html(&sort_names.join(", ")),
+        components = components.join(", "),
+        priority = result.priority,
+        length = result.length,
+        detail = render_detail(result),
+    )
+}
+
+/// 生成单个HTML文件的报告，可以排序和过滤，点击一行展开指纹、证据、跳转和favicon
+pub fn render_html(results: &[WhatWebResult], elapsed: Duration) -> String {
+    let rows: String = results.iter().map(render_row).collect();
+    let summary = ScanSummary::new(results, elapsed);
+    // 行里的内容来自目标，放在最后替换，避免里面的占位符被再次替换
+    TEMPLATE
+        .replace("{{summary}}", &escape_html(&summary.to_string()))
+        .replace("{{rows}}", &rows)
+}
+
+pub fn write_html<P: AsRef<Path>>(
+    path: P,
+    results: &[WhatWebResult],
+    elapsed: Duration,
+) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(render_html(results, elapsed).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_target_content() {
+        let mut result = WhatWebResult::new(String::from("http://10.0.0.1/?q=<script>"));
+        result.title = String::from("<script>alert(1)</script>");
+        result
+            .name
+            .insert(String::from("\"><img src=x onerror=alert(1)>"));
+        result.status_code = 200;
+        let html = render_html(&[result], Duration::from_secs(1));
+        assert!(!html.contains("<script>alert(1)</script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<img src=x"));
+        assert!(html.contains("&quot;&gt;&lt;img src=x onerror=alert(1)&gt;"));
+        assert!(html.contains("http://10.0.0.1/?q=&lt;script&gt;"));
+        assert_eq!(link("javascript:alert(1)"), "javascript:alert(1)");
+        assert!(link("https://10.0.0.1/'").starts_with(r#"<a href="https://10.0.0.1/&#39;""#));
+    }
+
+    #[test]
+    fn test_write_html_error() {
+        let path = std::env::temp_dir()
+            .join("observer_ward_missing_dir")
+            .join("report.html");
+        assert!(write_html(path, &[], Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::cli::ObserverWardConfig;
+use observer_ward_what_web::WhatWebResult;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 同一个目标和组件只有一行，重新扫描时更新，`last_seen`记录最后一次出现的时间
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS targets (
+    id INTEGER PRIMARY KEY,
+    url TEXT NOT NULL UNIQUE,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS matches (
+    target_id INTEGER NOT NULL REFERENCES targets(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    version TEXT,
+    confidence INTEGER,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    PRIMARY KEY (target_id, name)
+);
+CREATE TABLE IF NOT EXISTS metadata (
+    target_id INTEGER PRIMARY KEY REFERENCES targets(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    status_code INTEGER NOT NULL,
+    length INTEGER NOT NULL,
+    favicon TEXT NOT NULL,
+    redirect_chain TEXT NOT NULL,
+    last_seen INTEGER NOT NULL
+);
+";
+
+/// 把结果写到SQLite文件，表结构见`SCHEMA`。每个目标写完立即提交，
+/// 同一个文件多次扫描时按目标和组件名称更新，方便用SQL比较两次扫描
+pub struct SqliteOutput {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteOutput {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+    fn from_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+    pub fn write(&self, result: &WhatWebResult) -> rusqlite::Result<()> {
+        self.write_at(result, now_secs())
+    }
+    fn write_at(&self, result: &WhatWebResult, seen: i64) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap_or_else(|err| err.into_inner());
+        let tx = conn.transaction()?;
+        let target_id: i64 = tx.query_row(
+            "INSERT INTO targets (url, first_seen, last_seen) VALUES (?1, ?2, ?2)
+             ON CONFLICT(url) DO UPDATE SET last_seen = excluded.last_seen
+             RETURNING id",
+            params![result.url, seen],
+            |row| row.get(0),
+        )?;
+        let favicon = serde_json::to_string(&result.favicon).unwrap_or_default();
+        let redirect_chain = serde_json::to_string(&result.redirect_chain).unwrap_or_default();
+        tx.execute(
+            "INSERT INTO metadata (target_id, title, status_code, length, favicon, redirect_chain, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(target_id) DO UPDATE SET
+                title = excluded.title,
+                status_code = excluded.status_code,
+                length = excluded.length,
+                favicon = excluded.favicon,
+                redirect_chain = excluded.redirect_chain,
+                last_seen = excluded.last_seen",
+            params![
+                target_id,
+                result.title,
+                result.status_code,
+                result.length as i64,
+                favicon,
+                redirect_chain,
+                seen
+            ],
+        )?;
+        for name in &result.name {
+            let priority = result.matched.get(name).copied().unwrap_or(result.priority);
+            let version = result.version.get(name).map(|versions| {
+                let mut versions: Vec<&str> = versions.iter().map(String::as_str).collect();
+                versions.sort_unstable();
+                versions.join(",")
+            });
+            let confidence = result.confidence.get(name);
+            tx.execute(
+                "INSERT INTO matches (target_id, name, priority, version, confidence, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+                 ON CONFLICT(target_id, name) DO UPDATE SET
+                    priority = excluded.priority,
+                    version = excluded.version,
+                    confidence = excluded.confidence,
+                    last_seen = excluded.last_seen",
+                params![target_id, name, priority, version, confidence, seen],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+pub fn open_sqlite(config: &ObserverWardConfig) -> Option<SqliteOutput> {
+    if config.sqlite.is_empty() {
+        return None;
+    }
+    match SqliteOutput::open(&config.sqlite) {
+        Ok(output) => Some(output),
+        Err(err) => {
+            // 打不开数据库时结果没有保存，不能当成成功
+            eprintln!("Failed to open {}: {}", config.sqlite, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str, names: &[(&str, &str)]) -> WhatWebResult {
+        let mut result = WhatWebResult::new(url.to_string());
+        for (name, version) in names {
+            result.name.insert(name.to_string());
+            result.matched.insert(name.to_string(), 3);
+            result
+                .version
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+        }
+        result
+    }
+
+    #[test]
+    fn test_rescan_updates_rows() {
+        let output = SqliteOutput::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let mut first = result("http://example.com", &[("nginx", "1.20")]);
+        first.title = String::from("first");
+        output.write_at(&first, 1).unwrap();
+        let mut second = result("http://example.com", &[("nginx", "1.22"), ("php", "8.1")]);
+        second.title = String::from("second");
+        output.write_at(&second, 2).unwrap();
+        output
+            .write_at(&result("http://example.org", &[("nginx", "1.20")]), 2)
+            .unwrap();
+
+        let conn = output.conn.lock().unwrap();
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(*) FROM targets"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM metadata"), 2);
+        assert_eq!(count("SELECT COUNT(*) FROM matches"), 3);
+        let (version, first_seen, last_seen): (String, i64, i64) = conn
+            .query_row(
+                "SELECT version, m.first_seen, m.last_seen FROM matches m
+                 JOIN targets t ON t.id = m.target_id
+                 WHERE t.url = 'http://example.com' AND m.name = 'nginx'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((version.as_str(), first_seen, last_seen), ("1.22", 1, 2));
+        let title: String = conn
+            .query_row(
+                "SELECT title FROM metadata m JOIN targets t ON t.id = m.target_id
+                 WHERE t.url = 'http://example.com'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(title, "second");
+    }
+}
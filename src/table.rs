@@ -0,0 +1,313 @@
+use crossterm::style::Stylize;
+use observer_ward_what_web::WhatWebResult;
+use std::fmt;
+use std::time::Duration;
+use textwrap::core::display_width;
+
+// 组件优先级的分档，高的标红，中等的标黄
+const HIGH_PRIORITY: u32 = 4;
+const MEDIUM_PRIORITY: u32 = 2;
+// 缩到终端宽度时每列最少保留的宽度
+const MIN_COLUMN_WIDTH: usize = 6;
+
+type Style = fn(String) -> String;
+
+// 一个单元格，颜色在补齐宽度之后再加，不影响对齐
+struct TableCell {
+    parts: Vec<(String, Option<Style>)>,
+}
+
+impl TableCell {
+    fn plain(text: String) -> Self {
+        Self {
+            parts: vec![(text, None)],
+        }
+    }
+    fn width(&self) -> usize {
+        self.parts.iter().map(|(text, _)| display_width(text)).sum()
+    }
+    // 超出宽度的部分截掉，结尾换成`…`
+    fn render(&self, width: usize, color: bool) -> String {
+        let mut rendered = String::new();
+        let mut remaining = width;
+        let truncate = self.width() > width;
+        for (text, style) in &self.parts {
+            let mut part = String::new();
+            for c in text.chars() {
+                let c_width = display_width(c.encode_utf8(&mut [0; 4]));
+                if truncate && c_width + 1 > remaining {
+                    break;
+                }
+                if c_width > remaining {
+                    break;
+                }
+                part.push(c);
+                remaining -= c_width;
+            }
+            let cut = part.len() < text.len();
+            match style {
+                Some(style) if color && !part.is_empty() => rendered.push_str(&style(part)),
+                _ => rendered.push_str(&part),
+            }
+            if cut {
+                rendered.push('…');
+                remaining = remaining.saturating_sub(1);
+                break;
+            }
+        }
+        rendered.push_str(&" ".repeat(remaining));
+        rendered
+    }
+}
+
+fn red(text: String) -> String {
+    text.red().to_string()
+}
+
+fn yellow(text: String) -> String {
+    text.yellow().to_string()
+}
+
+fn green(text: String) -> String {
+    text.green().to_string()
+}
+
+fn components_cell(result: &WhatWebResult) -> TableCell {
+    let mut names: Vec<&String> = result.name.iter().collect();
+    names.sort();
+    let mut parts = vec![];
+    for (index, name) in names.into_iter().enumerate() {
+        if index > 0 {
+            parts.push((String::from(", "), None));
+        }
+        let text = match result.version.get(name) {
+            Some(versions) if !versions.is_empty() => {
+                let mut versions: Vec<&str> = versions.iter().map(String::as_str).collect();
+                versions.sort_unstable();
+                format!("{}/{}", name, versions.join(","))
+            }
+            _ => name.to_string(),
+        };
+        let style: Option<Style> = match result.matched.get(name) {
+            Some(priority) if *priority >= HIGH_PRIORITY => Some(red),
+            Some(priority) if *priority >= MEDIUM_PRIORITY => Some(yellow),
+            _ => None,
+        };
+        parts.push((text, style));
+    }
+    TableCell { parts }
+}
+
+fn status_cell(result: &WhatWebResult) -> TableCell {
+    let style: Style = match result.status_code {
+        200..=299 => green,
+        _ => red,
+    };
+    TableCell {
+        parts: vec![(result.status_code.to_string(), Some(style))],
+    }
+}
+
+/// 把结果渲染成表格，`width`是终端宽度，超出时截断最宽的列，为空时不截断。
+/// `color`为false时输出不带颜色的纯文本，适合重定向到文件
+pub fn render_table(
+    results: &[&WhatWebResult],
+    has_plugins: bool,
+    width: Option<usize>,
+    color: bool,
+) -> String {
+    let mut headers = vec!["url", "status", "title", "components", "length"];
+    if has_plugins {
+        headers.push("plugins");
+    }
+    let rows: Vec<Vec<TableCell>> = results
+        .iter()
+        .map(|result| {
+            let mut row = vec![
+                TableCell::plain(result.display_url()),
+                status_cell(result),
+                TableCell::plain(
+                    result
+                        .title
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                components_cell(result),
+                TableCell::plain(result.length.to_string()),
+            ];
+            if has_plugins {
+                let mut plugins: Vec<&String> = result.plugins.iter().collect();
+                plugins.sort();
+                let plugins: Vec<&str> = plugins.into_iter().map(String::as_str).collect();
+                row.push(TableCell {
+                    parts: vec![(plugins.join(", "), Some(red))],
+                });
+            }
+            row
+        })
+        .collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    // 每列两边各一个空格加一个竖线
+    if let Some(max_width) = width {
+        let border = 3 * widths.len() + 1;
+        while widths.iter().sum::<usize>() + border > max_width {
+            let (index, widest) = match widths.iter().enumerate().max_by_key(|(_, w)| **w) {
+                Some((index, widest)) => (index, *widest),
+                None => break,
+            };
+            if widest <= MIN_COLUMN_WIDTH {
+                break;
+            }
+            widths[index] = widest - 1;
+        }
+    }
+    let separator = |fill: char| {
+        let mut line = String::from("+");
+        for width in &widths {
+            line.push_str(&fill.to_string().repeat(width + 2));
+            line.push('+');
+        }
+        line.push('\n');
+        line
+    };
+    let render_row = |cells: &[TableCell]| {
+        let mut line = String::from("|");
+        for (cell, width) in cells.iter().zip(&widths) {
+            line.push(' ');
+            line.push_str(&cell.render(*width, color));
+            line.push_str(" |");
+        }
+        line.push('\n');
+        line
+    };
+    let mut table = separator('-');
+    let header_cells: Vec<TableCell> = headers
+        .iter()
+        .map(|h| TableCell::plain(h.to_string()))
+        .collect();
+    table.push_str(&render_row(&header_cells));
+    table.push_str(&separator('='));
+    for row in &rows {
+        table.push_str(&render_row(row));
+    }
+    table.push_str(&separator('-'));
+    table
+}
+
+// 扫描结束后的汇总
+pub struct ScanSummary {
+    pub targets: usize,
+    pub reachable: usize,
+    pub matched: usize,
+    pub errored: usize,
+    pub elapsed: Duration,
+}
+
+impl ScanSummary {
+    pub fn new(results: &[WhatWebResult], elapsed: Duration) -> Self {
+        Self {
+            targets: results.len(),
+            reachable: results.iter().filter(|r| r.error.is_none()).count(),
+            matched: results.iter().filter(|r| !r.name.is_empty()).count(),
+            errored: results.iter().filter(|r| r.error.is_some()).count(),
+            elapsed,
+        }
+    }
+}
+
+impl fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} targets scanned, {} reachable, {} matched, {} errored in {:.1}s",
+            self.targets,
+            self.reachable,
+            self.matched,
+            self.errored,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(url: &str, title: &str) -> WhatWebResult {
+        let mut result = WhatWebResult::new(url.to_string());
+        result.status_code = 200;
+        result.title = title.to_string();
+        result.length = 612;
+        result
+    }
+
+    #[test]
+    fn test_render_table() {
+        let mut nginx = result("http://10.0.0.1", "Welcome   to\nnginx");
+        nginx.name.insert(String::from("nginx"));
+        nginx.matched.insert(String::from("nginx"), 1);
+        nginx
+            .version
+            .entry(String::from("nginx"))
+            .or_default()
+            .insert(String::from("1.20.1"));
+        let table = render_table(&[&nginx], false, None, false);
+        assert_eq!(
+            table,
+            "+-----------------+--------+------------------+--------------+--------+\n\
+             | url             | status | title            | components   | length |\n\
+             +=================+========+==================+==============+========+\n\
+             | http://10.0.0.1 | 200    | Welcome to nginx | nginx/1.20.1 | 612    |\n\
+             +-----------------+--------+------------------+--------------+--------+\n"
+        );
+        // 每一行的显示宽度一样，中文按两个字符宽
+        let chinese = result("http://10.0.0.2", "管理后台");
+        let table = render_table(&[&nginx, &chinese], false, None, false);
+        let widths: Vec<usize> = table.lines().map(display_width).collect();
+        assert!(widths.iter().all(|width| *width == widths[0]));
+    }
+
+    #[test]
+    fn test_render_table_width_and_color() {
+        let mut target = result(
+            "http://10.0.0.1/a/very/long/path/to/the/login/page",
+            "Login",
+        );
+        target.name.insert(String::from("weblogic"));
+        target.matched.insert(String::from("weblogic"), 5);
+        target.plugins.insert(String::from("cve-2020-14882"));
+        let table = render_table(&[&target], true, Some(60), false);
+        for line in table.lines() {
+            assert_eq!(display_width(line), 60);
+        }
+        assert!(table.contains('…'));
+        assert!(table.contains("| plugins"));
+        // 颜色在补齐宽度之后加，去掉颜色后和不带颜色的一样
+        let colored = render_table(&[&target], true, Some(60), true);
+        assert!(colored.contains(&"weblogic".red().to_string()));
+        assert!(colored.contains(&"200".green().to_string()));
+        assert_ne!(colored, table);
+    }
+
+    #[test]
+    fn test_scan_summary() {
+        let mut errored = result("http://10.0.0.2", "");
+        errored.error = Some(String::from("connection refused"));
+        let mut matched = result("http://10.0.0.1", "");
+        matched.name.insert(String::from("nginx"));
+        let summary = ScanSummary::new(
+            &[matched, errored, result("http://10.0.0.3", "")],
+            Duration::from_millis(1250),
+        );
+        assert_eq!(
+            summary.to_string(),
+            "3 targets scanned, 2 reachable, 1 matched, 1 errored in 1.2s"
+        );
+    }
+}
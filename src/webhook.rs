@@ -0,0 +1,316 @@
+use crate::cli::ObserverWardConfig;
+use observer_ward_what_web::{ScanObserver, WhatWebResult};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::redirect::Policy;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+// 推送失败后重试的次数，每次多等一秒
+const WEBHOOK_RETRIES: u32 = 2;
+
+/// 扫完一个目标就推送到webhook服务器，攒够`batch_size`个或者等了`interval`就发一次，
+/// 一次一个时发送对象，多个时发送数组；失败的重试后记录日志，不影响扫描
+pub struct WebhookObserver {
+    sender: Mutex<Option<UnboundedSender<WhatWebResult>>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl WebhookObserver {
+    pub fn new(
+        url: &str,
+        extra_header: Option<(HeaderName, HeaderValue)>,
+        batch_size: usize,
+        interval: Duration,
+    ) -> Self {
+        let mut headers = HeaderMap::new();
+        let ua = "Mozilla/5.0 (X11; Linux x86_64; rv:94.0) Gecko/20100101 Firefox/94.0";
+        headers.insert(header::USER_AGENT, HeaderValue::from_static(ua));
+        if let Some((name, value)) = extra_header {
+            headers.insert(name, value);
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .danger_accept_invalid_certs(true)
+            .redirect(Policy::none())
+            .timeout(Duration::new(10, 0))
+            .build()
+            .unwrap_or_default();
+        let (sender, receiver) = unbounded_channel();
+        let pusher = WebhookPusher {
+            client,
+            url: url.to_string(),
+            batch_size: batch_size.max(1),
+            interval,
+        };
+        let handle = tokio::task::spawn(pusher.run(receiver));
+        Self {
+            sender: Mutex::new(Some(sender)),
+            handle: Mutex::new(Some(handle)),
+        }
+    }
+    // 扫描结束时调用，推送还没发送的结果，等全部发完
+    pub async fn finish(&self) {
+        self.sender
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        let handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take();
+        if let Some(handle) = handle {
+            handle.await.unwrap_or_default();
+        }
+    }
+}
+
+impl ScanObserver for WebhookObserver {
+    fn target_finished(&self, result: &WhatWebResult) {
+        if let Some(sender) = self
+            .sender
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .as_ref()
+        {
+            sender.send(result.clone()).unwrap_or_default();
+        }
+    }
+}
+
+struct WebhookPusher {
+    client: reqwest::Client,
+    url: String,
+    batch_size: usize,
+    interval: Duration,
+}
+
+impl WebhookPusher {
+    async fn run(self, mut receiver: UnboundedReceiver<WhatWebResult>) {
+        let mut batch: Vec<WhatWebResult> = Vec::new();
+        // 这一批第一个结果到达后最多等多久
+        let mut deadline = Instant::now();
+        loop {
+            let next = if batch.is_empty() {
+                receiver.recv().await
+            } else {
+                match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        self.push(std::mem::take(&mut batch)).await;
+                        continue;
+                    }
+                }
+            };
+            match next {
+                Some(result) => {
+                    if batch.is_empty() {
+                        deadline = Instant::now() + self.interval;
+                    }
+                    batch.push(result);
+                    if batch.len() >= self.batch_size {
+                        self.push(std::mem::take(&mut batch)).await;
+                    }
+                }
+                None => {
+                    if !batch.is_empty() {
+                        self.push(batch).await;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    async fn push(&self, batch: Vec<WhatWebResult>) {
+        let body = if self.batch_size == 1 && batch.len() == 1 {
+            serde_json::to_vec(&batch[0])
+        } else {
+            serde_json::to_vec(&batch)
+        };
+        let body = match body {
+            Ok(body) => body,
+            Err(err) => {
+                log::error!("Failed to serialize webhook results: {}", err);
+                return;
+            }
+        };
+        let mut attempt = 0;
+        loop {
+            let response = self
+                .client
+                .post(&self.url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match response {
+                Ok(_) => return,
+                Err(err) if attempt < WEBHOOK_RETRIES => {
+                    attempt += 1;
+                    log::warn!(
+                        "Webhook push failed, retry {}/{}: {}",
+                        attempt,
+                        WEBHOOK_RETRIES,
+                        err
+                    );
+                    tokio::time::sleep(Duration::from_secs(attempt as u64)).await;
+                }
+                Err(err) => {
+                    log::error!("Failed to push {} results to webhook: {}", batch.len(), err);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// `Name: value`格式的请求头
+fn parse_header(header: &str) -> Option<(HeaderName, HeaderValue)> {
+    let (name, value) = header.split_once(':')?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+    let value = HeaderValue::from_str(value.trim()).ok()?;
+    Some((name, value))
+}
+
+pub fn open_webhook(config: &ObserverWardConfig) -> Option<WebhookObserver> {
+    if config.webhook.is_empty() {
+        return None;
+    }
+    let extra_header = if config.webhook_header.is_empty() {
+        None
+    } else {
+        match parse_header(&config.webhook_header) {
+            Some(header) => Some(header),
+            None => {
+                println!(
+                    "Invalid webhook header {}, expected `Name: value`",
+                    config.webhook_header
+                );
+                std::process::exit(0);
+            }
+        }
+    };
+    Some(WebhookObserver::new(
+        &config.webhook,
+        extra_header,
+        config.webhook_batch,
+        Duration::from_secs(config.webhook_interval),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 记录收到的每个请求体，前`failures`个请求返回500
+    async fn webhook_server(failures: usize) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(vec![]));
+        let bodies = received.clone();
+        tokio::spawn(async move {
+            let mut count = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buf = [0_u8; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length: usize = head
+                            .lines()
+                            .find_map(|line| {
+                                line.to_lowercase()
+                                    .strip_prefix("content-length: ")
+                                    .map(String::from)
+                            })
+                            .and_then(|length| length.trim().parse().ok())
+                            .unwrap_or_default();
+                        if body.len() >= length {
+                            break body.to_string();
+                        }
+                    }
+                };
+                bodies
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_str(&body).unwrap());
+                count += 1;
+                let status = if count <= failures {
+                    "500 Internal Server Error"
+                } else {
+                    "200 OK"
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    fn urls(payload: &Value) -> Vec<&str> {
+        payload
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["url"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_webhook_payload() {
+        // 一次一个时发送对象
+        let (url, received) = webhook_server(0).await;
+        let webhook = WebhookObserver::new(&url, None, 1, Duration::from_secs(60));
+        webhook.target_finished(&WhatWebResult::new(String::from("http://10.0.0.1")));
+        webhook.target_finished(&WhatWebResult::new(String::from("http://10.0.0.2")));
+        webhook.finish().await;
+        let payloads = received.lock().unwrap().clone();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[0]["url"], "http://10.0.0.1");
+        assert_eq!(payloads[1]["url"], "http://10.0.0.2");
+        // 攒够一批发送数组，结束时发送剩下的
+        let (url, received) = webhook_server(0).await;
+        let webhook = WebhookObserver::new(&url, None, 2, Duration::from_secs(60));
+        for i in 1..=3 {
+            webhook.target_finished(&WhatWebResult::new(format!("http://10.0.0.{}", i)));
+        }
+        webhook.finish().await;
+        let payloads = received.lock().unwrap().clone();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(urls(&payloads[0]), ["http://10.0.0.1", "http://10.0.0.2"]);
+        assert_eq!(urls(&payloads[1]), ["http://10.0.0.3"]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_retry() {
+        let (url, received) = webhook_server(1).await;
+        let webhook = WebhookObserver::new(&url, None, 1, Duration::from_secs(60));
+        webhook.target_finished(&WhatWebResult::new(String::from("http://10.0.0.1")));
+        webhook.finish().await;
+        let payloads = received.lock().unwrap().clone();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads[0], payloads[1]);
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let (name, value) = parse_header("X-Token:  secret ").unwrap();
+        assert_eq!(name, "x-token");
+        assert_eq!(value, "secret");
+        assert!(parse_header("no separator").is_none());
+        assert!(parse_header("bad name: value").is_none());
+    }
+}
@@ -0,0 +1,170 @@
+// cargo bench -p what_web
+// 可以带一个参数只跑名字包含它的用例：cargo bench -p what_web -- favicon
+use std::hint::black_box;
+use std::str::FromStr;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use url::Url;
+use what_web::extract::{favicon_hash, get_next_jump, get_title};
+use what_web::fingerprint::{WebFingerPrint, WebFingerPrintLib};
+use what_web::{RawData, RequestOption, WhatWeb};
+
+const INDEX_HTML: &str = include_str!("../tests/fixtures/index.html");
+const JUMP_HTML: &str = include_str!("../tests/fixtures/jump.html");
+const HEADERS: &str = include_str!("../tests/fixtures/headers.txt");
+const FAVICON: &[u8] = include_bytes!("../tests/fixtures/favicon.ico");
+
+const BODY_SIZE: usize = 500 * 1024;
+const INDEX_FINGERPRINTS: usize = 3000;
+const VERSION_FINGERPRINTS: usize = 1000;
+
+fn fixture_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for line in HEADERS.lines().skip(1) {
+        if let Some((name, value)) = line.split_once(": ") {
+            headers.append(
+                HeaderName::from_str(name).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+    }
+    headers
+}
+
+// 样例首页重复到500KB
+fn large_body() -> String {
+    let mut body = String::with_capacity(BODY_SIZE + INDEX_HTML.len());
+    while body.len() < BODY_SIZE {
+        body.push_str(INDEX_HTML);
+    }
+    body
+}
+
+// 关键词、响应头和正则三种规则轮流，每500个有一个能匹配上样例
+fn index_fingerprints() -> Vec<WebFingerPrint> {
+    (0..INDEX_FINGERPRINTS)
+        .map(|i| {
+            let hit = i % 500 == 0;
+            let keyword = if hit {
+                String::from("Powered by ExampleCMS")
+            } else {
+                format!("bench-component-{}", i)
+            };
+            let mut fingerprint = serde_json::json!({
+                "name": format!("bench-{}", i),
+                "path": "/",
+                "request_method": "get",
+                "request_headers": {},
+                "request_data": "",
+                "status_code": 0,
+                "headers": {},
+                "keyword": [keyword],
+            });
+            match i % 3 {
+                1 => {
+                    let server = if hit { "nginx" } else { "bench-server" };
+                    fingerprint["headers"] = serde_json::json!({ "server": server });
+                }
+                2 => {
+                    fingerprint["keyword"] = serde_json::json!([]);
+                    fingerprint["regex"] =
+                        serde_json::json!([format!(r"bench-version-{}/(\d+\.\d+)", i)]);
+                }
+                _ => {}
+            }
+            serde_json::from_value(fingerprint).unwrap()
+        })
+        .collect()
+}
+
+// 全部命中并且都要从响应头提取版本号
+fn version_fingerprints() -> Vec<WebFingerPrint> {
+    (0..VERSION_FINGERPRINTS)
+        .map(|i| {
+            serde_json::from_value(serde_json::json!({
+                "name": format!("bench-version-{}", i),
+                "path": "/",
+                "request_method": "get",
+                "request_headers": {},
+                "request_data": "",
+                "status_code": 0,
+                "headers": {"x-generator": "ExampleCMS"},
+                "keyword": [],
+                "version_regex": [r"x-version: (\d+\.\d+\.\d+)"],
+            }))
+            .unwrap()
+        })
+        .collect()
+}
+
+fn raw_data(body: &str) -> RawData {
+    RawData::from_parts(
+        Url::parse("http://127.0.0.1/").unwrap(),
+        StatusCode::OK,
+        fixture_headers(),
+        Bytes::from(body.to_string()),
+    )
+}
+
+// 离线识别一个响应，和扫描时一样匹配首页指纹、提取标题和版本
+fn bench_match(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let config = RequestOption::builder().build().unwrap();
+    let body = large_body();
+    let index = WhatWeb::from_fingerprint_lib(
+        config.clone(),
+        WebFingerPrintLib::new(index_fingerprints()).unwrap(),
+    );
+    c.bench_function("what_web 500KB body x 3000 index", |b| {
+        b.iter(|| black_box(runtime.block_on(index.scan_raw_data([raw_data(&body)]))))
+    });
+    let version = WhatWeb::from_fingerprint_lib(
+        config,
+        WebFingerPrintLib::new(version_fingerprints()).unwrap(),
+    );
+    c.bench_function("what_web 40 headers x 1000 version", |b| {
+        b.iter(|| black_box(runtime.block_on(version.scan_raw_data([raw_data(INDEX_HTML)]))))
+    });
+}
+
+// 解码并解析整个页面
+fn bench_raw_data(c: &mut Criterion) {
+    let url = Url::parse("http://127.0.0.1/").unwrap();
+    let headers = fixture_headers();
+    let body = Bytes::from(large_body());
+    c.bench_function("RawData::from_parts 500KB body", |b| {
+        b.iter(|| {
+            black_box(RawData::from_parts(
+                url.clone(),
+                StatusCode::OK,
+                headers.clone(),
+                body.clone(),
+            ))
+        })
+    });
+}
+
+fn bench_extract(c: &mut Criterion) {
+    let url = Url::parse("http://127.0.0.1/").unwrap();
+    let empty_headers = HeaderMap::new();
+    c.bench_function("get_title index.html", |b| {
+        b.iter(|| get_title(black_box(INDEX_HTML)))
+    });
+    c.bench_function("get_next_jump index.html", |b| {
+        b.iter(|| get_next_jump(&empty_headers, &url, black_box(INDEX_HTML)))
+    });
+    c.bench_function("get_next_jump jump.html", |b| {
+        b.iter(|| get_next_jump(&empty_headers, &url, black_box(JUMP_HTML)))
+    });
+    c.bench_function("favicon_hash 50KB icon", |b| {
+        b.iter(|| favicon_hash(black_box(FAVICON)))
+    });
+}
+
+criterion_group!(benches, bench_match, bench_raw_data, bench_extract);
+criterion_main!(benches);
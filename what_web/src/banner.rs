@@ -0,0 +1,110 @@
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::fingerprint::{parse_web_fingerprint, WebFingerPrint, WebFingerPrintLib};
+use crate::observer::ScanObserver;
+use crate::ward::{find_keywords, what_web, FingerprintMatch, RawData};
+
+// 内置的banner指纹，和普通指纹格式一样，`tcp`为true。只有连接后服务端先发送数据的协议能识别
+const BUILTIN_TCP_FINGERPRINT: &str = r#"[
+{"name":"openssh","tcp":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["ssh-2.0-openssh"],"version_regex":["SSH-2\\.0-OpenSSH_([\\w.]+)"]},
+{"name":"dropbear","tcp":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["ssh-2.0-dropbear"],"version_regex":["SSH-2\\.0-dropbear_([\\w.]+)"]},
+{"name":"ssh","tcp":true,"priority":2,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"not_keyword":["ssh-2.0-openssh","ssh-2.0-dropbear"],"regex":["^SSH-\\d\\.\\d+-"]},
+{"name":"mysql","tcp":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["mysql_native_password"],"not_keyword":["mariadb"],"version_regex":["\\n(\\d+\\.\\d+\\.\\d+)[\\w.-]*\\x00"]},
+{"name":"mariadb","tcp":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["mariadb"],"version_regex":["(\\d+\\.\\d+\\.\\d+)-MariaDB"]},
+{"name":"vsftpd","tcp":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["vsftpd"],"version_regex":["vsFTPd ([\\d.]+)"]},
+{"name":"ftp","tcp":true,"priority":2,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["ftp"],"not_keyword":["vsftpd"],"regex":["^220[ -]"]}
+]"#;
+
+static BUILTIN_TCP: Lazy<Vec<WebFingerPrint>> = Lazy::new(|| {
+    parse_web_fingerprint(BUILTIN_TCP_FINGERPRINT.as_bytes()).expect("BUILTIN_TCP_FINGERPRINT")
+});
+
+pub(crate) fn builtin_tcp_fingerprint() -> &'static [WebFingerPrint] {
+    &BUILTIN_TCP
+}
+
+// 写进结果的banner，控制字符换成`.`，二进制的握手包也能看
+pub(crate) fn printable_banner(banner: &[u8]) -> String {
+    String::from_utf8_lossy(banner)
+        .chars()
+        .map(|c| {
+            if c.is_control() && !matches!(c, '\r' | '\n' | '\t') {
+                '.'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// 用tcp指纹匹配banner，证据里的地址是`tcp://host:port`
+pub(crate) fn detect_banner(
+    url: &Url,
+    banner: &[u8],
+    fingerprint_lib: &WebFingerPrintLib,
+    observer: &dyn ScanObserver,
+) -> Vec<FingerprintMatch> {
+    let tcp_url = match Url::parse(&format!(
+        "tcp://{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )) {
+        Ok(tcp_url) => tcp_url,
+        Err(_) => return vec![],
+    };
+    let raw_data = RawData::from_parts(
+        tcp_url,
+        StatusCode::OK,
+        HeaderMap::new(),
+        Bytes::copy_from_slice(banner),
+    );
+    let keywords = find_keywords(&raw_data, fingerprint_lib);
+    fingerprint_lib
+        .tcp
+        .iter()
+        .filter_map(|fingerprint| what_web(&raw_data, fingerprint, &keywords, observer))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::banner::{detect_banner, printable_banner};
+    use crate::fingerprint::WebFingerPrintLib;
+    use crate::observer::NoopObserver;
+    use url::Url;
+
+    #[test]
+    fn test_printable_banner() {
+        assert_eq!(
+            printable_banner(b"\x4a\x00\x00\x00\x0a8.0.28\x00ok\r\n"),
+            "J...\n8.0.28.ok"
+        );
+    }
+
+    #[test]
+    fn test_detect_banner() {
+        let lib = WebFingerPrintLib::new(vec![]).unwrap();
+        let url = Url::parse("https://127.0.0.1:3306/").unwrap();
+        let mysql =
+            b"\x4a\x00\x00\x00\x0a8.0.28\x00\x08\x00\x00\x00abc\x00mysql_native_password\x00";
+        let matches = detect_banner(&url, mysql, &lib, &NoopObserver);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].name, "mysql");
+        assert_eq!(matches[0].version.as_deref(), Some("8.0.28"));
+        assert_eq!(matches[0].evidence.url, "tcp://127.0.0.1:3306");
+        let matches = detect_banner(&url, b"220 (vsFTPd 3.0.3)\r\n", &lib, &NoopObserver);
+        let names: Vec<&str> = matches.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["vsftpd"]);
+        assert_eq!(matches[0].version.as_deref(), Some("3.0.3"));
+        let matches = detect_banner(&url, b"220 ProFTPD Server ready.\r\n", &lib, &NoopObserver);
+        let names: Vec<&str> = matches.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["ftp"]);
+        assert!(detect_banner(&url, b"+PONG\r\n", &lib, &NoopObserver).is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::request::{CertInfo, FaviconHash, FetchResult};
+
+const DEFAULT_CAPACITY: usize = 100;
+
+struct CacheEntry<V> {
+    value: V,
+    inserted: Instant,
+    // 最近一次使用的序号，满了淘汰最小的
+    used: u64,
+}
+
+pub(crate) struct LruCache<V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: Mutex<(HashMap<String, CacheEntry<V>>, u64)>,
+}
+
+impl<V: Clone> LruCache<V> {
+    fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new((HashMap::new(), 0)),
+        }
+    }
+    // 过期的在读取时丢弃
+    pub(crate) fn get(&self, key: &str) -> Option<V> {
+        let mut guard = self.entries.lock().ok()?;
+        let (entries, tick) = &mut *guard;
+        let expired = entries
+            .get(key)
+            .map(|entry| self.ttl.is_some_and(|ttl| entry.inserted.elapsed() > ttl))?;
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+        *tick += 1;
+        let entry = entries.get_mut(key)?;
+        entry.used = *tick;
+        Some(entry.value.clone())
+    }
+    pub(crate) fn insert(&self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut guard = match self.entries.lock() {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        let (entries, tick) = &mut *guard;
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(k, _)| k.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        *tick += 1;
+        entries.insert(
+            key,
+            CacheEntry {
+                value,
+                inserted: Instant::now(),
+                used: *tick,
+            },
+        );
+    }
+    fn clear(&self) {
+        if let Ok(mut guard) = self.entries.lock() {
+            guard.0.clear();
+        }
+    }
+    fn len(&self) -> usize {
+        self.entries.lock().map(|guard| guard.0.len()).unwrap_or(0)
+    }
+}
+
+/// 同一个`RequestOption`和它的克隆共用的缓存，保存首页和特殊请求的响应、favicon哈希和证书。
+/// 容量是每一种各自最多保存的条目数，为0时不缓存；没有过期时间的一直有效，直到被淘汰或者`clear`
+pub struct ScanCache {
+    pub(crate) fetch: LruCache<FetchResult>,
+    pub(crate) favicon: LruCache<FaviconHash>,
+    pub(crate) cert: LruCache<CertInfo>,
+}
+
+impl ScanCache {
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            fetch: LruCache::new(capacity, ttl),
+            favicon: LruCache::new(capacity, ttl),
+            cert: LruCache::new(capacity, ttl),
+        }
+    }
+    // 每次扫描都重新请求
+    pub fn disabled() -> Self {
+        Self::new(0, None)
+    }
+    pub fn capacity(&self) -> usize {
+        self.fetch.capacity
+    }
+    pub fn ttl(&self) -> Option<Duration> {
+        self.fetch.ttl
+    }
+    pub fn len(&self) -> usize {
+        self.fetch.len() + self.favicon.len() + self.cert.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn clear(&self) {
+        self.fetch.clear();
+        self.favicon.clear();
+        self.cert.clear();
+    }
+}
+
+impl Default for ScanCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, None)
+    }
+}
+
+impl fmt::Debug for ScanCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanCache")
+            .field("capacity", &self.capacity())
+            .field("ttl", &self.ttl())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{LruCache, ScanCache};
+    use crate::FaviconHash;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lru_cache() {
+        let cache = LruCache::new(2, None);
+        cache.insert(String::from("a"), 1);
+        cache.insert(String::from("b"), 2);
+        // 用过的a留下，淘汰b
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert(String::from("c"), 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+        // 覆盖已有的不淘汰
+        cache.insert(String::from("c"), 4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("c"), Some(4));
+        cache.clear();
+        assert_eq!(cache.get("a"), None);
+
+        let cache = LruCache::new(10, Some(Duration::from_millis(20)));
+        cache.insert(String::from("a"), 1);
+        assert_eq!(cache.get("a"), Some(1));
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.len(), 0);
+
+        let cache = ScanCache::disabled();
+        let favicon_hash = FaviconHash {
+            md5: String::new(),
+            mmh3: 0,
+            dhash: None,
+        };
+        cache.favicon.insert(String::from("a"), favicon_hash);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hickory_resolver::config::{
+    ConnectionConfig, LookupIpStrategy, NameServerConfig, ResolveHosts, ResolverConfig,
+    ResolverOpts,
+};
+use hickory_resolver::net::runtime::TokioRuntimeProvider;
+use hickory_resolver::net::NetError;
+use hickory_resolver::{system_conf, TokioResolver};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::WardError;
+
+// 解析结果按记录的TTL缓存，限制在这个范围内，长时间运行的服务也能拿到新的地址
+const MIN_TTL: Duration = Duration::from_secs(30);
+const MAX_TTL: Duration = Duration::from_secs(3600);
+// 解析失败可能只是服务器临时出错，很快重新查询
+const NEGATIVE_TTL: Duration = Duration::from_secs(10);
+
+// 一个域名的解析结果和过期时间，第一个查询的任务写入，同时等待的任务共用
+type Lookup = Arc<OnceCell<(Result<Vec<IpAddr>, String>, Instant)>>;
+
+/// 解析目标域名用的DNS服务器，`10.0.0.53`、`10.0.0.53:5353`、`udp://10.0.0.53`，
+/// 或者DNS over HTTPS的地址`https://1.1.1.1/dns-query`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsServer {
+    Udp(SocketAddr),
+    Https(Url),
+}
+
+impl FromStr for DnsServer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with("https://") {
+            let url = Url::parse(s).map_err(|err| format!("Invalid DNS server {}: {}", s, err))?;
+            return Ok(DnsServer::Https(url));
+        }
+        let addr = s.strip_prefix("udp://").unwrap_or(s);
+        if let Ok(ip) = addr.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(DnsServer::Udp(SocketAddr::new(ip, 53)));
+        }
+        addr.parse().map(DnsServer::Udp).map_err(|_| {
+            format!(
+                "Invalid DNS server {}, expected IP[:PORT] or an https:// DoH url",
+                s
+            )
+        })
+    }
+}
+
+impl fmt::Display for DnsServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsServer::Udp(addr) => write!(f, "udp://{}", addr),
+            DnsServer::Https(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+// 克隆出来的`RequestOption`共用一个解析器，同一个域名在一次扫描里只查询一次，
+// 展开成多个端口的目标同时解析时也只发一次查询。
+// 作为reqwest的解析器装在共用的客户端上，自己连接的证书和banner请求也用它
+#[derive(Clone)]
+pub(crate) struct DnsResolver {
+    server: DnsServer,
+    timeout: Duration,
+    // DoH服务器的域名要先解析，第一次查询时才创建
+    resolver: Arc<OnceCell<Result<TokioResolver, String>>>,
+    lookups: Arc<Mutex<HashMap<String, Lookup>>>,
+    negative_ttl: Duration,
+}
+
+impl fmt::Debug for DnsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DnsResolver")
+            .field("server", &self.server)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl DnsResolver {
+    pub(crate) fn new(server: DnsServer, timeout: Duration) -> Self {
+        Self {
+            server,
+            timeout,
+            resolver: Arc::default(),
+            lookups: Arc::default(),
+            negative_ttl: NEGATIVE_TTL,
+        }
+    }
+    pub(crate) fn server(&self) -> &DnsServer {
+        &self.server
+    }
+    // 域名的所有A和AAAA记录
+    pub(crate) async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, WardError> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        let cell = match self.lookups.lock() {
+            Ok(mut lookups) => {
+                let now = Instant::now();
+                // 过期的结果换成新的查询，顺便清理其他过期的域名
+                let expired = |cell: &Lookup| cell.get().is_some_and(|(_, expiry)| *expiry <= now);
+                if lookups.get(&host).is_some_and(expired) {
+                    lookups.retain(|_, cell| !expired(cell));
+                }
+                lookups.entry(host.clone()).or_default().clone()
+            }
+            Err(_) => Arc::new(OnceCell::new()),
+        };
+        let (result, _) = cell
+            .get_or_init(|| async {
+                match self.query(&host).await {
+                    Ok((ips, ttl)) => (Ok(ips), Instant::now() + ttl.clamp(MIN_TTL, MAX_TTL)),
+                    Err(err) => (Err(err), Instant::now() + self.negative_ttl),
+                }
+            })
+            .await;
+        result
+            .clone()
+            .map_err(|reason| WardError::Dns { host, reason })
+    }
+    async fn query(&self, host: &str) -> Result<(Vec<IpAddr>, Duration), String> {
+        let resolver = self
+            .resolver
+            .get_or_init(|| build_resolver(&self.server, self.timeout))
+            .await
+            .as_ref()
+            .map_err(Clone::clone)?;
+        let lookup = match tokio::time::timeout(self.timeout, resolver.lookup_ip(host)).await {
+            Ok(lookup) => lookup.map_err(lookup_error)?,
+            Err(_) => {
+                return Err(format!(
+                    "{} timed out after {}s",
+                    self.server,
+                    self.timeout.as_secs()
+                ))
+            }
+        };
+        let ips: Vec<IpAddr> = lookup.iter().collect();
+        if ips.is_empty() {
+            return Err(String::from("no A or AAAA records"));
+        }
+        let ttl = lookup
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+        Ok((ips, ttl))
+    }
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let ips = resolver.lookup(name.as_str()).await?;
+            // reqwest忽略这里的端口，用URL里的
+            let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+// UDP应答被截断或者出错时改用TCP，搜索域和ndots沿用系统的配置，只把名称服务器换成指定的
+async fn build_resolver(server: &DnsServer, timeout: Duration) -> Result<TokioResolver, String> {
+    let name_server = match server {
+        DnsServer::Udp(addr) => {
+            let mut name_server = NameServerConfig::udp_and_tcp(addr.ip());
+            for connection in &mut name_server.connections {
+                connection.port = addr.port();
+            }
+            name_server
+        }
+        DnsServer::Https(url) => {
+            let host = url
+                .host_str()
+                .unwrap_or_default()
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            let port = url.port_or_known_default().unwrap_or(443);
+            // DoH服务器本身的域名用系统的DNS解析
+            let ip = match host.parse() {
+                Ok(ip) => ip,
+                Err(_) => tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|err| format!("{}: {}", url, err))?
+                    .next()
+                    .ok_or_else(|| format!("{}: no address found", url))?
+                    .ip(),
+            };
+            let mut connection =
+                ConnectionConfig::https(Arc::from(host), Some(Arc::from(url.path())));
+            connection.port = port;
+            NameServerConfig::new(ip, true, vec![connection])
+        }
+    };
+    let (config, system_options) = match system_conf::read_system_conf() {
+        Ok((config, options)) => {
+            let (domain, search, _) = config.into_parts();
+            (
+                ResolverConfig::from_parts(domain, search, vec![name_server]),
+                options,
+            )
+        }
+        Err(_) => (
+            ResolverConfig::from_name_servers(vec![name_server]),
+            ResolverOpts::default(),
+        ),
+    };
+    let mut builder = TokioResolver::builder_with_config(config, TokioRuntimeProvider::default());
+    let options = builder.options_mut();
+    options.ndots = system_options.ndots;
+    options.timeout = timeout;
+    options.try_tcp_on_error = true;
+    // 同时查A和AAAA，IPv4的地址排在前面
+    options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    // 指定了DNS服务器就只听它的，不读hosts文件
+    options.use_hosts_file = ResolveHosts::Never;
+    builder.build().map_err(|err| err.to_string())
+}
+
+fn lookup_error(err: NetError) -> String {
+    if err.is_nx_domain() {
+        String::from("no such host")
+    } else if err.is_no_records_found() {
+        String::from("no A or AAAA records")
+    } else {
+        err.to_string()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::dns::{DnsResolver, DnsServer};
+    use crate::WardError;
+    use hickory_resolver::proto::op::{Message, OpCode, ResponseCode};
+    use hickory_resolver::proto::rr::rdata::A;
+    use hickory_resolver::proto::rr::{RData, Record, RecordType};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, UdpSocket};
+
+    // missing开头的域名不存在，many开头的域名UDP只回截断的应答，TCP返回两个A记录，
+    // 其他域名只有一个A记录
+    pub(crate) fn answer(query: &[u8], ip: [u8; 4], tcp: bool) -> Vec<u8> {
+        let query = Message::from_vec(query).unwrap();
+        let question = query.queries[0].clone();
+        let name = question.name().clone();
+        let mut response = Message::response(query.metadata.id, OpCode::Query);
+        response.metadata.recursion_desired = true;
+        response.metadata.recursion_available = true;
+        response.add_query(question.clone());
+        let mut ips = vec![ip];
+        if name.to_string().starts_with("missing") {
+            response.metadata.response_code = ResponseCode::NXDomain;
+            ips.clear();
+        } else if name.to_string().starts_with("many") {
+            response.metadata.truncation = !tcp;
+            ips = if tcp {
+                vec![ip, [ip[0], ip[1], ip[2], ip[3] + 1]]
+            } else {
+                vec![]
+            };
+        }
+        if question.query_type() == RecordType::A {
+            for ip in ips {
+                let rdata = RData::A(A::from(Ipv4Addr::from(ip)));
+                response.add_answer(Record::from_rdata(name.clone(), 60, rdata));
+            }
+        }
+        response.to_vec().unwrap()
+    }
+
+    // 在同一个端口上用UDP和TCP应答的DNS服务器，返回地址和收到的查询数
+    pub(crate) async fn dns_server(ip: [u8; 4]) -> (SocketAddr, Arc<AtomicUsize>) {
+        let (socket, listener) = loop {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            if let Ok(listener) = TcpListener::bind(socket.local_addr().unwrap()).await {
+                break (socket, listener);
+            }
+        };
+        let addr = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let count = queries.clone();
+        tokio::spawn(async move {
+            let mut buf = [0_u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf).await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.send_to(&answer(&buf[..n], ip, false), peer).await;
+            }
+        });
+        let count = queries.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let count = count.clone();
+                tokio::spawn(async move {
+                    // TCP的消息前面有两个字节的长度
+                    while let Ok(len) = stream.read_u16().await {
+                        let mut query = vec![0_u8; len as usize];
+                        if stream.read_exact(&mut query).await.is_err() {
+                            break;
+                        }
+                        count.fetch_add(1, Ordering::SeqCst);
+                        let response = answer(&query, ip, true);
+                        let _ = stream.write_u16(response.len() as u16).await;
+                        let _ = stream.write_all(&response).await;
+                    }
+                });
+            }
+        });
+        (addr, queries)
+    }
+
+    #[test]
+    fn test_dns_server() {
+        assert_eq!(
+            DnsServer::from_str("10.0.0.53"),
+            Ok(DnsServer::Udp("10.0.0.53:53".parse().unwrap()))
+        );
+        assert_eq!(
+            DnsServer::from_str("udp://10.0.0.53:5353"),
+            Ok(DnsServer::Udp("10.0.0.53:5353".parse().unwrap()))
+        );
+        assert_eq!(
+            DnsServer::from_str("[::1]"),
+            Ok(DnsServer::Udp("[::1]:53".parse().unwrap()))
+        );
+        assert!(matches!(
+            DnsServer::from_str("https://1.1.1.1/dns-query"),
+            Ok(DnsServer::Https(_))
+        ));
+        assert!(DnsServer::from_str("dns.example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dns_resolver() {
+        let (addr, queries) = dns_server([10, 0, 0, 8]).await;
+        let resolver = Arc::new(DnsResolver::new(
+            DnsServer::Udp(addr),
+            Duration::from_secs(5),
+        ));
+        let lookups = (0..4).map(|_| {
+            let resolver = resolver.clone();
+            tokio::spawn(async move { resolver.lookup("intranet.example.test").await })
+        });
+        for lookup in lookups {
+            assert_eq!(
+                lookup.await.unwrap().unwrap(),
+                vec![IpAddr::from([10, 0, 0, 8])]
+            );
+        }
+        // 同时解析同一个域名也只查询一次A和AAAA
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+        // UDP应答被截断时改用TCP，返回所有的地址
+        assert_eq!(
+            resolver.lookup("many.example.test").await.unwrap(),
+            vec![IpAddr::from([10, 0, 0, 8]), IpAddr::from([10, 0, 0, 9])]
+        );
+        let err = resolver.lookup("missing.example.test").await.unwrap_err();
+        assert!(matches!(err, WardError::Dns { .. }));
+        assert!(err.to_string().contains("no such host"));
+    }
+
+    #[tokio::test]
+    async fn test_negative_ttl() {
+        // 关掉开关之前服务器一直出错
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let failing = Arc::new(AtomicBool::new(true));
+        let (count, fail) = (queries.clone(), failing.clone());
+        tokio::spawn(async move {
+            let mut buf = [0_u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buf).await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let mut response = answer(&buf[..n], [10, 0, 0, 9], false);
+                if fail.load(Ordering::SeqCst) {
+                    let mut message = Message::from_vec(&response).unwrap();
+                    message.answers.clear();
+                    message.metadata.response_code = ResponseCode::ServFail;
+                    response = message.to_vec().unwrap();
+                }
+                let _ = socket.send_to(&response, peer).await;
+            }
+        });
+        let mut resolver = DnsResolver::new(DnsServer::Udp(addr), Duration::from_secs(5));
+        resolver.negative_ttl = Duration::from_millis(50);
+        let err = resolver.lookup("flaky.example.test").await.unwrap_err();
+        assert!(matches!(err, WardError::Dns { .. }));
+        // 失败的结果过期前不重新查询
+        let sent = queries.load(Ordering::SeqCst);
+        assert!(resolver.lookup("flaky.example.test").await.is_err());
+        assert_eq!(queries.load(Ordering::SeqCst), sent);
+        failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(
+            resolver.lookup("flaky.example.test").await.unwrap(),
+            vec![IpAddr::from([10, 0, 0, 9])]
+        );
+        assert!(queries.load(Ordering::SeqCst) > sent);
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::limit::BudgetExhausted;
+use crate::option::RequestOptionError;
+use crate::request::Unreachable;
+
+/// 库里所有公开函数返回的错误，调用方可以按失败原因分别处理
+#[derive(Debug, Error)]
+pub enum WardError {
+    // 发送请求或者读取响应失败，连接、TLS和超时的细节在里层的错误里
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    // 自定义DNS服务器解析失败，和连接失败分开
+    #[error("Failed to resolve `{host}`: {reason}")]
+    Dns { host: String, reason: String },
+    #[error("`{url}` timed out after {secs}s")]
+    Timeout { url: String, secs: u64 },
+    // 单个目标的所有请求超过了`target_deadline`
+    #[error("`{url}` did not finish within {deadline:?}")]
+    TargetDeadline { url: String, deadline: Duration },
+    #[error("Invalid url `{url}`: {reason}")]
+    InvalidUrl { url: String, reason: String },
+    #[error("Invalid proxy `{proxy}`: {reason}")]
+    InvalidProxy { proxy: String, reason: String },
+    // 连续的目标连接失败后代理本身也连不上，剩下的目标不再发送请求
+    #[error("Proxy `{0}` is unreachable, skipped")]
+    ProxyDown(String),
+    // 代理拒绝了CONNECT请求，例如需要认证或者不允许连接这个端口
+    #[error("Proxy CONNECT to `{target}` failed: {status}")]
+    ProxyConnect { target: String, status: String },
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("Invalid request method `{0}`")]
+    InvalidMethod(String),
+    // 命令行和配置里的取值不合法，比如跳转策略和HTTP版本
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("Failed to decode {0}")]
+    Decode(String),
+    // 目标文件里JSON或者CSV格式的一行不合法
+    #[error("Invalid target `{target}`: {reason}")]
+    InvalidTarget { target: String, reason: String },
+    // 状态码不是200或者响应不是图片
+    #[error("`{url}` is not an icon (status {status}, content type `{content_type}`)")]
+    NotAnIcon {
+        url: String,
+        status: u16,
+        content_type: String,
+    },
+    #[error("`{url}` is larger than {limit} bytes")]
+    BodyTooLarge { url: String, limit: usize },
+    #[error("{0}")]
+    FingerprintLoad(String),
+    // 单独握手获取证书时的错误
+    #[error("{0}")]
+    Certificate(String),
+    #[error(transparent)]
+    Tls(#[from] native_tls::Error),
+    #[error(transparent)]
+    Openssl(#[from] openssl::error::ErrorStack),
+    #[error(transparent)]
+    Socks(#[from] tokio_socks::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Unreachable(#[from] Unreachable),
+    #[error(transparent)]
+    BudgetExhausted(#[from] BudgetExhausted),
+    #[error(transparent)]
+    InvalidOption(#[from] RequestOptionError),
+}
+
+impl From<reqwest::header::InvalidHeaderName> for WardError {
+    fn from(err: reqwest::header::InvalidHeaderName) -> Self {
+        WardError::InvalidHeader(err.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for WardError {
+    fn from(err: reqwest::header::InvalidHeaderValue) -> Self {
+        WardError::InvalidHeader(err.to_string())
+    }
+}
+
+impl WardError {
+    // 最里层的原因，外层的错误信息都会带上请求的地址
+    pub fn root_cause(&self) -> String {
+        let mut cause: &dyn std::error::Error = self;
+        while let Some(source) = cause.source() {
+            cause = source;
+        }
+        cause.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::WardError;
+    use crate::{HttpVersion, RedirectPolicy, RequestOption};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_ward_error_variants() {
+        let err = RequestOption::builder()
+            .proxy("ftp://127.0.0.1")
+            .build()
+            .map_err(WardError::from)
+            .unwrap_err();
+        assert!(matches!(err, WardError::InvalidOption(_)));
+        assert!(matches!(
+            HttpVersion::from_str("h3"),
+            Err(WardError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            RedirectPolicy::from_str("sometimes"),
+            Err(WardError::InvalidValue(_))
+        ));
+        let mut config = RequestOption::default();
+        assert!(matches!(
+            config.add_target_header("bad header", "value"),
+            Err(WardError::InvalidHeader(_))
+        ));
+        // 没有里层原因时就是自己的信息
+        let err = WardError::Io(std::io::Error::other("refused"));
+        assert_eq!(err.root_cause(), "refused");
+    }
+}
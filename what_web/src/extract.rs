@@ -0,0 +1,213 @@
+//! 从已经拿到的响应里提取标题、跳转、favicon和正文编码，不发送任何请求。
+//!
+//! 这个模块是给其他工具嵌入用的稳定接口，函数签名和行为在小版本之间保持兼容；
+//! 发送请求和匹配指纹的内部实现不在这里，随时可能调整。
+
+use std::collections::HashSet;
+
+use encoding_rs::Encoding;
+use reqwest::header::HeaderMap;
+use url::Url;
+
+use crate::request::{self, ParsedHtml};
+use crate::{FaviconHash, JumpSource};
+
+/// 提取页面标题，依次尝试`<title>`、`og:title`等`<meta>`和小页面里的`document.title`，
+/// 合并空白并截断到200个字符，没有标题时返回空字符串
+///
+/// ```
+/// use what_web::extract::get_title;
+///
+/// assert_eq!(get_title("<title>\n  Sign   in </title>"), "Sign in");
+/// assert_eq!(get_title("no title here"), "");
+/// ```
+pub fn get_title(text: &str) -> String {
+    request::get_title(text)
+}
+
+/// 计算favicon的md5和mmh3，mmh3和Shodan的`http.favicon.hash`一致
+///
+/// ```
+/// use what_web::extract::favicon_hash;
+///
+/// let hash = favicon_hash(b"icon");
+/// assert_eq!(hash.md5.len(), 32);
+/// assert_eq!(hash, favicon_hash(b"icon"));
+/// ```
+pub fn favicon_hash(content: &[u8]) -> FaviconHash {
+    request::favicon_hash(content)
+}
+
+/// 从响应头的`Location`、`<meta http-equiv="refresh">`和JS里找下一跳，
+/// 相对地址按`url`补全，跳回自己的不算
+///
+/// ```
+/// use reqwest::header::HeaderMap;
+/// use url::Url;
+/// use what_web::extract::get_next_jump;
+/// use what_web::JumpSource;
+///
+/// let url = Url::parse("http://example.com/").unwrap();
+/// let html = r#"<meta http-equiv="refresh" content="0;url=/login">"#;
+/// let (next, source) = get_next_jump(&HeaderMap::new(), &url, html).unwrap();
+/// assert_eq!(next.as_str(), "http://example.com/login");
+/// assert_eq!(source, JumpSource::MetaRefresh);
+/// ```
+pub fn get_next_jump(headers: &HeaderMap, url: &Url, text: &str) -> Option<(Url, JumpSource)> {
+    request::get_next_jump(headers, url, text)
+}
+
+/// 页面里`<link rel="icon">`指向的图标，相对地址按`base_url`补全，总是包含`/favicon.ico`
+///
+/// ```
+/// use url::Url;
+/// use what_web::extract::get_favicon_link;
+///
+/// let base_url = Url::parse("http://example.com/app/").unwrap();
+/// let links = get_favicon_link(r#"<link rel="icon" href="img/logo.png">"#, &base_url);
+/// assert!(links.contains(&Url::parse("http://example.com/app/img/logo.png").unwrap()));
+/// assert!(links.contains(&Url::parse("http://example.com/favicon.ico").unwrap()));
+/// ```
+pub fn get_favicon_link(text: &str, base_url: &Url) -> HashSet<Url> {
+    request::get_favicon_link(&ParsedHtml::parse(text), base_url)
+}
+
+/// 解码正文，编码依次取`Content-Type`的charset、HTML里的`<meta charset>`，
+/// 都没有声明时默认UTF-8，乱码太多时尝试GBK
+///
+/// ```
+/// use reqwest::header::HeaderMap;
+/// use what_web::extract::get_default_encoding;
+///
+/// let (text, encoding) = get_default_encoding("中文".as_bytes(), &HeaderMap::new());
+/// assert_eq!(text, "中文");
+/// assert_eq!(encoding.name(), "UTF-8");
+/// ```
+pub fn get_default_encoding(byte: &[u8], headers: &HeaderMap) -> (String, &'static Encoding) {
+    let (text, encoding, _) = request::get_default_encoding(byte, headers);
+    (text, encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extract::{
+        favicon_hash, get_default_encoding, get_favicon_link, get_next_jump, get_title,
+    };
+    use encoding_rs::GBK;
+    use rand::distributions::{Alphanumeric, DistString};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, LOCATION};
+    use url::Url;
+
+    const CASES: usize = 200;
+
+    // 随机的片段里混着标签、实体和空白
+    fn random_text(rng: &mut StdRng) -> String {
+        let pieces = [
+            "<", ">", "</", "title", "meta", "&amp;", "&#x4e2d;", " ", "\n", "\t", "中文", "\"",
+            "'", "=",
+        ];
+        let mut text = String::new();
+        for _ in 0..rng.gen_range(0..40) {
+            if rng.gen_bool(0.5) {
+                text.push_str(pieces[rng.gen_range(0..pieces.len())]);
+            } else {
+                let len = rng.gen_range(1..8);
+                text.push_str(&Alphanumeric.sample_string(rng, len));
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_title_properties() {
+        let mut rng = StdRng::seed_from_u64(71);
+        for _ in 0..CASES {
+            let text = random_text(&mut rng);
+            let title = get_title(&text);
+            // 合并过空白，不超过最大长度
+            assert_eq!(title, title.trim(), "{:?}", text);
+            assert!(!title.contains(['\n', '\t']), "{:?}", text);
+            assert!(!title.contains("  "), "{:?}", text);
+            assert!(title.chars().count() <= 200, "{:?}", text);
+            let word = Alphanumeric.sample_string(&mut rng, 12);
+            assert_eq!(get_title(&format!("<title>{}</title>{}", word, text)), word);
+        }
+    }
+
+    #[test]
+    fn test_favicon_hash_properties() {
+        let mut rng = StdRng::seed_from_u64(71);
+        for _ in 0..CASES {
+            let len = rng.gen_range(0..2048);
+            let content: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let hash = favicon_hash(&content);
+            assert_eq!(hash, favicon_hash(&content));
+            assert_eq!(hash.md5.len(), 32);
+            assert!(hash.md5.chars().all(|c| c.is_ascii_hexdigit()));
+            let mut changed = content.clone();
+            changed.push(0);
+            assert_ne!(favicon_hash(&changed).md5, hash.md5);
+        }
+    }
+
+    #[test]
+    fn test_next_jump_properties() {
+        let mut rng = StdRng::seed_from_u64(71);
+        let url = Url::parse("http://example.com/index").unwrap();
+        for _ in 0..CASES {
+            let text = random_text(&mut rng);
+            // 跳转的目标都是补全后的地址，不会跳回自己
+            if let Some((next, _)) = get_next_jump(&HeaderMap::new(), &url, &text) {
+                assert_ne!(next, url, "{:?}", text);
+            }
+            let path = format!("/{}", Alphanumeric.sample_string(&mut rng, 10));
+            let mut headers = HeaderMap::new();
+            headers.insert(LOCATION, HeaderValue::from_str(&path).unwrap());
+            let (next, _) = get_next_jump(&headers, &url, &text).unwrap();
+            assert_eq!(next, url.join(&path).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_favicon_link_properties() {
+        let mut rng = StdRng::seed_from_u64(71);
+        let base_url = Url::parse("https://example.com/app/").unwrap();
+        let default = base_url.join("/favicon.ico").unwrap();
+        for _ in 0..CASES {
+            let text = random_text(&mut rng);
+            assert!(get_favicon_link(&text, &base_url).contains(&default));
+            let href = format!("{}.png", Alphanumeric.sample_string(&mut rng, 8));
+            let html = format!(r#"<link rel="icon" href="{}">{}"#, href, text);
+            let links = get_favicon_link(&html, &base_url);
+            assert!(links.contains(&base_url.join(&href).unwrap()), "{:?}", html);
+        }
+    }
+
+    #[test]
+    fn test_default_encoding_properties() {
+        let mut rng = StdRng::seed_from_u64(71);
+        let mut gbk_headers = HeaderMap::new();
+        gbk_headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=gbk"),
+        );
+        for _ in 0..CASES {
+            let text = random_text(&mut rng);
+            let (decoded, encoding) = get_default_encoding(text.as_bytes(), &HeaderMap::new());
+            assert_eq!(decoded, text);
+            assert_eq!(encoding.name(), "UTF-8");
+            // 声明了编码的按声明的解码
+            let (bytes, _, _) = GBK.encode(&text);
+            let (decoded, encoding) = get_default_encoding(&bytes, &gbk_headers);
+            assert_eq!(decoded, text);
+            assert_eq!(encoding, GBK);
+        }
+        // 没有声明编码的GBK中文
+        let (bytes, _, _) = GBK.encode("<html><title>管理系统登录页面</title></html>");
+        let (decoded, encoding) = get_default_encoding(&bytes, &HeaderMap::new());
+        assert_eq!(encoding, GBK);
+        assert!(decoded.contains("管理系统登录页面"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::Read;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::WardError;
+
+/// 外部favicon哈希库的一条记录，哈希的写法和指纹里的`favicon_hash`一样，例如`mmh3:116323821`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct FaviconDbEntry {
+    pub hash: String,
+    pub name: String,
+    #[serde(default = "default_priority")]
+    pub priority: u32,
+}
+
+fn default_priority() -> u32 {
+    1
+}
+
+/// 哈希库生成的指纹归到哪一类：`favicon`按组件识别，`waf`当作WAF和CDN单独识别，不算作组件
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FaviconDbCategory {
+    #[default]
+    Favicon,
+    Waf,
+}
+
+impl FromStr for FaviconDbCategory {
+    type Err = WardError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "favicon" => Ok(FaviconDbCategory::Favicon),
+            "waf" => Ok(FaviconDbCategory::Waf),
+            _ => Err(WardError::InvalidValue(format!(
+                "Unsupported favicon database category `{}`, expected favicon or waf",
+                s
+            ))),
+        }
+    }
+}
+
+/// 读取favicon哈希库，JSON是对象数组，CSV第一行是表头，需要`hash`和`name`两列，`priority`可以不写，默认1。
+/// CSV里空行和`#`开头的行跳过
+pub fn read_favicon_db<R: Read>(mut reader: R) -> Result<Vec<FaviconDbEntry>, WardError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|err| WardError::FingerprintLoad(format!("Favicon database: {}", err)))?;
+    let text = text.trim_start_matches('\u{feff}');
+    if text.trim_start().starts_with('[') {
+        return serde_json::from_str(text)
+            .map_err(|err| WardError::FingerprintLoad(format!("Favicon database: {}", err)));
+    }
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+    let invalid = |line: u64, reason: String| {
+        WardError::FingerprintLoad(format!("Favicon database line {}: {}", line, reason))
+    };
+    let headers = csv_reader
+        .headers()
+        .map_err(|err| invalid(1, err.to_string()))?;
+    let position = |column: &str| {
+        headers
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(column))
+    };
+    let (hash, name) = match (position("hash"), position("name")) {
+        (Some(hash), Some(name)) => (hash, name),
+        _ => return Err(invalid(1, String::from("missing `hash` or `name` column"))),
+    };
+    let priority = position("priority");
+    let mut entries = vec![];
+    for record in csv_reader.records() {
+        let record = record.map_err(|err| {
+            let line = err.position().map(|p| p.line()).unwrap_or_default();
+            invalid(line, err.to_string())
+        })?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let field = |index: usize| record.get(index).unwrap_or_default();
+        if field(hash).is_empty() || field(name).is_empty() {
+            return Err(invalid(line, String::from("empty hash or name")));
+        }
+        let priority = match priority.map(field).filter(|value| !value.is_empty()) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| invalid(line, format!("invalid priority `{}`", value)))?,
+            None => default_priority(),
+        };
+        entries.push(FaviconDbEntry {
+            hash: field(hash).to_string(),
+            name: field(name).to_string(),
+            priority,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_favicon_db() {
+        let entries = read_favicon_db(
+            "\u{feff}Hash,Name,Priority\n# exported\nmmh3:116323821,Spring Boot,3\n\n4807c51099c5c9f46276b04a57d994e0,example-icon,\n".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            [
+                FaviconDbEntry {
+                    hash: String::from("mmh3:116323821"),
+                    name: String::from("Spring Boot"),
+                    priority: 3,
+                },
+                FaviconDbEntry {
+                    hash: String::from("4807c51099c5c9f46276b04a57d994e0"),
+                    name: String::from("example-icon"),
+                    priority: 1,
+                },
+            ]
+        );
+        let entries =
+            read_favicon_db(r#"[{"hash": "mmh3:-1", "name": "router"}]"#.as_bytes()).unwrap();
+        assert_eq!(entries[0].priority, 1);
+        assert!(read_favicon_db("hash,product\n1,a\n".as_bytes()).is_err());
+        assert!(read_favicon_db("hash,name\n1,a\n2,b,x\n3,\n".as_bytes()).is_err());
+        assert!(read_favicon_db("hash,name,priority\n1,a,high\n".as_bytes()).is_err());
+        assert_eq!(
+            "WAF".parse::<FaviconDbCategory>().unwrap(),
+            FaviconDbCategory::Waf
+        );
+        assert!("index".parse::<FaviconDbCategory>().is_err());
+    }
+}
@@ -1,22 +1,695 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use regex::{Regex, RegexBuilder};
+use reqwest::header::HeaderName;
 use serde::{Deserialize, Serialize};
 
+use crate::banner::builtin_tcp_fingerprint;
+use crate::error::WardError;
+use crate::favicon_db::{FaviconDbCategory, FaviconDbEntry};
+use crate::phash::dhash_distance;
+use crate::request::{decode_request_data, parse_header, request_method, BodyHash, FaviconHash};
+use crate::waf::builtin_waf_fingerprint;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebFingerPrintRequest {
     pub path: String,
     pub request_method: String,
     pub request_headers: HashMap<String, String>,
     pub request_data: String,
+    // 不在首页link标签里的favicon路径，请求后合并到当前响应再匹配
+    #[serde(default)]
+    pub favicon_paths: Vec<String>,
+    // 这个请求单独的超时，故意延时的探测用，不写用全局的
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+// 默认是首页的GET请求
+impl Default for WebFingerPrintRequest {
+    fn default() -> Self {
+        Self {
+            path: String::from("/"),
+            request_method: String::from("get"),
+            request_headers: HashMap::new(),
+            request_data: String::new(),
+            favicon_paths: vec![],
+            timeout_secs: None,
+        }
+    }
+}
+
+impl WebFingerPrintRequest {
+    // 方法、请求体和请求头不依赖目标，加载时就能检查出来，错误里带上指纹名称和字段
+    pub(crate) fn check(&self, name: &str) -> Result<(), WardError> {
+        let load_error = |field: &str, err: WardError| {
+            WardError::FingerprintLoad(format!(
+                "Invalid {} in fingerprint `{}`: {}",
+                field, name, err
+            ))
+        };
+        request_method(&self.request_method).map_err(|err| load_error("request_method", err))?;
+        decode_request_data(&self.request_data).map_err(|err| load_error("request_data", err))?;
+        for (header_name, value) in self.request_headers.iter() {
+            parse_header(header_name, value).map_err(|err| load_error("request_headers", err))?;
+        }
+        Ok(())
+    }
+}
+
+/// 一个状态码或者一段范围，范围写成`"3xx"`或者`"300-399"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusCodeRule {
+    Exact(u16),
+    Range(u16, u16),
+}
+
+impl StatusCodeRule {
+    fn contains(&self, status_code: u16) -> bool {
+        match self {
+            Self::Exact(code) => *code == status_code,
+            Self::Range(start, end) => (*start..=*end).contains(&status_code),
+        }
+    }
+}
+
+impl FromStr for StatusCodeRule {
+    type Err = WardError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            WardError::InvalidValue(format!(
+                "Invalid status code `{}`, expected 404, 4xx or 400-499",
+                s
+            ))
+        };
+        if let Some(class) = s.strip_suffix("xx").or_else(|| s.strip_suffix("XX")) {
+            let class: u16 = class.parse().map_err(|_| invalid())?;
+            if !(1..=9).contains(&class) {
+                return Err(invalid());
+            }
+            return Ok(Self::Range(class * 100, class * 100 + 99));
+        }
+        if let Some((start, end)) = s.split_once('-') {
+            let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+            let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+            if start > end {
+                return Err(invalid());
+            }
+            return Ok(Self::Range(start, end));
+        }
+        s.parse().map(Self::Exact).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for StatusCodeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(code) => write!(f, "{}", code),
+            Self::Range(start, end) if start % 100 == 0 && *end == start + 99 => {
+                write!(f, "{}xx", start / 100)
+            }
+            Self::Range(start, end) => write!(f, "{}-{}", start, end),
+        }
+    }
+}
+
+/// 指纹要求的状态码，命中任意一个就行，0和空列表不限制。
+/// 可以写整数、字符串或者它们的列表，例如`401`、`"3xx"`、`[401, "500-599"]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusCodes(pub Vec<StatusCodeRule>);
+
+impl StatusCodes {
+    pub fn is_any(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn matches(&self, status_code: u16) -> bool {
+        self.is_any() || self.0.iter().any(|rule| rule.contains(status_code))
+    }
+}
+
+/// 原始响应的字节数，整数要求完全相等，`"1000-2000"`是一段范围，两端都包含
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthRule {
+    Exact(usize),
+    Range(usize, usize),
+}
+
+impl LengthRule {
+    pub fn matches(&self, length: usize) -> bool {
+        match self {
+            Self::Exact(exact) => *exact == length,
+            Self::Range(start, end) => (*start..=*end).contains(&length),
+        }
+    }
+}
+
+impl FromStr for LengthRule {
+    type Err = WardError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            WardError::InvalidValue(format!(
+                "Invalid length `{}`, expected 1256 or 1000-2000",
+                s
+            ))
+        };
+        if let Some((start, end)) = s.split_once('-') {
+            let start: usize = start.trim().parse().map_err(|_| invalid())?;
+            let end: usize = end.trim().parse().map_err(|_| invalid())?;
+            if start > end {
+                return Err(invalid());
+            }
+            return Ok(Self::Range(start, end));
+        }
+        s.parse().map(Self::Exact).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for LengthRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(length) => write!(f, "{}", length),
+            Self::Range(start, end) => write!(f, "{}-{}", start, end),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LengthValue {
+    Length(usize),
+    Rule(String),
+}
+
+impl<'de> Deserialize<'de> for LengthRule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match LengthValue::deserialize(deserializer)? {
+            LengthValue::Length(length) => Ok(Self::Exact(length)),
+            LengthValue::Rule(rule) => rule.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for LengthRule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Exact(length) => LengthValue::Length(*length),
+            rule => LengthValue::Rule(rule.to_string()),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StatusCodeValue {
+    Code(u16),
+    Rule(String),
+}
+
+impl StatusCodeValue {
+    fn into_rule(self) -> Result<Option<StatusCodeRule>, WardError> {
+        match self {
+            Self::Code(0) => Ok(None),
+            Self::Code(code) => Ok(Some(StatusCodeRule::Exact(code))),
+            Self::Rule(rule) => rule.parse().map(Some),
+        }
+    }
+}
+
+impl From<&StatusCodeRule> for StatusCodeValue {
+    fn from(rule: &StatusCodeRule) -> Self {
+        match rule {
+            StatusCodeRule::Exact(code) => Self::Code(*code),
+            rule => Self::Rule(rule.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusCodes {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(StatusCodeValue),
+            Many(Vec<StatusCodeValue>),
+        }
+        let values = match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        };
+        let mut rules = Vec::with_capacity(values.len());
+        for value in values {
+            if let Some(rule) = value.into_rule().map_err(serde::de::Error::custom)? {
+                rules.push(rule);
+            }
+        }
+        Ok(Self(rules))
+    }
+}
+
+// 只有一个状态码时按整数输出，兼容旧版本
+impl Serialize for StatusCodes {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.as_slice() {
+            [] => 0_u16.serialize(serializer),
+            [rule] => StatusCodeValue::from(rule).serialize(serializer),
+            rules => serializer.collect_seq(rules.iter().map(StatusCodeValue::from)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WebFingerPrintMatch {
-    pub status_code: u16,
+    pub status_code: StatusCodes,
+    // 原始响应的字节数，有些设备只能靠默认页面的大小区分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<LengthRule>,
     #[serde(default)]
     pub favicon_hash: Vec<String>,
     pub headers: HashMap<String, String>,
     pub keyword: Vec<String>,
+    #[serde(default)]
+    pub regex: Vec<String>,
+    // 正则默认忽略大小写
+    #[serde(default)]
+    pub regex_case_sensitive: bool,
+    // 提取版本号的正则，使用命名分组`version`或者第一个分组，不参与匹配
+    #[serde(default)]
+    pub version_regex: Vec<String>,
+    // 排除规则，出现任意一个都不匹配
+    #[serde(default)]
+    pub not_keyword: Vec<String>,
+    #[serde(default)]
+    pub not_headers: HashMap<String, String>,
+    // https证书的主题和颁发者关键词，需要全部命中
+    #[serde(default)]
+    pub cert_subject: Vec<String>,
+    #[serde(default)]
+    pub cert_issuer: Vec<String>,
+    // 十六进制表示的字节序列，在原始响应里查找，需要全部命中，例如Java序列化的`aced0005`
+    #[serde(default)]
+    pub hex_keyword: Vec<String>,
+    // 401响应`WWW-Authenticate`里的realm，写法和请求头的值一样
+    #[serde(default)]
+    pub realm: Vec<String>,
+    // 站点robots.txt里的关键词，例如`Disallow: /wp-admin/`，需要全部命中
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub robots: Vec<String>,
+    // `<meta name="generator">`的内容，写法和请求头的值一样
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meta_generator: Vec<String>,
+    // 状态行里的原因短语，例如thttpd的`Document follows`，写法和请求头的值一样，需要全部命中。
+    // 只在首页的HTTP/1.x响应上读取，HTTP/2没有原因短语，不会命中
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reason_phrase: Vec<String>,
+    // 页面里脚本和样式的路径前缀，例如`/wp-includes/`，忽略大小写
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub script_src: Vec<String>,
+    // 响应设置的Cookie名，忽略大小写，`*`结尾时按前缀比较，例如`BIGipServer*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<String>,
+    // 原始响应的哈希，命中任意一个即可，写法见BodyHashRule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub body_hash: Vec<String>,
+    #[serde(skip)]
+    pub(crate) regex_set: Vec<Regex>,
+    #[serde(skip)]
+    pub(crate) version_regex_set: Vec<Regex>,
+    // 关键词在AC自动机里的编号，和keyword、not_keyword一一对应
+    #[serde(skip)]
+    pub(crate) keyword_ids: Vec<usize>,
+    #[serde(skip)]
+    pub(crate) not_keyword_ids: Vec<usize>,
+    #[serde(skip)]
+    pub(crate) hex_keyword_bytes: Vec<Vec<u8>>,
+    #[serde(skip)]
+    pub(crate) header_rules: Vec<(String, HeaderRule)>,
+    #[serde(skip)]
+    pub(crate) not_header_rules: Vec<(String, HeaderRule)>,
+    #[serde(skip)]
+    pub(crate) realm_rules: Vec<HeaderRule>,
+    #[serde(skip)]
+    pub(crate) generator_rules: Vec<HeaderRule>,
+    #[serde(skip)]
+    pub(crate) reason_phrase_rules: Vec<HeaderRule>,
+    #[serde(skip)]
+    pub(crate) favicon_rules: Vec<FaviconRule>,
+    #[serde(skip)]
+    pub(crate) body_hash_rules: Vec<BodyHashRule>,
+}
+
+// dHash默认允许不同的位数，重新编码的图标一般只差一两位
+const DEFAULT_DHASH_DISTANCE: u32 = 4;
+
+// favicon的哈希：`md5:`和`mmh3:`开头的指定算法，没有前缀的兼容旧指纹，整数是mmh3，其他按md5比较。
+// `dhash:`后面是16位十六进制，可以用`dhash:<hash>:<distance>`指定最多允许不同的位数
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum FaviconRule {
+    Md5(String),
+    Mmh3(i32),
+    DHash { hash: u64, distance: u32 },
+}
+
+impl FaviconRule {
+    fn parse(hash: &str, name: &str) -> Result<Self, WardError> {
+        let invalid = || {
+            WardError::FingerprintLoad(format!(
+                "Invalid favicon_hash `{}` in fingerprint `{}`",
+                hash, name
+            ))
+        };
+        let hash = hash.trim();
+        match hash.split_once(':') {
+            Some((family, value)) => match family.to_lowercase().as_str() {
+                "md5" if value.len() == 32 && value.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                    Ok(Self::Md5(value.to_lowercase()))
+                }
+                "mmh3" => value.parse().map(Self::Mmh3).map_err(|_| invalid()),
+                "dhash" => {
+                    let (hash, distance) = match value.split_once(':') {
+                        Some((hash, distance)) => (hash, distance.parse().map_err(|_| invalid())?),
+                        None => (value, DEFAULT_DHASH_DISTANCE),
+                    };
+                    if hash.len() != 16 || distance > 64 {
+                        return Err(invalid());
+                    }
+                    let hash = u64::from_str_radix(hash, 16).map_err(|_| invalid())?;
+                    Ok(Self::DHash { hash, distance })
+                }
+                _ => Err(invalid()),
+            },
+            None => match hash.parse() {
+                Ok(mmh3) => Ok(Self::Mmh3(mmh3)),
+                Err(_) => Ok(Self::Md5(hash.to_lowercase())),
+            },
+        }
+    }
+    pub(crate) fn is_match(&self, favicon_hash: &FaviconHash) -> bool {
+        match self {
+            Self::Md5(md5) => *md5 == favicon_hash.md5,
+            Self::Mmh3(mmh3) => *mmh3 == favicon_hash.mmh3,
+            Self::DHash { hash, distance } => favicon_hash
+                .dhash
+                .is_some_and(|dhash| dhash_distance(*hash, dhash) <= *distance),
+        }
+    }
+}
+
+// 证据里带上算法，能看出是哪一种哈希命中的
+impl fmt::Display for FaviconRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Md5(md5) => write!(f, "md5:{}", md5),
+            Self::Mmh3(mmh3) => write!(f, "mmh3:{}", mmh3),
+            Self::DHash { hash, distance } if *distance == DEFAULT_DHASH_DISTANCE => {
+                write!(f, "dhash:{:016x}", hash)
+            }
+            Self::DHash { hash, distance } => write!(f, "dhash:{:016x}:{}", hash, distance),
+        }
+    }
+}
+
+// 只有md5和mmh3规则的指纹按哈希建索引，匹配时直接查表；有dHash规则或者没有图标规则的逐个匹配
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FaviconIndex {
+    by_hash: HashMap<String, Vec<usize>>,
+    scan: Vec<usize>,
+    // 建索引时所有指纹图标规则的哈希，对不上说明指纹改过没有重建；
+    // 数量不变只改了规则或者顺序也能发现
+    signature: Option<u64>,
+}
+
+impl FaviconIndex {
+    // 索引只和每个位置上指纹的图标规则有关，其他字段改了不影响
+    fn signature(fingerprints: &[V3WebFingerPrint]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for fingerprint in fingerprints {
+            fingerprint.match_rules.favicon_rules.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+    fn build(fingerprints: &[V3WebFingerPrint]) -> Self {
+        let mut index = Self {
+            signature: Some(Self::signature(fingerprints)),
+            ..Default::default()
+        };
+        for (i, fingerprint) in fingerprints.iter().enumerate() {
+            let rules = &fingerprint.match_rules.favicon_rules;
+            if rules.is_empty()
+                || rules
+                    .iter()
+                    .any(|rule| matches!(rule, FaviconRule::DHash { .. }))
+            {
+                index.scan.push(i);
+                continue;
+            }
+            for rule in rules {
+                index.by_hash.entry(rule.to_string()).or_default().push(i);
+            }
+        }
+        index
+    }
+    // 可能命中的指纹，按指纹库里的顺序，图标以外的规则还要再匹配。
+    // 索引过期时（例如反序列化得到的指纹库）退回逐个匹配
+    pub(crate) fn candidates<'a, 'b>(
+        &self,
+        fingerprints: &'a [V3WebFingerPrint],
+        favicon: impl Iterator<Item = &'b FaviconHash>,
+    ) -> Vec<&'a V3WebFingerPrint> {
+        if self.signature != Some(Self::signature(fingerprints)) {
+            return fingerprints.iter().collect();
+        }
+        let mut candidates: BTreeSet<usize> = self.scan.iter().copied().collect();
+        for hash in favicon {
+            for key in [format!("md5:{}", hash.md5), format!("mmh3:{}", hash.mmh3)] {
+                if let Some(indices) = self.by_hash.get(&key) {
+                    candidates.extend(indices);
+                }
+            }
+        }
+        candidates.into_iter().map(|i| &fingerprints[i]).collect()
+    }
+}
+
+// 原始响应的哈希：`md5:`和`sha256:`开头的指定算法，没有前缀的按长度区分，32位是md5，64位是sha256
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum BodyHashRule {
+    Md5(String),
+    Sha256(String),
+}
+
+impl BodyHashRule {
+    fn parse(hash: &str, name: &str) -> Result<Self, WardError> {
+        let invalid = || {
+            WardError::FingerprintLoad(format!(
+                "Invalid body_hash `{}` in fingerprint `{}`",
+                hash, name
+            ))
+        };
+        let (family, value) = match hash.trim().split_once(':') {
+            Some((family, value)) => (family.to_lowercase(), value),
+            None => (String::new(), hash.trim()),
+        };
+        if !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let value = value.to_lowercase();
+        match (family.as_str(), value.len()) {
+            ("md5" | "", 32) => Ok(Self::Md5(value)),
+            ("sha256" | "", 64) => Ok(Self::Sha256(value)),
+            _ => Err(invalid()),
+        }
+    }
+    pub(crate) fn is_match(&self, body_hash: &BodyHash) -> bool {
+        match self {
+            Self::Md5(md5) => *md5 == body_hash.md5,
+            Self::Sha256(sha256) => *sha256 == body_hash.sha256,
+        }
+    }
+}
+
+impl fmt::Display for BodyHashRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Md5(md5) => write!(f, "md5:{}", md5),
+            Self::Sha256(sha256) => write!(f, "sha256:{}", sha256),
+        }
+    }
+}
+
+// 请求头的值：`*`只要求存在，`=`开头完全相等，`~`开头是正则，其他按子串查找，除了正则都忽略大小写
+#[derive(Debug, Clone)]
+pub(crate) enum HeaderRule {
+    Exists,
+    Exact(String),
+    Contains(String),
+    Regex(Regex),
+}
+
+impl HeaderRule {
+    fn parse(rule: &str, case_sensitive: bool, name: &str) -> Result<Self, WardError> {
+        if rule == "*" {
+            return Ok(Self::Exists);
+        }
+        if let Some(exact) = rule.strip_prefix('=') {
+            return Ok(Self::Exact(exact.to_lowercase()));
+        }
+        if let Some(reg) = rule.strip_prefix('~') {
+            let re = RegexBuilder::new(reg)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map_err(|err| {
+                    WardError::FingerprintLoad(format!(
+                        "Invalid header regex `{}` in fingerprint `{}`: {}",
+                        reg, name, err
+                    ))
+                })?;
+            return Ok(Self::Regex(re));
+        }
+        Ok(Self::Contains(rule.to_lowercase()))
+    }
+    pub(crate) fn is_match(&self, value: &str) -> bool {
+        match self {
+            Self::Exists => true,
+            Self::Exact(exact) => value.to_lowercase() == *exact,
+            Self::Contains(part) => value.to_lowercase().contains(part),
+            Self::Regex(re) => re.is_match(value),
+        }
+    }
+}
+
+fn build_header_rules(
+    headers: &HashMap<String, String>,
+    case_sensitive: bool,
+    name: &str,
+) -> Result<Vec<(String, HeaderRule)>, WardError> {
+    let mut header_rules = Vec::with_capacity(headers.len());
+    for (k, v) in headers.iter() {
+        header_rules.push((
+            k.to_lowercase(),
+            HeaderRule::parse(v, case_sensitive, name)?,
+        ));
+    }
+    Ok(header_rules)
+}
+
+impl WebFingerPrintMatch {
+    // 没有任何匹配条件，排除规则不算
+    fn is_empty(&self) -> bool {
+        self.status_code.is_any()
+            && self.length.is_none()
+            && self.realm.is_empty()
+            && self.robots.is_empty()
+            && self.meta_generator.is_empty()
+            && self.reason_phrase.is_empty()
+            && self.script_src.is_empty()
+            && self.cookies.is_empty()
+            && self.body_hash.is_empty()
+            && self.favicon_hash.is_empty()
+            && self.headers.is_empty()
+            && self.keyword.is_empty()
+            && self.regex.is_empty()
+            && self.cert_subject.is_empty()
+            && self.cert_issuer.is_empty()
+            && self.hex_keyword.is_empty()
+    }
+    // 加载指纹库时编译正则，避免每次请求重复编译
+    fn compile_regex(&mut self, name: &str) -> Result<(), WardError> {
+        self.regex_set = build_regex(&self.regex, self.regex_case_sensitive, name)?;
+        self.version_regex_set = build_regex(&self.version_regex, self.regex_case_sensitive, name)?;
+        self.header_rules = build_header_rules(&self.headers, self.regex_case_sensitive, name)?;
+        self.not_header_rules =
+            build_header_rules(&self.not_headers, self.regex_case_sensitive, name)?;
+        self.realm_rules = self
+            .realm
+            .iter()
+            .map(|rule| HeaderRule::parse(rule, self.regex_case_sensitive, name))
+            .collect::<Result<_, WardError>>()?;
+        self.generator_rules = self
+            .meta_generator
+            .iter()
+            .map(|rule| HeaderRule::parse(rule, self.regex_case_sensitive, name))
+            .collect::<Result<_, WardError>>()?;
+        self.reason_phrase_rules = self
+            .reason_phrase
+            .iter()
+            .map(|rule| HeaderRule::parse(rule, self.regex_case_sensitive, name))
+            .collect::<Result<_, WardError>>()?;
+        Ok(())
+    }
+    fn decode_hex_keyword(&mut self, name: &str) -> Result<(), WardError> {
+        let mut hex_keyword_bytes = Vec::with_capacity(self.hex_keyword.len());
+        for hex_keyword in self.hex_keyword.iter() {
+            let bytes = decode_hex(hex_keyword).ok_or_else(|| {
+                WardError::FingerprintLoad(format!(
+                    "Invalid hex_keyword `{}` in fingerprint `{}`",
+                    hex_keyword, name
+                ))
+            })?;
+            hex_keyword_bytes.push(bytes);
+        }
+        self.hex_keyword_bytes = hex_keyword_bytes;
+        Ok(())
+    }
+    fn parse_favicon_hash(&mut self, name: &str) -> Result<(), WardError> {
+        self.favicon_rules = self
+            .favicon_hash
+            .iter()
+            .map(|hash| FaviconRule::parse(hash, name))
+            .collect::<Result<_, WardError>>()?;
+        self.body_hash_rules = self
+            .body_hash
+            .iter()
+            .map(|hash| BodyHashRule::parse(hash, name))
+            .collect::<Result<_, WardError>>()?;
+        Ok(())
+    }
+}
+
+// 忽略空白和`\x`前缀，`ac ed 00 05`和`\xac\xed\x00\x05`都可以
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex
+        .replace("\\x", "")
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+fn build_regex(
+    regex: &[String],
+    case_sensitive: bool,
+    name: &str,
+) -> Result<Vec<Regex>, WardError> {
+    let mut regex_set = Vec::with_capacity(regex.len());
+    for reg in regex.iter() {
+        let re = RegexBuilder::new(reg)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|err| {
+                WardError::FingerprintLoad(format!(
+                    "Invalid regex `{}` in fingerprint `{}`: {}",
+                    reg, name, err
+                ))
+            })?;
+        regex_set.push(re);
+    }
+    Ok(regex_set)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,40 +700,163 @@ pub struct V3WebFingerPrint {
     pub priority: u32,
     pub request: WebFingerPrintRequest,
     pub match_rules: WebFingerPrintMatch,
+    // 首页识别出这些组件之一时才发送的特殊请求，为空时每个目标都发送
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+}
+
+impl V3WebFingerPrint {
+    // 比较请求和匹配规则，不包括编译后的正则
+    fn same_rules(&self, other: &V3WebFingerPrint) -> bool {
+        self.priority == other.priority
+            && serde_json::to_value(&self.request).ok() == serde_json::to_value(&other.request).ok()
+            && serde_json::to_value(&self.match_rules).ok()
+                == serde_json::to_value(&other.match_rules).ok()
+    }
+}
+
+// 同一个组件的常见写法，键是小写的别名
+const DEFAULT_NAME_ALIASES: &[(&str, &str)] = &[
+    ("nginx-waf", "nginx"),
+    ("microsoft iis", "microsoft-iis"),
+    ("apache tomcat", "apache-tomcat"),
+    ("apache shiro", "apache-shiro"),
+    ("oracle weblogic", "oracle-weblogic"),
+    ("spring boot", "spring-boot"),
+];
+
+/// 内置的组件别名表
+pub fn default_name_aliases() -> HashMap<String, String> {
+    DEFAULT_NAME_ALIASES
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
 }
 
 //TODO 整理lib文件
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct WebFingerPrint {
     #[serde(default)]
     pub name: String,
     path: String,
-    status_code: u16,
+    status_code: StatusCodes,
     headers: HashMap<String, String>,
     keyword: Vec<String>,
     #[serde(default)]
     pub priority: u32,
     request_method: String,
     request_headers: HashMap<String, String>,
+    #[serde(default)]
     request_data: String,
+    // 请求体文件，相对于指纹文件所在的目录，加载时读进request_data
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    request_data_file: String,
     #[serde(default)]
     favicon_hash: Vec<String>,
+    #[serde(default)]
+    favicon_paths: Vec<String>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    regex: Vec<String>,
+    #[serde(default)]
+    regex_case_sensitive: bool,
+    #[serde(default)]
+    version_regex: Vec<String>,
+    #[serde(default)]
+    not_keyword: Vec<String>,
+    #[serde(default)]
+    not_headers: HashMap<String, String>,
+    #[serde(default)]
+    cert_subject: Vec<String>,
+    #[serde(default)]
+    cert_issuer: Vec<String>,
+    #[serde(default)]
+    hex_keyword: Vec<String>,
+    #[serde(default)]
+    realm: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    robots: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    meta_generator: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    reason_phrase: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    script_src: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cookies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    body_hash: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    length: Option<LengthRule>,
+    #[serde(default)]
+    requires: Vec<String>,
+    // WAF和CDN的指纹，单独识别，不算作组件
+    #[serde(default)]
+    waf: bool,
+    // 不是HTTP的服务主动发送的banner，只用keyword、hex_keyword、regex和version_regex
+    #[serde(default)]
+    tcp: bool,
 }
 
-impl Default for WebFingerPrint {
-    fn default() -> Self {
-        Self {
-            path: String::new(),
-            name: String::new(),
-            status_code: 0,
-            headers: HashMap::new(),
-            keyword: vec![],
-            priority: 1,
-            request_method: String::new(),
-            request_headers: HashMap::new(),
-            request_data: String::new(),
-            favicon_hash: vec![],
-        }
+// 编译正则、解析哈希，不区分是哪一类指纹
+impl TryFrom<WebFingerPrint> for V3WebFingerPrint {
+    type Error = WardError;
+    fn try_from(f_rule: WebFingerPrint) -> Result<Self, Self::Error> {
+        let request = WebFingerPrintRequest {
+            path: f_rule.path,
+            request_method: f_rule.request_method,
+            request_headers: f_rule.request_headers,
+            request_data: f_rule.request_data,
+            favicon_paths: f_rule.favicon_paths,
+            timeout_secs: f_rule.timeout_secs,
+        };
+        // 发送时同样会失败，加载时直接报错，不再悄悄发一个空的请求体
+        request.check(&f_rule.name)?;
+        let mut match_rules = WebFingerPrintMatch {
+            status_code: f_rule.status_code,
+            length: f_rule.length,
+            favicon_hash: f_rule.favicon_hash,
+            headers: f_rule.headers,
+            keyword: f_rule.keyword,
+            regex: f_rule.regex,
+            regex_case_sensitive: f_rule.regex_case_sensitive,
+            version_regex: f_rule.version_regex,
+            not_keyword: f_rule.not_keyword,
+            not_headers: f_rule.not_headers,
+            cert_subject: f_rule.cert_subject,
+            cert_issuer: f_rule.cert_issuer,
+            hex_keyword: f_rule.hex_keyword,
+            realm: f_rule.realm,
+            robots: f_rule.robots,
+            meta_generator: f_rule.meta_generator,
+            reason_phrase: f_rule.reason_phrase,
+            script_src: f_rule.script_src,
+            cookies: f_rule.cookies,
+            body_hash: f_rule.body_hash,
+            regex_set: vec![],
+            version_regex_set: vec![],
+            keyword_ids: vec![],
+            not_keyword_ids: vec![],
+            hex_keyword_bytes: vec![],
+            header_rules: vec![],
+            not_header_rules: vec![],
+            realm_rules: vec![],
+            generator_rules: vec![],
+            reason_phrase_rules: vec![],
+            favicon_rules: vec![],
+            body_hash_rules: vec![],
+        };
+        match_rules.compile_regex(&f_rule.name)?;
+        match_rules.decode_hex_keyword(&f_rule.name)?;
+        match_rules.parse_favicon_hash(&f_rule.name)?;
+        Ok(V3WebFingerPrint {
+            name: f_rule.name,
+            priority: f_rule.priority,
+            request,
+            match_rules,
+            requires: f_rule.requires,
+        })
     }
 }
 
@@ -70,56 +866,527 @@ pub struct WebFingerPrintLib {
     pub index: Vec<V3WebFingerPrint>,
     pub special: Vec<V3WebFingerPrint>,
     pub favicon: Vec<V3WebFingerPrint>,
+    #[serde(default)]
+    pub waf: Vec<V3WebFingerPrint>,
+    #[serde(default)]
+    pub tcp: Vec<V3WebFingerPrint>,
+    // 所有指纹的关键词建一个AC自动机，每个响应只扫描一遍正文
+    #[serde(skip)]
+    pub(crate) keyword_matcher: Option<AhoCorasick>,
+    // favicon和waf两类指纹的图标哈希索引，每个图标查一次表，不用逐个指纹比较
+    #[serde(skip)]
+    pub(crate) favicon_index: FaviconIndex,
+    #[serde(skip)]
+    pub(crate) waf_favicon_index: FaviconIndex,
+    // 调用过`normalize_names`后记住别名表，合并进来的指纹也按同样的规则统一名称
+    #[serde(skip)]
+    name_aliases: Option<HashMap<String, String>>,
+}
+
+// 解析指纹库JSON，格式错误时指出是第几个指纹
+pub fn parse_web_fingerprint<R: Read>(reader: R) -> Result<Vec<WebFingerPrint>, WardError> {
+    let entries: Vec<serde_json::Value> = serde_json::from_reader(reader).map_err(|err| {
+        WardError::FingerprintLoad(format!("Invalid fingerprint library: {}", err))
+    })?;
+    let mut web_fingerprint = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let name = entry
+            .get("name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let fingerprint: WebFingerPrint = serde_json::from_value(entry).map_err(|err| {
+            WardError::FingerprintLoad(format!(
+                "Invalid fingerprint #{} `{}`: {}",
+                index, name, err
+            ))
+        })?;
+        web_fingerprint.push(fingerprint);
+    }
+    Ok(web_fingerprint)
+}
+
+// 请求体会发给每个目标，只能读取指纹文件所在目录下的文件，
+// 不允许绝对路径和`..`，解析符号链接后也要在这个目录下
+fn request_data_path(base_dir: &Path, file: &str) -> std::io::Result<PathBuf> {
+    let relative = Path::new(file);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "must be a relative path inside the fingerprint directory",
+        ));
+    }
+    let base_dir = if base_dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base_dir
+    };
+    let base_dir = base_dir.canonicalize()?;
+    let path = base_dir.join(relative).canonicalize()?;
+    if !path.starts_with(&base_dir) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "resolves outside the fingerprint directory",
+        ));
+    }
+    Ok(path)
+}
+
+/// 读取指纹里`request_data_file`引用的请求体，路径相对于`base_dir`，
+/// 读取后按base64写进`request_data`。同时写了`request_data`的报错
+pub fn load_request_data_files(
+    web_fingerprint: &mut [WebFingerPrint],
+    base_dir: &Path,
+) -> Result<(), WardError> {
+    for fingerprint in web_fingerprint
+        .iter_mut()
+        .filter(|f| !f.request_data_file.is_empty())
+    {
+        if !fingerprint.request_data.is_empty() {
+            return Err(WardError::FingerprintLoad(format!(
+                "Fingerprint `{}` sets both request_data and request_data_file",
+                fingerprint.name
+            )));
+        }
+        let path = request_data_path(base_dir, &fingerprint.request_data_file).map_err(|err| {
+            WardError::FingerprintLoad(format!(
+                "Fingerprint `{}` request_data_file `{}`: {}",
+                fingerprint.name, fingerprint.request_data_file, err
+            ))
+        })?;
+        let data = std::fs::read(&path).map_err(|err| {
+            WardError::FingerprintLoad(format!(
+                "Fingerprint `{}` request_data_file {:?}: {}",
+                fingerprint.name, path, err
+            ))
+        })?;
+        fingerprint.request_data = base64::encode(data);
+        fingerprint.request_data_file.clear();
+    }
+    Ok(())
 }
 
 impl WebFingerPrintLib {
-    pub fn new(web_fingerprint: Vec<WebFingerPrint>) -> Self {
+    // 可以配合include_bytes!把指纹库编译进程序，不读取文件
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, WardError> {
+        Self::new(parse_web_fingerprint(reader)?)
+    }
+    pub fn new(web_fingerprint: Vec<WebFingerPrint>) -> Result<Self, WardError> {
         let mut index: Vec<V3WebFingerPrint> = vec![];
         let mut special: Vec<V3WebFingerPrint> = vec![];
         let mut favicon: Vec<V3WebFingerPrint> = vec![];
-        for f_rule in web_fingerprint {
-            let request = WebFingerPrintRequest {
-                path: f_rule.path.clone(),
-                request_method: f_rule.request_method.clone(),
-                request_headers: f_rule.request_headers.clone(),
-                request_data: f_rule.request_data.clone(),
-            };
-            let match_rules = WebFingerPrintMatch {
-                status_code: f_rule.status_code,
-                favicon_hash: f_rule.favicon_hash.clone(),
-                headers: f_rule.headers,
-                keyword: f_rule.keyword,
-            };
-            let v3_web_fingerprint = V3WebFingerPrint {
-                name: f_rule.name,
-                priority: f_rule.priority,
-                request,
-                match_rules,
-            };
-            let is_index = || {
-                f_rule.path == "/"
-                    && f_rule.request_headers.is_empty()
-                    && f_rule.request_method.to_uppercase() == "GET"
-                    && f_rule.request_data.is_empty()
-                    && f_rule.favicon_hash.is_empty()
-            };
+        let mut waf: Vec<V3WebFingerPrint> = vec![];
+        let mut tcp: Vec<V3WebFingerPrint> = vec![];
+        // 内置的WAF和banner指纹，指纹库里可以补充
+        let builtin_waf = builtin_waf_fingerprint().iter().cloned();
+        let builtin_tcp = builtin_tcp_fingerprint().iter().cloned();
+        for f_rule in builtin_waf.chain(builtin_tcp).chain(web_fingerprint) {
+            // 没有经过load_request_data_files的指纹不知道文件相对哪个目录
+            if !f_rule.request_data_file.is_empty() {
+                return Err(WardError::FingerprintLoad(format!(
+                    "Fingerprint `{}` request_data_file `{}` is not loaded",
+                    f_rule.name, f_rule.request_data_file
+                )));
+            }
+            let is_index = f_rule.path == "/"
+                && f_rule.request_headers.is_empty()
+                && f_rule.request_method.to_uppercase() == "GET"
+                && f_rule.request_data.is_empty()
+                && f_rule.favicon_hash.is_empty()
+                && f_rule.favicon_paths.is_empty();
+            let (waf_rule, tcp_rule) = (f_rule.waf, f_rule.tcp);
+            let has_favicon = !f_rule.favicon_hash.is_empty();
+            let fetch_favicon = f_rule.path != "/" || !f_rule.favicon_paths.is_empty();
+            let v3_web_fingerprint = V3WebFingerPrint::try_from(f_rule)?;
             // 首页请求，有FaviconHash
-            if is_index() {
+            if waf_rule {
+                waf.push(v3_web_fingerprint);
+            } else if tcp_rule {
+                tcp.push(v3_web_fingerprint);
+            } else if is_index {
                 index.push(v3_web_fingerprint);
-            } else if !f_rule.favicon_hash.is_empty() {
+            } else if has_favicon {
                 favicon.push(v3_web_fingerprint.clone());
                 // 固定路径的FaviconHash
-                if f_rule.path != "/" {
+                if fetch_favicon {
                     special.push(v3_web_fingerprint);
                 }
             } else {
                 special.push(v3_web_fingerprint);
             }
         }
-        Self {
+        let mut fingerprint_lib = Self {
             index,
             special,
             favicon,
+            waf,
+            tcp,
+            keyword_matcher: None,
+            favicon_index: FaviconIndex::default(),
+            waf_favicon_index: FaviconIndex::default(),
+            name_aliases: None,
+        };
+        fingerprint_lib.rebuild_indexes();
+        Ok(fingerprint_lib)
+    }
+    // 合并其他来源的指纹库，同名同规则的去重，同名不同规则的都保留
+    pub fn merge(&mut self, other: WebFingerPrintLib) {
+        let merge_into = |target: &mut Vec<V3WebFingerPrint>, source: Vec<V3WebFingerPrint>| {
+            for fingerprint in source {
+                let same_name: Vec<&V3WebFingerPrint> = target
+                    .iter()
+                    .filter(|f| f.name == fingerprint.name)
+                    .collect();
+                if same_name.iter().any(|f| f.same_rules(&fingerprint)) {
+                    continue;
+                }
+                if !same_name.is_empty() {
+                    log::warn!(
+                        "Fingerprint `{}` has different rules in multiple sources",
+                        fingerprint.name
+                    );
+                }
+                target.push(fingerprint);
+            }
+        };
+        merge_into(&mut self.index, other.index);
+        merge_into(&mut self.special, other.special);
+        merge_into(&mut self.favicon, other.favicon);
+        merge_into(&mut self.waf, other.waf);
+        merge_into(&mut self.tcp, other.tcp);
+        // 不同来源的写法可能不一样
+        self.renormalize_names();
+        self.rebuild_indexes();
+    }
+    /// 外部的favicon哈希库生成只有图标规则的指纹，加到`category`这一类里，返回新加的数量。
+    /// 同名同哈希的跳过，哈希写法不对的报错，指出是哪个组件
+    pub fn add_favicon_db(
+        &mut self,
+        entries: Vec<FaviconDbEntry>,
+        category: FaviconDbCategory,
+    ) -> Result<usize, WardError> {
+        let target = match category {
+            FaviconDbCategory::Favicon => &mut self.favicon,
+            FaviconDbCategory::Waf => &mut self.waf,
+        };
+        let mut seen: HashSet<(String, String)> = target
+            .iter()
+            .filter(|f| f.match_rules.favicon_rules.len() == 1)
+            .map(|f| {
+                let rule = f.match_rules.favicon_rules[0].to_string();
+                (f.name.to_lowercase(), rule)
+            })
+            .collect();
+        let mut added = 0;
+        for entry in entries {
+            let f_rule = WebFingerPrint {
+                name: entry.name,
+                priority: entry.priority,
+                path: String::from("/"),
+                request_method: String::from("get"),
+                favicon_hash: vec![entry.hash],
+                waf: category == FaviconDbCategory::Waf,
+                ..Default::default()
+            };
+            let fingerprint = V3WebFingerPrint::try_from(f_rule)?;
+            let rule = fingerprint.match_rules.favicon_rules[0].to_string();
+            if seen.insert((fingerprint.name.to_lowercase(), rule)) {
+                target.push(fingerprint);
+                added += 1;
+            }
         }
+        self.renormalize_names();
+        self.rebuild_indexes();
+        Ok(added)
+    }
+    /// 重新建立关键词和图标哈希的索引。`new`、`merge`和`add_favicon_db`会自动调用，
+    /// 直接修改了`index`、`favicon`等字段或者反序列化得到的指纹库需要手动调用
+    pub fn rebuild_indexes(&mut self) {
+        self.build_keyword_matcher();
+        self.favicon_index = FaviconIndex::build(&self.favicon);
+        self.waf_favicon_index = FaviconIndex::build(&self.waf);
+    }
+    /// 统一组件名称：去掉首尾空白，别名表里的换成标准写法，其他大小写不同的按第一次出现的写法，
+    /// 同一个组件的优先级取最大的。别名表的键不区分大小写。
+    ///
+    /// 默认不统一，指纹库里的名称原样输出；调用后别名表和之前的合并，
+    /// 之后`merge`和`add_favicon_db`加进来的指纹也会统一。内置的别名表见`default_name_aliases`
+    pub fn normalize_names(&mut self, aliases: &HashMap<String, String>) {
+        let name_aliases = self.name_aliases.get_or_insert_with(HashMap::new);
+        for (k, v) in aliases {
+            name_aliases.insert(k.trim().to_lowercase(), v.trim().to_string());
+        }
+        self.renormalize_names();
+        self.rebuild_indexes();
+    }
+    // 没有开启统一名称时什么都不做
+    fn renormalize_names(&mut self) {
+        let aliases = match self.name_aliases.clone() {
+            Some(aliases) => aliases,
+            None => return,
+        };
+        // 小写名称到统一后的写法，别名的标准写法优先
+        let mut spelling: HashMap<String, String> = aliases
+            .values()
+            .map(|v| (v.to_lowercase(), v.clone()))
+            .collect();
+        let mut priority: HashMap<String, u32> = HashMap::new();
+        for fingerprint in self.components_mut() {
+            let name = fingerprint.name.trim();
+            let name = aliases
+                .get(&name.to_lowercase())
+                .map(String::as_str)
+                .unwrap_or(name);
+            fingerprint.name = spelling
+                .entry(name.to_lowercase())
+                .or_insert_with(|| name.to_string())
+                .clone();
+            let max_priority = priority.entry(fingerprint.name.clone()).or_default();
+            *max_priority = (*max_priority).max(fingerprint.priority);
+        }
+        for fingerprint in self.components_mut() {
+            fingerprint.priority = priority[&fingerprint.name];
+            // 依赖的组件名称也统一写法
+            for name in fingerprint.requires.iter_mut() {
+                let trimmed = name.trim();
+                let trimmed = aliases
+                    .get(&trimmed.to_lowercase())
+                    .map(String::as_str)
+                    .unwrap_or(trimmed);
+                *name = spelling
+                    .get(&trimmed.to_lowercase())
+                    .cloned()
+                    .unwrap_or_else(|| trimmed.to_string());
+            }
+        }
+    }
+    // 有指纹用到robots规则时才请求robots.txt
+    pub(crate) fn has_robots_rules(&self) -> bool {
+        self.index
+            .iter()
+            .chain(self.special.iter())
+            .any(|f| !f.match_rules.robots.is_empty())
+    }
+    // 有指纹用到reason_phrase规则时才另外读取状态行
+    pub(crate) fn has_reason_phrase_rules(&self) -> bool {
+        self.index
+            .iter()
+            .chain(self.special.iter())
+            .any(|f| !f.match_rules.reason_phrase.is_empty())
+    }
+    /// 检查指纹库里的问题，加载时已经检查过的正则和十六进制关键词不在这里
+    pub fn validate(&self) -> Vec<FingerprintIssue> {
+        let mut issues = vec![];
+        // 固定路径的favicon指纹同时在favicon和special里，只检查一次
+        let favicon_only = self.favicon.iter().filter(|f| {
+            !self
+                .special
+                .iter()
+                .any(|s| s.name == f.name && s.same_rules(f))
+        });
+        let fingerprints: Vec<&V3WebFingerPrint> = self
+            .index
+            .iter()
+            .chain(self.special.iter())
+            .chain(favicon_only)
+            .chain(self.waf.iter())
+            .chain(self.tcp.iter())
+            .collect();
+        for (index, fingerprint) in fingerprints.iter().enumerate() {
+            let mut issue = |severity: IssueSeverity, field: &str, message: String| {
+                issues.push(FingerprintIssue {
+                    name: fingerprint.name.clone(),
+                    field: field.to_string(),
+                    severity,
+                    message,
+                })
+            };
+            let request = &fingerprint.request;
+            let match_rules = &fingerprint.match_rules;
+            if match_rules.is_empty() {
+                issue(
+                    IssueSeverity::Error,
+                    "match_rules",
+                    String::from("no match rules, matches every response"),
+                );
+            }
+            if decode_request_data(&request.request_data).is_err() {
+                issue(
+                    IssueSeverity::Error,
+                    "request_data",
+                    format!("invalid base64 `{}`", request.request_data),
+                );
+            }
+            if request_method(&request.request_method).is_err() {
+                issue(
+                    IssueSeverity::Error,
+                    "request_method",
+                    format!("invalid method `{}`", request.request_method),
+                );
+            }
+            if request.timeout_secs == Some(0) {
+                issue(
+                    IssueSeverity::Error,
+                    "timeout_secs",
+                    String::from("timeout must be greater than 0"),
+                );
+            }
+            if !request.path.starts_with('/') {
+                issue(
+                    IssueSeverity::Error,
+                    "path",
+                    format!("`{}` does not start with `/`", request.path),
+                );
+            }
+            for rule in &match_rules.status_code.0 {
+                let (start, end) = match *rule {
+                    StatusCodeRule::Exact(code) => (code, code),
+                    StatusCodeRule::Range(start, end) => (start, end),
+                };
+                if !(100..=599).contains(&start) || !(100..=599).contains(&end) {
+                    issue(
+                        IssueSeverity::Error,
+                        "status_code",
+                        format!("{} is not an HTTP status code", rule),
+                    );
+                }
+            }
+            for (field, keywords) in [
+                ("keyword", &match_rules.keyword),
+                ("not_keyword", &match_rules.not_keyword),
+            ] {
+                if keywords.iter().any(|k| k.trim().is_empty()) {
+                    issue(
+                        IssueSeverity::Error,
+                        field,
+                        String::from("empty or whitespace keyword"),
+                    );
+                }
+            }
+            for (field, headers) in [
+                ("request_headers", &request.request_headers),
+                ("headers", &match_rules.headers),
+                ("not_headers", &match_rules.not_headers),
+            ] {
+                for name in headers.keys() {
+                    if HeaderName::from_str(name).is_err() {
+                        issue(
+                            IssueSeverity::Error,
+                            field,
+                            format!("invalid header name `{}`", name),
+                        );
+                    }
+                }
+            }
+            // 请求头的值发送时也要合法，匹配规则里的值不用
+            for (name, value) in request.request_headers.iter() {
+                if HeaderName::from_str(name).is_ok() && parse_header(name, value).is_err() {
+                    issue(
+                        IssueSeverity::Error,
+                        "request_headers",
+                        format!("invalid value of `{}`", name),
+                    );
+                }
+            }
+            // 首页请求能识别出来的组件才能作为依赖
+            for name in &fingerprint.requires {
+                if !self
+                    .index
+                    .iter()
+                    .chain(self.favicon.iter())
+                    .any(|f| f.name.eq_ignore_ascii_case(name))
+                {
+                    issue(
+                        IssueSeverity::Warning,
+                        "requires",
+                        format!("no index fingerprint named `{}`, never requested", name),
+                    );
+                }
+            }
+            // 同名同规则的只报告后面出现的
+            if fingerprints[..index]
+                .iter()
+                .any(|f| f.name == fingerprint.name && f.same_rules(fingerprint))
+            {
+                issue(
+                    IssueSeverity::Warning,
+                    "name",
+                    String::from("duplicate of another fingerprint with the same rules"),
+                );
+            }
+        }
+        issues
+    }
+    // 组件指纹，不包括WAF
+    fn components_mut(&mut self) -> impl Iterator<Item = &mut V3WebFingerPrint> {
+        self.index
+            .iter_mut()
+            .chain(self.special.iter_mut())
+            .chain(self.favicon.iter_mut())
+            .chain(self.tcp.iter_mut())
+    }
+    fn build_keyword_matcher(&mut self) {
+        let mut patterns: Vec<String> = vec![];
+        let mut pattern_ids: HashMap<String, usize> = HashMap::new();
+        let mut keyword_id = |keyword: &String| -> usize {
+            let keyword = keyword.to_lowercase();
+            *pattern_ids.entry(keyword.clone()).or_insert_with(|| {
+                patterns.push(keyword);
+                patterns.len() - 1
+            })
+        };
+        for fingerprint in self
+            .index
+            .iter_mut()
+            .chain(self.special.iter_mut())
+            .chain(self.favicon.iter_mut())
+            .chain(self.waf.iter_mut())
+            .chain(self.tcp.iter_mut())
+        {
+            let match_rules = &mut fingerprint.match_rules;
+            match_rules.keyword_ids = match_rules.keyword.iter().map(&mut keyword_id).collect();
+            match_rules.not_keyword_ids = match_rules
+                .not_keyword
+                .iter()
+                .map(&mut keyword_id)
+                .collect();
+        }
+        // 关键词和正文都转成小写再匹配，西里尔、希腊这些非ASCII字母同样忽略大小写
+        self.keyword_matcher = Some(AhoCorasickBuilder::new().build(&patterns));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueSeverity {
+    Error,
+    // 不影响识别，但是应该清理
+    Warning,
+}
+
+/// 指纹库检查发现的一个问题
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FingerprintIssue {
+    pub name: String,
+    // 出问题的字段
+    pub field: String,
+    pub severity: IssueSeverity,
+    pub message: String,
+}
+
+impl fmt::Display for FingerprintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            IssueSeverity::Error => "error",
+            IssueSeverity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}: `{}` {}: {}",
+            severity, self.name, self.field, self.message
+        )
     }
 }
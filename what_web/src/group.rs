@@ -0,0 +1,46 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::WhatWebResult;
+
+/// 按组件反过来列出目标，组件和目标都按名称排序，同一个目标只出现一次，没有识别出组件的目标不在里面
+pub fn group_by_component(results: &[WhatWebResult]) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+    for result in results {
+        for name in result.name.iter() {
+            groups
+                .entry(name.clone())
+                .or_default()
+                .insert(result.url.as_str());
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, urls)| (name, urls.into_iter().map(String::from).collect()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_component() {
+        let result = |url: &str, names: &[&str]| {
+            let mut what_web_result = WhatWebResult::new(url.to_string());
+            what_web_result.name = names.iter().map(|name| name.to_string()).collect();
+            what_web_result
+        };
+        let results = vec![
+            result("http://b.example.com/", &["tomcat", "confluence"]),
+            result("http://a.example.com/", &["tomcat"]),
+            result("http://c.example.com/", &[]),
+            result("http://a.example.com/", &["tomcat"]),
+        ];
+        let groups = group_by_component(&results);
+        assert_eq!(
+            serde_json::to_string(&groups).unwrap(),
+            r#"{"confluence":["http://b.example.com/"],"tomcat":["http://a.example.com/","http://b.example.com/"]}"#
+        );
+        assert!(group_by_component(&[]).is_empty());
+    }
+}
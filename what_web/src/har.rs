@@ -0,0 +1,184 @@
+use std::io::Read;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::WardError;
+use crate::ward::RawData;
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+    // 请求总耗时，毫秒
+    #[serde(default)]
+    time: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarRequest {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    status: u16,
+    #[serde(default, rename = "statusText")]
+    status_text: String,
+    #[serde(default, rename = "httpVersion")]
+    http_version: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    #[serde(default)]
+    content: HarContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct HarContent {
+    #[serde(default)]
+    text: Option<String>,
+    // 二进制内容是`base64`
+    #[serde(default)]
+    encoding: Option<String>,
+}
+
+impl HarEntry {
+    // 被拦截或者没有响应的请求状态码是0，跳过
+    fn into_raw_data(self) -> Option<RawData> {
+        let url = Url::parse(&self.request.url).ok()?;
+        let status_code = StatusCode::from_u16(self.response.status).ok()?;
+        let mut headers = HeaderMap::new();
+        // HTTP/2的`:status`这类伪头不是合法的请求头
+        for header in self.response.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_str(&header.name),
+                HeaderValue::from_str(&header.value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+        let text = self.response.content.text.unwrap_or_default();
+        let body = match self.response.content.encoding.as_deref() {
+            Some("base64") => base64::decode(text.trim()).ok()?,
+            _ => text.into_bytes(),
+        };
+        let mut raw_data = RawData::from_parts(url, status_code, headers, Bytes::from(body));
+        raw_data.version = parse_http_version(&self.response.http_version);
+        // 浏览器记录了原因短语，HTTP/2的是空的
+        if raw_data.version <= reqwest::Version::HTTP_11 && !self.response.status_text.is_empty() {
+            raw_data.set_status_line(format!(
+                "{:?} {} {}",
+                raw_data.version, self.response.status, self.response.status_text
+            ));
+        }
+        if self.time > 0.0 {
+            raw_data.response_time = Duration::from_secs_f64(self.time / 1000.0);
+        }
+        Some(raw_data)
+    }
+}
+
+// 浏览器导出的写法不统一，`HTTP/2`、`h2`和`http/2.0`都有
+fn parse_http_version(version: &str) -> reqwest::Version {
+    match version.to_lowercase().as_str() {
+        "http/0.9" => reqwest::Version::HTTP_09,
+        "http/1.0" => reqwest::Version::HTTP_10,
+        "http/2" | "http/2.0" | "h2" => reqwest::Version::HTTP_2,
+        "http/3" | "http/3.0" | "h3" => reqwest::Version::HTTP_3,
+        _ => reqwest::Version::HTTP_11,
+    }
+}
+
+/// 读取HAR文件里的响应，按文件里的顺序返回，不发送任何请求
+pub fn read_har<R: Read>(reader: R) -> Result<impl Iterator<Item = RawData>, WardError> {
+    let har: Har = serde_json::from_reader(reader)
+        .map_err(|err| WardError::Decode(format!("HAR file: {}", err)))?;
+    Ok(har
+        .log
+        .entries
+        .into_iter()
+        .filter_map(HarEntry::into_raw_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::WebFingerPrint;
+    use crate::har::read_har;
+    use crate::{RequestOption, WhatWeb};
+    use std::collections::HashSet;
+
+    const HAR: &str = r#"{"log":{"version":"1.2","entries":[
+    {"time":35.5,"request":{"method":"GET","url":"https://example.com/static/app.js"},"response":{"status":200,"httpVersion":"h2","headers":[{"name":":status","value":"200"},{"name":"content-type","value":"application/javascript"}],"content":{"text":"var a = 1;"}}},
+    {"time":120,"request":{"method":"GET","url":"https://example.com/"},"response":{"status":200,"httpVersion":"HTTP/2","headers":[{"name":"server","value":"nginx"},{"name":"content-type","value":"text/html; charset=utf-8"}],"content":{"text":"<title>Example</title><p>powered by example-cms</p>"}}},
+    {"time":10,"request":{"method":"GET","url":"https://example.com/favicon.ico"},"response":{"status":200,"httpVersion":"HTTP/2","headers":[{"name":"content-type","value":"image/x-icon"}],"content":{"text":"AAABAGV4YW1wbGUtaWNvbg==","encoding":"base64"}}},
+    {"time":0,"request":{"method":"GET","url":"https://blocked.example.net/"},"response":{"status":0,"headers":[],"content":{}}},
+    {"time":8,"request":{"method":"GET","url":"http://other.example.org:8080/admin"},"response":{"status":404,"statusText":"Not Here","httpVersion":"HTTP/1.1","headers":[],"content":{"text":"not found"}}}
+    ]}}"#;
+
+    #[tokio::test]
+    async fn test_scan_har() {
+        let raw_data: Vec<_> = read_har(HAR.as_bytes()).unwrap().collect();
+        // 没有响应的请求跳过
+        assert_eq!(raw_data.len(), 4);
+        assert_eq!(raw_data[0].version, reqwest::Version::HTTP_2);
+        assert!(raw_data[0].headers.get("content-type").is_some());
+        // HTTP/1.1的原因短语来自statusText
+        assert_eq!(raw_data[0].reason_phrase(), None);
+        assert_eq!(raw_data[3].status_line(), Some("HTTP/1.1 404 Not Here"));
+        assert_eq!(raw_data[3].reason_phrase(), Some("Not Here"));
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"example-cms","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":["powered by example-cms"]},
+            {"name":"example-icon","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["4807c51099c5c9f46276b04a57d994e0"]}]"#,
+        )
+        .unwrap();
+        // 代理地址不可用，识别过程发出请求会失败
+        let request_option = RequestOption::builder()
+            .timeout(1)
+            .proxy("http://127.0.0.1:1")
+            .build()
+            .unwrap();
+        let what_web = WhatWeb::new(request_option, fingerprint).unwrap();
+        let results = what_web.scan_raw_data(raw_data).await;
+        assert_eq!(results.len(), 2);
+        let result = &results[0];
+        assert_eq!(result.url, "https://example.com/");
+        assert_eq!(result.title, "Example");
+        assert_eq!(result.server, "nginx");
+        assert_eq!(result.response_time, 120);
+        assert_eq!(result.peer_addr, None);
+        assert_eq!(
+            result.name,
+            HashSet::from([String::from("example-cms"), String::from("example-icon")])
+        );
+        assert!(result
+            .favicon
+            .contains_key("https://example.com/favicon.ico"));
+        let result = &results[1];
+        assert_eq!(result.url, "http://other.example.org:8080/admin");
+        assert_eq!(result.status_code, 404);
+        assert!(result.name.is_empty());
+        assert!(read_har(&b"{}"[..]).is_err());
+    }
+}
@@ -1,82 +1,602 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::FromIterator;
 use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
 use std::str;
-use std::sync::Arc;
-use std::{fmt, process};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use url::Url;
 
-use fingerprint::{WebFingerPrintLib, WebFingerPrintRequest};
-use request::{get_title, index_fetch};
+use banner::{detect_banner, printable_banner};
+pub use cache::ScanCache;
+use dns::DnsResolver;
+pub use dns::DnsServer;
+pub use error::WardError;
+pub use favicon_db::{read_favicon_db, FaviconDbCategory, FaviconDbEntry};
+use fingerprint::{V3WebFingerPrint, WebFingerPrintLib, WebFingerPrintRequest};
+use futures::future;
+use futures::stream::{self, Stream, StreamExt};
+pub use group::group_by_component;
+pub use har::read_har;
+pub use limit::BudgetExhausted;
+use limit::HostLimiter;
+pub use nuclei::{to_nuclei_tags, NucleiTags};
+use observer::HttpHook;
+#[allow(deprecated)]
+pub use observer::{debug_observer, DebugObserver};
+pub use observer::{HttpRequest, HttpResponse, NoopObserver, ScanObserver};
+pub use option::{RequestOptionBuilder, RequestOptionError};
+pub use phash::dhash_distance;
+use phash::{deserialize_dhash, serialize_dhash};
+use preview::preview_target;
+pub use preview::{ProbeStage, RequestPreview};
+use proxy::ProxyMonitor;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use request::{
+    ascii_host, build_client, expand_target, grab_banner, index_fetch, normalize_target,
+    shiro_request, split_credentials, unicode_host,
+};
+pub use request::{
+    AuthScheme, BodyHash, CertInfo, FaviconHash, FetchError, FetchErrorKind, Hsts, HttpVersion,
+    TlsVerification, Unreachable,
+};
+use reqwest::header::{HeaderName, HeaderValue, SERVER, STRICT_TRANSPORT_SECURITY};
+pub use robots::Robots;
+use robots::{request_path, robots_request, robots_text};
 use serde::{de, Deserialize, Deserializer, Serialize};
-use ward::check;
+use simhash::simhash;
+pub use simhash::{cluster_by_simhash, SimHashCluster};
+pub use stats::{ScanCounters, ScanStats};
+pub use target::{read_scan_output, read_targets, ScanTarget};
+use waf::detect_waf;
+use ward::{check, FingerprintMatch};
+pub use ward::{
+    extract_snippet, JumpSource, MatchEvidence, MatchedRule, RawData, RedirectJump, RedirectPolicy,
+    SetCookie,
+};
 
 use crate::fingerprint::WebFingerPrint;
 
+mod banner;
+mod cache;
+mod dns;
+mod error;
+pub mod extract;
+mod favicon_db;
 pub mod fingerprint;
+mod group;
+mod har;
+mod limit;
+mod nuclei;
+mod observer;
+mod option;
+mod phash;
+mod preview;
+mod proxy;
 mod request;
+mod robots;
+mod simhash;
+mod stats;
+mod target;
+mod waf;
 mod ward;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WhatWebResult {
     pub url: String,
+    // 国际化域名的Unicode形式，`url`里是请求用的punycode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idn_host: Option<String>,
     #[serde(deserialize_with = "string_to_hashset")]
     pub name: HashSet<String>,
     pub priority: u32,
+    // 首页解码后正文的字节数
     pub length: usize,
+    // 首页原始响应的字节数，相同的长度多半是同一个默认页面
+    #[serde(default)]
+    pub body_length: usize,
+    // 首页原始响应的md5和sha256，用来给还没有指纹的设备归类
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_hash: Option<BodyHash>,
+    // 首页正文的SimHash，相似的页面只差几位，用来给没有识别出来的目标聚类
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_dhash",
+        deserialize_with = "deserialize_dhash"
+    )]
+    pub simhash: Option<u64>,
     pub title: String,
+    // 首页`<meta name="generator">`的内容
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub generator: Vec<String>,
+    // 首页引用的脚本和样式的路径
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub asset_paths: Vec<String>,
     pub status_code: u16,
     #[serde(default)]
     pub is_web: bool,
     #[serde(default)]
     pub plugins: HashSet<String>,
+    // 组件名称到提取的版本号
+    #[serde(default)]
+    pub version: HashMap<String, HashSet<String>>,
+    #[serde(default)]
+    pub redirect_chain: Vec<RedirectJump>,
+    // 跳转策略不允许跟随的跳转
+    #[serde(default)]
+    pub skipped_redirect: Option<RedirectJump>,
+    // 首页响应的HTTP协议版本
+    #[serde(default)]
+    pub http_version: String,
+    // 首页响应状态行里的原因短语，只在有指纹用到时记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason_phrase: Option<String>,
+    // 目标前面的WAF或者CDN
+    #[serde(default)]
+    pub waf: Option<String>,
+    // 在WAF拦截页面上匹配到的组件，可能是误报
+    #[serde(default)]
+    pub low_confidence: HashSet<String>,
+    // 组件名称到命中指纹的优先级
+    #[serde(default)]
+    pub matched: HashMap<String, u32>,
+    // 组件名称到可信度，0到100，同一个组件取最高的
+    #[serde(default)]
+    pub confidence: HashMap<String, u32>,
+    // 组件名称到命中的规则，开启`verbose_matches`时才记录
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub evidence: HashMap<String, Vec<MatchEvidence>>,
+    // favicon的URL到Hash
+    #[serde(default)]
+    pub favicon: HashMap<String, FaviconHash>,
+    #[serde(default)]
+    pub server: String,
+    #[serde(default)]
+    pub cert: Option<CertInfo>,
+    // 首页响应里第一个Strict-Transport-Security
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hsts: Option<Hsts>,
+    // 目标无法访问时的错误信息
+    #[serde(default)]
+    pub error: Option<String>,
+    // 不是HTTP的服务主动发送的banner，控制字符换成了`.`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banner: Option<String>,
+    // 首页每个协议请求失败的原因，部分协议能访问时也记录
+    #[serde(default)]
+    pub errors: Vec<FetchError>,
+    // 首页响应耗时，毫秒
+    #[serde(default)]
+    pub response_time: u64,
+    // 主机的请求数用完后跳过的特殊请求数
+    #[serde(default)]
+    pub skipped_probes: u32,
+    // 开启polite时路径被robots.txt禁止而没有发送的特殊请求数
+    #[serde(default)]
+    pub disallowed_probes: u32,
+    // 超过`target_deadline`后没有扫描完，结果里只有之前完成的请求
+    #[serde(default)]
+    pub deadline_exceeded: bool,
+    // 首页拿到响应的协议，按请求的顺序
+    #[serde(default)]
+    pub schemes: Vec<String>,
+    // `--resolve`指定的IP，请求发到这个IP，Host和SNI还是域名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_ip: Option<IpAddr>,
+    // 首页响应实际连接的IP和端口，经过代理时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_addr: Option<SocketAddr>,
     #[serde(skip)]
     pub template_result: Vec<TemplateResult>,
+    // 扫描时使用的目标，跳转后`url`会变
+    #[serde(skip)]
+    pub target: String,
 }
 
 impl WhatWebResult {
     pub fn new(url: String) -> Self {
         Self {
+            target: url.clone(),
             url,
+            idn_host: None,
             name: HashSet::new(),
             priority: 0,
             length: 0,
+            body_length: 0,
+            body_hash: None,
+            simhash: None,
             status_code: 0,
             title: String::new(),
+            generator: vec![],
+            asset_paths: vec![],
             plugins: HashSet::new(),
+            version: HashMap::new(),
+            redirect_chain: vec![],
+            skipped_redirect: None,
+            http_version: String::new(),
+            reason_phrase: None,
+            waf: None,
+            low_confidence: HashSet::new(),
+            matched: HashMap::new(),
+            confidence: HashMap::new(),
+            evidence: HashMap::new(),
+            favicon: HashMap::new(),
+            server: String::new(),
+            cert: None,
+            hsts: None,
+            error: None,
+            banner: None,
+            errors: vec![],
+            response_time: 0,
+            skipped_probes: 0,
+            disallowed_probes: 0,
+            deadline_exceeded: false,
+            schemes: vec![],
+            resolved_ip: None,
+            peer_addr: None,
             template_result: vec![],
             is_web: true,
         }
     }
+    /// 显示用的URL，国际化域名换成Unicode形式
+    pub fn display_url(&self) -> String {
+        match &self.idn_host {
+            Some(idn_host) => self.url.replacen(&ascii_host(idn_host), idn_host, 1),
+            None => self.url.clone(),
+        }
+    }
+    // 匹配到太多组件的是蜜罐，只保留蜜罐的标记
+    fn set_names(&mut self, mut name: HashSet<String>) {
+        if name.len() > 10 {
+            let count = name.len();
+            name.clear();
+            name.insert(format!("Honeypot 蜜罐{}", count));
+            self.matched.clear();
+            self.confidence.clear();
+            self.evidence.clear();
+            self.version.clear();
+            self.low_confidence.clear();
+        }
+        self.name = name;
+    }
+    fn insert_matches(
+        &mut self,
+        name: &mut HashSet<String>,
+        matches: Vec<FingerprintMatch>,
+        verbose_matches: bool,
+    ) {
+        for m in matches {
+            if verbose_matches {
+                let evidence = self.evidence.entry(m.name.clone()).or_default();
+                if !evidence.contains(&m.evidence) {
+                    evidence.push(m.evidence);
+                }
+            }
+            if let Some(version) = m.version {
+                self.version
+                    .entry(m.name.clone())
+                    .or_default()
+                    .insert(version);
+            }
+            let priority = self.matched.entry(m.name.clone()).or_default();
+            *priority = (*priority).max(m.priority);
+            let confidence = self.confidence.entry(m.name.clone()).or_default();
+            *confidence = (*confidence).max(m.confidence);
+            name.insert(m.name);
+            self.priority = m.priority;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RequestOption {
     timeout: u64,
-    pub proxy: Option<Url>,
+    proxy: Option<Url>,
+    // 只发给http(s)代理的请求头
+    proxy_headers: Vec<(String, String)>,
+    // 单独发送一次带rememberMe的请求识别Shiro，默认关闭，避免被WAF拦截
+    shiro_detect: bool,
+    // 连接成功但不是HTTP的目标读取服务主动发送的banner，用tcp指纹识别
+    tcp_banner: bool,
+    // 请求和目标不在同一个主机上的图标链接，默认跳过
+    cross_host_favicon: bool,
+    // 获取证书时再按系统的根证书校验一次，结果写进证书信息，不影响扫描
+    verify_tls: bool,
+    // 可信度低于这个值的匹配结果丢弃，过滤只命中一个通用关键词的指纹
+    min_confidence: u32,
+    // 结果里带上每个组件命中的具体规则
+    verbose_matches: bool,
+    user_agent: Option<String>,
+    // 合并到默认请求头，指纹自带的请求头优先
+    custom_headers: Vec<(String, String)>,
+    // 目标自己的请求头，例如会话Cookie，和认证信息一样只发给目标的主机
+    target_headers: Vec<(String, String)>,
+    // 认证信息和目标的请求头只发给这个主机，跳转和图标请求到别的主机时不带
+    credential_host: Option<String>,
+    // 连接失败或者超时后的重试次数和间隔，每次重试间隔翻倍
+    retries: u8,
+    retry_interval_ms: u64,
+    // 单个目标所有请求加起来的时间上限，超过后停止扫描，返回已经拿到的结果
+    target_deadline: Option<Duration>,
+    // 最多读取的响应大小，超过的部分丢弃
+    max_body_size: usize,
+    // 首页跟随跳转的范围
+    redirect_policy: RedirectPolicy,
+    // 没有写端口的目标展开到这些端口，每个端口一个结果
+    ports: Vec<u16>,
+    // 没有写协议的目标https和http都请求，默认第一个协议能正常访问时跳过另一个
+    all_schemes: bool,
+    // 每个主机每秒最多的请求数和总请求数，用完后跳过剩下的特殊请求
+    requests_per_second: Option<f32>,
+    max_requests_per_host: Option<u32>,
+    // 打乱特殊请求的顺序，同一个主机的相邻请求之间随机等待这个范围内的毫秒数
+    shuffle_probes: bool,
+    // 遵守robots.txt，跳过路径被禁止的特殊请求
+    polite: bool,
+    // 连续这么多个目标连接失败而且代理本身也连不上时认为代理断开，0表示不检查
+    proxy_failure_threshold: u32,
+    // 代理断开后剩下的目标直接连接，默认不再扫描
+    proxy_fallback: bool,
+    jitter_ms: Option<(u64, u64)>,
+    // 所有请求带上的认证信息，目标里写了用户名密码的用目标的
+    auth: Option<AuthScheme>,
+    http_version: HttpVersion,
+    // 域名到指定的IP，类似curl的`--resolve`，端口还是目标里的
+    resolve: HashMap<String, IpAddr>,
+    // 用指定的DNS服务器解析目标，没有时用系统的
+    dns: Option<Arc<DnsResolver>>,
+    // 同一个配置共用一个客户端，复用连接
+    client: reqwest::Client,
+    // 克隆出来的配置共用同一个缓存
+    cache: Arc<ScanCache>,
+    counters: Arc<ScanCounters>,
+    limiter: Arc<HostLimiter>,
+    proxy_monitor: Arc<ProxyMonitor>,
+    // 打乱顺序和随机延时用的随机数，可以指定种子复现
+    rng: Arc<Mutex<StdRng>>,
+    // 扫描一个目标时设置，把实际发送的请求和收到的响应交给观察者
+    http_hook: Option<HttpHook>,
+}
+
+impl Default for RequestOption {
+    // 默认配置不会校验失败
+    fn default() -> Self {
+        Self::builder()
+            .build()
+            .expect("default RequestOption is valid")
+    }
 }
 
 impl RequestOption {
+    pub fn builder() -> RequestOptionBuilder {
+        RequestOptionBuilder::default()
+    }
+    // 兼容旧接口，不会panic：不合法的超时和代理换成默认值并打印警告，
+    // 需要处理错误的用`try_new`或者`RequestOption::builder()`
+    #[deprecated(note = "use `RequestOption::builder()` instead")]
     pub fn new(timeout: &u64, proxy: &str) -> Self {
-        if !proxy.is_empty() {
-            match Url::parse(proxy) {
-                Ok(u) => {
-                    let proxy_url = Some(u);
-                    Self {
-                        timeout: *timeout,
-                        proxy: proxy_url,
-                    }
-                }
-                Err(err) => {
-                    println!("Invalid Proxy Uri {}", err);
-                    process::exit(0);
-                }
+        let timeout = match Self::builder().timeout(*timeout).build() {
+            Ok(_) => *timeout,
+            Err(err) => {
+                log::warn!("{}, using the default timeout", err);
+                option::DEFAULT_TIMEOUT
             }
-        } else {
-            Self {
-                timeout: *timeout,
-                proxy: None,
+        };
+        match Self::builder().timeout(timeout).proxy(proxy).build() {
+            Ok(request_option) => request_option,
+            Err(err) => {
+                log::warn!("{}, scanning without a proxy", err);
+                Self::builder().timeout(timeout).build().unwrap_or_default()
+            }
+        }
+    }
+    #[deprecated(note = "use `RequestOption::builder()` instead")]
+    pub fn try_new(timeout: &u64, proxy: &str) -> Result<Self, WardError> {
+        Ok(Self::builder().timeout(*timeout).proxy(proxy).build()?)
+    }
+    pub fn timeout(&self) -> u64 {
+        self.timeout
+    }
+    pub fn http_version(&self) -> HttpVersion {
+        self.http_version
+    }
+    // 下面是构建时确定的配置，只读，需要不同的配置时用`RequestOption::builder()`重新构建。
+    // 克隆出来的配置共用缓存、计数和随机数
+    pub fn proxy(&self) -> Option<&Url> {
+        self.proxy.as_ref()
+    }
+    pub fn shiro_detect(&self) -> bool {
+        self.shiro_detect
+    }
+    pub fn tcp_banner(&self) -> bool {
+        self.tcp_banner
+    }
+    pub fn cross_host_favicon(&self) -> bool {
+        self.cross_host_favicon
+    }
+    pub fn verify_tls(&self) -> bool {
+        self.verify_tls
+    }
+    pub fn min_confidence(&self) -> u32 {
+        self.min_confidence
+    }
+    pub fn verbose_matches(&self) -> bool {
+        self.verbose_matches
+    }
+    pub fn retries(&self) -> u8 {
+        self.retries
+    }
+    pub fn retry_interval_ms(&self) -> u64 {
+        self.retry_interval_ms
+    }
+    pub fn target_deadline(&self) -> Option<Duration> {
+        self.target_deadline
+    }
+    pub fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+    pub fn redirect_policy(&self) -> RedirectPolicy {
+        self.redirect_policy
+    }
+    pub fn ports(&self) -> &[u16] {
+        &self.ports
+    }
+    pub fn all_schemes(&self) -> bool {
+        self.all_schemes
+    }
+    pub fn requests_per_second(&self) -> Option<f32> {
+        self.requests_per_second
+    }
+    pub fn max_requests_per_host(&self) -> Option<u32> {
+        self.max_requests_per_host
+    }
+    pub fn shuffle_probes(&self) -> bool {
+        self.shuffle_probes
+    }
+    pub fn polite(&self) -> bool {
+        self.polite
+    }
+    pub fn proxy_failure_threshold(&self) -> u32 {
+        self.proxy_failure_threshold
+    }
+    pub fn proxy_fallback(&self) -> bool {
+        self.proxy_fallback
+    }
+    pub fn jitter_ms(&self) -> Option<(u64, u64)> {
+        self.jitter_ms
+    }
+    pub fn auth(&self) -> Option<&AuthScheme> {
+        self.auth.as_ref()
+    }
+    /// 配置了代理而且检测到代理已经断开
+    pub fn is_proxy_down(&self) -> bool {
+        self.proxy.is_some() && self.proxy_monitor.is_down()
+    }
+    // 代理断开后直接连接的配置，客户端只创建一次
+    fn without_proxy(&self) -> Result<RequestOption, WardError> {
+        let client = self.proxy_monitor.direct_client.get_or_try_init(|| {
+            build_client(
+                self.timeout,
+                None,
+                &[],
+                self.http_version,
+                &self.resolve,
+                self.dns.as_ref(),
+            )
+        })?;
+        let mut config = self.clone();
+        config.client = client.clone();
+        config.proxy = None;
+        Ok(config)
+    }
+    pub fn resolved_ip(&self, host: &str) -> Option<IpAddr> {
+        self.resolve.get(&ascii_host(host)).copied()
+    }
+    pub fn dns_server(&self) -> Option<&DnsServer> {
+        self.dns.as_ref().map(|dns| dns.server())
+    }
+    // 影响请求内容和连接方式的配置，共用缓存的不同配置不能拿到彼此的结果
+    pub(crate) fn cache_key(&self) -> String {
+        let mut resolve: Vec<_> = self.resolve.iter().collect();
+        resolve.sort_unstable();
+        format!(
+            "{:?}{:?}{:?}{}{}{:?}{:?}{:?}{:?}{:?}{:?}{:?}",
+            self.proxy.as_ref().map(Url::as_str),
+            self.dns_server().map(ToString::to_string),
+            self.http_version,
+            self.all_schemes,
+            self.max_body_size,
+            self.redirect_policy,
+            resolve,
+            self.user_agent,
+            self.auth.as_ref().map(|a| a.header_value()),
+            self.custom_headers,
+            self.target_headers,
+            self.proxy_headers,
+        )
+    }
+    // 连接这个主机用的IP，`--resolve`指定的优先，没有自定义DNS或者由代理解析时返回空
+    pub(crate) async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, WardError> {
+        if let Some(ip) = self.resolved_ip(host) {
+            return Ok(vec![ip]);
+        }
+        let dns = match &self.dns {
+            Some(dns) => dns,
+            None => return Ok(vec![]),
+        };
+        let is_ip = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok();
+        if host.is_empty() || is_ip {
+            return Ok(vec![]);
+        }
+        dns.lookup(host).await
+    }
+    // 这个主机还能发送的请求数，没有限制时返回None
+    pub fn remaining_requests(&self, host: &str) -> Option<u32> {
+        self.limiter.remaining(host, self.max_requests_per_host)
+    }
+    // 重新统计每个主机的请求数
+    pub fn reset_request_budget(&self) {
+        self.limiter.reset();
+    }
+    // 同一个主机的下一个请求至少要等的时间，取速率限制和随机延时里大的
+    pub(crate) fn request_interval(&self) -> Duration {
+        let rate = self
+            .requests_per_second
+            .filter(|rps| rps.is_finite() && *rps > 0.0)
+            .and_then(|rps| Duration::try_from_secs_f32(1.0 / rps).ok())
+            .unwrap_or_default();
+        let jitter = match self.jitter_ms {
+            Some((min, max)) if max > 0 => {
+                let mut rng = self.rng.lock().unwrap_or_else(|err| err.into_inner());
+                Duration::from_millis(rng.gen_range(min.min(max)..=max))
             }
+            _ => Duration::ZERO,
+        };
+        rate.max(jitter)
+    }
+    pub(crate) fn shuffle<T>(&self, items: &mut [T]) {
+        let mut rng = self.rng.lock().unwrap_or_else(|err| err.into_inner());
+        items.shuffle(&mut *rng);
+    }
+    pub fn cache(&self) -> &ScanCache {
+        &self.cache
+    }
+    // 所有克隆出来的配置发送的请求数和下载的字节数
+    pub fn counters(&self) -> &ScanCounters {
+        &self.counters
+    }
+    // 只发给目标主机的请求头
+    pub(crate) fn add_target_header(&mut self, name: &str, value: &str) -> Result<(), WardError> {
+        HeaderName::from_str(name)?;
+        HeaderValue::from_str(value)?;
+        self.target_headers
+            .push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+    // 认证信息和目标的请求头限定在目标的主机上，目标没有协议时按http解析
+    pub(crate) fn scope_credentials(&mut self, target: &str) {
+        let url = Url::parse(target)
+            .ok()
+            .filter(|url| url.has_host())
+            .or_else(|| Url::parse(&format!("http://{}", target)).ok());
+        self.credential_host = url.and_then(|url| url.host_str().map(str::to_lowercase));
+    }
+    // 请求的主机和目标的一样时才带上认证信息，没有限定主机时都带
+    pub(crate) fn sends_credentials_to(&self, url: &Url) -> bool {
+        match &self.credential_host {
+            Some(host) => url
+                .host_str()
+                .is_some_and(|url_host| url_host.eq_ignore_ascii_case(host)),
+            None => true,
         }
     }
 }
@@ -88,32 +608,221 @@ pub struct WhatWeb {
 }
 
 impl WhatWeb {
-    pub fn new(config: RequestOption, web_fingerprint: Vec<WebFingerPrint>) -> Self {
-        let fingerprint: Arc<WebFingerPrintLib> = Arc::new(WebFingerPrintLib::new(web_fingerprint));
-        Self {
+    pub fn new(
+        config: RequestOption,
+        web_fingerprint: Vec<WebFingerPrint>,
+    ) -> Result<Self, WardError> {
+        let fingerprint: Arc<WebFingerPrintLib> =
+            Arc::new(WebFingerPrintLib::new(web_fingerprint)?);
+        Ok(Self {
             fingerprint,
             config,
+        })
+    }
+    pub fn from_fingerprint_lib(config: RequestOption, fingerprint: WebFingerPrintLib) -> Self {
+        Self {
+            fingerprint: Arc::new(fingerprint),
+            config,
+        }
+    }
+    // 再次扫描同一个目标时重新请求
+    pub fn clear_cache(&self) {
+        self.config.cache().clear();
+    }
+    pub fn counters(&self) -> &ScanCounters {
+        self.config.counters()
+    }
+    // 开始新的一次扫描时调用，每个主机的请求数重新计算
+    pub fn reset_request_budget(&self) {
+        self.config.reset_request_budget();
+    }
+    // 扫描过程通过`log`输出：逐个指纹的匹配是trace，响应内容是debug，匹配结果是info，请求失败是warn
+    pub async fn scan<T: Into<ScanTarget>>(&self, target: T) -> WhatWebResult {
+        self.scan_with_observer(target, Arc::new(NoopObserver))
+            .await
+    }
+    /// 旧的扫描入口，`debug`为`true`时打印收到的响应和匹配上的指纹
+    #[deprecated(note = "use `scan`, scan details are logged through `log`")]
+    #[allow(deprecated)]
+    pub async fn scan_with_debug(&self, url: String, debug: bool) -> WhatWebResult {
+        self.scan_with_observer(url, debug_observer(debug)).await
+    }
+    // 扫描过程中的事件交给观察者处理
+    pub async fn scan_with_observer<T: Into<ScanTarget>>(
+        &self,
+        target: T,
+        observer: Arc<dyn ScanObserver>,
+    ) -> WhatWebResult {
+        let ScanTarget { url, headers } = target.into();
+        observer.target_started(&url);
+        let mut config = self.config.clone();
+        config.http_hook = Some(HttpHook::new(&url, observer.clone()));
+        for (name, value) in headers.iter() {
+            if let Err(err) = config.add_target_header(name, value) {
+                let what_web_result = errored_result(url, err, observer.as_ref());
+                observer.target_finished(&what_web_result);
+                return what_web_result;
+            }
+        }
+        if let Some(proxy) = &self.config.proxy {
+            let timeout = Duration::from_secs(self.config.timeout);
+            self.config.proxy_monitor.recheck(proxy, timeout).await;
         }
+        let mut what_web_result = match &self.config.proxy {
+            Some(proxy) if self.config.is_proxy_down() => {
+                self.scan_without_proxy(url, proxy, config, observer.as_ref())
+                    .await
+            }
+            Some(proxy) => {
+                let what_web_result = self.scan_target(url, config, observer.as_ref()).await;
+                let timeout = Duration::from_secs(self.config.timeout);
+                self.config
+                    .proxy_monitor
+                    .observe(
+                        &what_web_result,
+                        proxy,
+                        self.config.proxy_failure_threshold,
+                        timeout,
+                    )
+                    .await;
+                what_web_result
+            }
+            None => self.scan_target(url, config, observer.as_ref()).await,
+        };
+        what_web_result.idn_host = unicode_host(&what_web_result.url);
+        observer.target_finished(&what_web_result);
+        what_web_result
     }
-    pub async fn scan(&self, url: String, debug: bool) -> WhatWebResult {
+    // 代理断开后允许直连时不走代理扫描，否则不发送请求直接返回错误
+    async fn scan_without_proxy(
+        &self,
+        url: String,
+        proxy: &Url,
+        config: RequestOption,
+        observer: &dyn ScanObserver,
+    ) -> WhatWebResult {
+        let err = if config.proxy_fallback {
+            match config.without_proxy() {
+                Ok(config) => return self.scan_target(url, config, observer).await,
+                Err(err) => err,
+            }
+        } else {
+            WardError::ProxyDown(proxy.to_string())
+        };
+        errored_result(url, err, observer)
+    }
+    async fn scan_target(
+        &self,
+        url: String,
+        mut config: RequestOption,
+        observer: &dyn ScanObserver,
+    ) -> WhatWebResult {
+        // 目标里的用户名密码对这个目标的所有请求生效，不出现在结果里
+        let (url, auth) = split_credentials(&normalize_target(&url));
+        if auth.is_some() {
+            config.auth = auth;
+        }
+        config.scope_credentials(&url);
         let mut name: HashSet<String> = HashSet::new();
         let mut what_web_result: WhatWebResult = WhatWebResult::new(url.clone());
-        let default_request = WebFingerPrintRequest {
-            path: String::from("/"),
-            request_method: String::from("get"),
-            request_headers: Default::default(),
-            request_data: String::new(),
-        };
-        if let Ok(rdl) = index_fetch(&url, &default_request, true, self.config.clone()).await {
-            if rdl.is_empty() {
+        let deadline = config.target_deadline;
+        let scan = self.scan_steps(
+            url.clone(),
+            config,
+            observer,
+            &mut what_web_result,
+            &mut name,
+        );
+        match deadline {
+            Some(deadline) => {
+                // 超时后丢弃没有完成的请求，已经写进结果的匹配保留
+                if tokio::time::timeout(deadline, scan).await.is_err() {
+                    log::warn!("`{}` did not finish within {:?}", url, deadline);
+                    what_web_result.deadline_exceeded = true;
+                    // 首页也没有拿到响应
+                    if what_web_result.status_code == 0 && what_web_result.error.is_none() {
+                        let err = WardError::TargetDeadline {
+                            url: url.clone(),
+                            deadline,
+                        };
+                        observer.target_errored(&url, &err);
+                        what_web_result.is_web = false;
+                        what_web_result.error = Some(err.to_string());
+                    }
+                }
+            }
+            None => scan.await,
+        }
+        what_web_result.set_names(name);
+        what_web_result
+    }
+    // 按顺序发送首页、robots.txt、状态行、Shiro和特殊请求，识别结果逐步写进`what_web_result`
+    async fn scan_steps(
+        &self,
+        url: String,
+        config: RequestOption,
+        observer: &dyn ScanObserver,
+        what_web_result: &mut WhatWebResult,
+        name: &mut HashSet<String>,
+    ) {
+        let default_request = WebFingerPrintRequest::default();
+        observer.request_sent(&url, &default_request);
+        let index_result = index_fetch(&url, &default_request, true, config.clone()).await;
+        if let Err(err) = &index_result {
+            log::warn!("{}", err);
+            observer.target_errored(&url, err);
+            what_web_result.is_web = false;
+            what_web_result.error = Some(err.to_string());
+            if let WardError::Unreachable(unreachable) = err {
+                what_web_result.errors = unreachable.errors.clone();
+                if config.tcp_banner && unreachable.accepted_connection() {
+                    let matches = self
+                        .banner_matches(unreachable, &config, what_web_result, observer)
+                        .await;
+                    what_web_result.insert_matches(name, matches, config.verbose_matches);
+                }
+            }
+        }
+        let mut robots = None;
+        if let Ok(fetch_result) = index_result {
+            if fetch_result.raw_data.is_empty() {
                 what_web_result.is_web = false;
             }
+            what_web_result.errors = fetch_result.errors;
+            // 首页的匹配要用到robots.txt，先于首页的识别请求
+            if config.polite || self.fingerprint.has_robots_rules() {
+                if let Some(index) = fetch_result.raw_data.last() {
+                    if let Some(text) = self.fetch_robots(&index.url, &config, observer).await {
+                        for raw_data in fetch_result.raw_data.iter() {
+                            raw_data.set_robots(text.clone());
+                        }
+                        robots = Some(Robots::parse(&text));
+                    }
+                }
+            }
             //首页请求允许跳转
-            for raw_data in rdl {
-                let web_name_set = check(&raw_data, &self.fingerprint.to_owned(), debug).await;
-                for (k, v) in web_name_set {
-                    name.insert(k);
-                    what_web_result.priority = v;
+            for raw_data in fetch_result.raw_data {
+                if !what_web_result.schemes.contains(&raw_data.attempt_scheme) {
+                    what_web_result
+                        .schemes
+                        .push(raw_data.attempt_scheme.clone());
+                }
+                let web_name_set = self.check(&raw_data, observer).await;
+                if let Some(waf) = detect_waf(&raw_data, &self.fingerprint) {
+                    if waf.blocked {
+                        what_web_result
+                            .low_confidence
+                            .extend(web_name_set.iter().map(|m| m.name.clone()));
+                    }
+                    what_web_result.waf.get_or_insert(waf.name);
+                }
+                what_web_result.insert_matches(name, web_name_set, config.verbose_matches);
+                what_web_result.favicon.extend(raw_data.favicon.clone());
+                if what_web_result.hsts.is_none() {
+                    what_web_result.hsts = hsts(&raw_data);
+                }
+                if raw_data.skipped_jump.is_some() {
+                    what_web_result.skipped_redirect = raw_data.skipped_jump.clone();
                 }
                 if url.starts_with("http://") || url.starts_with("https://") {
                     // 本来有的协议
@@ -124,15 +833,38 @@ impl WhatWeb {
                 } else {
                     what_web_result.url = raw_data.url.as_str().to_string();
                 }
-                what_web_result.length = raw_data.text.len();
+                what_web_result.length = raw_data.text_length();
+                what_web_result.body_length = raw_data.body_length();
+                what_web_result.body_hash = raw_data.body_hash.clone();
+                what_web_result.simhash = simhash(&raw_data.text);
                 if what_web_result.status_code == 0 || raw_data.status_code.is_success() {
                     what_web_result.status_code = raw_data.status_code.as_u16();
                 }
                 if (raw_data.next_url.is_none() && what_web_result.title.is_empty())
                     || raw_data.status_code.is_success()
                 {
-                    what_web_result.title = get_title(&raw_data.text);
+                    what_web_result.title = raw_data.title.clone();
+                    what_web_result.generator = raw_data.generator.clone();
+                    what_web_result.asset_paths = raw_data.asset_paths.clone();
                     what_web_result.url = raw_data.url.as_str().to_string();
+                    what_web_result.redirect_chain = raw_data.redirect_chain.clone();
+                    what_web_result.server = raw_data
+                        .headers
+                        .get(SERVER)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string();
+                    what_web_result.cert = raw_data.cert.clone();
+                    what_web_result.response_time = raw_data.response_time.as_millis() as u64;
+                    what_web_result.http_version = format!("{:?}", raw_data.version);
+                    if self.fingerprint.has_reason_phrase_rules() {
+                        what_web_result.reason_phrase = raw_data.reason_phrase().map(String::from);
+                    }
+                    what_web_result.resolved_ip = raw_data
+                        .url
+                        .host_str()
+                        .and_then(|host| config.resolved_ip(host));
+                    what_web_result.peer_addr = raw_data.peer_addr;
                     what_web_result.priority += 1;
                 }
                 if raw_data.status_code.is_success() {
@@ -142,33 +874,284 @@ impl WhatWeb {
         };
         // 在首页请求时不是Web也没必要跑特殊请求了
         if !what_web_result.is_web {
-            return what_web_result;
+            // banner识别出来的服务
+            return;
+        }
+        let host = Url::parse(&what_web_result.url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+        if config.shiro_detect && config.remaining_requests(&host) == Some(0) {
+            what_web_result.skipped_probes += 1;
+        } else if config.shiro_detect {
+            let shiro_request = shiro_request();
+            observer.request_sent(&what_web_result.url, &shiro_request);
+            match index_fetch(&what_web_result.url, &shiro_request, false, config.clone()).await {
+                Ok(fetch_result) => {
+                    for raw_data in fetch_result.raw_data {
+                        let web_name_set = self.check(&raw_data, observer).await;
+                        what_web_result.insert_matches(name, web_name_set, config.verbose_matches);
+                    }
+                }
+                Err(err) => log::warn!("{}", err),
+            }
+        }
+        let fingerprint = self.fingerprint.clone();
+        let mut special: Vec<&V3WebFingerPrint> = fingerprint.special.iter().collect();
+        if config.shuffle_probes {
+            config.shuffle(&mut special);
         }
-        for special_wfp in self.fingerprint.to_owned().special.iter() {
-            if let Ok(rdl) = index_fetch(
+        for special_wfp in special {
+            // 依赖的组件在首页没有识别出来，不用发送
+            if !special_wfp.requires.is_empty()
+                && !special_wfp
+                    .requires
+                    .iter()
+                    .any(|r| name.iter().any(|n| n.eq_ignore_ascii_case(r)))
+            {
+                continue;
+            }
+            if config.polite
+                && robots.as_ref().is_some_and(|robots| {
+                    !robots.is_allowed(&request_path(&what_web_result.url, &special_wfp.request))
+                })
+            {
+                what_web_result.disallowed_probes += 1;
+                continue;
+            }
+            if config.remaining_requests(&host) == Some(0) {
+                what_web_result.skipped_probes += 1;
+                continue;
+            }
+            observer.request_sent(&what_web_result.url, &special_wfp.request);
+            match index_fetch(
                 &what_web_result.url,
                 &special_wfp.request,
                 false,
-                self.config.clone(),
+                config.clone(),
             )
             .await
             {
-                for raw_data in rdl {
-                    let web_name_set = check(&raw_data, &self.fingerprint.to_owned(), debug).await;
-                    for (k, v) in web_name_set {
-                        name.insert(k);
-                        what_web_result.priority = v;
+                Ok(fetch_result) => {
+                    for raw_data in fetch_result.raw_data {
+                        let web_name_set = self.check(&raw_data, observer).await;
+                        what_web_result.insert_matches(name, web_name_set, config.verbose_matches);
                     }
                 }
+                Err(err) => log::warn!("{}", err),
             }
         }
-        if name.len() > 10 {
-            let count = name.len();
-            name.clear();
-            name.insert(format!("Honeypot 蜜罐{}", count));
+        if what_web_result.skipped_probes > 0 {
+            log::warn!(
+                "`{}` request budget exhausted, skipped {} probes",
+                host,
+                what_web_result.skipped_probes
+            );
         }
-        what_web_result.name = name.clone();
-        what_web_result
+        if what_web_result.disallowed_probes > 0 {
+            log::info!(
+                "`{}` robots.txt disallowed {} probes",
+                host,
+                what_web_result.disallowed_probes
+            );
+        }
+    }
+    /// 离线识别保存下来的响应，同一个源（协议、主机和端口）的响应合并成一个结果，
+    /// 路径是`/`的响应作为首页，没有的话用第一个
+    pub async fn scan_raw_data<I>(&self, raw_data: I) -> Vec<WhatWebResult>
+    where
+        I: IntoIterator<Item = RawData>,
+    {
+        let mut origins: Vec<(String, Vec<Arc<RawData>>)> = vec![];
+        for raw_data in raw_data {
+            let origin = raw_data.url.origin().ascii_serialization();
+            match origins.iter_mut().find(|(o, _)| *o == origin) {
+                Some((_, group)) => group.push(Arc::new(raw_data)),
+                None => origins.push((origin, vec![Arc::new(raw_data)])),
+            }
+        }
+        let mut results = Vec::with_capacity(origins.len());
+        for (origin, group) in origins {
+            let mut name: HashSet<String> = HashSet::new();
+            let mut what_web_result = WhatWebResult::new(origin);
+            for raw_data in group.iter() {
+                let web_name_set = self.check(raw_data, &NoopObserver).await;
+                if let Some(waf) = detect_waf(raw_data, &self.fingerprint) {
+                    if waf.blocked {
+                        what_web_result
+                            .low_confidence
+                            .extend(web_name_set.iter().map(|m| m.name.clone()));
+                    }
+                    what_web_result.waf.get_or_insert(waf.name);
+                }
+                what_web_result.insert_matches(
+                    &mut name,
+                    web_name_set,
+                    self.config.verbose_matches,
+                );
+                what_web_result.favicon.extend(raw_data.favicon.clone());
+            }
+            let index = group
+                .iter()
+                .find(|raw_data| raw_data.path == "/")
+                .unwrap_or(&group[0]);
+            what_web_result.url = index.url.to_string();
+            what_web_result.idn_host = unicode_host(&what_web_result.url);
+            what_web_result.title = index.title.clone();
+            what_web_result.generator = index.generator.clone();
+            what_web_result.asset_paths = index.asset_paths.clone();
+            what_web_result.length = index.text_length();
+            what_web_result.body_length = index.body_length();
+            what_web_result.body_hash = index.body_hash.clone();
+            what_web_result.simhash = simhash(&index.text);
+            what_web_result.status_code = index.status_code.as_u16();
+            what_web_result.server = index
+                .headers
+                .get(SERVER)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            what_web_result.response_time = index.response_time.as_millis() as u64;
+            what_web_result.http_version = format!("{:?}", index.version);
+            what_web_result.hsts = hsts(index);
+            // 和在线识别一样，有首页加一，首页访问成功再加一
+            what_web_result.priority += 1;
+            if index.status_code.is_success() {
+                what_web_result.priority += 1;
+            }
+            what_web_result.set_names(name);
+            results.push(what_web_result);
+        }
+        results
+    }
+    // 批量识别，最多同时扫描concurrency个目标，先扫完的先返回，结果带上原始输入
+    // 目标展开到多个端口时每个端口一个结果
+    pub fn scan_targets<'a, I, T>(
+        &'a self,
+        targets: I,
+        concurrency: usize,
+    ) -> impl Stream<Item = (String, WhatWebResult)> + 'a
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: 'a,
+        T: Into<ScanTarget>,
+    {
+        self.scan_target_stream(stream::iter(targets), concurrency, Arc::new(NoopObserver))
+    }
+    // 首页不是HTTP时读取banner，用tcp指纹识别端口上的服务
+    async fn banner_matches(
+        &self,
+        unreachable: &Unreachable,
+        config: &RequestOption,
+        what_web_result: &mut WhatWebResult,
+        observer: &dyn ScanObserver,
+    ) -> Vec<FingerprintMatch> {
+        if self.fingerprint.tcp.is_empty() {
+            return vec![];
+        }
+        let url = match unreachable
+            .errors
+            .first()
+            .and_then(|err| Url::parse(&err.url).ok())
+        {
+            Some(url) => url,
+            None => return vec![],
+        };
+        let banner = match grab_banner(&url, config).await {
+            Ok(banner) if !banner.is_empty() => banner,
+            Ok(_) => return vec![],
+            Err(err) => {
+                log::debug!("No banner from {}: {}", url, err);
+                return vec![];
+            }
+        };
+        what_web_result.banner = Some(printable_banner(&banner));
+        detect_banner(&url, &banner, &self.fingerprint, observer)
+    }
+    // 同一个站点只请求一次，不跟随跳转
+    async fn fetch_robots(
+        &self,
+        url: &Url,
+        config: &RequestOption,
+        observer: &dyn ScanObserver,
+    ) -> Option<String> {
+        let request = robots_request();
+        observer.request_sent(url.as_str(), &request);
+        match index_fetch(url.as_str(), &request, false, config.clone()).await {
+            Ok(fetch_result) => fetch_result
+                .raw_data
+                .first()
+                .and_then(|raw_data| robots_text(raw_data)),
+            Err(err) => {
+                log::debug!("No robots.txt from {}: {}", url, err);
+                None
+            }
+        }
+    }
+    // 匹配指纹并丢弃可信度不够的结果
+    async fn check(
+        &self,
+        raw_data: &Arc<RawData>,
+        observer: &dyn ScanObserver,
+    ) -> Vec<FingerprintMatch> {
+        let mut matches = check(raw_data, &self.fingerprint, observer).await;
+        matches.retain(|m| m.confidence >= self.config.min_confidence);
+        matches
+    }
+    /// 不发送请求，按顺序列出扫描这个目标会发送的请求，端口展开后的每个目标都列出来
+    pub fn preview<T: Into<ScanTarget>>(
+        &self,
+        target: T,
+    ) -> Result<Vec<RequestPreview>, WardError> {
+        let target = target.into();
+        let mut config = self.config.clone();
+        for (name, value) in target.headers.iter() {
+            config.add_target_header(name, value)?;
+        }
+        let mut previews = vec![];
+        for probe in self.expand_target(&target.url) {
+            previews.extend(preview_target(&probe, &self.fingerprint, &config)?);
+        }
+        Ok(previews)
+    }
+    /// 代理断开而且不允许直连，`scan_target_stream`不再取新的目标
+    pub fn is_proxy_aborted(&self) -> bool {
+        self.config.is_proxy_down() && !self.config.proxy_fallback
+    }
+    /// 按配置的端口展开目标，展开后的结果再展开不会变
+    pub fn expand_target(&self, target: &str) -> Vec<String> {
+        expand_target(target, &self.config.ports)
+    }
+    // 目标边输入边识别，不用等全部读完，所有目标共用一个观察者
+    pub fn scan_target_stream<'a, S, T>(
+        &'a self,
+        targets: S,
+        concurrency: usize,
+        observer: Arc<dyn ScanObserver>,
+    ) -> impl Stream<Item = (String, WhatWebResult)> + 'a
+    where
+        S: Stream<Item = T> + 'a,
+        T: Into<ScanTarget>,
+    {
+        targets
+            .take_while(move |_| future::ready(!self.is_proxy_aborted()))
+            .flat_map(move |target| {
+                let target: ScanTarget = target.into();
+                let probes = expand_target(&target.url, &self.config.ports);
+                stream::iter(
+                    probes
+                        .into_iter()
+                        .map(move |probe| (target.url.clone(), target.with_url(probe))),
+                )
+            })
+            .map(move |(target, probe)| {
+                let observer = observer.clone();
+                async move {
+                    let result = self.scan_with_observer(probe, observer).await;
+                    (target, result)
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
     }
 }
 
@@ -191,6 +1174,24 @@ pub struct TemplateInfo {
     pub severity: String,
 }
 
+// 没有发送请求就失败的目标，例如代理已经断开或者目标带的请求头不合法
+fn errored_result(url: String, err: WardError, observer: &dyn ScanObserver) -> WhatWebResult {
+    let (url, _) = split_credentials(&url);
+    observer.target_errored(&url, &err);
+    let mut what_web_result = WhatWebResult::new(url);
+    what_web_result.is_web = false;
+    what_web_result.error = Some(err.to_string());
+    what_web_result
+}
+
+fn hsts(raw_data: &RawData) -> Option<Hsts> {
+    raw_data
+        .headers
+        .get(STRICT_TRANSPORT_SECURITY)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Hsts::parse)
+}
+
 fn string_to_hashset<'de, D>(deserializer: D) -> Result<HashSet<String>, D::Error>
 where
     D: Deserializer<'de>,
@@ -217,3 +1218,1175 @@ where
     }
     deserializer.deserialize_any(StringToHashSet(PhantomData))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::dns::tests::dns_server;
+    use crate::fingerprint::WebFingerPrint;
+    use crate::fingerprint::{V3WebFingerPrint, WebFingerPrintRequest};
+    use crate::request::tests::{
+        echo_server, http_response, probe_fingerprints, recording_server, stub_server,
+    };
+    use crate::request::unicode_host;
+    use crate::{
+        AuthScheme, DnsServer, FetchErrorKind, HttpRequest, HttpResponse, RawData, RedirectPolicy,
+        RequestOption, ScanCache, ScanObserver, ScanTarget, WardError, WhatWeb, WhatWebResult,
+    };
+    use futures::StreamExt;
+    use md5::{Digest, Md5};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn cookie_fingerprint() -> Vec<WebFingerPrint> {
+        serde_json::from_str(
+            r#"[{"name":"shiro-cookie","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["rememberme=admin"]}]"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_shiro_detect_option() {
+        let timeout = 10_u64;
+        let request_option = RequestOption::builder().timeout(timeout).build().unwrap();
+        assert!(!request_option.shiro_detect);
+        let what_web = WhatWeb::new(request_option.clone(), cookie_fingerprint()).unwrap();
+        let url = echo_server().await;
+        let result = what_web.scan(url.to_string()).await;
+        assert!(!result.name.contains("shiro-cookie"));
+        let mut request_option = request_option;
+        request_option.shiro_detect = true;
+        let what_web = WhatWeb::new(request_option, cookie_fingerprint()).unwrap();
+        let url = echo_server().await;
+        let result = what_web.scan(url.to_string()).await;
+        assert!(result.name.contains("shiro-cookie"));
+    }
+
+    #[tokio::test]
+    async fn test_reason_phrase() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = stub_server(move |request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match request.split_whitespace().nth(1) {
+                Some("/cgi-bin/status") => http_response("401 Access Denied", &[], b""),
+                _ => http_response(
+                    "200 Document follows",
+                    &[("Content-Type", "text/html")],
+                    b"ok",
+                ),
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"thttpd","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":[],"reason_phrase":["Document follows"]},
+            {"name":"goahead","path":"/cgi-bin/status","request_method":"get","request_headers":{},"request_data":"","status_code":401,"headers":{},"keyword":[],"reason_phrase":["Access Denied"]}]"#,
+        )
+        .unwrap();
+        let request_option = || RequestOption::builder().timeout(3).build().unwrap();
+        let what_web = WhatWeb::new(request_option(), fingerprint).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert!(result.name.contains("thttpd"));
+        // 特殊请求的响应也有原因短语
+        assert!(result.name.contains("goahead"));
+        assert_eq!(result.reason_phrase.as_deref(), Some("Document follows"));
+        // 原因短语从响应里读取，不另外发送请求
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        // 没有指纹用到时不记录
+        let what_web = WhatWeb::new(request_option(), cookie_fingerprint()).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.reason_phrase, None);
+    }
+
+    #[tokio::test]
+    async fn test_target_credentials() {
+        // admin:secret
+        let url = stub_server(|request| {
+            if request.contains("authorization: Basic YWRtaW46c2VjcmV0") {
+                http_response("200 OK", &[], b"<title>Dashboard</title>welcome admin")
+            } else {
+                http_response(
+                    "401 Unauthorized",
+                    &[("WWW-Authenticate", "Basic realm=\"Router Admin\"")],
+                    b"",
+                )
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"router-index","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["welcome admin"]},
+            {"name":"router-status","path":"/status","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["welcome admin"]},
+            {"name":"router-realm","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":401,"headers":{"www-authenticate":"router admin"},"keyword":[]}]"#,
+        )
+        .unwrap();
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(3).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert!(result.name.contains("router-realm"));
+        assert!(!result.name.contains("router-index"));
+        // 目标里的用户名密码对特殊请求也生效，结果里不保留
+        let mut target = url.clone();
+        target.set_username("admin").unwrap();
+        target.set_password(Some("secret")).unwrap();
+        let result = what_web.scan(target.to_string()).await;
+        assert_eq!(result.url, url.to_string());
+        assert_eq!(result.title, "Dashboard");
+        assert!(result.name.contains("router-index"));
+        assert!(result.name.contains("router-status"));
+    }
+
+    #[tokio::test]
+    async fn test_special_requires() {
+        let (url, paths) = recording_server(|path| match path {
+            "/" => http_response("200 OK", &[], b"Powered by ThinkPHP"),
+            "/thinkphp/version" => http_response("200 OK", &[], b"ThinkPHP V5.0.23"),
+            _ => http_response("404 Not Found", &[], b""),
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"ThinkPHP","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["powered by thinkphp"]},
+            {"name":"thinkphp-version","path":"/thinkphp/version","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["thinkphp v5"],"requires":["thinkphp"]},
+            {"name":"wordpress-version","path":"/wp-version","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["wordpress"],"requires":["wordpress"]},
+            {"name":"any-admin","path":"/admin","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["admin"]}]"#,
+        )
+        .unwrap();
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(3).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert!(result.name.contains("thinkphp-version"));
+        let paths = paths.lock().unwrap();
+        assert!(paths.contains(&String::from("/thinkphp/version")));
+        // 没有依赖的照常请求，依赖没有识别出来的跳过
+        assert!(paths.contains(&String::from("/admin")));
+        assert!(!paths.contains(&String::from("/wp-version")));
+        let issues = what_web.fingerprint.validate();
+        assert!(issues
+            .iter()
+            .any(|i| i.name == "wordpress-version" && i.field == "requires"));
+        assert!(!issues
+            .iter()
+            .any(|i| i.name == "thinkphp-version" && i.field == "requires"));
+    }
+
+    #[tokio::test]
+    async fn test_request_budget() {
+        let (url, paths) =
+            recording_server(|_| http_response("200 OK", &[], b"<title>Camera</title>")).await;
+        let fingerprint = probe_fingerprints(5, "camera");
+        let mut request_option = RequestOption::builder().timeout(3).build().unwrap();
+        request_option.max_requests_per_host = Some(4);
+        let what_web = WhatWeb::new(request_option, fingerprint).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        let paths = paths.lock().unwrap();
+        assert_eq!(paths.len(), 4);
+        let probed = paths.iter().filter(|p| p.starts_with("/probe-")).count() as u32;
+        assert!(result.skipped_probes > 0);
+        assert_eq!(probed + result.skipped_probes, 5);
+        assert_eq!(what_web.config.remaining_requests("127.0.0.1"), Some(0));
+        what_web.reset_request_budget();
+        assert_eq!(what_web.config.remaining_requests("127.0.0.1"), Some(4));
+    }
+
+    #[test]
+    fn test_request_interval() {
+        let mut request_option = RequestOption::builder().rate_limit(4.0).build().unwrap();
+        assert_eq!(
+            request_option.request_interval(),
+            Duration::from_millis(250)
+        );
+        // 构建后直接改的不合法速率当作不限制，不会panic
+        for rps in [0.0, -1.0, f32::NAN, f32::INFINITY, 1e-30] {
+            request_option.requests_per_second = Some(rps);
+            assert_eq!(request_option.request_interval(), Duration::ZERO);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shuffle_probes() {
+        let (url, paths) = recording_server(|_| http_response("200 OK", &[], b"")).await;
+        let fingerprint = probe_fingerprints(8, "probe");
+        let scan = |seed: u64| {
+            let request_option = RequestOption::builder()
+                .timeout(3)
+                .shuffle_probes(true)
+                .jitter_ms(10, 20)
+                .seed(seed)
+                .build()
+                .unwrap();
+            let what_web = WhatWeb::new(request_option, fingerprint.clone()).unwrap();
+            let paths = paths.clone();
+            let url = url.clone();
+            async move {
+                paths.lock().unwrap().clear();
+                let start = std::time::Instant::now();
+                what_web.scan(url.to_string()).await;
+                // 同一个主机的请求之间至少等10ms
+                let requested = paths.lock().unwrap().clone();
+                assert!(
+                    start.elapsed() >= Duration::from_millis(10 * (requested.len() as u64 - 1))
+                );
+                requested
+            }
+        };
+        let first = scan(7).await;
+        assert_eq!(first.iter().filter(|p| p.starts_with("/probe-")).count(), 8);
+        // 同样的种子顺序一样
+        assert_eq!(scan(7).await, first);
+        let ordered: Vec<String> = (0..8).map(|i| format!("/probe-{}", i)).collect();
+        let probes: Vec<String> = first
+            .into_iter()
+            .filter(|p| p.starts_with("/probe-"))
+            .collect();
+        assert_ne!(probes, ordered);
+    }
+
+    #[tokio::test]
+    async fn test_polite_robots() {
+        let (url, paths) = recording_server(|path| {
+            match path {
+                "/robots.txt" => http_response(
+                    "200 OK",
+                    &[("Content-Type", "text/plain")],
+                    b"User-agent: *\r\nDisallow: /wp-admin/ # admin\r\nAllow: /wp-admin/admin-ajax.php\r\n",
+                ),
+                // 特殊请求的关键词只出现在对应路径的响应里
+                _ => http_response("200 OK", &[], format!("<title>{}</title>", path).as_bytes()),
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"wordpress","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"robots":["disallow: /WP-ADMIN/"]},
+            {"name":"wp-login","path":"/wp-admin/install.php","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["install.php"]},
+            {"name":"wp-ajax","path":"/wp-admin/admin-ajax.php","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["admin-ajax.php"]}]"#,
+        )
+        .unwrap();
+        let scan = |polite: bool| {
+            let what_web = WhatWeb::new(
+                RequestOption::builder()
+                    .timeout(3)
+                    .polite(polite)
+                    .build()
+                    .unwrap(),
+                fingerprint.clone(),
+            )
+            .unwrap();
+            let paths = paths.clone();
+            let url = url.clone();
+            async move {
+                paths.lock().unwrap().clear();
+                let result = what_web.scan(url.to_string()).await;
+                let mut requested = paths.lock().unwrap().clone();
+                requested.sort();
+                (result, requested)
+            }
+        };
+        let (result, requested) = scan(true).await;
+        assert!(result.name.contains("wordpress"));
+        assert!(result.name.contains("wp-ajax"));
+        assert!(!result.name.contains("wp-login"));
+        assert_eq!(result.disallowed_probes, 1);
+        assert_eq!(
+            requested,
+            [
+                "/",
+                "/favicon.ico",
+                "/robots.txt",
+                "/wp-admin/admin-ajax.php"
+            ]
+        );
+        // 不开启时照样请求robots.txt给robots规则用，但不跳过特殊请求
+        let (result, requested) = scan(false).await;
+        assert!(result.name.contains("wordpress"));
+        assert!(result.name.contains("wp-login"));
+        assert_eq!(result.disallowed_probes, 0);
+        assert_eq!(requested.iter().filter(|p| *p == "/robots.txt").count(), 1);
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_scan_with_debug() {
+        let url = stub_server(|_| http_response("200 OK", &[], b"<title>Debug</title>")).await;
+        let what_web =
+            WhatWeb::new(RequestOption::builder().timeout(3).build().unwrap(), vec![]).unwrap();
+        for debug in [false, true] {
+            let result = what_web.scan_with_debug(url.to_string(), debug).await;
+            assert_eq!(result.title, "Debug");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_cache() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let url = stub_server(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            http_response("200 OK", &[], b"<title>Cached</title>")
+        })
+        .await;
+        let what_web =
+            WhatWeb::new(RequestOption::builder().timeout(3).build().unwrap(), vec![]).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.schemes, ["http"]);
+        let sent = requests.load(Ordering::SeqCst);
+        assert!(sent > 0);
+        what_web.scan(url.to_string()).await;
+        assert_eq!(requests.load(Ordering::SeqCst), sent);
+        assert!(!what_web.config.cache().is_empty());
+        // 共用缓存但请求方式不同的配置重新请求
+        for change in [
+            |config: &mut RequestOption| config.redirect_policy = RedirectPolicy::Always,
+            |config: &mut RequestOption| config.user_agent = Some(String::from("scanner")),
+            |config: &mut RequestOption| config.max_body_size = 1024,
+        ] {
+            let mut config = what_web.config.clone();
+            change(&mut config);
+            let before = requests.load(Ordering::SeqCst);
+            WhatWeb::new(config, vec![])
+                .unwrap()
+                .scan(url.to_string())
+                .await;
+            assert!(requests.load(Ordering::SeqCst) > before);
+        }
+        let base = requests.load(Ordering::SeqCst);
+        what_web.scan(url.to_string()).await;
+        assert_eq!(requests.load(Ordering::SeqCst), base);
+        what_web.clear_cache();
+        what_web.scan(url.to_string()).await;
+        assert_eq!(requests.load(Ordering::SeqCst), base + sent);
+        // 不缓存的每次都请求
+        let request_option = RequestOption::builder()
+            .timeout(3)
+            .cache(ScanCache::disabled())
+            .build()
+            .unwrap();
+        let what_web = WhatWeb::new(request_option, vec![]).unwrap();
+        what_web.scan(url.to_string()).await;
+        what_web.scan(url.to_string()).await;
+        assert_eq!(requests.load(Ordering::SeqCst), base + sent * 3);
+        assert!(what_web.config.cache().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_override() {
+        let url = stub_server(|request| {
+            if request.contains("host: vhost.example.test") {
+                http_response("200 OK", &[], b"<title>Virtual Host</title>vhost-status")
+            } else {
+                http_response("404 Not Found", &[], b"")
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"vhost-status","path":"/status","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["vhost-status"]}]"#,
+        )
+        .unwrap();
+        let request_option = RequestOption::builder()
+            .timeout(3)
+            .resolve("VHost.example.test", "127.0.0.1".parse().unwrap())
+            .build()
+            .unwrap();
+        let what_web = WhatWeb::new(request_option, fingerprint).unwrap();
+        let target = format!("http://vhost.example.test:{}/", url.port().unwrap());
+        let result = what_web.scan(target.clone()).await;
+        assert_eq!(result.url, target);
+        assert_eq!(result.title, "Virtual Host");
+        assert_eq!(result.resolved_ip, Some("127.0.0.1".parse().unwrap()));
+        assert_eq!(
+            result.peer_addr,
+            Some(
+                format!("127.0.0.1:{}", url.port().unwrap())
+                    .parse()
+                    .unwrap()
+            )
+        );
+        assert!(result.name.contains("vhost-status"));
+        // 没有指定的照常请求
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.status_code, 404);
+        assert_eq!(result.resolved_ip, None);
+    }
+
+    #[tokio::test]
+    async fn test_idn_target() {
+        let url = stub_server(|request| {
+            if request.contains("host: xn--bcher-kva.example") {
+                http_response("200 OK", &[], b"rememberme=admin")
+            } else {
+                http_response("404 Not Found", &[], b"")
+            }
+        })
+        .await;
+        let what_web = WhatWeb::new(
+            RequestOption::builder()
+                .timeout(3)
+                .resolve("BÜCHER.example", "127.0.0.1".parse().unwrap())
+                .resolve("💩.la", "127.0.0.1".parse().unwrap())
+                .build()
+                .unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let port = url.port().unwrap();
+        for target in [
+            format!("bücher.example:{}", port),
+            format!("http://bücher.example:{}/", port),
+        ] {
+            let result = what_web.scan(target.clone()).await;
+            assert!(result.name.contains("shiro-cookie"), "{}", target);
+            assert_eq!(
+                result.url,
+                format!("http://xn--bcher-kva.example:{}/", port)
+            );
+            assert_eq!(result.idn_host.as_deref(), Some("bücher.example"));
+            assert_eq!(
+                result.display_url(),
+                format!("http://bücher.example:{}/", port)
+            );
+        }
+        // 连不上的目标同样显示Unicode形式
+        let result = what_web.scan(String::from("💩.la:1")).await;
+        assert_eq!(result.url, "xn--ls8h.la:1");
+        assert_eq!(result.display_url(), "💩.la:1");
+        let result = WhatWebResult {
+            url: String::from("https://xn--pple-43d.com/xn--pple-43d.com"),
+            idn_host: unicode_host("https://xn--pple-43d.com/"),
+            ..WhatWebResult::new(String::new())
+        };
+        assert_eq!(result.display_url(), "https://аpple.com/xn--pple-43d.com");
+        assert_eq!(unicode_host("https://example.com/"), None);
+        assert_eq!(
+            unicode_host("https://xn--ls8h.la:8443/"),
+            Some(String::from("💩.la"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_server() {
+        let (dns_addr, queries) = dns_server([127, 0, 0, 1]).await;
+        let first = stub_server(|_| http_response("200 OK", &[], b"<title>Intranet</title>")).await;
+        let second = stub_server(|_| http_response("404 Not Found", &[], b"")).await;
+        let request_option = RequestOption::builder()
+            .timeout(3)
+            .dns_server(&dns_addr.to_string())
+            .build()
+            .unwrap();
+        assert_eq!(request_option.dns_server(), Some(&DnsServer::Udp(dns_addr)));
+        let what_web = WhatWeb::new(request_option, vec![]).unwrap();
+        let target = format!("http://intranet.example.test:{}/", first.port().unwrap());
+        let result = what_web.scan(target).await;
+        assert_eq!(result.title, "Intranet");
+        assert_eq!(
+            result.peer_addr.map(|addr| addr.ip()),
+            Some("127.0.0.1".parse().unwrap())
+        );
+        // 同一个域名的其他端口不再查询，A和AAAA各查询过一次
+        let target = format!("http://Intranet.example.test:{}/", second.port().unwrap());
+        assert_eq!(what_web.scan(target).await.status_code, 404);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+        // 解析失败和连接失败分开记录
+        let result = what_web
+            .scan(String::from("http://missing.example.test/"))
+            .await;
+        assert!(!result.is_web);
+        assert_eq!(result.errors[0].kind, FetchErrorKind::Dns);
+        assert!(result.errors[0].message.contains("no such host"));
+        assert!(RequestOption::builder()
+            .dns_server("resolver.example.test")
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_scan_targets() {
+        // 只接受连接不返回响应
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let timeout = 3_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let targets = vec![
+            hanging.clone(),
+            echo_server().await.to_string(),
+            echo_server().await.to_string(),
+        ];
+        let results: Vec<_> = what_web.scan_targets(targets.clone(), 2).collect().await;
+        assert_eq!(results.len(), 3);
+        // 卡住的目标不影响其他目标先返回
+        assert_eq!(results.last().unwrap().0, hanging);
+        for (target, result) in results.iter() {
+            assert!(targets.contains(target));
+            assert_eq!(result.status_code == 200, target != &hanging);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_target_deadline() {
+        // 首页和图标马上返回，特殊请求不返回响应
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0_u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap_or_default();
+                if buf[..n].starts_with(b"GET / ") {
+                    let _ = stream
+                        .write_all(&http_response("200 OK", &[], b"rememberme=admin"))
+                        .await;
+                } else if buf[..n].starts_with(b"GET /favicon.ico ") {
+                    let _ = stream
+                        .write_all(&http_response("404 Not Found", &[], b""))
+                        .await;
+                } else {
+                    streams.push(stream);
+                }
+            }
+        });
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"shiro-cookie","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["rememberme=admin"]},
+            {"name":"slow-console","path":"/console","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["console"]}]"#,
+        )
+        .unwrap();
+        let request_option = |deadline: Option<Duration>| {
+            let builder = RequestOption::builder().timeout(10).retries(0);
+            match deadline {
+                Some(deadline) => builder.target_deadline(deadline),
+                None => builder,
+            }
+            .build()
+            .unwrap()
+        };
+        let what_web =
+            WhatWeb::new(request_option(Some(Duration::from_secs(1))), fingerprint).unwrap();
+        let start = std::time::Instant::now();
+        let result = what_web.scan(url.clone()).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // 首页的结果保留
+        assert!(result.deadline_exceeded);
+        assert_eq!(result.status_code, 200);
+        assert!(result.name.contains("shiro-cookie"));
+        assert_eq!(result.error, None);
+        // 首页也没有响应时记录错误
+        let hanging = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging_url = format!("http://{}/", hanging.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((stream, _)) = hanging.accept().await {
+                streams.push(stream);
+            }
+        });
+        let what_web = WhatWeb::new(
+            request_option(Some(Duration::from_millis(500))),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let result = what_web.scan(hanging_url).await;
+        assert!(result.deadline_exceeded);
+        assert!(!result.is_web);
+        assert!(result.error.unwrap().contains("did not finish within"));
+        // 没有设置时和以前一样
+        let what_web = WhatWeb::new(request_option(None), cookie_fingerprint()).unwrap();
+        let result = what_web.scan(echo_server().await.to_string()).await;
+        assert!(!result.deadline_exceeded);
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_target() {
+        let listener = match TcpListener::bind("[::1]:0").await {
+            Ok(listener) => listener,
+            // 没有IPv6的环境跳过
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0_u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream
+                    .write_all(&http_response("200 OK", &[], b"rememberme=admin"))
+                    .await;
+            }
+        });
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(5).build().unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let expected = format!("http://[::1]:{}/", port);
+        for target in [format!("[::1]:{}", port), format!("http://[::1]:{}/", port)] {
+            let result = what_web.scan(target.clone()).await;
+            assert_eq!(result.url, expected, "{}", target);
+            assert!(result.name.contains("shiro-cookie"), "{}", target);
+        }
+        let results: Vec<_> = what_web
+            .scan_targets(vec![String::from("::1")], 1)
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1.url, "[::1]");
+        assert!(!results[0].1.is_web);
+    }
+
+    #[tokio::test]
+    async fn test_target_headers() {
+        // 带着会话Cookie才能看到登录后的页面
+        let url = stub_server(|request| {
+            if request.contains("cookie: JSESSIONID=abc; lang=zh")
+                && request.contains("x-tenant: ops")
+            {
+                http_response("200 OK", &[], b"rememberme=admin")
+            } else {
+                http_response("302 Found", &[("Location", "/login")], b"")
+            }
+        })
+        .await;
+        let what_web = WhatWeb::new(
+            RequestOption::builder()
+                .timeout(3)
+                .header("X-Tenant", "default")
+                .build()
+                .unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let target = ScanTarget::new(url.as_str())
+            .header("X-Tenant", "ops")
+            .cookie("JSESSIONID", "abc")
+            .cookie("lang", "zh");
+        let results: Vec<_> = what_web
+            .scan_targets(vec![target.clone(), ScanTarget::from(url.as_str())], 1)
+            .collect()
+            .await;
+        assert_eq!(results.len(), 2);
+        // 同一个URL的缓存按请求头区分
+        assert!(results
+            .iter()
+            .all(|(target_url, _)| target_url == url.as_str()));
+        assert!(results[0].1.name.contains("shiro-cookie"));
+        assert!(!results[1].1.name.contains("shiro-cookie"));
+        let previews = what_web.preview(target).unwrap();
+        assert!(previews[0]
+            .headers
+            .iter()
+            .any(|(name, value)| name == "cookie" && value == "JSESSIONID=abc; lang=zh"));
+        let result = what_web
+            .scan(ScanTarget::new(url.as_str()).header("Bad Name", "1"))
+            .await;
+        assert!(!result.is_web);
+        assert!(result.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_scoped_to_target_host() {
+        let recorded: Arc<Mutex<Vec<String>>> = Arc::default();
+        let cdn_recorded = recorded.clone();
+        // 同一个端口用localhost访问就是别的主机
+        let cdn = stub_server(move |request| {
+            cdn_recorded.lock().unwrap().push(request.to_lowercase());
+            http_response("200 OK", &[("Content-Type", "text/html")], b"cdn")
+        })
+        .await;
+        let cdn = format!("http://localhost:{}/", cdn.port().unwrap());
+        let target_recorded = recorded.clone();
+        // 首页跳转到别的主机，图标也在别的主机上
+        let url = stub_server(move |request| {
+            target_recorded.lock().unwrap().push(request.to_lowercase());
+            let body = format!("<link rel=\"icon\" href=\"{}favicon.ico\">", cdn);
+            http_response(
+                "302 Found",
+                &[("Location", &cdn), ("Content-Type", "text/html")],
+                body.as_bytes(),
+            )
+        })
+        .await;
+        let what_web = WhatWeb::new(
+            RequestOption::builder()
+                .timeout(3)
+                .redirect_policy(RedirectPolicy::Always)
+                .cross_host_favicon(true)
+                .auth(AuthScheme::bearer("t0ken"))
+                .header("X-Scan", "all")
+                .build()
+                .unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let target = ScanTarget::new(url.as_str()).cookie("JSESSIONID", "abc");
+        what_web.scan(target).await;
+        let recorded = recorded.lock().unwrap();
+        let (to_target, to_cdn): (Vec<&String>, Vec<&String>) = recorded
+            .iter()
+            .partition(|request| request.contains("host: 127.0.0.1"));
+        assert!(!to_target.is_empty());
+        assert!(to_target.iter().all(|request| {
+            request.contains("authorization: bearer t0ken")
+                && request.contains("cookie: jsessionid=abc")
+        }));
+        // 跳转和图标请求到别的主机时只带全局的请求头
+        assert!(to_cdn.len() >= 2, "{:?}", to_cdn);
+        assert!(to_cdn
+            .iter()
+            .any(|request| request.contains("/favicon.ico")));
+        assert!(to_cdn.iter().all(|request| {
+            !request.contains("authorization")
+                && !request.contains("cookie")
+                && request.contains("x-scan: all")
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_scan_ports() {
+        let first = echo_server().await;
+        let second = echo_server().await;
+        let timeout = 3_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let target = format!(
+            "127.0.0.1:{},{}",
+            first.port().unwrap(),
+            second.port().unwrap()
+        );
+        let results: Vec<_> = what_web
+            .scan_targets(vec![target.clone()], 2)
+            .collect()
+            .await;
+        // 每个端口一个结果，都带上原始输入
+        assert_eq!(results.len(), 2);
+        let mut urls: Vec<String> = results.iter().map(|(_, r)| r.url.clone()).collect();
+        urls.sort();
+        let mut expected = vec![first.to_string(), second.to_string()];
+        expected.sort();
+        assert_eq!(urls, expected);
+        assert!(results
+            .iter()
+            .all(|(t, r)| t == &target && r.status_code == 200));
+    }
+
+    #[tokio::test]
+    async fn test_structured_result() {
+        let url = stub_server(|_| {
+            http_response(
+                "200 OK",
+                &[("Server", "nginx/1.20.1"), ("Content-Type", "text/html")],
+                b"<title>Hello ObserverWard</title>",
+            )
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"hello","priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["hello observerward"]}]"#,
+        )
+        .unwrap();
+        let timeout = 10_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.title, "Hello ObserverWard");
+        assert_eq!(result.server, "nginx/1.20.1");
+        assert_eq!(result.status_code, 200);
+        assert_eq!(result.matched.get("hello"), Some(&3));
+        assert_eq!(result.length, "<title>Hello ObserverWard</title>".len());
+        assert_eq!(result.body_length, result.length);
+        let body_hash = result.body_hash.clone().unwrap();
+        assert_eq!(body_hash.md5, "42846b85b181d3aeb1acd0697047f9c9");
+        let peer_addr = result.peer_addr;
+        assert!(peer_addr.is_some());
+        let json = serde_json::to_string(&result).unwrap();
+        let mut result: WhatWebResult = serde_json::from_str(&json).unwrap();
+        assert!(result.name.contains("hello"));
+        assert_eq!(result.server, "nginx/1.20.1");
+        assert_eq!(result.body_hash, Some(body_hash));
+        assert_eq!(result.peer_addr, peer_addr);
+        // 经过代理时没有实际连接的地址，不输出这个字段
+        result.peer_addr = None;
+        assert!(!serde_json::to_string(&result)
+            .unwrap()
+            .contains("peer_addr"));
+    }
+
+    #[tokio::test]
+    async fn test_waf_detect() {
+        let url = stub_server(|_| {
+            http_response(
+                "403 Forbidden",
+                &[("Server", "cloudflare"), ("Content-Type", "text/html")],
+                b"<title>Attention Required! | Cloudflare</title>powered by nginx",
+            )
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["powered by nginx"]}]"#,
+        )
+        .unwrap();
+        let timeout = 10_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.waf.as_deref(), Some("cloudflare"));
+        // WAF不算作组件，拦截页面上的匹配结果标记为可能误报
+        assert!(!result.name.contains("cloudflare"));
+        assert!(result.name.contains("nginx"));
+        assert!(result.low_confidence.contains("nginx"));
+    }
+
+    #[tokio::test]
+    async fn test_bigip_cookie() {
+        let url = stub_server(|_| {
+            http_response(
+                "200 OK",
+                &[
+                    ("Content-Type", "text/html"),
+                    ("Set-Cookie", "JSESSIONID=1; Path=/"),
+                    (
+                        "Set-Cookie",
+                        "BIGipServer~Common~pool_80=rd5o00000000000000000000ffffc0000201o80; path=/; Httponly",
+                    ),
+                ],
+                b"<title>portal</title>",
+            )
+        })
+        .await;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(10).build().unwrap(),
+            vec![],
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.waf.as_deref(), Some("f5-bigip"));
+        assert!(result.name.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_min_confidence() {
+        let url = stub_server(|_| {
+            http_response(
+                "200 OK",
+                &[("Server", "nginx"), ("Content-Type", "text/html")],
+                b"<title>login</title>welcome to nginx",
+            )
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"generic-login","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["login"]},
+            {"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"=nginx"},"keyword":["welcome to nginx"]}]"#,
+        )
+        .unwrap();
+        let timeout = 10_u64;
+        let mut request_option = RequestOption::builder().timeout(timeout).build().unwrap();
+        let what_web = WhatWeb::new(request_option.clone(), fingerprint.clone()).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.confidence.get("generic-login"), Some(&10));
+        assert_eq!(result.confidence.get("nginx"), Some(&40));
+        // 只命中一个通用关键词的指纹被过滤
+        request_option.min_confidence = 20;
+        let what_web = WhatWeb::new(request_option, fingerprint).unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert_eq!(result.name, HashSet::from([String::from("nginx")]));
+        assert!(!result.confidence.contains_key("generic-login"));
+    }
+
+    #[derive(Default)]
+    struct RecordObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RecordObserver {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl ScanObserver for RecordObserver {
+        fn target_started(&self, target: &str) {
+            self.record(format!("started {}", target));
+        }
+        fn request_sent(&self, _url: &str, request: &WebFingerPrintRequest) {
+            self.record(format!("request {}", request.path));
+        }
+        fn raw_data_received(&self, raw_data: &RawData) {
+            self.record(format!("raw_data {}", raw_data.status_code.as_u16()));
+        }
+        fn fingerprint_matched(&self, _raw_data: &RawData, fingerprint: &V3WebFingerPrint) {
+            self.record(format!("matched {}", fingerprint.name));
+        }
+        fn target_finished(&self, result: &WhatWebResult) {
+            self.record(format!("finished {}", result.is_web));
+        }
+        fn target_errored(&self, target: &str, _error: &WardError) {
+            self.record(format!("errored {}", target));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_banner() {
+        // 连接后先发送banner，不理会收到的请求
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _ = stream
+                        .write_all(b"SSH-2.0-OpenSSH_8.2p1 Ubuntu-4ubuntu0.5\r\n")
+                        .await;
+                    let mut buf = [0_u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                });
+            }
+        });
+        let config = RequestOption::builder().timeout(5).build().unwrap();
+        let what_web = WhatWeb::new(config, vec![]).unwrap();
+        let result = what_web.scan(addr.to_string()).await;
+        assert!(!result.is_web);
+        assert!(result.error.is_some());
+        assert_eq!(
+            result.banner.as_deref(),
+            Some("SSH-2.0-OpenSSH_8.2p1 Ubuntu-4ubuntu0.5")
+        );
+        assert_eq!(result.name, HashSet::from([String::from("openssh")]));
+        assert_eq!(
+            result.version["openssh"],
+            HashSet::from([String::from("8.2p1")])
+        );
+        // 关闭后只报错
+        let config = RequestOption::builder()
+            .timeout(5)
+            .tcp_banner(false)
+            .build()
+            .unwrap();
+        let what_web = WhatWeb::new(config, vec![]).unwrap();
+        let result = what_web.scan(addr.to_string()).await;
+        assert!(result.banner.is_none());
+        assert!(result.name.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_observer() {
+        let url = stub_server(|request| {
+            if request.starts_with("GET /admin ") {
+                http_response("404 Not Found", &[], b"")
+            } else {
+                http_response("200 OK", &[("Content-Type", "text/html")], b"observer")
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"observer","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["observer"]},
+            {"name":"admin","path":"/admin","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":["admin panel"]}]"#,
+        )
+        .unwrap();
+        let timeout = 10_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let observer = Arc::new(RecordObserver::default());
+        let result = what_web
+            .scan_with_observer(url.to_string(), observer.clone())
+            .await;
+        assert!(result.name.contains("observer"));
+        let events = observer.events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            [
+                format!("started {}", url),
+                String::from("request /"),
+                String::from("raw_data 200"),
+                String::from("matched observer"),
+                String::from("request /admin"),
+                String::from("raw_data 404"),
+                String::from("finished true"),
+            ]
+        );
+        // 首页请求失败时先报错再结束
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let observer = Arc::new(RecordObserver::default());
+        let result = what_web
+            .scan_with_observer(closed.clone(), observer.clone())
+            .await;
+        assert!(!result.is_web);
+        let events = observer.events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            [
+                format!("started {}", closed),
+                String::from("request /"),
+                format!("errored {}", closed),
+                String::from("finished false"),
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct HttpRecordObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl ScanObserver for HttpRecordObserver {
+        fn http_request(&self, _target: &str, sequence: u64, request: &HttpRequest) {
+            let probe = request.headers.get("x-probe").is_some();
+            self.events.lock().unwrap().push(format!(
+                "request {} {} {} {} {}",
+                sequence,
+                request.method,
+                request.url.path(),
+                probe,
+                String::from_utf8_lossy(request.body)
+            ));
+        }
+        fn http_response(&self, _target: &str, sequence: u64, response: &HttpResponse) {
+            self.events.lock().unwrap().push(format!(
+                "response {} {} {}",
+                sequence,
+                response.status_code.as_u16(),
+                String::from_utf8_lossy(response.body)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_observer() {
+        let url = stub_server(|request| {
+            if request.starts_with("POST /api ") {
+                http_response("200 OK", &[], b"pong")
+            } else if request.starts_with("GET /favicon.ico ") {
+                http_response("404 Not Found", &[], b"missing")
+            } else {
+                http_response("200 OK", &[("Content-Type", "text/html")], b"index")
+            }
+        })
+        .await;
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(
+            r#"[{"name":"api","path":"/api","request_method":"post","request_headers":{"X-Probe":"1"},"request_data":"cGluZw==","status_code":0,"headers":{},"keyword":["pong"]}]"#,
+        )
+        .unwrap();
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(10).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let observer = Arc::new(HttpRecordObserver::default());
+        let result = what_web
+            .scan_with_observer(url.to_string(), observer.clone())
+            .await;
+        assert!(result.name.contains("api"));
+        // 每个请求按顺序编号，没有读正文的图标响应是空的
+        let events = observer.events.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            [
+                "request 1 GET / false ",
+                "response 1 200 index",
+                "request 2 GET /favicon.ico false ",
+                "response 2 404 ",
+                "request 3 POST /api true ping",
+                "response 3 200 pong",
+            ]
+        );
+        // 每个目标单独编号
+        observer.events.lock().unwrap().clear();
+        what_web.clear_cache();
+        what_web
+            .scan_with_observer(url.to_string(), observer.clone())
+            .await;
+        assert_eq!(observer.events.lock().unwrap()[0], "request 1 GET / false ");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_down() {
+        let dead = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let mut targets = vec![];
+        for _ in 0..6 {
+            targets.push(echo_server().await.to_string());
+        }
+        let builder = || {
+            RequestOption::builder()
+                .timeout(3)
+                .proxy(&dead)
+                .proxy_failure_threshold(2)
+        };
+        let what_web = WhatWeb::new(builder().build().unwrap(), cookie_fingerprint()).unwrap();
+        let results: Vec<_> = what_web.scan_targets(targets.clone(), 1).collect().await;
+        // 代理断开后剩下的目标不再分发
+        assert!(what_web.is_proxy_aborted());
+        assert!(results.len() < targets.len());
+        let result = what_web.scan(targets[0].clone()).await;
+        assert_eq!(
+            result.error,
+            Some(WardError::ProxyDown(format!("{}/", dead)).to_string())
+        );
+        // 允许直连时代理断开后的目标都能访问
+        let what_web = WhatWeb::new(
+            builder().proxy_fallback(true).build().unwrap(),
+            cookie_fingerprint(),
+        )
+        .unwrap();
+        let results: Vec<_> = what_web.scan_targets(targets.clone(), 1).collect().await;
+        assert!(!what_web.is_proxy_aborted());
+        assert_eq!(results.len(), targets.len());
+        assert!(results.iter().any(|(_, result)| result.status_code == 200));
+        assert_eq!(what_web.scan(targets[0].clone()).await.status_code, 200);
+    }
+
+    #[tokio::test]
+    async fn test_favicon_paths() {
+        let icon: &[u8] = b"\x00\x00\x01\x00weblogic-icon";
+        let url = stub_server(move |request| {
+            if request.starts_with("GET /console/images/favicon.ico ") {
+                http_response("200 OK", &[("Content-Type", "image/x-icon")], icon)
+            } else {
+                http_response(
+                    "200 OK",
+                    &[("Content-Type", "text/html")],
+                    b"<title>login</title>",
+                )
+            }
+        })
+        .await;
+        let md5 = format!("{:x}", Md5::digest(icon));
+        let fingerprint: Vec<WebFingerPrint> = serde_json::from_str(&format!(
+            r#"[{{"name":"weblogic","path":"/","request_method":"get","request_headers":{{}},"request_data":"","status_code":0,"headers":{{}},"keyword":[],"favicon_hash":["{}"],"favicon_paths":["/console/images/favicon.ico"]}}]"#,
+            md5
+        ))
+        .unwrap();
+        let timeout = 10_u64;
+        let what_web = WhatWeb::new(
+            RequestOption::builder().timeout(timeout).build().unwrap(),
+            fingerprint,
+        )
+        .unwrap();
+        let result = what_web.scan(url.to_string()).await;
+        assert!(result.name.contains("weblogic"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 这个主机的请求次数已经用完
+#[derive(Debug, Clone)]
+pub struct BudgetExhausted {
+    pub host: String,
+    pub max_requests: u32,
+}
+
+impl fmt::Display for BudgetExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` reached the limit of {} requests",
+            self.host, self.max_requests
+        )
+    }
+}
+
+impl std::error::Error for BudgetExhausted {}
+
+#[derive(Debug, Default)]
+struct HostState {
+    sent: u32,
+    // 下一个请求最早可以发送的时间
+    next_slot: Option<Instant>,
+}
+
+// 按主机统计请求次数和间隔，不同端口算同一个主机，RequestOption的克隆共用
+#[derive(Debug, Default)]
+pub(crate) struct HostLimiter {
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl HostLimiter {
+    // 先占一个位置再等待，同一个主机的并发请求依次排开，不影响其他主机
+    // interval是和上一个请求至少间隔的时间，由速率限制和随机延时决定
+    pub(crate) async fn acquire(
+        &self,
+        host: &str,
+        interval: Duration,
+        max_requests: Option<u32>,
+    ) -> Result<(), BudgetExhausted> {
+        if interval.is_zero() && max_requests.is_none() {
+            return Ok(());
+        }
+        let wait = {
+            let mut hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+            let state = hosts.entry(host.to_lowercase()).or_default();
+            if let Some(max_requests) = max_requests {
+                if state.sent >= max_requests {
+                    return Err(BudgetExhausted {
+                        host: host.to_string(),
+                        max_requests,
+                    });
+                }
+            }
+            state.sent += 1;
+            let now = Instant::now();
+            let slot = state.next_slot.map_or(now, |slot| slot.max(now));
+            state.next_slot = Some(slot + interval);
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+    // 没有限制时返回None
+    pub(crate) fn remaining(&self, host: &str, max_requests: Option<u32>) -> Option<u32> {
+        let max_requests = max_requests?;
+        let hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+        let sent = hosts
+            .get(&host.to_lowercase())
+            .map(|state| state.sent)
+            .unwrap_or_default();
+        Some(max_requests.saturating_sub(sent))
+    }
+    pub(crate) fn reset(&self) {
+        self.hosts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limit::HostLimiter;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_host_limiter() {
+        let limiter = HostLimiter::default();
+        for _ in 0..3 {
+            limiter
+                .acquire("10.0.0.1", Duration::ZERO, Some(3))
+                .await
+                .unwrap();
+        }
+        assert_eq!(limiter.remaining("10.0.0.1", Some(3)), Some(0));
+        let err = limiter
+            .acquire("10.0.0.1", Duration::ZERO, Some(3))
+            .await
+            .unwrap_err();
+        assert_eq!(err.max_requests, 3);
+        // 其他主机不受影响
+        assert_eq!(limiter.remaining("10.0.0.2", Some(3)), Some(3));
+        assert_eq!(limiter.remaining("10.0.0.1", None), None);
+        limiter.reset();
+        assert_eq!(limiter.remaining("10.0.0.1", Some(3)), Some(3));
+
+        // 第一个不等待，后面每个间隔50ms
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter
+                .acquire("Example.com", Duration::from_millis(50), None)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        let start = Instant::now();
+        limiter
+            .acquire("example.org", Duration::from_millis(50), None)
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{BTreeSet, HashMap};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::WhatWebResult;
+
+// 指纹名称和nuclei标签写法不一样的组件
+const DEFAULT_TAG_ALIASES: &[(&str, &str)] = &[
+    ("apache-shiro", "shiro"),
+    ("apache-tomcat", "tomcat"),
+    ("apache-struts", "struts"),
+    ("apache-struts2", "struts"),
+    ("apache-solr", "solr"),
+    ("apache-flink", "flink"),
+    ("apache-kafka", "kafka"),
+    ("oracle-weblogic", "weblogic"),
+    ("oracle-weblogic-server", "weblogic"),
+    ("atlassian-confluence", "confluence"),
+    ("atlassian-jira", "jira"),
+    ("microsoft-exchange", "exchange"),
+    ("spring-boot", "springboot"),
+    ("jboss-as", "jboss"),
+    ("fortinet-fortigate", "fortigate"),
+];
+
+// 名称末尾的版本号，例如`thinkphp-v5`、`nginx 1.20.1`
+static RE_TRAILING_VERSION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:[-_/]v?|v)[0-9]+(?:\.[0-9]+)*$").expect("RE_TRAILING_VERSION"));
+
+static DEFAULT_NUCLEI_TAGS: Lazy<NucleiTags> = Lazy::new(NucleiTags::default);
+
+/// 把识别到的组件名称转换成nuclei的`-tags`
+#[derive(Debug, Clone)]
+pub struct NucleiTags {
+    aliases: HashMap<String, String>,
+}
+
+impl Default for NucleiTags {
+    fn default() -> Self {
+        let mut nuclei_tags = Self::new();
+        for (name, tag) in DEFAULT_TAG_ALIASES {
+            nuclei_tags.add_alias(name, tag);
+        }
+        nuclei_tags
+    }
+}
+
+impl NucleiTags {
+    /// 不带内置别名表
+    pub fn new() -> Self {
+        Self {
+            aliases: HashMap::new(),
+        }
+    }
+    /// 添加或者覆盖别名，名称按归一化后的结果匹配
+    pub fn add_alias(&mut self, name: &str, tag: &str) {
+        self.aliases
+            .insert(normalize_name(name), normalize_name(tag));
+    }
+    pub fn normalize(&self, name: &str) -> String {
+        let name = normalize_name(name);
+        let name = RE_TRAILING_VERSION.replace(&name, "").to_string();
+        match self.aliases.get(&name) {
+            Some(tag) => tag.clone(),
+            None => name,
+        }
+    }
+    pub fn tags(&self, what_web_result: &WhatWebResult) -> Vec<String> {
+        let tags: BTreeSet<String> = what_web_result
+            .name
+            .iter()
+            .map(|name| self.normalize(name))
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.into_iter().collect()
+    }
+}
+
+// 小写，空白换成-
+fn normalize_name(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+/// 使用内置别名表生成nuclei标签
+pub fn to_nuclei_tags(what_web_result: &WhatWebResult) -> Vec<String> {
+    DEFAULT_NUCLEI_TAGS.tags(what_web_result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_nuclei_tags() {
+        let mut what_web_result = WhatWebResult::new("http://127.0.0.1/".to_string());
+        what_web_result.name = ["Apache-Shiro", "thinkphp-v5", "nginx 1.20.1", "Spring Boot"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(
+            to_nuclei_tags(&what_web_result),
+            vec!["nginx", "shiro", "springboot", "thinkphp"]
+        );
+        let mut nuclei_tags = NucleiTags::default();
+        nuclei_tags.add_alias("Spring Boot", "spring");
+        assert_eq!(
+            nuclei_tags.tags(&what_web_result),
+            vec!["nginx", "shiro", "spring", "thinkphp"]
+        );
+    }
+}
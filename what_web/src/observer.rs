@@ -0,0 +1,135 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode, Version};
+use url::Url;
+
+use crate::error::WardError;
+use crate::fingerprint::{V3WebFingerPrint, WebFingerPrintRequest};
+use crate::ward::RawData;
+use crate::WhatWebResult;
+
+/// 实际发送的一个HTTP请求，HTTP库自动加上的`Host`、`Accept-Encoding`等请求头不在里面
+#[derive(Debug, Clone, Copy)]
+pub struct HttpRequest<'a> {
+    pub method: &'a Method,
+    pub url: &'a Url,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+/// 收到的一个HTTP响应，正文是自动解压后读到的内容，大小受`max_body_size`限制，没有读取正文的响应是空的
+#[derive(Debug, Clone, Copy)]
+pub struct HttpResponse<'a> {
+    pub url: &'a Url,
+    pub status_code: StatusCode,
+    pub version: Version,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+    pub truncated: bool,
+}
+
+/// 扫描过程中的事件回调，默认什么都不做，只需要实现关心的事件
+///
+/// 同一个观察者会被并发的多个目标同时调用
+pub trait ScanObserver: Send + Sync {
+    /// 开始扫描一个目标，端口展开后每个目标一次
+    fn target_started(&self, _target: &str) {}
+    /// 发送一个指纹请求，`url`是请求的目标
+    fn request_sent(&self, _url: &str, _request: &WebFingerPrintRequest) {}
+    /// 收到一个响应，首页跟随跳转时每一跳一次
+    fn raw_data_received(&self, _raw_data: &RawData) {}
+    /// 一个指纹匹配上了响应
+    fn fingerprint_matched(&self, _raw_data: &RawData, _fingerprint: &V3WebFingerPrint) {}
+    /// 目标扫描结束，出错的目标也会调用
+    fn target_finished(&self, _result: &WhatWebResult) {}
+    /// 首页请求失败，目标不是Web
+    fn target_errored(&self, _target: &str, _error: &WardError) {}
+    /// 发出一个HTTP请求，跳转的每一跳、图标和重试都算，`sequence`是同一个目标里从1开始的编号
+    fn http_request(&self, _target: &str, _sequence: u64, _request: &HttpRequest) {}
+    /// 读完一个HTTP响应，`sequence`和对应的请求一样，请求失败时没有
+    fn http_response(&self, _target: &str, _sequence: u64, _response: &HttpResponse) {}
+}
+
+/// 不处理任何事件
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopObserver;
+
+impl ScanObserver for NoopObserver {}
+
+/// 调试模式，打印收到的响应和匹配上的指纹
+#[deprecated(note = "scan details are logged through `log`, enable debug or trace logging instead")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DebugObserver;
+
+#[allow(deprecated)]
+impl ScanObserver for DebugObserver {
+    fn raw_data_received(&self, raw_data: &RawData) {
+        println!("{}", raw_data);
+    }
+    fn fingerprint_matched(&self, _raw_data: &RawData, fingerprint: &V3WebFingerPrint) {
+        println!("Matching fingerprint{:#?}", fingerprint);
+    }
+}
+
+/// 按调试开关选择内置的观察者
+#[deprecated(note = "scan details are logged through `log`, enable debug or trace logging instead")]
+#[allow(deprecated)]
+pub fn debug_observer(debug: bool) -> Arc<dyn ScanObserver> {
+    if debug {
+        Arc::new(DebugObserver)
+    } else {
+        Arc::new(NoopObserver)
+    }
+}
+
+// 发送请求时记下编号，读完响应后用它对应到请求
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HttpSequence(pub(crate) u64);
+
+// 扫描一个目标时挂在配置上，把HTTP请求和响应交给观察者
+#[derive(Clone)]
+pub(crate) struct HttpHook {
+    target: Arc<str>,
+    observer: Arc<dyn ScanObserver>,
+    sequence: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for HttpHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpHook")
+            .field("target", &self.target)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpHook {
+    pub(crate) fn new(target: &str, observer: Arc<dyn ScanObserver>) -> Self {
+        Self {
+            target: Arc::from(target),
+            observer,
+            sequence: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    pub(crate) fn request(&self, request: &reqwest::Request) -> HttpSequence {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let body = request.body().and_then(|body| body.as_bytes());
+        self.observer.http_request(
+            &self.target,
+            sequence,
+            &HttpRequest {
+                method: request.method(),
+                url: request.url(),
+                headers: request.headers(),
+                body: body.unwrap_or_default(),
+            },
+        );
+        HttpSequence(sequence)
+    }
+    pub(crate) fn response(&self, sequence: HttpSequence, response: &HttpResponse) {
+        self.observer
+            .http_response(&self.target, sequence.0, response);
+    }
+}
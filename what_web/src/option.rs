@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use reqwest::header::{HeaderName, HeaderValue};
+
+use crate::dns::{DnsResolver, DnsServer};
+use crate::limit::HostLimiter;
+use crate::proxy::ProxyMonitor;
+use crate::request::{ascii_host, build_client, connect_dropped_headers, parse_proxy};
+use crate::{AuthScheme, HttpVersion, RedirectPolicy, RequestOption, ScanCache, ScanCounters};
+
+pub(crate) const DEFAULT_TIMEOUT: u64 = 10;
+const DEFAULT_RETRY_INTERVAL_MS: u64 = 500;
+const DEFAULT_MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+// 代理断开时一般所有目标都连接失败，连续20个足够排除偶尔连不上的目标
+const DEFAULT_PROXY_FAILURE_THRESHOLD: u32 = 20;
+
+/// `RequestOptionBuilder::build`的错误，配置不合法时在创建时报告，而不是每个请求都失败
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestOptionError {
+    ZeroTimeout,
+    ZeroTargetDeadline,
+    InvalidProxy(String),
+    InvalidUserAgent(String),
+    InvalidHeader { name: String, value: String },
+    InvalidRateLimit(f32),
+    InvalidJitter { min: u64, max: u64 },
+    InvalidMinConfidence(u32),
+    InvalidDnsServer(String),
+    ZeroRequestBudget,
+    // 同时设置了不能一起使用的选项
+    Conflict(String),
+    Client(String),
+}
+
+impl fmt::Display for RequestOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestOptionError::ZeroTimeout => write!(f, "Timeout must be at least 1 second"),
+            RequestOptionError::ZeroTargetDeadline => {
+                write!(f, "Target deadline must be greater than zero")
+            }
+            RequestOptionError::InvalidProxy(err) => write!(f, "{}", err),
+            RequestOptionError::InvalidUserAgent(user_agent) => {
+                write!(f, "Invalid User-Agent {}", user_agent)
+            }
+            RequestOptionError::InvalidHeader { name, value } => {
+                write!(f, "Invalid Header {}: {}", name, value)
+            }
+            RequestOptionError::InvalidRateLimit(rps) => {
+                write!(f, "Invalid rate limit {}, expected a positive number", rps)
+            }
+            RequestOptionError::InvalidJitter { min, max } => {
+                write!(f, "Invalid jitter {}-{}, MIN is larger than MAX", min, max)
+            }
+            RequestOptionError::InvalidMinConfidence(confidence) => {
+                write!(f, "Invalid min confidence {}, expected 0-100", confidence)
+            }
+            RequestOptionError::InvalidDnsServer(err) => write!(f, "{}", err),
+            RequestOptionError::ZeroRequestBudget => {
+                write!(f, "Max requests per host must be at least 1")
+            }
+            RequestOptionError::Conflict(err) => write!(f, "{}", err),
+            RequestOptionError::Client(err) => write!(f, "Failed to build http client: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RequestOptionError {}
+
+/// 逐项设置`RequestOption`，`build`时统一校验。没有设置的项和`RequestOption::default()`一样
+///
+/// ```
+/// use what_web::{RedirectPolicy, RequestOption};
+///
+/// let option = RequestOption::builder()
+///     .timeout(5)
+///     .user_agent("scanner/1.0")
+///     .header("X-Scan", "1")
+///     .retries(2)
+///     .redirect_policy(RedirectPolicy::SameDomain)
+///     .rate_limit(10.0)
+///     .build()
+///     .unwrap();
+/// assert_eq!(option.timeout(), 5);
+/// assert!(RequestOption::builder().timeout(0).build().is_err());
+/// ```
+#[derive(Debug)]
+pub struct RequestOptionBuilder {
+    timeout: u64,
+    proxy: String,
+    proxy_headers: Vec<(String, String)>,
+    user_agent: Option<String>,
+    headers: Vec<(String, String)>,
+    retries: u8,
+    retry_interval_ms: u64,
+    target_deadline: Option<Duration>,
+    max_body_size: usize,
+    redirect_policy: RedirectPolicy,
+    ports: Vec<u16>,
+    all_schemes: bool,
+    shiro_detect: bool,
+    tcp_banner: bool,
+    cross_host_favicon: bool,
+    verify_tls: bool,
+    min_confidence: u32,
+    verbose_matches: bool,
+    requests_per_second: Option<f32>,
+    max_requests_per_host: Option<u32>,
+    shuffle_probes: bool,
+    polite: bool,
+    proxy_failure_threshold: u32,
+    proxy_fallback: bool,
+    jitter_ms: Option<(u64, u64)>,
+    seed: Option<u64>,
+    auth: Option<AuthScheme>,
+    http_version: HttpVersion,
+    resolve: Vec<(String, IpAddr)>,
+    dns_server: Option<String>,
+    cache: Option<ScanCache>,
+}
+
+impl Default for RequestOptionBuilder {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            proxy: String::new(),
+            proxy_headers: vec![],
+            user_agent: None,
+            headers: vec![],
+            retries: 0,
+            retry_interval_ms: DEFAULT_RETRY_INTERVAL_MS,
+            target_deadline: None,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            redirect_policy: RedirectPolicy::default(),
+            ports: vec![],
+            all_schemes: false,
+            shiro_detect: false,
+            tcp_banner: true,
+            cross_host_favicon: false,
+            verify_tls: false,
+            min_confidence: 0,
+            verbose_matches: false,
+            requests_per_second: None,
+            max_requests_per_host: None,
+            shuffle_probes: false,
+            polite: false,
+            proxy_failure_threshold: DEFAULT_PROXY_FAILURE_THRESHOLD,
+            proxy_fallback: false,
+            jitter_ms: None,
+            seed: None,
+            auth: None,
+            http_version: HttpVersion::default(),
+            resolve: vec![],
+            dns_server: None,
+            cache: None,
+        }
+    }
+}
+
+impl RequestOptionBuilder {
+    // 请求超时的秒数
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    // http、https、socks5或者socks5h代理，空字符串表示不使用代理
+    pub fn proxy(mut self, proxy: &str) -> Self {
+        self.proxy = proxy.to_string();
+        self
+    }
+    // 可以多次调用，只发给http(s)代理，例如事先算好的`Proxy-Authorization`
+    pub fn proxy_header(mut self, name: &str, value: &str) -> Self {
+        self.proxy_headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+    // 可以多次调用，合并到默认请求头，指纹自带的请求头优先
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+    pub fn retry_interval_ms(mut self, retry_interval_ms: u64) -> Self {
+        self.retry_interval_ms = retry_interval_ms;
+        self
+    }
+    // 单个目标包括跳转、图标和特殊请求在内的总时间，超过后返回已经拿到的结果
+    pub fn target_deadline(mut self, deadline: Duration) -> Self {
+        self.target_deadline = Some(deadline);
+        self
+    }
+    pub fn max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+    pub fn redirect_policy(mut self, redirect_policy: RedirectPolicy) -> Self {
+        self.redirect_policy = redirect_policy;
+        self
+    }
+    pub fn ports(mut self, ports: Vec<u16>) -> Self {
+        self.ports = ports;
+        self
+    }
+    pub fn all_schemes(mut self, all_schemes: bool) -> Self {
+        self.all_schemes = all_schemes;
+        self
+    }
+    pub fn shiro_detect(mut self, shiro_detect: bool) -> Self {
+        self.shiro_detect = shiro_detect;
+        self
+    }
+    // 首页不是HTTP时读取banner识别其他服务，默认开启
+    pub fn tcp_banner(mut self, tcp_banner: bool) -> Self {
+        self.tcp_banner = tcp_banner;
+        self
+    }
+    pub fn cross_host_favicon(mut self, cross_host_favicon: bool) -> Self {
+        self.cross_host_favicon = cross_host_favicon;
+        self
+    }
+    // 扫描照常接受任何证书，另外记录证书能不能通过校验
+    pub fn verify_tls(mut self, verify_tls: bool) -> Self {
+        self.verify_tls = verify_tls;
+        self
+    }
+    pub fn min_confidence(mut self, min_confidence: u32) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+    pub fn verbose_matches(mut self, verbose_matches: bool) -> Self {
+        self.verbose_matches = verbose_matches;
+        self
+    }
+    // 每个主机每秒最多的请求数
+    pub fn rate_limit(mut self, requests_per_second: f32) -> Self {
+        self.requests_per_second = Some(requests_per_second);
+        self
+    }
+    pub fn max_requests_per_host(mut self, max_requests: u32) -> Self {
+        self.max_requests_per_host = Some(max_requests);
+        self
+    }
+    pub fn shuffle_probes(mut self, shuffle_probes: bool) -> Self {
+        self.shuffle_probes = shuffle_probes;
+        self
+    }
+    // 请求robots.txt，不发送路径被`User-agent: *`禁止的特殊请求
+    pub fn polite(mut self, polite: bool) -> Self {
+        self.polite = polite;
+        self
+    }
+    // 连续这么多个目标连接失败时检查代理，代理也连不上时剩下的目标不再扫描，0表示不检查
+    pub fn proxy_failure_threshold(mut self, threshold: u32) -> Self {
+        self.proxy_failure_threshold = threshold;
+        self
+    }
+    // 检测到代理断开后剩下的目标直接连接
+    pub fn proxy_fallback(mut self, proxy_fallback: bool) -> Self {
+        self.proxy_fallback = proxy_fallback;
+        self
+    }
+    pub fn jitter_ms(mut self, min: u64, max: u64) -> Self {
+        self.jitter_ms = Some((min, max));
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    pub fn auth(mut self, auth: AuthScheme) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+    pub fn http_version(mut self, http_version: HttpVersion) -> Self {
+        self.http_version = http_version;
+        self
+    }
+    /// 把域名的请求发到指定的IP，包括favicon和证书的请求。可以多次调用，同一个域名后面的覆盖前面的。
+    /// 经过http代理或者socks5h代理时由代理解析域名，不生效
+    pub fn resolve(mut self, host: &str, ip: IpAddr) -> Self {
+        self.resolve.push((host.to_string(), ip));
+        self
+    }
+    // 解析目标域名的DNS服务器，`IP[:PORT]`走UDP，`https://`开头的是DoH
+    pub fn dns_server(mut self, dns_server: &str) -> Self {
+        self.dns_server = Some(dns_server.to_string());
+        self
+    }
+    /// 长时间运行的服务可以设置过期时间，或者用`ScanCache::disabled()`每次都重新请求
+    pub fn cache(mut self, cache: ScanCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    pub fn build(self) -> Result<RequestOption, RequestOptionError> {
+        if self.timeout == 0 {
+            return Err(RequestOptionError::ZeroTimeout);
+        }
+        if self.target_deadline == Some(Duration::ZERO) {
+            return Err(RequestOptionError::ZeroTargetDeadline);
+        }
+        let proxy = if self.proxy.is_empty() {
+            None
+        } else {
+            Some(
+                parse_proxy(&self.proxy)
+                    .map_err(|err| RequestOptionError::InvalidProxy(err.to_string()))?,
+            )
+        };
+        if let Some(user_agent) = &self.user_agent {
+            if HeaderValue::from_str(user_agent).is_err() {
+                return Err(RequestOptionError::InvalidUserAgent(user_agent.clone()));
+            }
+        }
+        for (name, value) in self.headers.iter().chain(self.proxy_headers.iter()) {
+            if HeaderName::from_str(name).is_err() || HeaderValue::from_str(value).is_err() {
+                return Err(RequestOptionError::InvalidHeader {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+        if let Some(rps) = self.requests_per_second {
+            // 太小的速率算出来的间隔放不进Duration
+            if !rps.is_finite() || rps <= 0.0 || Duration::try_from_secs_f32(1.0 / rps).is_err() {
+                return Err(RequestOptionError::InvalidRateLimit(rps));
+            }
+        }
+        if let Some((min, max)) = self.jitter_ms {
+            if min > max {
+                return Err(RequestOptionError::InvalidJitter { min, max });
+            }
+        }
+        if self.min_confidence > 100 {
+            return Err(RequestOptionError::InvalidMinConfidence(
+                self.min_confidence,
+            ));
+        }
+        if self.max_requests_per_host == Some(0) {
+            return Err(RequestOptionError::ZeroRequestBudget);
+        }
+        let dns_server = match &self.dns_server {
+            Some(dns_server) => Some(
+                DnsServer::from_str(dns_server).map_err(RequestOptionError::InvalidDnsServer)?,
+            ),
+            None => None,
+        };
+        match &proxy {
+            _ if self.proxy_headers.is_empty() => {}
+            None => {
+                return Err(RequestOptionError::Conflict(String::from(
+                    "Proxy headers require a proxy",
+                )))
+            }
+            Some(proxy) if proxy.scheme().starts_with("socks5") => {
+                return Err(RequestOptionError::Conflict(String::from(
+                    "Proxy headers do not apply to a socks5 proxy",
+                )))
+            }
+            Some(_) => {
+                // 不拒绝，http目标和获取证书、banner的连接照样带上
+                let dropped = connect_dropped_headers(&self.proxy_headers);
+                if !dropped.is_empty() {
+                    log::warn!(
+                        "Proxy headers {} are not sent on CONNECT for https targets, only http targets and certificate and banner connections carry them",
+                        dropped.join(", ")
+                    );
+                }
+            }
+        }
+        // 只有socks5在本地解析域名，其他代理由代理解析，指定的IP不会生效
+        if let Some(proxy) = &proxy {
+            if !self.resolve.is_empty() && proxy.scheme() != "socks5" {
+                return Err(RequestOptionError::Conflict(format!(
+                    "Resolve overrides do not apply through a {} proxy, use socks5 instead",
+                    proxy.scheme()
+                )));
+            }
+            if dns_server.is_some() && proxy.scheme() != "socks5" {
+                return Err(RequestOptionError::Conflict(format!(
+                    "A custom DNS server does not apply through a {} proxy, use socks5 instead",
+                    proxy.scheme()
+                )));
+            }
+        }
+        let resolve: HashMap<String, IpAddr> = self
+            .resolve
+            .into_iter()
+            .map(|(host, ip)| (ascii_host(&host), ip))
+            .collect();
+        let dns = dns_server.map(|dns_server| {
+            Arc::new(DnsResolver::new(
+                dns_server,
+                Duration::from_secs(self.timeout),
+            ))
+        });
+        let client = build_client(
+            self.timeout,
+            proxy.clone(),
+            &self.proxy_headers,
+            self.http_version,
+            &resolve,
+            dns.as_ref(),
+        )
+        .map_err(|err| RequestOptionError::Client(err.to_string()))?;
+        let rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ok(RequestOption {
+            timeout: self.timeout,
+            proxy,
+            proxy_headers: self.proxy_headers,
+            shiro_detect: self.shiro_detect,
+            tcp_banner: self.tcp_banner,
+            cross_host_favicon: self.cross_host_favicon,
+            verify_tls: self.verify_tls,
+            min_confidence: self.min_confidence,
+            verbose_matches: self.verbose_matches,
+            user_agent: self.user_agent,
+            custom_headers: self.headers,
+            target_headers: vec![],
+            credential_host: None,
+            retries: self.retries,
+            retry_interval_ms: self.retry_interval_ms,
+            target_deadline: self.target_deadline,
+            max_body_size: self.max_body_size,
+            redirect_policy: self.redirect_policy,
+            ports: self.ports,
+            all_schemes: self.all_schemes,
+            requests_per_second: self.requests_per_second,
+            max_requests_per_host: self.max_requests_per_host,
+            shuffle_probes: self.shuffle_probes,
+            polite: self.polite,
+            proxy_failure_threshold: self.proxy_failure_threshold,
+            proxy_fallback: self.proxy_fallback,
+            jitter_ms: self.jitter_ms,
+            auth: self.auth,
+            http_version: self.http_version,
+            resolve,
+            dns,
+            client,
+            cache: Arc::new(self.cache.unwrap_or_default()),
+            counters: Arc::new(ScanCounters::default()),
+            limiter: Arc::new(HostLimiter::default()),
+            proxy_monitor: Arc::new(ProxyMonitor::default()),
+            rng: Arc::new(Mutex::new(rng)),
+            http_hook: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::option::{RequestOptionError, DEFAULT_TIMEOUT};
+    use crate::{RequestOption, ScanCache};
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_request_option_builder() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let option = RequestOption::builder()
+            .timeout(3)
+            .proxy("socks5://127.0.0.1:1080")
+            .user_agent("scanner/1.0")
+            .header("X-Scan", "1")
+            .retries(2)
+            .max_requests_per_host(20)
+            .jitter_ms(10, 20)
+            .resolve("Example.com", ip)
+            .cache(ScanCache::disabled())
+            .build()
+            .unwrap();
+        assert_eq!(option.timeout(), 3);
+        assert_eq!(option.retries(), 2);
+        assert_eq!(option.max_requests_per_host(), Some(20));
+        assert_eq!(option.jitter_ms(), Some((10, 20)));
+        assert_eq!(
+            option.proxy().map(|proxy| proxy.as_str()),
+            Some("socks5://127.0.0.1:1080")
+        );
+        assert_eq!(option.resolved_ip("example.com"), Some(ip));
+        assert_eq!(option.remaining_requests("example.com"), Some(20));
+        assert_eq!(option.cache().capacity(), 0);
+        let default = RequestOption::default();
+        assert_eq!(default.timeout(), 10);
+        assert!(default.proxy().is_none());
+
+        let err = |builder: crate::RequestOptionBuilder| builder.build().unwrap_err();
+        assert_eq!(
+            err(RequestOption::builder().timeout(0)),
+            RequestOptionError::ZeroTimeout
+        );
+        assert!(matches!(
+            err(RequestOption::builder().proxy("ftp://127.0.0.1")),
+            RequestOptionError::InvalidProxy(_)
+        ));
+        assert!(matches!(
+            err(RequestOption::builder().header("Bad Name", "1")),
+            RequestOptionError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            err(RequestOption::builder().user_agent("a\nb")),
+            RequestOptionError::InvalidUserAgent(_)
+        ));
+        assert_eq!(
+            err(RequestOption::builder().rate_limit(0.0)),
+            RequestOptionError::InvalidRateLimit(0.0)
+        );
+        for rps in [-1.0, f32::NAN, f32::INFINITY, 1e-30] {
+            assert!(matches!(
+                err(RequestOption::builder().rate_limit(rps)),
+                RequestOptionError::InvalidRateLimit(_)
+            ));
+        }
+        assert_eq!(
+            err(RequestOption::builder().jitter_ms(20, 10)),
+            RequestOptionError::InvalidJitter { min: 20, max: 10 }
+        );
+        assert_eq!(
+            err(RequestOption::builder().max_requests_per_host(0)),
+            RequestOptionError::ZeroRequestBudget
+        );
+        assert_eq!(
+            err(RequestOption::builder().target_deadline(Duration::ZERO)),
+            RequestOptionError::ZeroTargetDeadline
+        );
+        assert!(matches!(
+            err(RequestOption::builder()
+                .proxy("http://127.0.0.1:8080")
+                .resolve("example.com", ip)),
+            RequestOptionError::Conflict(_)
+        ));
+        assert!(matches!(
+            err(RequestOption::builder().proxy_header("Proxy-Authorization", "Basic a2FsaQ==")),
+            RequestOptionError::Conflict(_)
+        ));
+        assert!(matches!(
+            err(RequestOption::builder()
+                .proxy("socks5://127.0.0.1:1080")
+                .proxy_header("Proxy-Authorization", "Basic a2FsaQ==")),
+            RequestOptionError::Conflict(_)
+        ));
+        assert!(matches!(
+            err(RequestOption::builder()
+                .proxy("http://127.0.0.1:8080")
+                .proxy_header("Proxy-Authorization", "a\nb")),
+            RequestOptionError::InvalidHeader { .. }
+        ));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_new_falls_back_to_defaults() {
+        let option = RequestOption::new(&0, "http://127.0.0.1:8080");
+        assert_eq!(option.timeout(), DEFAULT_TIMEOUT);
+        assert!(option.proxy().is_some());
+        let option = RequestOption::new(&5, "ftp://127.0.0.1");
+        assert_eq!(option.timeout(), 5);
+        assert!(option.proxy().is_none());
+        assert!(RequestOption::try_new(&5, "ftp://127.0.0.1").is_err());
+    }
+}
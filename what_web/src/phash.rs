@@ -0,0 +1,293 @@
+use std::io::Cursor;
+
+use image::{ImageReader, Limits};
+use serde::{Deserialize, Deserializer, Serializer};
+
+// 图标一般不超过256像素，再大的多半是解压炸弹
+const MAX_DIMENSION: usize = 2048;
+// 解码时最多分配的内存，最大尺寸的16位RGBA图片
+const MAX_ALLOC: u64 = (MAX_DIMENSION * MAX_DIMENSION * 8) as u64;
+
+// 解码后的图片，每个像素一个灰度值，透明的部分和白色混合
+struct GrayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<f32>,
+}
+
+impl GrayImage {
+    fn new(width: usize, height: usize) -> Option<Self> {
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            pixels: vec![255.0; width * height],
+        })
+    }
+    fn set(&mut self, x: usize, y: usize, [r, g, b, a]: [u8; 4]) {
+        let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+        let alpha = a as f32 / 255.0;
+        self.pixels[y * self.width + x] = luma * alpha + 255.0 * (1.0 - alpha);
+    }
+    // 缩小到width*height，每个格子取覆盖的像素的平均值，比格子还小的图片取最近的像素
+    fn resize(&self, width: usize, height: usize) -> Vec<f32> {
+        let span = |i: usize, from: usize, to: usize| {
+            let start = i * from / to;
+            let end = ((i + 1) * from / to).max(start + 1).min(from);
+            start..end
+        };
+        let mut resized = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let rows = span(y, self.height, height);
+            for x in 0..width {
+                let columns = span(x, self.width, width);
+                let mut sum = 0.0;
+                for row in rows.clone() {
+                    sum += self.pixels
+                        [row * self.width + columns.start..row * self.width + columns.end]
+                        .iter()
+                        .sum::<f32>();
+                }
+                resized.push(sum / (rows.len() * columns.len()) as f32);
+            }
+        }
+        resized
+    }
+}
+
+/// favicon的差异哈希（dHash），缩小成9x8的灰度图，每一行相邻像素左边比右边亮时对应的位是1。
+/// 重新编码过的图标内容不同但哈希相同或者只差几位，支持PNG、BMP和ICO，解不出来时返回`None`
+pub(crate) fn dhash(content: &[u8]) -> Option<u64> {
+    let image = decode_image(content)?;
+    let pixels = image.resize(9, 8);
+    let mut hash = 0_u64;
+    for row in pixels.chunks(9) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | (pair[0] > pair[1]) as u64;
+        }
+    }
+    Some(hash)
+}
+
+/// 两个dHash不同的位数
+pub fn dhash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// 解码前先按文件头里的尺寸检查上限，超过的不分配内存
+fn decode_image(content: &[u8]) -> Option<GrayImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION as u32);
+    limits.max_image_height = Some(MAX_DIMENSION as u32);
+    limits.max_alloc = Some(MAX_ALLOC);
+    let mut reader = ImageReader::new(Cursor::new(content))
+        .with_guessed_format()
+        .ok()?;
+    reader.limits(limits);
+    let decoded = reader.decode().ok()?.into_rgba8();
+    let mut image = GrayImage::new(decoded.width() as usize, decoded.height() as usize)?;
+    for (x, y, pixel) in decoded.enumerate_pixels() {
+        image.set(x as usize, y as usize, pixel.0);
+    }
+    Some(image)
+}
+
+// 结果里写成16位的十六进制，JSON的数字放不下u64
+pub(crate) fn serialize_dhash<S: Serializer>(
+    dhash: &Option<u64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match dhash {
+        Some(dhash) => serializer.serialize_str(&format!("{:016x}", dhash)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn deserialize_dhash<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(hex) => u64::from_str_radix(&hex, 16)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::{Compression, Crc};
+
+    use super::*;
+    use crate::request::{favicon_hash, FaviconHash};
+
+    const SIDE: usize = 16;
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    // 白底上一个偏左上的深色圆
+    fn pixel(x: usize, y: usize) -> [u8; 4] {
+        let (dx, dy) = (x as i32 - 6, y as i32 - 5);
+        if dx * dx + dy * dy < 20 {
+            [20, 40, 160, 255]
+        } else {
+            [250, 250, 250, 255]
+        }
+    }
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let mut crc = Crc::new();
+        crc.update(kind);
+        crc.update(data);
+        png.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+
+    // 只有文件头的PNG，像素数据是空的
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut encoder = ZlibEncoder::new(vec![], Compression::fast());
+        encoder.write_all(&[0; 64]).unwrap();
+        let mut png = PNG_SIGNATURE.to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    // 32位的BMP文件头，没有像素数据
+    fn bmp_header(width: i32, height: i32) -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        bmp.extend_from_slice(&54_u32.to_le_bytes());
+        bmp.extend_from_slice(&[0; 4]);
+        bmp.extend_from_slice(&54_u32.to_le_bytes());
+        bmp.extend_from_slice(&40_u32.to_le_bytes());
+        bmp.extend_from_slice(&width.to_le_bytes());
+        bmp.extend_from_slice(&height.to_le_bytes());
+        bmp.extend_from_slice(&1_u16.to_le_bytes());
+        bmp.extend_from_slice(&32_u16.to_le_bytes());
+        bmp.extend_from_slice(&[0; 24]);
+        bmp
+    }
+
+    fn encode_png(filter: u8, level: Compression) -> Vec<u8> {
+        let mut raw = vec![];
+        for y in 0..SIDE {
+            raw.push(filter);
+            let line: Vec<u8> = (0..SIDE).flat_map(|x| pixel(x, y)).collect();
+            for (i, byte) in line.iter().enumerate() {
+                // Sub：减去左边一个像素同一通道的值
+                let left = if filter == 1 && i >= 4 {
+                    line[i - 4]
+                } else {
+                    0
+                };
+                raw.push(byte.wrapping_sub(left));
+            }
+        }
+        let mut encoder = ZlibEncoder::new(vec![], level);
+        encoder.write_all(&raw).unwrap();
+        let mut header = vec![];
+        header.extend_from_slice(&(SIDE as u32).to_be_bytes());
+        header.extend_from_slice(&(SIDE as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut png = PNG_SIGNATURE.to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    // 32位BMP的ICO，从下往上存，后面跟全0的掩码
+    fn encode_ico() -> Vec<u8> {
+        let mut dib = vec![];
+        dib.extend_from_slice(&40_u32.to_le_bytes());
+        dib.extend_from_slice(&(SIDE as u32).to_le_bytes());
+        dib.extend_from_slice(&(SIDE as u32 * 2).to_le_bytes());
+        dib.extend_from_slice(&1_u16.to_le_bytes());
+        dib.extend_from_slice(&32_u16.to_le_bytes());
+        dib.extend_from_slice(&[0; 24]);
+        for y in (0..SIDE).rev() {
+            for x in 0..SIDE {
+                let [r, g, b, a] = pixel(x, y);
+                dib.extend_from_slice(&[b, g, r, a]);
+            }
+        }
+        dib.extend_from_slice(&[0; 4 * SIDE]);
+        let mut ico = vec![0, 0, 1, 0, 1, 0];
+        ico.extend_from_slice(&[SIDE as u8, SIDE as u8, 0, 0, 1, 0, 32, 0]);
+        ico.extend_from_slice(&(dib.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&22_u32.to_le_bytes());
+        ico.extend_from_slice(&dib);
+        ico
+    }
+
+    #[test]
+    fn test_dhash() {
+        let png = encode_png(0, Compression::best());
+        let reencoded = encode_png(1, Compression::fast());
+        let ico = encode_ico();
+        assert_ne!(png, reencoded);
+        let hash = dhash(&png).unwrap();
+        assert_ne!(hash, 0);
+        assert_eq!(dhash(&reencoded), Some(hash));
+        assert_eq!(dhash(&ico), Some(hash));
+        // 图标底部加了条纹，哈希只差几位
+        let mut changed = ico.clone();
+        for (i, pixel) in changed[62..62 + 8 * SIDE].chunks_mut(4).enumerate() {
+            if i % 3 == 0 {
+                pixel.copy_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+        let changed = dhash(&changed).unwrap();
+        assert!((1..=16).contains(&dhash_distance(hash, changed)));
+        // 解不出来的图标只有md5和mmh3
+        for content in [
+            &ico[..30],
+            &png[..40],
+            b"\x00\x00\x01\x00\x01\x00",
+            b"<html>",
+        ] {
+            assert_eq!(dhash(content), None);
+            let favicon_hash = favicon_hash(content);
+            assert_eq!(favicon_hash.dhash, None);
+            assert_eq!(favicon_hash.md5.len(), 32);
+        }
+        // 结果里是十六进制，解不出来时没有这个字段
+        let png_hash = favicon_hash(&png);
+        let json = serde_json::to_value(&png_hash).unwrap();
+        assert_eq!(json["dhash"], format!("{:016x}", hash));
+        assert_eq!(
+            serde_json::from_value::<FaviconHash>(json).unwrap(),
+            png_hash
+        );
+        let json = serde_json::to_value(favicon_hash(b"<html>")).unwrap();
+        assert!(json.get("dhash").is_none());
+    }
+
+    #[test]
+    fn test_dhash_rejects_bad_images() {
+        let png = encode_png(0, Compression::best());
+        // 截断在像素数据中间
+        assert_eq!(dhash(&png[..png.len() - 20]), None);
+        assert_eq!(dhash(&encode_ico()[..100]), None);
+        // 文件头里的尺寸超过上限，不分配内存直接放弃
+        assert_eq!(dhash(&png_header(100_000, 100_000)), None);
+        assert_eq!(dhash(&png_header(4096, 16)), None);
+        assert_eq!(dhash(&bmp_header(100_000, 100_000)), None);
+        assert_eq!(dhash(&bmp_header(16, -100_000)), None);
+        // 宽或者高为0
+        assert_eq!(dhash(&png_header(0, 16)), None);
+        assert_eq!(dhash(&png_header(16, 0)), None);
+        assert_eq!(dhash(&bmp_header(0, 16)), None);
+    }
+}
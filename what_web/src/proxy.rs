@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::OnceCell;
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::request::FetchErrorKind;
+use crate::WhatWebResult;
+
+// 连续的失败要在这段时间内发生，隔很久才出现的零星失败不算
+const FAILURE_WINDOW: Duration = Duration::from_secs(120);
+// 代理断开后隔这么久再检查一次，不用每个目标都去连接
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct FailureRun {
+    count: u32,
+    since: Option<Instant>,
+}
+
+// 连续多个目标都连接失败时检查代理本身还能不能连上，RequestOption的克隆共用
+#[derive(Debug, Default)]
+pub(crate) struct ProxyMonitor {
+    run: Mutex<FailureRun>,
+    down: AtomicBool,
+    // 上一次检查代理能不能连接的时间
+    last_probe: Mutex<Option<Instant>>,
+    // 代理断开后直连用的客户端，第一次用到时创建
+    pub(crate) direct_client: OnceCell<reqwest::Client>,
+}
+
+impl ProxyMonitor {
+    pub(crate) fn is_down(&self) -> bool {
+        self.down.load(Ordering::SeqCst)
+    }
+    // 记录一个目标的结果，返回是否攒够了连续的失败，攒够后重新计数
+    fn record(&self, failed: bool, threshold: u32) -> bool {
+        let mut run = self.run.lock().unwrap_or_else(|err| err.into_inner());
+        if !failed {
+            *run = FailureRun::default();
+            return false;
+        }
+        let now = Instant::now();
+        match run.since {
+            Some(since) if now.duration_since(since) <= FAILURE_WINDOW => run.count += 1,
+            _ => {
+                run.count = 1;
+                run.since = Some(now);
+            }
+        }
+        if run.count >= threshold {
+            *run = FailureRun::default();
+            return true;
+        }
+        false
+    }
+    // 目标本身连不上时代理还能连接，只有代理也连不上才认为代理断开了
+    pub(crate) async fn observe(
+        &self,
+        result: &WhatWebResult,
+        proxy: &Url,
+        threshold: u32,
+        timeout: Duration,
+    ) {
+        if threshold == 0 || self.is_down() {
+            return;
+        }
+        if !self.record(is_connect_failure(result), threshold) {
+            return;
+        }
+        if !proxy_reachable(proxy, timeout).await {
+            log::error!(
+                "Proxy `{}` is unreachable after {} targets in a row failed to connect",
+                proxy,
+                threshold
+            );
+            *self
+                .last_probe
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()) = Some(Instant::now());
+            self.down.store(true, Ordering::SeqCst);
+        }
+    }
+    // 代理断开后每隔一段时间检查一次，能连上了就恢复走代理
+    pub(crate) async fn recheck(&self, proxy: &Url, timeout: Duration) {
+        if !self.is_down() {
+            return;
+        }
+        {
+            let mut last_probe = self
+                .last_probe
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            if matches!(*last_probe, Some(last) if last.elapsed() < RECHECK_INTERVAL) {
+                return;
+            }
+            *last_probe = Some(Instant::now());
+        }
+        if proxy_reachable(proxy, timeout).await {
+            log::info!("Proxy `{}` is reachable again", proxy);
+            self.down.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+// 所有协议都是连接失败或者超时，代理断开时每个目标都是这样
+fn is_connect_failure(result: &WhatWebResult) -> bool {
+    result.error.is_some()
+        && !result.errors.is_empty()
+        && result
+            .errors
+            .iter()
+            .all(|err| matches!(err.kind, FetchErrorKind::Connect | FetchErrorKind::Timeout))
+}
+
+async fn proxy_reachable(proxy: &Url, timeout: Duration) -> bool {
+    let host = proxy
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = proxy.port_or_known_default().unwrap_or(1080);
+    matches!(
+        tokio::time::timeout(timeout, TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FetchError;
+    use tokio::net::TcpListener;
+
+    fn failed_result(kind: FetchErrorKind) -> WhatWebResult {
+        let mut result = WhatWebResult::new(String::from("http://10.0.0.1/"));
+        let url = Url::parse("http://10.0.0.1/").unwrap();
+        result.error = Some(String::from("error sending request"));
+        result.errors = vec![FetchError::new(&url, kind, String::from("refused"))];
+        result
+    }
+
+    #[tokio::test]
+    async fn test_proxy_monitor() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let alive = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        // 绑定后马上关闭，端口上没有服务
+        let dead = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            Url::parse(&format!("socks5://{}", listener.local_addr().unwrap())).unwrap()
+        };
+        let timeout = Duration::from_secs(2);
+        let connect = failed_result(FetchErrorKind::Connect);
+        let monitor = ProxyMonitor::default();
+        // 中间有一个目标能访问时重新计数
+        let reachable = WhatWebResult::new(String::from("http://10.0.0.2/"));
+        for result in [&connect, &connect, &reachable, &connect] {
+            monitor.observe(result, &dead, 3, timeout).await;
+        }
+        assert!(!monitor.is_down());
+        // TLS握手失败说明已经通过代理连上了
+        for _ in 0..3 {
+            monitor
+                .observe(&failed_result(FetchErrorKind::Tls), &dead, 3, timeout)
+                .await;
+        }
+        assert!(!monitor.is_down());
+        // 代理还能连上时是目标的问题
+        for _ in 0..3 {
+            monitor.observe(&connect, &alive, 3, timeout).await;
+        }
+        assert!(!monitor.is_down());
+        for _ in 0..3 {
+            monitor.observe(&connect, &dead, 0, timeout).await;
+        }
+        assert!(!monitor.is_down());
+        monitor.observe(&connect, &dead, 3, timeout).await;
+        monitor
+            .observe(&failed_result(FetchErrorKind::Timeout), &dead, 3, timeout)
+            .await;
+        assert!(!monitor.is_down());
+        monitor.observe(&connect, &dead, 3, timeout).await;
+        assert!(monitor.is_down());
+        // 刚检查过不再连接，过了间隔后代理能连上就恢复
+        monitor.recheck(&alive, timeout).await;
+        assert!(monitor.is_down());
+        let expire = || {
+            *monitor.last_probe.lock().unwrap() = Instant::now().checked_sub(RECHECK_INTERVAL);
+        };
+        expire();
+        monitor.recheck(&dead, timeout).await;
+        assert!(monitor.is_down());
+        expire();
+        monitor.recheck(&alive, timeout).await;
+        assert!(!monitor.is_down());
+    }
+}
@@ -0,0 +1,223 @@
+use reqwest::header;
+use url::Url;
+
+use crate::fingerprint::WebFingerPrintRequest;
+use crate::ward::RawData;
+
+// 只保留前面这么多字节，再大的robots.txt多半不是给爬虫看的
+const MAX_ROBOTS_SIZE: usize = 512 * 1024;
+
+pub(crate) fn robots_request() -> WebFingerPrintRequest {
+    WebFingerPrintRequest {
+        path: String::from("/robots.txt"),
+        ..Default::default()
+    }
+}
+
+// 特殊请求实际访问的路径和查询参数，和robots.txt的规则比较
+pub(crate) fn request_path(base_url: &str, request: &WebFingerPrintRequest) -> String {
+    match Url::parse(base_url).and_then(|url| url.join(&request.path)) {
+        Ok(url) => match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        },
+        Err(_) => request.path.clone(),
+    }
+}
+
+// 不存在的路径都返回首页的站点会把html当成robots.txt，只要200而且不是html的响应
+pub(crate) fn robots_text(raw_data: &RawData) -> Option<String> {
+    let is_html = raw_data
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_lowercase().contains("html"));
+    if !raw_data.status_code.is_success() || is_html || raw_data.text.trim().is_empty() {
+        return None;
+    }
+    let mut text = raw_data.text.clone();
+    if text.len() > MAX_ROBOTS_SIZE {
+        let mut end = MAX_ROBOTS_SIZE;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    Some(text)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RobotsRule {
+    Allow(String),
+    Disallow(String),
+}
+
+impl RobotsRule {
+    fn pattern(&self) -> &str {
+        match self {
+            Self::Allow(pattern) | Self::Disallow(pattern) => pattern,
+        }
+    }
+}
+
+/// 解析后的robots.txt，只看`User-agent: *`的规则，多个`*`分组合并在一起
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Robots {
+    rules: Vec<RobotsRule>,
+}
+
+impl Robots {
+    /// 忽略注释、空行和不认识的字段，连续的多行`User-agent`共用后面的规则
+    pub fn parse(text: &str) -> Self {
+        let mut rules = vec![];
+        let mut in_global = false;
+        // 上一行是规则时，下一行`User-agent`开始新的分组
+        let mut after_rule = true;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            match field.as_str() {
+                "user-agent" => {
+                    if after_rule {
+                        in_global = false;
+                        after_rule = false;
+                    }
+                    in_global |= value == "*";
+                }
+                "allow" | "disallow" => {
+                    after_rule = true;
+                    // 空的Disallow表示不限制
+                    if !in_global || value.is_empty() {
+                        continue;
+                    }
+                    let pattern = value.to_string();
+                    rules.push(if field == "allow" {
+                        RobotsRule::Allow(pattern)
+                    } else {
+                        RobotsRule::Disallow(pattern)
+                    });
+                }
+                _ => {}
+            }
+        }
+        Self { rules }
+    }
+    /// 路径带上查询参数，最长的规则生效，一样长时Allow优先
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut matched: Option<&RobotsRule> = None;
+        for rule in self.rules.iter() {
+            if !pattern_match(rule.pattern(), path) {
+                continue;
+            }
+            let longer = match matched {
+                None => true,
+                Some(current) => {
+                    let (len, current_len) = (rule.pattern().len(), current.pattern().len());
+                    len > current_len
+                        || (len == current_len && matches!(rule, RobotsRule::Allow(_)))
+                }
+            };
+            if longer {
+                matched = Some(rule);
+            }
+        }
+        !matches!(matched, Some(RobotsRule::Disallow(_)))
+    }
+}
+
+// `*`匹配任意字符，结尾的`$`要求路径到这里结束，其他按前缀比较
+fn pattern_match(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        // 要求结尾的最后一段从末尾匹配，其他取最早出现的位置
+        if anchored && index == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(offset) => rest = &rest[offset + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let robots = Robots::parse(
+            "# robots for example.com\r\n\
+             User-agent: Googlebot\r\n\
+             Disallow: /nogoogle/\r\n\
+             \r\n\
+             User-agent: Bingbot\r\n\
+             User-Agent: *  # everyone else\r\n\
+             Disallow: /admin/\r\n\
+             Allow: /admin/login\r\n\
+             Disallow: /*.php$\r\n\
+             Disallow:\r\n\
+             \r\n\
+             User-agent: *\r\n\
+             Disallow: /wp-admin/\r\n\
+             Sitemap: https://example.com/sitemap.xml\r\n",
+        );
+        for (path, allowed) in [
+            ("/", true),
+            ("/nogoogle/index.html", true),
+            ("/admin/", false),
+            ("/admin/config", false),
+            ("/admin/login", true),
+            ("/admin/login?next=/", true),
+            ("/index.php", false),
+            ("/index.php?id=1", true),
+            ("/wp-admin/install.php", false),
+            ("/WP-ADMIN/", true),
+        ] {
+            assert_eq!(robots.is_allowed(path), allowed, "{}", path);
+        }
+        // 没有`*`分组时都允许
+        let robots = Robots::parse("User-agent: Googlebot\nDisallow: /\n");
+        assert!(robots.is_allowed("/admin"));
+        let robots = Robots::parse("User-agent: *\nDisallow: /\n");
+        assert!(!robots.is_allowed("/"));
+        assert!(Robots::parse("").is_allowed("/"));
+    }
+
+    #[test]
+    fn test_pattern_match() {
+        for (pattern, path, matched) in [
+            ("/admin", "/admin/index", true),
+            ("/admin", "/administrator", true),
+            ("/admin$", "/admin/", false),
+            ("/*/private", "/a/b/private/c", true),
+            ("/*.json$", "/api/v1/users.json", true),
+            ("/*.json$", "/api/v1/users.json.bak", false),
+            ("/*", "/", true),
+            ("/a*b*c", "/axxbyyc", true),
+            ("/a*b*c", "/axxcyyb", false),
+        ] {
+            assert_eq!(
+                pattern_match(pattern, path),
+                matched,
+                "{} {}",
+                pattern,
+                path
+            );
+        }
+    }
+}
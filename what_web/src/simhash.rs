@@ -0,0 +1,163 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::WhatWebResult;
+
+// 少于这么多词的页面太短，哈希没有区分度
+const MIN_TOKENS: usize = 8;
+
+/// 正文按词计算64位SimHash，模板相同只有主机名、时间之类不同的页面只差几位，词太少时返回`None`。
+/// 字母、数字和汉字连在一起算一个词，忽略大小写，纯数字不算，出现多次的词权重更高
+pub(crate) fn simhash(text: &str) -> Option<u64> {
+    let mut tokens: HashMap<String, i64> = HashMap::new();
+    for token in text.split(|c: char| !c.is_alphanumeric()) {
+        if token.is_empty() || token.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        *tokens.entry(token.to_lowercase()).or_default() += 1;
+    }
+    if tokens.values().sum::<i64>() < MIN_TOKENS as i64 {
+        return None;
+    }
+    let mut weights = [0_i64; 64];
+    for (token, count) in tokens {
+        let hash = token_hash(&token);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += count;
+            } else {
+                *weight -= count;
+            }
+        }
+    }
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0, |hash, (bit, _)| hash | 1 << bit),
+    )
+}
+
+// FNV-1a再打散一次，std的Hasher不保证不同版本的结果一样，保存下来的哈希要能比较
+fn token_hash(token: &str) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for b in token.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ hash >> 33
+}
+
+/// 相似的一组目标
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimHashCluster {
+    // 组里第一个目标的SimHash
+    pub simhash: String,
+    pub urls: Vec<String>,
+}
+
+/// 把SimHash相差不超过`max_distance`位的目标放在一组，和组里任意一个目标相似就算，没有SimHash的目标不参与。
+/// 目标多的组在前，组里的目标按URL排序去重
+pub fn cluster_by_simhash<'a>(
+    results: impl IntoIterator<Item = &'a WhatWebResult>,
+    max_distance: u32,
+) -> Vec<SimHashCluster> {
+    let hashes: Vec<(&str, u64)> = results
+        .into_iter()
+        .filter_map(|result| result.simhash.map(|hash| (result.url.as_str(), hash)))
+        .collect();
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            if (hashes[i].1 ^ hashes[j].1).count_ones() <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups: HashMap<usize, (u64, BTreeSet<&str>)> = HashMap::new();
+    for (i, (url, hash)) in hashes.iter().enumerate() {
+        let index = root(&mut parent, i);
+        groups
+            .entry(index)
+            .or_insert_with(|| (*hash, BTreeSet::new()))
+            .1
+            .insert(url);
+    }
+    let mut clusters: Vec<SimHashCluster> = groups
+        .into_values()
+        .map(|(hash, urls)| SimHashCluster {
+            simhash: format!("{:016x}", hash),
+            urls: urls.into_iter().map(String::from).collect(),
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.urls.len().cmp(&a.urls.len()).then(a.urls.cmp(&b.urls)));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER_A: &str = include_str!("../tests/fixtures/router_a.html");
+    const ROUTER_B: &str = include_str!("../tests/fixtures/router_b.html");
+    const INDEX: &str = include_str!("../tests/fixtures/index.html");
+    const JUMP: &str = include_str!("../tests/fixtures/jump.html");
+
+    fn distance(a: &str, b: &str) -> u32 {
+        (simhash(a).unwrap() ^ simhash(b).unwrap()).count_ones()
+    }
+
+    #[test]
+    fn test_simhash() {
+        // 同一个型号的两台设备，序列号、主机名和时间不同
+        assert!(distance(ROUTER_A, ROUTER_B) <= 3);
+        assert_eq!(simhash(ROUTER_A), simhash(ROUTER_A));
+        assert_eq!(distance(ROUTER_A, &ROUTER_A.to_uppercase()), 0);
+        for (a, b) in [(ROUTER_A, INDEX), (ROUTER_B, INDEX), (ROUTER_A, JUMP)] {
+            assert!(distance(a, b) > 10);
+        }
+        assert_eq!(simhash(""), None);
+        assert_eq!(simhash("<html>403 Forbidden</html>"), None);
+    }
+
+    #[test]
+    fn test_cluster_by_simhash() {
+        let result = |url: &str, text: &str| {
+            let mut what_web_result = WhatWebResult::new(url.to_string());
+            what_web_result.simhash = simhash(text);
+            what_web_result
+        };
+        let results = vec![
+            result("http://10.0.0.3/", ROUTER_B),
+            result("http://10.0.0.1/", INDEX),
+            result("http://10.0.0.2/", ROUTER_A),
+            result("http://10.0.0.4/", ""),
+            result("http://10.0.0.2/", ROUTER_A),
+        ];
+        let clusters = cluster_by_simhash(&results, 3);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].urls, ["http://10.0.0.2/", "http://10.0.0.3/"]);
+        assert_eq!(
+            clusters[0].simhash,
+            format!("{:016x}", simhash(ROUTER_B).unwrap())
+        );
+        assert_eq!(clusters[1].urls, ["http://10.0.0.1/"]);
+        // 距离为0时只有完全一样的页面在一组
+        assert_eq!(cluster_by_simhash(&results, 0).len(), 3);
+        assert!(cluster_by_simhash(&results[3..4], 3).is_empty());
+    }
+}
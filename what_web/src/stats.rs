@@ -0,0 +1,310 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::WhatWebResult;
+
+// 汇总里列出的组件和标题个数
+const TOP_COUNT: usize = 10;
+
+/// 发送的请求数和下载的字节数，克隆出来的`RequestOption`共用同一份，
+/// 重试、favicon和特殊请求都算在里面
+#[derive(Debug, Default)]
+pub struct ScanCounters {
+    requests: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ScanCounters {
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+    pub fn reset(&self) {
+        self.requests.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+    pub(crate) fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn add_bytes(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// 一次扫描的汇总统计，可以边扫边`add`，也可以用`from_results`从结果计算
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ScanStats {
+    pub targets: usize,
+    pub reachable: usize,
+    pub matched: usize,
+    pub errored: usize,
+    // 组件名称到识别出这个组件的目标数
+    pub components: BTreeMap<String, usize>,
+    pub status_codes: BTreeMap<u16, usize>,
+    #[serde(rename = "top_titles", serialize_with = "serialize_top")]
+    pub titles: HashMap<String, usize>,
+    // 请求失败的原因到次数，一个目标的每个协议分别计数
+    pub errors: BTreeMap<String, usize>,
+    pub requests: u64,
+    pub bytes: u64,
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+    // 每个目标首页的响应耗时，毫秒
+    #[serde(rename = "response_time_ms", serialize_with = "serialize_percentiles")]
+    response_times: Vec<u64>,
+}
+
+fn top<K: Ord + Clone>(counts: impl Iterator<Item = (K, usize)>, n: usize) -> Vec<(K, usize)> {
+    let mut counts: Vec<(K, usize)> = counts.collect();
+    // 次数一样的按名称排，输出稳定
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+}
+
+fn serialize_top<S: Serializer>(
+    titles: &HashMap<String, usize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    top(titles.iter().map(|(k, v)| (k.clone(), *v)), TOP_COUNT).serialize(serializer)
+}
+
+fn serialize_secs<S: Serializer>(elapsed: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(elapsed.as_secs_f64())
+}
+
+#[derive(Serialize)]
+struct Percentiles {
+    p50: Option<u64>,
+    p90: Option<u64>,
+    p99: Option<u64>,
+    max: Option<u64>,
+}
+
+fn serialize_percentiles<S: Serializer>(
+    response_times: &[u64],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut sorted = response_times.to_vec();
+    sorted.sort_unstable();
+    Percentiles {
+        p50: nearest_rank(&sorted, 50.0),
+        p90: nearest_rank(&sorted, 90.0),
+        p99: nearest_rank(&sorted, 99.0),
+        max: sorted.last().copied(),
+    }
+    .serialize(serializer)
+}
+
+// 最近秩法，不插值，结果总是某个目标真实的耗时
+fn nearest_rank(sorted: &[u64], percentile: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len()) - 1).copied()
+}
+
+impl ScanStats {
+    pub fn from_results(results: &[WhatWebResult]) -> Self {
+        let mut stats = Self::default();
+        for result in results {
+            stats.add(result);
+        }
+        stats
+    }
+    pub fn add(&mut self, result: &WhatWebResult) {
+        self.targets += 1;
+        for error in &result.errors {
+            *self.errors.entry(error.kind.to_string()).or_default() += 1;
+        }
+        if !result.is_web || result.error.is_some() {
+            self.errored += 1;
+            if result.errors.is_empty() {
+                *self.errors.entry(String::from("other")).or_default() += 1;
+            }
+            return;
+        }
+        self.reachable += 1;
+        if !result.name.is_empty() {
+            self.matched += 1;
+        }
+        for name in &result.name {
+            *self.components.entry(name.clone()).or_default() += 1;
+        }
+        *self.status_codes.entry(result.status_code).or_default() += 1;
+        if !result.title.is_empty() {
+            *self.titles.entry(result.title.clone()).or_default() += 1;
+        }
+        self.response_times.push(result.response_time);
+    }
+    // 记录扫描期间的请求数和字节数
+    pub fn with_counters(mut self, counters: &ScanCounters) -> Self {
+        self.requests = counters.requests();
+        self.bytes = counters.bytes();
+        self
+    }
+    pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = elapsed;
+        self
+    }
+    /// 能访问的目标首页响应耗时的百分位数，`percentile`取0到100
+    pub fn response_time_percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut sorted = self.response_times.clone();
+        sorted.sort_unstable();
+        nearest_rank(&sorted, percentile).map(Duration::from_millis)
+    }
+    pub fn top_components(&self, n: usize) -> Vec<(String, usize)> {
+        top(self.components.iter().map(|(k, v)| (k.clone(), *v)), n)
+    }
+    pub fn top_titles(&self, n: usize) -> Vec<(String, usize)> {
+        top(self.titles.iter().map(|(k, v)| (k.clone(), *v)), n)
+    }
+}
+
+fn write_counts<K: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    counts: &[(K, usize)],
+) -> fmt::Result {
+    if counts.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "{}:", title)?;
+    for (key, count) in counts {
+        writeln!(f, "  {:>6}  {}", count, key)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ScanStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} targets, {} reachable, {} matched, {} errored",
+            self.targets, self.reachable, self.matched, self.errored
+        )?;
+        writeln!(
+            f,
+            "{} requests, {} bytes downloaded in {:.1}s",
+            self.requests,
+            self.bytes,
+            self.elapsed.as_secs_f64()
+        )?;
+        let ms = |p: f64| {
+            self.response_time_percentile(p)
+                .map(|d| format!("{}ms", d.as_millis()))
+                .unwrap_or_else(|| String::from("-"))
+        };
+        writeln!(
+            f,
+            "response time p50 {}, p90 {}, p99 {}",
+            ms(50.0),
+            ms(90.0),
+            ms(99.0)
+        )?;
+        write_counts(f, "Components", &self.top_components(TOP_COUNT))?;
+        let status_codes: Vec<(u16, usize)> =
+            self.status_codes.iter().map(|(k, v)| (*k, *v)).collect();
+        write_counts(f, "Status codes", &status_codes)?;
+        let titles: Vec<(String, usize)> = self
+            .top_titles(TOP_COUNT)
+            .into_iter()
+            .map(|(title, count)| (format!("{:?}", title), count))
+            .collect();
+        write_counts(f, "Titles", &titles)?;
+        let errors = top(self.errors.iter().map(|(k, v)| (k.clone(), *v)), usize::MAX);
+        write_counts(f, "Errors", &errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{nearest_rank, ScanCounters, ScanStats};
+    use crate::{FetchError, FetchErrorKind, WhatWebResult};
+    use std::time::Duration;
+    use url::Url;
+
+    fn result(names: &[&str], status_code: u16, title: &str, response_time: u64) -> WhatWebResult {
+        let mut result = WhatWebResult::new(String::from("http://example.com/"));
+        result.name = names.iter().map(|name| name.to_string()).collect();
+        result.status_code = status_code;
+        result.title = title.to_string();
+        result.response_time = response_time;
+        result
+    }
+
+    #[test]
+    fn test_nearest_rank() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(nearest_rank(&sorted, 50.0), Some(50));
+        assert_eq!(nearest_rank(&sorted, 99.0), Some(99));
+        assert_eq!(nearest_rank(&sorted, 0.0), Some(1));
+        assert_eq!(nearest_rank(&[7], 90.0), Some(7));
+        assert_eq!(nearest_rank(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_scan_stats() {
+        let mut unreachable = WhatWebResult::new(String::from("http://10.0.0.1/"));
+        unreachable.is_web = false;
+        unreachable.error = Some(String::from("unreachable"));
+        let url = Url::parse("http://10.0.0.1/").unwrap();
+        unreachable.errors = vec![
+            FetchError::new(&url, FetchErrorKind::Timeout, String::from("timed out")),
+            FetchError::new(&url, FetchErrorKind::Connect, String::from("refused")),
+        ];
+        let results = vec![
+            result(&["tomcat", "java"], 200, "Apache Tomcat", 30),
+            result(&["tomcat"], 404, "Apache Tomcat", 10),
+            result(&[], 200, "", 20),
+            unreachable,
+        ];
+        let counters = ScanCounters::default();
+        counters.add_request();
+        counters.add_request();
+        counters.add_bytes(1024);
+        let stats = ScanStats::from_results(&results)
+            .with_counters(&counters)
+            .with_elapsed(Duration::from_millis(1500));
+        assert_eq!(
+            (stats.targets, stats.reachable, stats.matched, stats.errored),
+            (4, 3, 2, 1)
+        );
+        assert_eq!(stats.components["tomcat"], 2);
+        assert_eq!(stats.status_codes[&200], 2);
+        assert_eq!(stats.errors["timeout"], 1);
+        assert_eq!(stats.errors["connect"], 1);
+        assert_eq!(stats.top_components(1), vec![(String::from("tomcat"), 2)]);
+        assert_eq!(
+            stats.top_titles(5),
+            vec![(String::from("Apache Tomcat"), 2)]
+        );
+        assert_eq!(
+            stats.response_time_percentile(50.0),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!((stats.requests, stats.bytes), (2, 1024));
+        let text = stats.to_string();
+        assert!(text.contains("4 targets, 3 reachable, 2 matched, 1 errored"));
+        assert!(text.contains("2 requests, 1024 bytes downloaded in 1.5s"));
+        assert!(text.contains("response time p50 20ms, p90 30ms, p99 30ms"));
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["top_titles"][0][0], "Apache Tomcat");
+        assert_eq!(json["response_time_ms"]["max"], 30);
+        assert_eq!(json["elapsed_secs"], 1.5);
+        // 一边扫一边累加和从结果计算一样
+        let mut incremental = ScanStats::default();
+        for result in &results {
+            incremental.add(result);
+        }
+        assert_eq!(incremental.components, stats.components);
+        assert_eq!(incremental.titles, stats.titles);
+    }
+}
@@ -0,0 +1,824 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::BufRead;
+use std::net::Ipv6Addr;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+
+use crate::WardError;
+
+/// 要扫描的目标，带上的请求头只对这个目标的请求生效，覆盖默认请求头，指纹自带的请求头优先
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ScanTarget {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl ScanTarget {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim().to_string(),
+            headers: vec![],
+        }
+    }
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    // 多次调用合并到同一个Cookie请求头
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        let cookie = format!("{}={}", name, value);
+        match self
+            .headers
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+        {
+            Some((_, value)) => {
+                value.push_str("; ");
+                value.push_str(&cookie);
+            }
+            None => self.headers.push((String::from("Cookie"), cookie)),
+        }
+        self
+    }
+    // 按端口展开后的目标带着同样的请求头
+    pub(crate) fn with_url(&self, url: String) -> Self {
+        Self {
+            url,
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+// JSON格式的一行目标
+#[derive(Debug, Deserialize)]
+struct TargetLine {
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    cookies: BTreeMap<String, String>,
+}
+
+impl From<TargetLine> for ScanTarget {
+    fn from(line: TargetLine) -> Self {
+        let mut target = ScanTarget::new(&line.url);
+        for (name, value) in line.headers.iter() {
+            target = target.header(name, value);
+        }
+        for (name, value) in line.cookies.iter() {
+            target = target.cookie(name, value);
+        }
+        target
+    }
+}
+
+// CSV表头里各列的位置
+#[derive(Debug, Clone, Copy)]
+struct CsvColumns {
+    url: usize,
+    headers: Option<usize>,
+    cookies: Option<usize>,
+}
+
+impl CsvColumns {
+    // 表头有`url`列而且都是列名，其他不认识的列忽略
+    fn parse(line: &str) -> Option<Self> {
+        let names: Vec<String> = line
+            .split(',')
+            .map(|name| name.trim().trim_matches('"').to_lowercase())
+            .collect();
+        let is_header = names.len() > 1
+            && names
+                .iter()
+                .all(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        let position = |column: &str| names.iter().position(|name| name == column);
+        Some(Self {
+            url: position("url").filter(|_| is_header)?,
+            headers: position("headers"),
+            cookies: position("cookies"),
+        })
+    }
+    fn target(&self, record: &csv::StringRecord) -> Result<Option<ScanTarget>, WardError> {
+        let url = record.get(self.url).unwrap_or_default().trim();
+        if url.is_empty() || url.starts_with('#') {
+            return Ok(None);
+        }
+        let invalid = |reason: String| WardError::InvalidTarget {
+            target: url.to_string(),
+            reason,
+        };
+        let mut target = ScanTarget::new(url);
+        // 一行一个`NAME: VALUE`
+        let headers = self.headers.and_then(|index| record.get(index));
+        for header in headers.unwrap_or_default().lines() {
+            if header.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| invalid(format!("header `{}` is not NAME: VALUE", header)))?;
+            target = target.header(name.trim(), value.trim());
+        }
+        // 和Cookie请求头一样用`;`分隔
+        let cookies = self.cookies.and_then(|index| record.get(index));
+        for cookie in cookies.unwrap_or_default().split(';') {
+            if cookie.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = cookie
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("cookie `{}` is not NAME=VALUE", cookie)))?;
+            target = target.cookie(name.trim(), value.trim());
+        }
+        Ok(Some(target))
+    }
+}
+
+// 一行一个URL，或者一个JSON对象，空行和`#`注释返回None
+fn parse_target_line(line: &str) -> Option<Result<ScanTarget, WardError>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    if !line.starts_with('{') {
+        return Some(Ok(ScanTarget::new(line)));
+    }
+    Some(
+        serde_json::from_str::<TargetLine>(line)
+            .map(ScanTarget::from)
+            .map_err(|err| WardError::InvalidTarget {
+                target: line.to_string(),
+                reason: err.to_string(),
+            }),
+    )
+}
+
+/// 读取目标文件，跳过空行和`#`注释。每行是一个URL或者JSON对象，例如
+/// `{"url": "http://10.0.0.1/", "headers": {"X-Tenant": "ops"}, "cookies": {"JSESSIONID": "abc"}}`。
+/// 第一行是`url,headers,cookies`这样的表头时按CSV读取，headers一行一个`NAME: VALUE`，cookies是`a=1; b=2`。
+/// 边读边返回，格式不对的行返回错误，不影响后面的行
+pub fn read_targets<R>(mut reader: R) -> Box<dyn Iterator<Item = Result<ScanTarget, WardError>>>
+where
+    R: BufRead + 'static,
+{
+    // 第一行有效内容决定文件的格式
+    let mut first = String::new();
+    loop {
+        first.clear();
+        match reader.read_line(&mut first) {
+            Ok(0) | Err(_) => return Box::new(std::iter::empty()),
+            Ok(_) => {}
+        }
+        // Excel导出的CSV开头有BOM
+        let line = first.trim_start_matches('\u{feff}').trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            break;
+        }
+    }
+    let first = first.trim_start_matches('\u{feff}').to_string();
+    match CsvColumns::parse(&first) {
+        Some(columns) => {
+            let records = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(reader)
+                .into_records();
+            Box::new(records.filter_map(move |record| match record {
+                Ok(record) => columns.target(&record).transpose(),
+                Err(err) => Some(Err(WardError::InvalidTarget {
+                    target: String::from("csv"),
+                    reason: err.to_string(),
+                })),
+            }))
+        }
+        None => Box::new(
+            std::iter::once(first)
+                .chain(reader.lines().map_while(Result::ok))
+                .filter_map(|line| parse_target_line(&line)),
+        ),
+    }
+}
+
+// 没有服务信息时按端口判断是不是HTTP
+const HTTP_PORTS: &[u16] = &[
+    80, 81, 443, 591, 593, 800, 808, 888, 2082, 2083, 2086, 2087, 3000, 5000, 7001, 7080, 7443,
+    8000, 8001, 8008, 8080, 8081, 8088, 8443, 8888, 9000, 9080, 9090, 9443,
+];
+
+// nmap的`http-proxy`、`https-alt`，masscan的`http.server`和`title`都算HTTP
+fn is_http_service(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("http") || name.contains("www") || name == "title" || name == "html"
+}
+
+// IPv6地址需要用方括号
+fn host_port(host: &str, port: u16) -> String {
+    match host.parse::<Ipv6Addr>() {
+        Ok(_) => format!("[{}]:{}", host, port),
+        Err(_) => format!("{}:{}", host, port),
+    }
+}
+
+fn invalid_scan_output(format: &str, reason: String) -> WardError {
+    WardError::InvalidTarget {
+        target: format.to_string(),
+        reason,
+    }
+}
+
+// nmap结果里一个开放的端口
+#[derive(Debug, Default)]
+struct NmapPort {
+    port: u16,
+    tcp: bool,
+    open: bool,
+    service: Option<String>,
+    probed: bool,
+    ssl: bool,
+}
+
+impl NmapPort {
+    // 没有`-sV`时服务名称是按端口查表得到的，同时参考常见的HTTP端口
+    fn is_http(&self) -> bool {
+        self.service.as_deref().is_some_and(is_http_service)
+            || (!self.probed && HTTP_PORTS.contains(&self.port))
+    }
+    // 识别出协议的带上协议，其他的两个协议都试
+    fn scheme(&self) -> &'static str {
+        let service = self.service.as_deref().unwrap_or_default();
+        if self.ssl || service.starts_with("https") {
+            "https://"
+        } else if self.probed && is_http_service(service) {
+            "http://"
+        } else {
+            ""
+        }
+    }
+}
+
+// 读到`</host>`之前一台主机的信息
+#[derive(Debug)]
+struct NmapHost {
+    up: bool,
+    address: Option<String>,
+    hostname: Option<String>,
+    ports: Vec<NmapPort>,
+}
+
+impl Default for NmapHost {
+    fn default() -> Self {
+        Self {
+            up: true,
+            address: None,
+            hostname: None,
+            ports: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct NmapState {
+    all_ports: bool,
+    host: Option<NmapHost>,
+    in_port: bool,
+    pending: VecDeque<ScanTarget>,
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == name)
+        .and_then(|attr| attr.unescape_value().ok().map(|value| value.into_owned()))
+}
+
+impl NmapState {
+    fn start(&mut self, element: &BytesStart) {
+        let name = element.name();
+        if name.as_ref() == b"host" {
+            self.host = Some(NmapHost::default());
+            return;
+        }
+        // `hosthint`里也有`status`和`address`，只看`host`里面的
+        let host = match self.host.as_mut() {
+            Some(host) => host,
+            None => return,
+        };
+        match name.as_ref() {
+            b"status" => host.up = attribute(element, b"state").as_deref() == Some("up"),
+            // 同一台主机还会有`mac`地址
+            b"address"
+                if host.address.is_none()
+                    && matches!(
+                        attribute(element, b"addrtype").as_deref(),
+                        Some("ipv4" | "ipv6")
+                    ) =>
+            {
+                host.address = attribute(element, b"addr")
+            }
+            // 扫描时写的域名，虚拟主机需要用域名访问
+            b"hostname"
+                if host.hostname.is_none()
+                    && attribute(element, b"type").as_deref() == Some("user") =>
+            {
+                host.hostname = attribute(element, b"name")
+            }
+            b"port" => {
+                self.in_port = true;
+                host.ports.push(NmapPort {
+                    port: attribute(element, b"portid")
+                        .and_then(|port| port.parse().ok())
+                        .unwrap_or_default(),
+                    tcp: attribute(element, b"protocol").as_deref() == Some("tcp"),
+                    ..NmapPort::default()
+                });
+            }
+            b"state" if self.in_port => {
+                if let Some(port) = host.ports.last_mut() {
+                    port.open = attribute(element, b"state").as_deref() == Some("open");
+                }
+            }
+            b"service" if self.in_port => {
+                if let Some(port) = host.ports.last_mut() {
+                    port.service = attribute(element, b"name");
+                    port.probed = attribute(element, b"method").as_deref() == Some("probed");
+                    port.ssl = attribute(element, b"tunnel").as_deref() == Some("ssl");
+                }
+            }
+            _ => {}
+        }
+    }
+    fn end(&mut self, name: &[u8]) {
+        match name {
+            b"port" => self.in_port = false,
+            b"host" => {
+                let host = match self.host.take() {
+                    Some(host) if host.up => host,
+                    _ => return,
+                };
+                let name = match host.hostname.or(host.address) {
+                    Some(name) => name,
+                    None => return,
+                };
+                let all_ports = self.all_ports;
+                self.pending.extend(
+                    host.ports
+                        .iter()
+                        .filter(|port| port.tcp && port.open && port.port != 0)
+                        .filter(|port| all_ports || port.is_http())
+                        .map(|port| {
+                            ScanTarget::new(&format!(
+                                "{}{}",
+                                port.scheme(),
+                                host_port(&name, port.port)
+                            ))
+                        }),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+// 按`host`元素边读边返回
+struct NmapTargets<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    state: NmapState,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for NmapTargets<R> {
+    type Item = Result<ScanTarget, WardError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(target) = self.state.pending.pop_front() {
+                return Some(Ok(target));
+            }
+            if self.done {
+                return None;
+            }
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf) {
+                Ok(Event::Start(element)) => self.state.start(&element),
+                Ok(Event::Empty(element)) => {
+                    self.state.start(&element);
+                    self.state.end(element.name().as_ref());
+                }
+                Ok(Event::End(element)) => self.state.end(element.name().as_ref()),
+                Ok(Event::Eof) => self.done = true,
+                Ok(_) => {}
+                // XML不完整时前面读到的主机照常返回
+                Err(err) => {
+                    self.done = true;
+                    let position = self.reader.buffer_position();
+                    return Some(Err(invalid_scan_output(
+                        "nmap xml",
+                        format!("at byte {}: {}", position, err),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+// masscan -oJ的一条记录，开放状态和banner是分开的两条记录
+#[derive(Debug, Deserialize)]
+struct MasscanRecord {
+    ip: String,
+    #[serde(default)]
+    ports: Vec<MasscanPort>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MasscanPort {
+    port: u16,
+    #[serde(default)]
+    proto: String,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    service: Option<MasscanService>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MasscanService {
+    name: String,
+}
+
+// 同一个端口的多条记录合在一起，按主机和端口排序
+#[derive(Debug, Default)]
+struct MasscanPorts {
+    ports: BTreeMap<(String, u16), Vec<String>>,
+}
+
+impl MasscanPorts {
+    // 没有开放状态只有banner的记录也说明端口是开放的
+    fn add(
+        &mut self,
+        ip: &str,
+        port: u16,
+        proto: &str,
+        status: Option<&str>,
+        service: Option<&str>,
+    ) {
+        if proto != "tcp" || status.is_some_and(|status| status != "open") {
+            return;
+        }
+        let services = self.ports.entry((ip.to_string(), port)).or_default();
+        services.extend(service.map(|service| service.to_lowercase()));
+    }
+    // `ssl`和`X509`的banner说明不了上面是什么协议，没有其他banner时按端口判断
+    fn targets(self, all_ports: bool) -> impl Iterator<Item = ScanTarget> {
+        self.ports
+            .into_iter()
+            .filter(move |((_, port), services)| {
+                let services: Vec<&String> = services
+                    .iter()
+                    .filter(|service| !matches!(service.as_str(), "ssl" | "x509"))
+                    .collect();
+                all_ports
+                    || match services.is_empty() {
+                        true => HTTP_PORTS.contains(port),
+                        false => services.iter().any(|service| is_http_service(service)),
+                    }
+            })
+            .map(|((ip, port), _)| ScanTarget::new(&host_port(&ip, port)))
+    }
+}
+
+// 新版本每行一条记录，逗号在行首；旧版本逗号在行尾，最后一行是`{finished: 1}`
+fn read_masscan_json(text: &str, ports: &mut MasscanPorts) -> Vec<WardError> {
+    let records = match serde_json::from_str::<Vec<MasscanRecord>>(text) {
+        Ok(records) => records.into_iter().map(Ok).collect(),
+        Err(_) => text
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['[', ',']))
+            .map(|line| line.trim_end_matches([']', ',']).trim())
+            .filter(|line| !line.is_empty() && !line.starts_with("{finished"))
+            .map(|line| {
+                serde_json::from_str::<MasscanRecord>(line)
+                    .map_err(|err| invalid_scan_output(line, err.to_string()))
+            })
+            .collect::<Vec<_>>(),
+    };
+    let mut errors = vec![];
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+        for port in record.ports {
+            ports.add(
+                &record.ip,
+                port.port,
+                &port.proto,
+                port.status.as_deref(),
+                port.service.as_ref().map(|service| service.name.as_str()),
+            );
+        }
+    }
+    errors
+}
+
+// `open tcp 80 10.0.0.1 1390380064`，banner行在时间后面多了服务名称和banner
+fn read_masscan_list(text: &str, ports: &mut MasscanPorts) -> Vec<WardError> {
+    let mut errors = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let port = fields.get(2).and_then(|port| port.parse::<u16>().ok());
+        match (fields.first(), fields.get(1), port, fields.get(3)) {
+            (Some(&"banner"), Some(proto), Some(port), Some(ip)) => {
+                ports.add(ip, port, proto, None, fields.get(5).copied())
+            }
+            (Some(status), Some(proto), Some(port), Some(ip)) => {
+                ports.add(ip, port, proto, Some(status), None)
+            }
+            _ => errors.push(invalid_scan_output(
+                line,
+                String::from("not a masscan list line"),
+            )),
+        }
+    }
+    errors
+}
+
+/// 读取端口扫描的结果作为目标，按内容判断是nmap的XML（`-oX`）还是masscan的JSON（`-oJ`）或者列表（`-oL`），
+/// 只取开放的TCP端口，nmap主机有扫描时写的域名时用域名。`all_ports`为false时只取服务名称像HTTP的端口，
+/// 没有服务信息时按常见的HTTP端口判断。nmap的结果边读边返回，masscan同一个端口的记录是分开的，读完后再返回
+pub fn read_scan_output<R>(
+    mut reader: R,
+    all_ports: bool,
+) -> Box<dyn Iterator<Item = Result<ScanTarget, WardError>>>
+where
+    R: BufRead + 'static,
+{
+    // 第一个有效字符决定格式
+    let first = loop {
+        let buf = match reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(err) => {
+                return Box::new(std::iter::once(Err(invalid_scan_output(
+                    "scan output",
+                    err.to_string(),
+                ))))
+            }
+        };
+        let skip = match buf {
+            [] => return Box::new(std::iter::empty()),
+            [0xef, 0xbb, 0xbf, ..] => 3,
+            _ => buf.iter().take_while(|b| b.is_ascii_whitespace()).count(),
+        };
+        if skip == 0 {
+            break buf[0];
+        }
+        reader.consume(skip);
+    };
+    if first == b'<' {
+        let mut reader = Reader::from_reader(reader);
+        reader.trim_text(true);
+        return Box::new(NmapTargets {
+            reader,
+            buf: vec![],
+            state: NmapState {
+                all_ports,
+                ..NmapState::default()
+            },
+            done: false,
+        });
+    }
+    let mut text = String::new();
+    if let Err(err) = reader.read_to_string(&mut text) {
+        return Box::new(std::iter::once(Err(invalid_scan_output(
+            "masscan",
+            err.to_string(),
+        ))));
+    }
+    let mut ports = MasscanPorts::default();
+    let errors = match first {
+        b'[' | b'{' => read_masscan_json(&text, &mut ports),
+        _ => read_masscan_list(&text, &mut ports),
+    };
+    Box::new(
+        errors
+            .into_iter()
+            .map(Err)
+            .chain(ports.targets(all_ports).map(Ok)),
+    )
+}
+
+impl From<String> for ScanTarget {
+    fn from(url: String) -> Self {
+        Self::new(&url)
+    }
+}
+
+impl From<&str> for ScanTarget {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn read(text: &str) -> Vec<Result<ScanTarget, WardError>> {
+        read_targets(Cursor::new(text.to_string())).collect()
+    }
+
+    #[test]
+    fn test_read_targets() {
+        // 只有URL的文件和以前一样
+        let targets = read("# targets\n\nhttp://10.0.0.1/\n10.0.0.2:8080\n");
+        assert_eq!(
+            targets.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            [
+                ScanTarget::new("http://10.0.0.1/"),
+                ScanTarget::new("10.0.0.2:8080")
+            ]
+        );
+        // JSON和URL可以混在一起，不合法的行单独报错
+        let targets = read(concat!(
+            "10.0.0.1\n",
+            r#"{"url": "https://intranet.example/", "headers": {"X-Tenant": "ops"}, "cookies": {"JSESSIONID": "abc"}}"#,
+            "\n{\"headers\": {}}\n",
+            "10.0.0.3\n"
+        ));
+        assert_eq!(targets.len(), 4);
+        assert_eq!(
+            targets[1].as_ref().unwrap(),
+            &ScanTarget::new("https://intranet.example/")
+                .header("X-Tenant", "ops")
+                .cookie("JSESSIONID", "abc")
+        );
+        assert!(matches!(targets[2], Err(WardError::InvalidTarget { .. })));
+        assert_eq!(targets[3].as_ref().unwrap().url, "10.0.0.3");
+        // CSV按表头找列，headers一行一个
+        let targets = read(concat!(
+            "\u{feff}# exported\n",
+            "URL,cookies,headers\n",
+            "https://oa.example/,\"sid=1; lang=zh\",\"X-Tenant: ops\nAuthorization: Bearer t\"\n",
+            "10.0.0.2,,\n",
+            "10.0.0.3,sid,\n",
+        ));
+        assert_eq!(targets.len(), 3);
+        assert_eq!(
+            targets[0].as_ref().unwrap(),
+            &ScanTarget::new("https://oa.example/")
+                .header("X-Tenant", "ops")
+                .header("Authorization", "Bearer t")
+                .cookie("sid", "1")
+                .cookie("lang", "zh")
+        );
+        assert_eq!(targets[1].as_ref().unwrap(), &ScanTarget::new("10.0.0.2"));
+        assert!(matches!(targets[2], Err(WardError::InvalidTarget { .. })));
+        assert!(read("").is_empty());
+        // 不是表头的第一行当成目标
+        assert_eq!(read("10.0.0.1,10.0.0.2\n").len(), 1);
+    }
+
+    fn scan_output(text: &str, all_ports: bool) -> Vec<String> {
+        read_scan_output(Cursor::new(text.to_string()), all_ports)
+            .map(|target| target.map(|target| target.url).unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_scan_output() {
+        let nmap = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE nmaprun>
+<nmaprun scanner="nmap" args="nmap -sV 10.0.0.0/24" start="1660000000" version="7.92" xmloutputversion="1.05">
+<hosthint><status state="up" reason="arp-response" reason_ttl="0"/><address addr="10.0.0.1" addrtype="ipv4"/></hosthint>
+<host><status state="up" reason="arp-response" reason_ttl="0"/>
+<address addr="10.0.0.1" addrtype="ipv4"/><address addr="00:0C:29:AA:BB:CC" addrtype="mac" vendor="VMware"/>
+<hostnames><hostname name="gw.lan" type="PTR"/></hostnames>
+<ports><extraports state="closed" count="995"/>
+<port protocol="tcp" portid="22"><state state="open" reason="syn-ack" reason_ttl="64"/><service name="ssh" method="probed" conf="10"/></port>
+<port protocol="tcp" portid="80"><state state="open" reason="syn-ack" reason_ttl="64"/><service name="http" product="nginx" method="probed" conf="10"/></port>
+<port protocol="tcp" portid="443"><state state="open" reason="syn-ack" reason_ttl="64"/><service name="http" tunnel="ssl" method="probed" conf="10"/></port>
+<port protocol="tcp" portid="8080"><state state="open" reason="syn-ack" reason_ttl="64"/><service name="http-proxy" method="table" conf="3"/></port>
+<port protocol="tcp" portid="8081"><state state="filtered" reason="no-response" reason_ttl="0"/></port>
+<port protocol="udp" portid="161"><state state="open" reason="udp-response" reason_ttl="64"/><service name="snmp" method="probed" conf="10"/></port>
+</ports></host>
+<host><status state="up" reason="user-set" reason_ttl="0"/><address addr="2001:db8::1" addrtype="ipv6"/>
+<hostnames><hostname name="oa.example" type="user"/></hostnames>
+<ports><port protocol="tcp" portid="8443"><state state="open" reason="syn-ack" reason_ttl="0"/></port></ports></host>
+<host><status state="up" reason="echo-reply" reason_ttl="0"/><address addr="2001:db8::2" addrtype="ipv6"/>
+<ports><port protocol="tcp" portid="3306"><state state="open" reason="syn-ack" reason_ttl="0"/></port></ports></host>
+<host><status state="down" reason="no-response" reason_ttl="0"/><address addr="10.0.0.9" addrtype="ipv4"/>
+<ports><port protocol="tcp" portid="80"><state state="open" reason="syn-ack" reason_ttl="0"/></port></ports></host>
+<runstats><finished time="1660000100" elapsed="100.00" exit="success"/><hosts up="3" down="1" total="4"/></runstats>
+</nmaprun>
+"#;
+        // 多个地址时取IP，mac地址和反查的域名不用，udp、没开放和不是HTTP的端口跳过
+        assert_eq!(
+            scan_output(nmap, false),
+            [
+                "http://10.0.0.1:80",
+                "https://10.0.0.1:443",
+                "10.0.0.1:8080",
+                "oa.example:8443"
+            ]
+        );
+        assert_eq!(
+            scan_output(nmap, true),
+            [
+                "10.0.0.1:22",
+                "http://10.0.0.1:80",
+                "https://10.0.0.1:443",
+                "10.0.0.1:8080",
+                "oa.example:8443",
+                "[2001:db8::2]:3306"
+            ]
+        );
+        // 中断的扫描没有结束标签，已经写完的主机照常返回；标签不匹配时最后返回错误
+        let interrupted = &nmap[..nmap
+            .find("<host><status state=\"up\" reason=\"echo-reply\"")
+            .unwrap()];
+        assert_eq!(scan_output(interrupted, false).len(), 4);
+        let targets: Vec<_> =
+            read_scan_output(Cursor::new(format!("{}<host></port>", interrupted)), false).collect();
+        assert_eq!(targets.len(), 5);
+        assert!(matches!(targets[4], Err(WardError::InvalidTarget { .. })));
+        // 新版本masscan -oJ，同一个端口的banner是单独的记录，没有banner时按端口判断
+        let masscan = r#"[
+{   "ip": "10.0.0.2",   "timestamp": "1660000000", "ports": [ {"port": 8080, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 64} ] }
+,
+{   "ip": "10.0.0.2",   "timestamp": "1660000001", "ports": [ {"port": 2222, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 64} ] }
+,
+{   "ip": "10.0.0.2",   "timestamp": "1660000002", "ports": [ {"port": 2222, "proto": "tcp", "service": {"name": "http", "banner": "HTTP/1.1 200 OK"} } ] }
+,
+{   "ip": "10.0.0.1",   "timestamp": "1660000003", "ports": [ {"port": 8443, "proto": "tcp", "service": {"name": "ssl", "banner": "TLS/1.2"} } ] }
+,
+{   "ip": "10.0.0.1",   "timestamp": "1660000004", "ports": [ {"port": 22, "proto": "tcp", "status": "open", "reason": "syn-ack", "ttl": 64} ] }
+,
+{   "ip": "10.0.0.1",   "timestamp": "1660000005", "ports": [ {"port": 22, "proto": "tcp", "service": {"name": "ssh", "banner": "SSH-2.0-OpenSSH_8.9"} } ] }
+]
+"#;
+        assert_eq!(
+            scan_output(masscan, false),
+            ["10.0.0.1:8443", "10.0.0.2:2222", "10.0.0.2:8080"]
+        );
+        assert_eq!(scan_output(masscan, true).len(), 4);
+        // 旧版本逗号在行尾，最后是`{finished: 1}`
+        let masscan = "[\n{ \"ip\": \"10.0.0.3\", \"timestamp\": \"1\", \"ports\": [ {\"port\": 80, \"proto\": \"tcp\", \"status\": \"open\"} ] },\n{finished: 1}\n]\n";
+        assert_eq!(scan_output(masscan, false), ["10.0.0.3:80"]);
+        let masscan = concat!(
+            "#masscan\n",
+            "open tcp 443 10.0.0.4 1660000000\n",
+            "open tcp 25 10.0.0.4 1660000000\n",
+            "banner tcp 25 10.0.0.4 1660000001 smtp 220 mail ESMTP\n",
+            "open udp 53 10.0.0.4 1660000000\n",
+            "closed tcp 80 10.0.0.4 1660000000\n",
+            "open tcp 8080 2001:db8::3 1660000000\n",
+            "# end\n",
+        );
+        assert_eq!(
+            scan_output(masscan, false),
+            ["10.0.0.4:443", "[2001:db8::3]:8080"]
+        );
+        assert_eq!(scan_output("\u{feff}\n", false).len(), 0);
+        assert!(matches!(
+            read_scan_output(Cursor::new("not a scan\n"), false).next(),
+            Some(Err(WardError::InvalidTarget { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_scan_target() {
+        let target = ScanTarget::from(" https://intranet.example/ ")
+            .header("X-Tenant", "ops")
+            .cookie("JSESSIONID", "abc")
+            .cookie("lang", "zh");
+        assert_eq!(target.url, "https://intranet.example/");
+        assert_eq!(
+            target.headers,
+            [
+                (String::from("X-Tenant"), String::from("ops")),
+                (
+                    String::from("Cookie"),
+                    String::from("JSESSIONID=abc; lang=zh")
+                ),
+            ]
+        );
+        // 已经有Cookie请求头时追加到后面
+        let target = ScanTarget::new("10.0.0.1")
+            .header("cookie", "a=1")
+            .cookie("b", "2");
+        assert_eq!(
+            target.headers,
+            [(String::from("cookie"), String::from("a=1; b=2"))]
+        );
+    }
+}
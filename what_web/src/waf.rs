@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+
+use crate::fingerprint::{parse_web_fingerprint, WebFingerPrint, WebFingerPrintLib};
+use crate::observer::NoopObserver;
+use crate::ward::{find_keywords, what_web, RawData};
+
+// 内置的WAF、CDN和负载均衡指纹，和普通指纹格式一样，`waf`为true
+const BUILTIN_WAF_FINGERPRINT: &str = r#"[
+{"name":"cloudflare","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"cloudflare"},"keyword":[]},
+{"name":"cloudflare","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"cf-ray":"*"},"keyword":[]},
+{"name":"cloudflare","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"set-cookie":"__cf_bm"},"keyword":[]},
+{"name":"akamai","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"akamaighost"},"keyword":[]},
+{"name":"akamai","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":403,"headers":{},"keyword":["access denied","errors.edgesuite.net"]},
+{"name":"aws-waf","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"x-amzn-waf-action":"*"},"keyword":[]},
+{"name":"aws-waf","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":403,"headers":{},"keyword":["request blocked","generated by cloudfront"]},
+{"name":"aws-cloudfront","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"x-amz-cf-id":"*"},"keyword":[]},
+{"name":"safedog","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"safedog"},"keyword":[]},
+{"name":"safedog","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"set-cookie":"safedog-flow-item"},"keyword":[]},
+{"name":"safedog","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["安全狗","网站防火墙"]},
+{"name":"f5-bigip","waf":true,"priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"cookies":["BIGipServer*"]}
+]"#;
+
+static BUILTIN_WAF: Lazy<Vec<WebFingerPrint>> = Lazy::new(|| {
+    parse_web_fingerprint(BUILTIN_WAF_FINGERPRINT.as_bytes()).expect("BUILTIN_WAF_FINGERPRINT")
+});
+
+pub(crate) fn builtin_waf_fingerprint() -> &'static [WebFingerPrint] {
+    &BUILTIN_WAF
+}
+
+// 拦截页面常见的状态码
+const BLOCK_STATUS_CODE: [u16; 6] = [403, 405, 406, 429, 501, 503];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WafDetection {
+    pub name: String,
+    // 当前响应是WAF的拦截页面，在上面匹配到的指纹不可信
+    pub blocked: bool,
+}
+
+// 在首页响应上识别WAF和CDN
+pub(crate) fn detect_waf(
+    raw_data: &Arc<RawData>,
+    fingerprint_lib: &WebFingerPrintLib,
+) -> Option<WafDetection> {
+    let keywords = find_keywords(raw_data, fingerprint_lib);
+    // 图标哈希库生成的WAF指纹很多，按哈希查出可能命中的
+    let fingerprint_match = fingerprint_lib
+        .waf_favicon_index
+        .candidates(&fingerprint_lib.waf, raw_data.favicon.values())
+        .into_iter()
+        .find_map(|fingerprint| what_web(raw_data, fingerprint, &keywords, &NoopObserver))?;
+    Some(WafDetection {
+        name: fingerprint_match.name,
+        blocked: BLOCK_STATUS_CODE.contains(&raw_data.status_code.as_u16()),
+    })
+}
@@ -1,10 +1,20 @@
-use crate::fingerprint::{V3WebFingerPrint, WebFingerPrintLib};
-use futures::future::join_all;
+use crate::error::WardError;
+use crate::fingerprint::{HeaderRule, V3WebFingerPrint, WebFingerPrintLib, WebFingerPrintMatch};
+use crate::observer::ScanObserver;
+use crate::request::{BodyHash, CertInfo, FaviconHash};
+use bytes::Bytes;
+use encoding_rs::Encoding;
+use once_cell::sync::OnceCell;
+use reqwest::header::CONTENT_LENGTH;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::Write as FmtWrite;
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::sync::Arc;
-use url::Url;
+use std::time::Duration;
+use url::{Host, Url};
 
 #[derive(Debug)]
 pub struct RawData {
@@ -12,9 +22,246 @@ pub struct RawData {
     pub path: String,
     pub headers: reqwest::header::HeaderMap,
     pub status_code: reqwest::StatusCode,
+    // 解码后的正文，保留原始大小写
     pub text: String,
-    pub favicon: HashMap<String, String>,
+    // 页面标题，和跳转、favicon链接在同一次解析里提取
+    pub title: String,
+    // `<meta name="generator">`的内容，例如`WordPress 6.3`
+    pub generator: Vec<String>,
+    // 页面引用的脚本和样式的路径，不带查询参数
+    pub asset_paths: Vec<String>,
+    pub favicon: HashMap<String, FaviconHash>,
     pub next_url: Option<Url>,
+    pub jump_source: Option<JumpSource>,
+    // 跳转到当前响应经过的链接
+    pub redirect_chain: Vec<RedirectJump>,
+    // https握手拿到的证书
+    pub cert: Option<CertInfo>,
+    // 响应超过max_body_size被截断
+    pub truncated: bool,
+    // 发送请求到收到响应头的耗时
+    pub response_time: Duration,
+    // 原始响应，大小受max_body_size限制，用于匹配二进制内容
+    pub body: Bytes,
+    // 原始响应的md5和sha256，截断的响应没有
+    pub body_hash: Option<BodyHash>,
+    // 跳转策略不允许跟随的下一跳
+    pub skipped_jump: Option<RedirectJump>,
+    // 实际协商的HTTP协议版本
+    pub version: reqwest::Version,
+    // 解码正文使用的编码
+    pub encoding: &'static Encoding,
+    // 401响应里的认证域，很多设备的realm就是型号
+    pub auth_realm: Option<String>,
+    // 响应设置的Cookie，按`Set-Cookie`的顺序
+    pub cookies: Vec<SetCookie>,
+    // 实际连接的对端地址，经过代理或者离线导入的响应没有
+    pub peer_addr: Option<SocketAddr>,
+    // 没有自动解压、按魔数手动解压的压缩格式，由外到内，例如重复压缩的`["gzip", "gzip"]`
+    pub decompressed: Vec<&'static str>,
+    // 这次尝试最开始请求的协议，跳转到其他协议也不变，用来区分没有写协议的目标先后试的https和http
+    pub attempt_scheme: String,
+    // 序列化后的响应头，第一次用到时生成
+    pub(crate) header_string: OnceCell<String>,
+    // 转成小写的正文，关键词匹配第一次用到时生成
+    pub(crate) lowercase_text: OnceCell<String>,
+    // 同一个站点的robots.txt，首页请求完成后设置，`robots`规则在这里查找
+    pub(crate) robots: OnceCell<String>,
+    // 原始的状态行，原因短语从hyper的响应扩展里读取，HTTP/2没有
+    pub(crate) status_line: OnceCell<String>,
+}
+
+impl RawData {
+    /// 响应头按`name: value`每行一个拼起来，同一个响应只生成一次
+    pub fn header_string(&self) -> &str {
+        self.header_string
+            .get_or_init(|| header_to_string(&self.headers))
+    }
+    /// 转成小写的正文，同一个响应只转换一次
+    pub(crate) fn lowercase_text(&self) -> &str {
+        self.lowercase_text.get_or_init(|| self.text.to_lowercase())
+    }
+    /// 站点的robots.txt，没有请求或者站点没有时为`None`
+    pub fn robots(&self) -> Option<&str> {
+        self.robots.get().map(String::as_str)
+    }
+    /// 只能设置一次，离线识别时可以把保存下来的robots.txt交给首页
+    pub fn set_robots(&self, robots: String) {
+        let _ = self.robots.set(robots);
+    }
+    /// 原始的状态行，例如`HTTP/1.0 200 Document follows`，HTTP/2时为`None`
+    pub fn status_line(&self) -> Option<&str> {
+        self.status_line.get().map(String::as_str)
+    }
+    /// 状态行里的原因短语，很多嵌入式设备的和标准的不一样
+    pub fn reason_phrase(&self) -> Option<&str> {
+        self.status_line().and_then(reason_phrase)
+    }
+    /// 只能设置一次，状态码和响应不一样的状态行不设置
+    pub fn set_status_line(&self, status_line: String) {
+        let status_code = status_line.split_whitespace().nth(1);
+        if status_code == Some(self.status_code.as_str()) {
+            let _ = self.status_line.set(status_line);
+        }
+    }
+    /// 解码后正文的字节数，响应是图片时为0
+    pub fn text_length(&self) -> usize {
+        self.text.len()
+    }
+    /// 原始响应的字节数，被截断时用`Content-Length`。
+    /// 手动解压过的`Content-Length`是压缩后的大小，只能用解压出来的字节数
+    pub fn body_length(&self) -> usize {
+        let content_length = || {
+            self.headers
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+        };
+        match self.truncated && self.decompressed.is_empty() {
+            true => content_length().unwrap_or(self.body.len()),
+            false => self.body.len(),
+        }
+    }
+}
+
+// 下一跳URL的来源
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum JumpSource {
+    Location,
+    MetaRefresh,
+    JavaScript,
+    // http响应要求改用https，比如426和空正文带着HSTS
+    Upgrade,
+}
+
+/// 一个`Set-Cookie`响应头，属性名转成小写，`HttpOnly`这类没有值的属性值为空
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    pub name: String,
+    pub value: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl SetCookie {
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let attributes = parts
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| match part.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_string()),
+                None => (part.to_lowercase(), String::new()),
+            })
+            .collect();
+        Some(Self {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            attributes,
+        })
+    }
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RedirectJump {
+    pub from: Url,
+    pub to: Url,
+    pub source: JumpSource,
+    // 是否跳转到了其他主机
+    pub cross_host: bool,
+}
+
+impl RedirectJump {
+    pub fn new(from: Url, to: Url, source: JumpSource) -> Self {
+        let cross_host = from.host_str() != to.host_str();
+        Self {
+            from,
+            to,
+            source,
+            cross_host,
+        }
+    }
+}
+
+// 首页跟随跳转的范围，默认只跟随同一个主机
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedirectPolicy {
+    None,
+    #[default]
+    SameHost,
+    SameDomain,
+    Always,
+}
+
+impl FromStr for RedirectPolicy {
+    type Err = WardError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(RedirectPolicy::None),
+            "same-host" => Ok(RedirectPolicy::SameHost),
+            "same-domain" => Ok(RedirectPolicy::SameDomain),
+            "always" => Ok(RedirectPolicy::Always),
+            _ => Err(WardError::InvalidValue(format!(
+                "Unsupported redirect policy `{}`, expected none, same-host, same-domain or always",
+                s
+            ))),
+        }
+    }
+}
+
+impl RedirectPolicy {
+    // 和原始目标比较，避免跳转到第三方登录页后识别出别人的组件
+    pub fn allows(&self, target: &Url, next_url: &Url) -> bool {
+        match self {
+            RedirectPolicy::None => false,
+            RedirectPolicy::SameHost => target.host_str() == next_url.host_str(),
+            RedirectPolicy::SameDomain => match (target.host(), next_url.host()) {
+                (Some(Host::Domain(a)), Some(Host::Domain(b))) => {
+                    registrable_domain(a) == registrable_domain(b)
+                }
+                (a, b) => a == b,
+            },
+            RedirectPolicy::Always => true,
+        }
+    }
+}
+
+// 没有公共后缀列表，按常见的二级后缀粗略取主域名，例如`www.example.com.cn`取`example.com.cn`
+fn registrable_domain(host: &str) -> String {
+    let host = host.trim_end_matches('.').to_lowercase();
+    let labels: Vec<&str> = host.split('.').collect();
+    let mut keep = 2;
+    if labels.len() > 2 {
+        let tld = labels[labels.len() - 1];
+        let sld = labels[labels.len() - 2];
+        if tld.len() == 2 && ["com", "net", "org", "gov", "edu", "co", "ac"].contains(&sld) {
+            keep = 3;
+        }
+    }
+    labels[labels.len().saturating_sub(keep)..].join(".")
+}
+
+// `HTTP/1.1 200 OK`第二个空格后面的部分，原因短语可以为空
+pub(crate) fn reason_phrase(status_line: &str) -> Option<&str> {
+    if !status_line.starts_with("HTTP/") {
+        return None;
+    }
+    status_line
+        .splitn(3, ' ')
+        .nth(2)
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty())
 }
 
 impl fmt::Display for RawData {
@@ -24,123 +271,2027 @@ impl fmt::Display for RawData {
             let _ = write!(s, "Url: {}\r\n", u);
         }
         s.push_str("Headers:\r\n");
-        s.push_str(&header_to_string(&self.headers));
+        s.push_str(self.header_string());
         let _ = write!(s, "StatusCode: {}\r\n", self.status_code.as_u16());
+        if let Some(status_line) = self.status_line() {
+            let _ = write!(s, "StatusLine: {}\r\n", status_line);
+        }
+        let _ = write!(s, "Version: {:?}\r\n", self.version);
+        let _ = write!(s, "Encoding: {}\r\n", self.encoding.name());
+        if !self.decompressed.is_empty() {
+            let _ = write!(s, "Decompressed: {}\r\n", self.decompressed.join(", "));
+        }
+        let _ = write!(s, "ResponseTime: {}ms\r\n", self.response_time.as_millis());
+        let _ = write!(s, "TextLength: {}\r\n", self.text_length());
+        let _ = write!(s, "BodyLength: {}\r\n", self.body_length());
+        if let Some(body_hash) = &self.body_hash {
+            let _ = write!(s, "BodyMd5: {}\r\n", body_hash.md5);
+            let _ = write!(s, "BodySha256: {}\r\n", body_hash.sha256);
+        }
+        if let Some(peer_addr) = &self.peer_addr {
+            let _ = write!(s, "PeerAddr: {}\r\n", peer_addr);
+        }
         s.push_str("Text:\r\n");
         s.push_str(&self.text);
         s.push_str("\r\n");
+        if self.truncated {
+            s.push_str("Truncated: true\r\n");
+        }
+        if let Some(realm) = &self.auth_realm {
+            let _ = write!(s, "AuthRealm: {}\r\n", realm);
+        }
+        if !self.generator.is_empty() {
+            let _ = write!(s, "Generator: {}\r\n", self.generator.join(", "));
+        }
+        if !self.asset_paths.is_empty() {
+            let _ = write!(s, "AssetPaths: {}\r\n", self.asset_paths.join(", "));
+        }
         if !self.favicon.is_empty() {
             let _ = write!(s, "Favicon: {:#?}\r\n", self.favicon);
         }
         if let Some(next_url) = &self.next_url {
             let _ = write!(s, "NextUrl: {}\r\n", next_url);
         }
+        if let Some(jump) = &self.skipped_jump {
+            let _ = write!(s, "SkippedJump: {}\r\n", jump.to);
+        }
+        if let Some(cert) = &self.cert {
+            let _ = write!(s, "Cert: {:#?}\r\n", cert);
+        }
+        if !self.redirect_chain.is_empty() {
+            let mut chain: Vec<String> = vec![];
+            for jump in self.redirect_chain.iter() {
+                if chain.is_empty() {
+                    chain.push(jump.from.to_string());
+                }
+                chain.push(jump.to.to_string());
+            }
+            let _ = write!(s, "RedirectChain: {}\r\n", chain.join(" -> "));
+        }
         write!(f, "{}", s)
     }
 }
 
-pub async fn check(
+// 匹配到的指纹
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FingerprintMatch {
+    pub name: String,
+    pub priority: u32,
+    pub version: Option<String>,
+    // 命中的规则越多越具体，可信度越高
+    pub confidence: u32,
+    pub evidence: MatchEvidence,
+}
+
+/// 指纹在一个响应上命中的具体规则，用来排查误报
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MatchEvidence {
+    // 命中的响应，关键词的偏移是这个响应正文里的
+    pub url: String,
+    pub rules: Vec<MatchedRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MatchedRule {
+    // 偏移是关键词在正文里第一次出现的字节位置，片段是去掉标签后的上下文
+    Keyword {
+        keyword: String,
+        offset: usize,
+        snippet: String,
+    },
+    Regex {
+        pattern: String,
+        offset: usize,
+        snippet: String,
+    },
+    // 偏移是字节序列在原始响应里的位置
+    HexKeyword {
+        hex: String,
+        offset: usize,
+    },
+    Header {
+        name: String,
+        value: String,
+    },
+    // 命中的哈希，带`md5:`或者`mmh3:`前缀，和命中的图标链接
+    Favicon {
+        hash: String,
+        url: String,
+    },
+    StatusCode {
+        status_code: u16,
+    },
+    // 原始响应的字节数
+    Length {
+        length: usize,
+    },
+    Realm {
+        realm: String,
+    },
+    MetaGenerator {
+        generator: String,
+    },
+    ReasonPhrase {
+        reason_phrase: String,
+    },
+    // 命中的前缀和页面里的资源路径
+    ScriptSrc {
+        prefix: String,
+        path: String,
+    },
+    // 指纹里的Cookie名和响应实际设置的名字
+    Cookie {
+        rule: String,
+        name: String,
+    },
+    // robots.txt里包含关键词的那一行
+    Robots {
+        keyword: String,
+        line: String,
+    },
+    Cert {
+        subject: String,
+        issuer: String,
+    },
+    // 命中的哈希，带上算法前缀
+    BodyHash {
+        hash: String,
+    },
+}
+
+// 每种规则命中后增加的可信度，加起来不超过MAX_CONFIDENCE
+const CONFIDENCE_FAVICON: u32 = 60;
+// 原始响应完全一样，不会误报
+const CONFIDENCE_BODY_HASH: u32 = 100;
+const CONFIDENCE_CERT: u32 = 40;
+// 完全相等或者正则的请求头比子串和只要求存在的更具体
+const CONFIDENCE_HEADER_EXACT: u32 = 30;
+const CONFIDENCE_REALM: u32 = 30;
+const CONFIDENCE_ROBOTS: u32 = 10;
+const CONFIDENCE_GENERATOR: u32 = 30;
+const CONFIDENCE_REASON_PHRASE: u32 = 30;
+const CONFIDENCE_SCRIPT_SRC: u32 = 20;
+const CONFIDENCE_COOKIE: u32 = 20;
+const CONFIDENCE_HEADER: u32 = 15;
+const CONFIDENCE_HEX_KEYWORD: u32 = 20;
+const CONFIDENCE_REGEX: u32 = 15;
+const CONFIDENCE_KEYWORD: u32 = 10;
+const CONFIDENCE_STATUS_CODE: u32 = 5;
+const CONFIDENCE_LENGTH: u32 = 10;
+const MAX_CONFIDENCE: u32 = 100;
+
+// 按指纹里的规则计算可信度，调用时所有规则都已经命中
+pub(crate) fn confidence(match_rules: &WebFingerPrintMatch) -> u32 {
+    let count = |len: usize| len as u32;
+    let mut score = 0;
+    if !match_rules.favicon_hash.is_empty() {
+        score += CONFIDENCE_FAVICON;
+    }
+    if !match_rules.body_hash.is_empty() {
+        score += CONFIDENCE_BODY_HASH;
+    }
+    if !match_rules.cert_subject.is_empty() || !match_rules.cert_issuer.is_empty() {
+        score += CONFIDENCE_CERT;
+    }
+    for (_k, rule) in &match_rules.header_rules {
+        score += match rule {
+            HeaderRule::Exact(_) | HeaderRule::Regex(_) => CONFIDENCE_HEADER_EXACT,
+            HeaderRule::Contains(_) | HeaderRule::Exists => CONFIDENCE_HEADER,
+        };
+    }
+    score += count(match_rules.hex_keyword_bytes.len()) * CONFIDENCE_HEX_KEYWORD;
+    score += count(match_rules.regex_set.len()) * CONFIDENCE_REGEX;
+    score += count(match_rules.keyword.len()) * CONFIDENCE_KEYWORD;
+    score += count(match_rules.realm_rules.len()) * CONFIDENCE_REALM;
+    score += count(match_rules.robots.len()) * CONFIDENCE_ROBOTS;
+    score += count(match_rules.generator_rules.len()) * CONFIDENCE_GENERATOR;
+    score += count(match_rules.reason_phrase_rules.len()) * CONFIDENCE_REASON_PHRASE;
+    score += count(match_rules.script_src.len()) * CONFIDENCE_SCRIPT_SRC;
+    score += count(match_rules.cookies.len()) * CONFIDENCE_COOKIE;
+    if !match_rules.status_code.is_any() {
+        score += CONFIDENCE_STATUS_CODE;
+    }
+    if match_rules.length.is_some() {
+        score += CONFIDENCE_LENGTH;
+    }
+    score.min(MAX_CONFIDENCE)
+}
+
+// 匹配本身没有IO，保留async只是因为都在异步代码里调用
+pub(crate) async fn check(
     raw_data: &Arc<RawData>,
     fingerprint_lib: &WebFingerPrintLib,
-    debug: bool,
-) -> HashMap<String, u32> {
-    if debug {
-        println!("{}", raw_data);
-    }
-    let mut futures_e = vec![];
-    let mut web_name_set: HashMap<String, u32> = HashMap::new();
-    for fingerprint in fingerprint_lib.special.iter() {
-        futures_e.push(what_web(raw_data.clone(), fingerprint, debug));
-    }
-    for fingerprint in fingerprint_lib.index.iter() {
-        futures_e.push(what_web(raw_data.clone(), fingerprint, debug));
-    }
-    if !raw_data.favicon.is_empty() {
-        for fingerprint in fingerprint_lib.favicon.iter() {
-            futures_e.push(what_web(raw_data.clone(), fingerprint, debug));
-        }
-    }
-    let results = join_all(futures_e).await;
-    for res in results {
-        let (is_match, match_web_fingerprint) = res;
-        if is_match {
-            web_name_set.insert(
-                match_web_fingerprint.name.clone(),
-                match_web_fingerprint.priority,
-            );
+    observer: &dyn ScanObserver,
+) -> Vec<FingerprintMatch> {
+    log::debug!("{}", raw_data);
+    observer.raw_data_received(raw_data);
+    let keywords = find_keywords(raw_data, fingerprint_lib);
+    let mut web_name_set: Vec<FingerprintMatch> = Vec::new();
+    // 没有图标时不用匹配图标指纹，有图标时按哈希查出可能命中的
+    let favicon: Vec<&V3WebFingerPrint> = if raw_data.favicon.is_empty() {
+        vec![]
+    } else {
+        fingerprint_lib
+            .favicon_index
+            .candidates(&fingerprint_lib.favicon, raw_data.favicon.values())
+    };
+    // 固定路径的图标指纹在favicon里也有一份，查表时已经匹配过
+    let results = fingerprint_lib
+        .special
+        .iter()
+        .filter(|fingerprint| fingerprint.match_rules.favicon_rules.is_empty())
+        .chain(fingerprint_lib.index.iter())
+        .chain(favicon)
+        .filter_map(|fingerprint| what_web(raw_data, fingerprint, &keywords, observer));
+    for fingerprint_match in results {
+        // 同一个组件同一个版本只保留一个，可信度取最高的
+        match web_name_set
+            .iter_mut()
+            .find(|m| m.name == fingerprint_match.name && m.version == fingerprint_match.version)
+        {
+            Some(m) => {
+                m.priority = m.priority.max(fingerprint_match.priority);
+                m.confidence = m.confidence.max(fingerprint_match.confidence);
+            }
+            None => web_name_set.push(fingerprint_match),
         }
     }
+    // 同一个组件已经有版本号的，去掉没有版本号的结果
+    let versioned: HashSet<String> = web_name_set
+        .iter()
+        .filter(|m| m.version.is_some())
+        .map(|m| m.name.clone())
+        .collect();
+    web_name_set.retain(|m| m.version.is_some() || !versioned.contains(&m.name));
     web_name_set
 }
 
-pub async fn what_web(
-    raw_data: Arc<RawData>,
+// 路径前缀忽略大小写，`/wp-includes/`匹配`/wp-includes/js/jquery.js`
+fn find_asset_path<'a>(asset_paths: &'a [String], prefix: &str) -> Option<&'a str> {
+    let prefix = prefix.to_lowercase();
+    asset_paths
+        .iter()
+        .find(|path| path.to_lowercase().starts_with(&prefix))
+        .map(String::as_str)
+}
+
+// Cookie名忽略大小写，`*`结尾时按前缀比较
+fn find_cookie<'a>(cookies: &'a [SetCookie], rule: &str) -> Option<&'a str> {
+    let rule = rule.to_lowercase();
+    let matched = |name: &str| match rule.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == rule,
+    };
+    cookies
+        .iter()
+        .find(|cookie| matched(&cookie.name.to_lowercase()))
+        .map(|cookie| cookie.name.as_str())
+}
+
+// 正文中出现的所有关键词
+pub(crate) fn find_keywords(
+    raw_data: &RawData,
+    fingerprint_lib: &WebFingerPrintLib,
+) -> HashSet<usize> {
+    match &fingerprint_lib.keyword_matcher {
+        Some(matcher) => matcher
+            .find_overlapping_iter(raw_data.lowercase_text())
+            .map(|m| m.pattern())
+            .collect(),
+        None => HashSet::new(),
+    }
+}
+
+// 只做匹配没有IO，调用的地方不用等待
+pub(crate) fn what_web(
+    raw_data: &RawData,
     fingerprint: &V3WebFingerPrint,
-    debug: bool,
-) -> (bool, &V3WebFingerPrint) {
+    keywords: &HashSet<usize>,
+    observer: &dyn ScanObserver,
+) -> Option<FingerprintMatch> {
+    log::trace!(
+        "Evaluating fingerprint `{}` on {}",
+        fingerprint.name,
+        raw_data.url
+    );
     // 默认匹配不到
-    let mut default_result = (false, fingerprint);
+    let default_result = None;
     // 匹配FaviconHash
-    if !fingerprint.match_rules.favicon_hash.is_empty() {
-        let mut hash_set = HashSet::new();
-        for (_key, value) in raw_data.favicon.iter() {
-            hash_set.insert(value);
+    if !fingerprint.match_rules.favicon_rules.is_empty() {
+        // 任意一个图标的哈希命中任意一条规则，请求中没有找到FaviconHash时不匹配
+        let favicon_match = raw_data.favicon.values().any(|value| {
+            fingerprint
+                .match_rules
+                .favicon_rules
+                .iter()
+                .any(|rule| rule.is_match(value))
+        });
+        if !favicon_match {
+            return default_result;
+        }
+    }
+    // 任意一个哈希命中，截断的响应没有哈希，不匹配
+    if !fingerprint.match_rules.body_hash_rules.is_empty() {
+        let body_match = raw_data.body_hash.as_ref().is_some_and(|body_hash| {
+            fingerprint
+                .match_rules
+                .body_hash_rules
+                .iter()
+                .any(|rule| rule.is_match(body_hash))
+        });
+        if !body_match {
+            return default_result;
+        }
+    }
+    // 关键词匹配
+    if !fingerprint
+        .match_rules
+        .status_code
+        .matches(raw_data.status_code.as_u16())
+    {
+        return default_result;
+    }
+    if let Some(length) = &fingerprint.match_rules.length {
+        if !length.matches(raw_data.body_length()) {
+            return default_result;
+        }
+    }
+    for (k, rule) in &fingerprint.match_rules.header_rules {
+        if !header_match(&raw_data.headers, k, rule) {
+            return default_result;
+        }
+    }
+    for rule in &fingerprint.match_rules.realm_rules {
+        if !raw_data
+            .auth_realm
+            .as_deref()
+            .is_some_and(|realm| rule.is_match(realm))
+        {
+            return default_result;
         }
-        // 请求中没有找到FaviconHash
-        if hash_set.is_empty() {
+    }
+    // 没有读取状态行或者是HTTP/2时不命中
+    for rule in &fingerprint.match_rules.reason_phrase_rules {
+        if !raw_data
+            .reason_phrase()
+            .is_some_and(|reason_phrase| rule.is_match(reason_phrase))
+        {
             return default_result;
         }
-        let mut fph_set = HashSet::new();
-        for fph in fingerprint.match_rules.favicon_hash.iter() {
-            fph_set.insert(fph);
+    }
+    // 每条规则都要有一个generator命中
+    for rule in &fingerprint.match_rules.generator_rules {
+        if !raw_data
+            .generator
+            .iter()
+            .any(|generator| rule.is_match(generator))
+        {
+            return default_result;
         }
-        if hash_set.intersection(&fph_set).count() == 0 {
+    }
+    // 每个前缀都要有一个脚本或者样式的路径以它开头
+    for prefix in &fingerprint.match_rules.script_src {
+        if find_asset_path(&raw_data.asset_paths, prefix).is_none() {
             return default_result;
         }
     }
-    // 关键词匹配
-    let not_match_status_code = || {
-        fingerprint.match_rules.status_code != 0
-            && raw_data.status_code.as_u16() != fingerprint.match_rules.status_code
+    for rule in &fingerprint.match_rules.cookies {
+        if find_cookie(&raw_data.cookies, rule).is_none() {
+            return default_result;
+        }
+    }
+    // robots.txt里的关键词，忽略大小写，需要全部命中
+    if !fingerprint.match_rules.robots.is_empty() {
+        let robots = match raw_data.robots() {
+            Some(robots) => robots.to_lowercase(),
+            None => return default_result,
+        };
+        if !fingerprint
+            .match_rules
+            .robots
+            .iter()
+            .all(|keyword| robots.contains(&keyword.to_lowercase()))
+        {
+            return default_result;
+        }
+    }
+    // 没有编号的关键词直接在正文里查找，两边都转小写
+    let has_keyword = |keyword: &String, id: Option<&usize>| match id {
+        Some(id) => keywords.contains(id),
+        None => raw_data.lowercase_text().contains(&keyword.to_lowercase()),
     };
-    if not_match_status_code() {
-        return default_result;
+    for (index, keyword) in fingerprint.match_rules.keyword.iter().enumerate() {
+        if !has_keyword(keyword, fingerprint.match_rules.keyword_ids.get(index)) {
+            return default_result;
+        }
     }
-    for (k, v) in &fingerprint.match_rules.headers {
-        let matcher_part = header_to_string(&raw_data.headers);
-        if k == "set-cookie" && !matcher_part.contains(v) {
+    // 正则匹配，需要全部命中
+    for re in &fingerprint.match_rules.regex_set {
+        if !re.is_match(&raw_data.text) {
             return default_result;
         }
-        if raw_data.headers.contains_key(k) {
-            let is_match = matcher_part.to_lowercase().find(&v.to_lowercase());
-            if is_match == None && v != "*" {
-                return default_result;
-            }
-        } else {
+    }
+    // 在原始响应里查找字节序列
+    for hex_keyword in &fingerprint.match_rules.hex_keyword_bytes {
+        if !raw_data
+            .body
+            .windows(hex_keyword.len())
+            .any(|window| window == hex_keyword.as_slice())
+        {
             return default_result;
         }
     }
-    for keyword in &fingerprint.match_rules.keyword {
-        if raw_data.text.find(&keyword.to_lowercase()) == None {
+    // 排除关键词和请求头，命中任意一个都不匹配
+    for (index, keyword) in fingerprint.match_rules.not_keyword.iter().enumerate() {
+        if has_keyword(keyword, fingerprint.match_rules.not_keyword_ids.get(index)) {
             return default_result;
         }
     }
-    default_result.0 = true;
-    if debug {
-        println!("Matching fingerprint{:#?}", fingerprint);
+    for (k, rule) in &fingerprint.match_rules.not_header_rules {
+        if header_match(&raw_data.headers, k, rule) {
+            return default_result;
+        }
+    }
+    if !fingerprint.match_rules.cert_subject.is_empty()
+        || !fingerprint.match_rules.cert_issuer.is_empty()
+    {
+        let cert = match &raw_data.cert {
+            Some(cert) => cert,
+            None => return default_result,
+        };
+        let subject = cert.subject.to_lowercase();
+        let issuer = cert.issuer.to_lowercase();
+        let is_match = |keywords: &Vec<String>, part: &str| {
+            keywords.iter().all(|k| part.contains(&k.to_lowercase()))
+        };
+        if !is_match(&fingerprint.match_rules.cert_subject, &subject)
+            || !is_match(&fingerprint.match_rules.cert_issuer, &issuer)
+        {
+            return default_result;
+        }
     }
-    default_result
+    log::info!(
+        "Matched fingerprint `{}` on {}",
+        fingerprint.name,
+        raw_data.url
+    );
+    observer.fingerprint_matched(raw_data, fingerprint);
+    Some(FingerprintMatch {
+        name: fingerprint.name.clone(),
+        priority: fingerprint.priority,
+        version: extract_version(raw_data, fingerprint),
+        confidence: confidence(&fingerprint.match_rules),
+        evidence: collect_evidence(raw_data, fingerprint),
+    })
 }
 
-fn header_to_string(headers: &reqwest::header::HeaderMap) -> String {
-    let mut header_string = String::new();
-    for (k, v) in headers.clone() {
-        if let Some(k) = k {
-            header_string.push_str(k.as_str());
-            header_string.push_str(": ");
+// 匹配成功后再找一遍命中的位置，没有匹配上的指纹不用多花时间
+fn collect_evidence(raw_data: &RawData, fingerprint: &V3WebFingerPrint) -> MatchEvidence {
+    let match_rules = &fingerprint.match_rules;
+    let mut rules = vec![];
+    for rule in match_rules.favicon_rules.iter() {
+        for (url, hash) in raw_data.favicon.iter() {
+            if rule.is_match(hash) {
+                rules.push(MatchedRule::Favicon {
+                    hash: rule.to_string(),
+                    url: url.clone(),
+                });
+            }
+        }
+    }
+    if let Some(body_hash) = &raw_data.body_hash {
+        if let Some(rule) = match_rules
+            .body_hash_rules
+            .iter()
+            .find(|rule| rule.is_match(body_hash))
+        {
+            rules.push(MatchedRule::BodyHash {
+                hash: rule.to_string(),
+            });
+        }
+    }
+    if !match_rules.status_code.is_any() {
+        rules.push(MatchedRule::StatusCode {
+            status_code: raw_data.status_code.as_u16(),
+        });
+    }
+    if match_rules.length.is_some() {
+        rules.push(MatchedRule::Length {
+            length: raw_data.body_length(),
+        });
+    }
+    if !match_rules.realm_rules.is_empty() {
+        if let Some(realm) = &raw_data.auth_realm {
+            rules.push(MatchedRule::Realm {
+                realm: realm.clone(),
+            });
+        }
+    }
+    if !match_rules.reason_phrase_rules.is_empty() {
+        if let Some(reason_phrase) = raw_data.reason_phrase() {
+            rules.push(MatchedRule::ReasonPhrase {
+                reason_phrase: reason_phrase.to_string(),
+            });
+        }
+    }
+    for rule in match_rules.generator_rules.iter() {
+        if let Some(generator) = raw_data.generator.iter().find(|g| rule.is_match(g)) {
+            rules.push(MatchedRule::MetaGenerator {
+                generator: generator.clone(),
+            });
+        }
+    }
+    for prefix in match_rules.script_src.iter() {
+        if let Some(path) = find_asset_path(&raw_data.asset_paths, prefix) {
+            rules.push(MatchedRule::ScriptSrc {
+                prefix: prefix.clone(),
+                path: path.to_string(),
+            });
+        }
+    }
+    for rule in match_rules.cookies.iter() {
+        if let Some(name) = find_cookie(&raw_data.cookies, rule) {
+            rules.push(MatchedRule::Cookie {
+                rule: rule.clone(),
+                name: name.to_string(),
+            });
+        }
+    }
+    if let Some(robots) = raw_data.robots() {
+        for keyword in match_rules.robots.iter() {
+            let keyword_lower = keyword.to_lowercase();
+            if let Some(line) = robots
+                .lines()
+                .find(|line| line.to_lowercase().contains(&keyword_lower))
+            {
+                rules.push(MatchedRule::Robots {
+                    keyword: keyword.clone(),
+                    line: line.trim().to_string(),
+                });
+            }
+        }
+    }
+    for (name, rule) in &match_rules.header_rules {
+        if let Some(value) = header_value(&raw_data.headers, name, rule) {
+            rules.push(MatchedRule::Header {
+                name: name.clone(),
+                value,
+            });
         }
+    }
+    for keyword in match_rules.keyword.iter() {
+        if let Some(offset) = find_keyword(&raw_data.text, keyword) {
+            rules.push(MatchedRule::Keyword {
+                keyword: keyword.clone(),
+                offset,
+                snippet: extract_snippet(&raw_data.text, offset, SNIPPET_RADIUS),
+            });
+        }
+    }
+    for re in &match_rules.regex_set {
+        if let Some(m) = re.find(&raw_data.text) {
+            rules.push(MatchedRule::Regex {
+                pattern: re.as_str().to_string(),
+                offset: m.start(),
+                snippet: extract_snippet(&raw_data.text, m.start(), SNIPPET_RADIUS),
+            });
+        }
+    }
+    for (hex, bytes) in match_rules
+        .hex_keyword
+        .iter()
+        .zip(match_rules.hex_keyword_bytes.iter())
+    {
+        if let Some(offset) = raw_data
+            .body
+            .windows(bytes.len())
+            .position(|window| window == bytes.as_slice())
+        {
+            rules.push(MatchedRule::HexKeyword {
+                hex: hex.clone(),
+                offset,
+            });
+        }
+    }
+    if !match_rules.cert_subject.is_empty() || !match_rules.cert_issuer.is_empty() {
+        if let Some(cert) = &raw_data.cert {
+            rules.push(MatchedRule::Cert {
+                subject: cert.subject.clone(),
+                issuer: cert.issuer.clone(),
+            });
+        }
+    }
+    MatchEvidence {
+        url: raw_data.url.to_string(),
+        rules,
+    }
+}
+
+// 命中位置前后各取多少个字符
+const SNIPPET_RADIUS: usize = 40;
+
+/// 取`offset`前后各`radius`个字符作为上下文，去掉HTML标签，连续的空白合并成一个空格。
+/// `offset`是字节位置，落在多字节字符中间时退到这个字符的开头
+pub fn extract_snippet(text: &str, offset: usize, radius: usize) -> String {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take(radius)
+        .last()
+        .map_or(offset, |(index, _)| index);
+    let end = text[offset..]
+        .char_indices()
+        .nth(radius)
+        .map_or(text.len(), |(index, _)| offset + index);
+    let mut window = &text[start..end];
+    // 从标签中间截断时，第一个`>`之前没有`<`的部分是标签的剩余
+    if start > 0 {
+        if let Some(gt) = window.find('>') {
+            if window.find('<').is_none_or(|lt| gt < lt) {
+                window = &window[gt + 1..];
+            }
+        }
+    }
+    let mut stripped = String::with_capacity(window.len());
+    let mut in_tag = false;
+    for c in window.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                // 标签两边的文字不连在一起
+                stripped.push(' ');
+            }
+            _ if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// 和AC自动机一样忽略ASCII字母的大小写，找不到再按Unicode忽略大小写
+fn find_keyword(text: &str, keyword: &str) -> Option<usize> {
+    if keyword.is_empty() {
+        return None;
+    }
+    let needle = keyword.as_bytes();
+    text.as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+        .or_else(|| {
+            regex::RegexBuilder::new(&regex::escape(keyword))
+                .case_insensitive(true)
+                .build()
+                .ok()?
+                .find(text)
+                .map(|m| m.start())
+        })
+}
+
+// 从响应头和正文中提取版本号
+fn extract_version(raw_data: &RawData, fingerprint: &V3WebFingerPrint) -> Option<String> {
+    if fingerprint.match_rules.version_regex_set.is_empty() {
+        return None;
+    }
+    for re in &fingerprint.match_rules.version_regex_set {
+        for haystack in [raw_data.header_string(), raw_data.text.as_str()] {
+            if let Some(caps) = re.captures(haystack) {
+                if let Some(version) = caps.name("version").or_else(|| caps.get(1)) {
+                    if !version.as_str().is_empty() {
+                        return Some(version.as_str().to_string());
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+// 同名的多个请求头（例如多个Set-Cookie）分别匹配，任意一个命中即可
+fn header_match(headers: &reqwest::header::HeaderMap, name: &str, rule: &HeaderRule) -> bool {
+    header_value(headers, name, rule).is_some()
+}
+
+// 同名请求头有多个值时返回第一个命中的
+fn header_value(
+    headers: &reqwest::header::HeaderMap,
+    name: &str,
+    rule: &HeaderRule,
+) -> Option<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()))
+        .find(|value| rule.is_match(value))
+        .map(|value| value.into_owned())
+}
+
+pub(crate) fn header_to_string(headers: &reqwest::header::HeaderMap) -> String {
+    let mut header_string = String::new();
+    for (k, v) in headers {
+        header_string.push_str(k.as_str());
+        header_string.push_str(": ");
         header_string.push_str(v.to_str().unwrap_or_default());
         header_string.push_str("\r\n");
     }
     header_string
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::WardError;
+    use crate::favicon_db::{FaviconDbCategory, FaviconDbEntry};
+    use crate::fingerprint::{
+        default_name_aliases, load_request_data_files, parse_web_fingerprint, FaviconRule,
+        IssueSeverity, LengthRule, StatusCodeRule, StatusCodes, WebFingerPrint, WebFingerPrintLib,
+    };
+    use crate::observer::NoopObserver;
+    use crate::request::{body_hash, CertInfo, FaviconHash};
+    use crate::waf::detect_waf;
+    use crate::ward::{
+        check, confidence, extract_snippet, header_to_string, reason_phrase, what_web,
+        FingerprintMatch, MatchedRule, RawData, RedirectPolicy, SetCookie,
+    };
+    use bytes::Bytes;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH};
+    use reqwest::StatusCode;
+    use std::collections::{HashMap, HashSet};
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use url::Url;
+
+    fn raw_data(text: &str) -> Arc<RawData> {
+        raw_data_with(|raw_data| raw_data.text = text.to_string())
+    }
+
+    fn raw_data_with_headers(text: &str, headers: HeaderMap) -> Arc<RawData> {
+        raw_data_with(|raw_data| {
+            raw_data.text = text.to_string();
+            raw_data.headers = headers;
+        })
+    }
+
+    fn raw_data_with<F: FnOnce(&mut RawData)>(f: F) -> Arc<RawData> {
+        let mut raw_data = RawData {
+            url: Url::parse("https://kali-team.cn").unwrap(),
+            path: String::from("/"),
+            headers: HeaderMap::new(),
+            status_code: StatusCode::OK,
+            text: String::new(),
+            title: String::new(),
+            generator: vec![],
+            asset_paths: vec![],
+            favicon: HashMap::new(),
+            next_url: None,
+            jump_source: None,
+            redirect_chain: vec![],
+            cert: None,
+            truncated: false,
+            response_time: Duration::default(),
+            body: Bytes::new(),
+            body_hash: None,
+            skipped_jump: None,
+            version: reqwest::Version::HTTP_11,
+            encoding: encoding_rs::UTF_8,
+            auth_realm: None,
+            cookies: vec![],
+            peer_addr: None,
+            decompressed: vec![],
+            attempt_scheme: String::from("https"),
+            header_string: Default::default(),
+            lowercase_text: Default::default(),
+            robots: Default::default(),
+            status_line: Default::default(),
+        };
+        f(&mut raw_data);
+        Arc::new(raw_data)
+    }
+
+    fn fingerprint_lib(json: &str) -> Result<WebFingerPrintLib, WardError> {
+        let web_fingerprint: Vec<WebFingerPrint> = serde_json::from_str(json).unwrap();
+        WebFingerPrintLib::new(web_fingerprint)
+    }
+
+    #[tokio::test]
+    async fn test_regex_match() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"jquery","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"regex":["jquery[ -]?(\\d+\\.\\d+)"]}]"#,
+        )
+        .unwrap();
+        let matched = check(
+            &raw_data(r#"<script src="/js/jQuery-1.12.min.js">"#),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert!(matched.iter().any(|m| m.name == "jquery"));
+        let matched = check(
+            &raw_data(r#"<script src="/js/jquery.min.js">"#),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert!(matched.is_empty());
+        // 区分大小写的正则按原始正文匹配
+        let lib = fingerprint_lib(
+            r#"[{"name":"jsf","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"regex":["javax\\.faces\\.ViewState"],"regex_case_sensitive":true}]"#,
+        )
+        .unwrap();
+        let matched = check(
+            &raw_data(r#"name="javax.faces.ViewState""#),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert_eq!(matched.len(), 1);
+        let matched = check(
+            &raw_data(r#"name="javax.faces.viewstate""#),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert!(matched.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let err = fingerprint_lib(
+            r#"[{"name":"bad-regex","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"regex":["(unclosed"]}]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("bad-regex"));
+    }
+
+    #[tokio::test]
+    async fn test_hex_keyword() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"java-serialized","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"hex_keyword":["ac ed 00 05"]}]"#,
+        )
+        .unwrap();
+        let matched = check(
+            &raw_data_with(|raw_data| {
+                raw_data.body = Bytes::from_static(b"\xac\xed\x00\x05sr\x00\x11java.util.HashMap")
+            }),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert_eq!(matched.len(), 1);
+        let matched = check(&raw_data("aced0005"), &lib, &NoopObserver).await;
+        assert!(matched.is_empty());
+        let err = fingerprint_lib(
+            r#"[{"name":"bad-hex","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"hex_keyword":["acx"]}]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("bad-hex"));
+    }
+
+    #[test]
+    fn test_redirect_policy() {
+        let target = Url::parse("https://www.example.com.cn/").unwrap();
+        let same_host = Url::parse("https://www.example.com.cn/login").unwrap();
+        let same_domain = Url::parse("https://sso.example.com.cn/login").unwrap();
+        let other = Url::parse("https://login.microsoftonline.com/").unwrap();
+        assert!(!RedirectPolicy::None.allows(&target, &same_host));
+        assert!(RedirectPolicy::SameHost.allows(&target, &same_host));
+        assert!(!RedirectPolicy::SameHost.allows(&target, &same_domain));
+        assert!(RedirectPolicy::SameDomain.allows(&target, &same_domain));
+        assert!(!RedirectPolicy::SameDomain.allows(&target, &other));
+        assert!(RedirectPolicy::Always.allows(&target, &other));
+        assert_eq!(
+            "same-domain".parse::<RedirectPolicy>().unwrap(),
+            RedirectPolicy::SameDomain
+        );
+        assert!("sometimes".parse::<RedirectPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_version_regex() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[],"version_regex":["Server: nginx/(?P<version>[\\d.]+)"]},
+            {"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]}]"#,
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "nginx/1.20.1".parse().unwrap());
+        let matched = check(&raw_data_with_headers("", headers), &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].version.as_deref(), Some("1.20.1"));
+    }
+
+    #[tokio::test]
+    async fn test_favicon_db() {
+        let mut lib = fingerprint_lib(
+            r#"[{"name":"example-icon","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["mmh3:-1137126024"]}]"#,
+        )
+        .unwrap();
+        // 几万条哈希，同名同哈希的只加一次
+        let mut entries: Vec<FaviconDbEntry> = (0..50_000)
+            .map(|i| FaviconDbEntry {
+                hash: format!("mmh3:{}", i),
+                name: format!("product-{}", i),
+                priority: 1,
+            })
+            .collect();
+        entries.push(FaviconDbEntry {
+            hash: String::from("MD5:4644F2D45601037B8423D45E13194C93"),
+            name: String::from("spring-boot"),
+            priority: 3,
+        });
+        entries.push(FaviconDbEntry {
+            hash: String::from("-1137126024"),
+            name: String::from("example-icon"),
+            priority: 1,
+        });
+        let added = lib
+            .add_favicon_db(entries, FaviconDbCategory::Favicon)
+            .unwrap();
+        assert_eq!(added, 50_001);
+        let with_favicon = |mmh3: i32| {
+            raw_data_with(|raw_data| {
+                raw_data.favicon.insert(
+                    String::from("https://kali-team.cn/favicon.ico"),
+                    FaviconHash {
+                        md5: String::from("4644f2d45601037b8423d45e13194c93"),
+                        mmh3,
+                        dhash: None,
+                    },
+                );
+            })
+        };
+        let names = |matched: Vec<FingerprintMatch>| {
+            let mut names: Vec<String> = matched.into_iter().map(|m| m.name).collect();
+            names.sort();
+            names
+        };
+        let raw_data = with_favicon(-1137126024);
+        assert_eq!(
+            names(check(&raw_data, &lib, &NoopObserver).await),
+            ["example-icon", "spring-boot"]
+        );
+        let raw_data = with_favicon(42);
+        assert_eq!(
+            names(check(&raw_data, &lib, &NoopObserver).await),
+            ["product-42", "spring-boot"]
+        );
+        // 当作WAF的只在WAF识别里出现
+        let mut lib = fingerprint_lib("[]").unwrap();
+        let waf = vec![FaviconDbEntry {
+            hash: String::from("mmh3:42"),
+            name: String::from("edge-cdn"),
+            priority: 1,
+        }];
+        lib.add_favicon_db(waf, FaviconDbCategory::Waf).unwrap();
+        assert!(check(&raw_data, &lib, &NoopObserver).await.is_empty());
+        assert_eq!(detect_waf(&raw_data, &lib).unwrap().name, "edge-cdn");
+        let invalid = vec![FaviconDbEntry {
+            hash: String::from("dhash:xyz"),
+            name: String::from("broken"),
+            priority: 1,
+        }];
+        assert!(lib
+            .add_favicon_db(invalid, FaviconDbCategory::Favicon)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_favicon_index() {
+        let fingerprint = |name: &str, favicon_hash: &str| {
+            format!(
+                r#"{{"name":"{}","path":"/favicon.png","request_method":"get","request_headers":{{}},"request_data":"","status_code":0,"headers":{{}},"keyword":[],"favicon_hash":[{}]}}"#,
+                name, favicon_hash
+            )
+        };
+        let mut lib = fingerprint_lib(&format!(
+            "[{},{}]",
+            fingerprint("md5-icon", r#""4644f2d45601037b8423d45e13194c93""#),
+            fingerprint("dhash-icon", r#""dhash:0f0f0f0f0f0f0f0f:4""#)
+        ))
+        .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.favicon.insert(
+                String::from("https://kali-team.cn/favicon.png"),
+                FaviconHash {
+                    md5: String::from("4644f2d45601037b8423d45e13194c93"),
+                    mmh3: 7,
+                    dhash: Some(0x0f0f_0f0f_0f0f_0f0e),
+                },
+            );
+        });
+        let names = |matched: Vec<FingerprintMatch>| {
+            let mut names: Vec<String> = matched.into_iter().map(|m| m.name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(check(&raw_data, &lib, &NoopObserver).await),
+            ["dhash-icon", "md5-icon"]
+        );
+        // 直接改字段后索引过期，退回逐个匹配，重建后查表
+        let other =
+            fingerprint_lib(&format!("[{}]", fingerprint("mmh3-icon", r#""mmh3:7""#))).unwrap();
+        lib.favicon.extend(other.favicon);
+        let expected = ["dhash-icon", "md5-icon", "mmh3-icon"];
+        assert_eq!(names(check(&raw_data, &lib, &NoopObserver).await), expected);
+        lib.rebuild_indexes();
+        assert_eq!(names(check(&raw_data, &lib, &NoopObserver).await), expected);
+        let mut lib: WebFingerPrintLib =
+            serde_json::from_str(&serde_json::to_string(&lib).unwrap()).unwrap();
+        assert_eq!(names(check(&raw_data, &lib, &NoopObserver).await), expected);
+        // 数量不变只改了规则，索引同样过期
+        let position = lib
+            .favicon
+            .iter()
+            .position(|f| f.name == "mmh3-icon")
+            .unwrap();
+        let rules = std::mem::replace(
+            &mut lib.favicon[position].match_rules.favicon_rules,
+            vec![FaviconRule::Mmh3(8)],
+        );
+        lib.rebuild_indexes();
+        assert_eq!(
+            names(check(&raw_data, &lib, &NoopObserver).await),
+            ["dhash-icon", "md5-icon"]
+        );
+        lib.favicon[position].match_rules.favicon_rules = rules;
+        assert_eq!(names(check(&raw_data, &lib, &NoopObserver).await), expected);
+    }
+
+    #[tokio::test]
+    async fn test_favicon_hash_format() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"md5-icon","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["4644f2d45601037b8423d45e13194c93"]},
+            {"name":"mmh3-icon","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["-1137126024"]},
+            {"name":"tagged-md5","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["MD5:4644F2D45601037B8423D45E13194C93"]},
+            {"name":"tagged-mmh3","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["mmh3:-1137126024"]},
+            {"name":"wrong-family","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["mmh3:1137126024"]},
+            {"name":"dhash-near","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["dhash:8f0f3b3b1b0b0f0e"]},
+            {"name":"dhash-far","path":"/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["dhash:8f0f3b3b1b0b00ff:2"]}]"#,
+        )
+        .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.favicon.insert(
+                String::from("https://kali-team.cn/favicon.ico"),
+                FaviconHash {
+                    md5: String::from("4644f2d45601037b8423d45e13194c93"),
+                    mmh3: -1137126024,
+                    dhash: Some(0x8f0f_3b3b_1b0b_0f0f),
+                },
+            );
+            raw_data.favicon.insert(
+                String::from("https://kali-team.cn/apple-touch-icon.png"),
+                FaviconHash {
+                    md5: String::from("d41d8cd98f00b204e9800998ecf8427e"),
+                    mmh3: 0,
+                    dhash: None,
+                },
+            );
+        });
+        let matched = check(&raw_data, &lib, &NoopObserver).await;
+        let mut names: Vec<&str> = matched.iter().map(|m| m.name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "dhash-near",
+                "md5-icon",
+                "mmh3-icon",
+                "tagged-md5",
+                "tagged-mmh3"
+            ]
+        );
+        // 证据里是命中的图标和带算法的哈希
+        let tagged = matched.iter().find(|m| m.name == "tagged-mmh3").unwrap();
+        assert_eq!(
+            tagged.evidence.rules,
+            [MatchedRule::Favicon {
+                hash: String::from("mmh3:-1137126024"),
+                url: String::from("https://kali-team.cn/favicon.ico"),
+            }]
+        );
+        // 图片相似的哈希只差几位，证据里是规则本身
+        let near = matched.iter().find(|m| m.name == "dhash-near").unwrap();
+        assert_eq!(
+            near.evidence.rules,
+            [MatchedRule::Favicon {
+                hash: String::from("dhash:8f0f3b3b1b0b0f0e"),
+                url: String::from("https://kali-team.cn/favicon.ico"),
+            }]
+        );
+        for hash in [
+            "sha1:abcd",
+            "md5:-1137126024",
+            "mmh3:4644f2d4",
+            "dhash:8f0f3b3b",
+            "dhash:8f0f3b3b1b0b0f0g",
+            "dhash:8f0f3b3b1b0b0f0f:65",
+        ] {
+            let fingerprint = format!(
+                r#"[{{"name":"bad","path":"/favicon.ico","request_method":"get","request_headers":{{}},"request_data":"","status_code":0,"headers":{{}},"keyword":[],"favicon_hash":["{}"]}}]"#,
+                hash
+            );
+            assert!(fingerprint_lib(&fingerprint).is_err(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn test_validate_fingerprint_lib() {
+        // 内置的WAF指纹没有问题
+        assert!(fingerprint_lib("[]").unwrap().validate().is_empty());
+        let lib = fingerprint_lib(
+            r#"[{"name":"ok","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["ok"]},
+            {"name":"empty","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[]},
+            {"name":"bad-path","path":"login","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["login"]},
+            {"name":"bad-status","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":999,"headers":{},"keyword":[]},
+            {"name":"blank-keyword","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["admin"," "]},
+            {"name":"bad-header","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"x powered":"php"},"keyword":[]},
+            {"name":"ok","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["ok"]},
+            {"name":"icon","path":"/static/favicon.ico","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"favicon_hash":["4644f2d45601037b8423d45e13194c93"]}]"#,
+        )
+        .unwrap();
+        let mut issues: Vec<(String, String, IssueSeverity)> = lib
+            .validate()
+            .into_iter()
+            .map(|issue| (issue.name, issue.field, issue.severity))
+            .collect();
+        issues.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected = [
+            ("bad-header", "headers", IssueSeverity::Error),
+            ("bad-path", "path", IssueSeverity::Error),
+            ("bad-status", "status_code", IssueSeverity::Error),
+            ("blank-keyword", "keyword", IssueSeverity::Error),
+            ("empty", "match_rules", IssueSeverity::Error),
+            ("ok", "name", IssueSeverity::Warning),
+        ]
+        .map(|(name, field, severity)| (name.to_string(), field.to_string(), severity));
+        assert_eq!(issues, expected);
+    }
+
+    #[tokio::test]
+    async fn test_match_evidence() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"nginx-admin","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{"server":"=nginx"},"keyword":["你好","powered by"],"regex":["v\\d"],"favicon_hash":["4644f2d45601037b8423d45e13194c93"]}]"#,
+        )
+        .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.text = String::from("<title>你好</title>\n<p>Powered by nginx v2</p>");
+            raw_data
+                .headers
+                .append("server", "nginx/1.20".parse().unwrap());
+            raw_data.headers.append("server", "nginx".parse().unwrap());
+            raw_data.favicon.insert(
+                String::from("https://kali-team.cn/static/logo.ico"),
+                FaviconHash {
+                    md5: String::from("4644f2d45601037b8423d45e13194c93"),
+                    mmh3: -1137126024,
+                    dhash: None,
+                },
+            );
+        });
+        let matched = check(&raw_data, &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        let evidence = &matched[0].evidence;
+        assert_eq!(evidence.url, "https://kali-team.cn/");
+        assert_eq!(
+            evidence.rules,
+            [
+                MatchedRule::Favicon {
+                    hash: String::from("md5:4644f2d45601037b8423d45e13194c93"),
+                    url: String::from("https://kali-team.cn/static/logo.ico"),
+                },
+                MatchedRule::StatusCode { status_code: 200 },
+                // 多个值的请求头给出命中的那个
+                MatchedRule::Header {
+                    name: String::from("server"),
+                    value: String::from("nginx"),
+                },
+                // 偏移按字节计算，原文的大小写不影响
+                MatchedRule::Keyword {
+                    keyword: String::from("你好"),
+                    offset: 7,
+                    snippet: String::from("你好 Powered by nginx v2"),
+                },
+                MatchedRule::Keyword {
+                    keyword: String::from("powered by"),
+                    offset: 25,
+                    snippet: String::from("你好 Powered by nginx v2"),
+                },
+                MatchedRule::Regex {
+                    pattern: String::from("v\\d"),
+                    offset: 42,
+                    snippet: String::from("你好 Powered by nginx v2"),
+                },
+            ]
+        );
+        let json = serde_json::to_value(&evidence.rules[3]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"type": "keyword", "keyword": "你好", "offset": 7, "snippet": "你好 Powered by nginx v2"})
+        );
+    }
+
+    #[test]
+    fn test_extract_snippet() {
+        let text = "<div class=\"footer\">\n  <p>版权所有  <b>某某公司</b></p>\n</div>";
+        let offset = text.find("某某").unwrap();
+        assert_eq!(extract_snippet(text, offset, 100), "版权所有 某某公司");
+        // 偏移落在汉字中间，按这个字的开头计算
+        for inside in [offset + 1, offset + 2] {
+            assert_eq!(extract_snippet(text, inside, 2), "某某");
+            assert_eq!(extract_snippet(text, inside, 4), "某某公司");
+            assert_eq!(extract_snippet(text, inside, 8), "权所有 某某公司");
+        }
+        // 从标签中间截断，残留的属性和没写完的标签都去掉
+        let offset = text.find("版权").unwrap();
+        assert_eq!(extract_snippet(text, offset, 9), "版权所有");
+        assert_eq!(extract_snippet(text, offset, 12), "版权所有 某某公");
+        let text = "你好，世界";
+        assert_eq!(extract_snippet(text, 4, 1), "你好");
+        assert_eq!(extract_snippet(text, text.len() + 10, 2), "世界");
+        assert_eq!(extract_snippet("", 0, 10), "");
+    }
+
+    #[tokio::test]
+    async fn test_confidence() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"generic","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["login"]},
+            {"name":"server-contains","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]},
+            {"name":"server-exact","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"=nginx"},"keyword":["welcome"]},
+            {"name":"icon-keywords","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["login","console"],"favicon_hash":["4644f2d45601037b8423d45e13194c93"]},
+            {"name":"everything","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{"server":"=nginx"},"keyword":["login"],"regex":["v\\d"],"favicon_hash":["4644f2d45601037b8423d45e13194c93"],"cert_subject":["CN=nginx"]}]"#,
+        )
+        .unwrap();
+        let score = |name: &str| {
+            lib.index
+                .iter()
+                .chain(lib.special.iter())
+                .chain(lib.favicon.iter())
+                .find(|f| f.name == name)
+                .map(|f| confidence(&f.match_rules))
+                .unwrap()
+        };
+        assert_eq!(score("generic"), 10);
+        assert_eq!(score("server-contains"), 15);
+        assert_eq!(score("server-exact"), 40);
+        assert_eq!(score("icon-keywords"), 80);
+        // 加起来超过上限
+        assert_eq!(score("everything"), 100);
+        // 同名的指纹取可信度最高的
+        let lib = fingerprint_lib(
+            r#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["nginx"]},
+            {"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"=nginx"},"keyword":[]}]"#,
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "nginx".parse().unwrap());
+        let matched = check(
+            &raw_data_with_headers("welcome to nginx", headers),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].confidence, 30);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_names() {
+        let mut lib = fingerprint_lib(
+            r#"[{"name":" Nginx ","priority":1,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]},
+            {"name":"NGINX-WAF","priority":3,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["welcome to nginx"]},
+            {"name":"OpenResty","priority":2,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["openresty"]}]"#,
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("server", "nginx".parse().unwrap());
+        let data = raw_data_with_headers("welcome to nginx, powered by openresty", headers);
+        let names = |matched: Vec<FingerprintMatch>| {
+            let mut names: Vec<(String, u32)> =
+                matched.into_iter().map(|m| (m.name, m.priority)).collect();
+            names.sort();
+            names
+        };
+        // 默认原样输出指纹库里的名称
+        assert_eq!(
+            names(check(&data, &lib, &NoopObserver).await),
+            [
+                (String::from(" Nginx "), 1),
+                (String::from("NGINX-WAF"), 3),
+                (String::from("OpenResty"), 2)
+            ]
+        );
+        lib.normalize_names(&default_name_aliases());
+        assert_eq!(
+            names(check(&data, &lib, &NoopObserver).await),
+            [(String::from("OpenResty"), 2), (String::from("nginx"), 3)]
+        );
+        // 开启后合并进来的指纹也统一名称
+        lib.merge(
+            fingerprint_lib(
+                r#"[{"name":"NGINX","priority":4,"path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["powered by"]}]"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            names(check(&data, &lib, &NoopObserver).await),
+            [(String::from("OpenResty"), 2), (String::from("nginx"), 4)]
+        );
+        // 用户的别名表
+        lib.normalize_names(&HashMap::from([(
+            String::from("openresty"),
+            String::from("nginx"),
+        )]));
+        let matched = check(&data, &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "nginx");
+    }
+
+    #[tokio::test]
+    async fn test_not_keyword() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"workspace-app","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["workspace"],"not_keyword":["wordpress"],"not_headers":{"x-powered-by":"php"}}]"#,
+        )
+        .unwrap();
+        let matched = check(&raw_data("<title>Workspace</title>"), &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        let matched = check(
+            &raw_data("<title>Workspace</title><meta name=generator content=WordPress>"),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert!(matched.is_empty());
+        let mut headers = HeaderMap::new();
+        headers.insert("x-powered-by", "PHP/7.4".parse().unwrap());
+        let matched = check(
+            &raw_data_with_headers("<title>Workspace</title>", headers),
+            &lib,
+            &NoopObserver,
+        )
+        .await;
+        assert!(matched.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_header_rules() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"php","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"x-powered-by":"php"},"keyword":[]},
+            {"name":"nginx-exact","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"Server":"=nginx"},"keyword":[]},
+            {"name":"java-session","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"set-cookie":"~JSESSIONID=\\w{32}"},"keyword":[]},
+            {"name":"has-powered-by","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"x-powered-by":"*"},"keyword":[]}]"#,
+        )
+        .unwrap();
+        let names = |headers: &[(&'static str, &'static str)]| {
+            let mut header_map = HeaderMap::new();
+            for (k, v) in headers {
+                header_map.append(*k, v.parse().unwrap());
+            }
+            let lib = &lib;
+            async move {
+                let mut names: Vec<String> =
+                    check(&raw_data_with_headers("", header_map), lib, &NoopObserver)
+                        .await
+                        .into_iter()
+                        .map(|m| m.name)
+                        .collect();
+                names.sort();
+                names
+            }
+        };
+        // 只在指定的请求头里查找，其他请求头里出现不算
+        let matched = names(&[("server", "nginx"), ("x-php", "php")]).await;
+        assert_eq!(matched, ["nginx-exact"]);
+        let matched = names(&[("server", "nginx/1.20.1"), ("x-powered-by", "Express")]).await;
+        assert_eq!(matched, ["has-powered-by"]);
+        let matched = names(&[("x-powered-by", "PHP/7.4")]).await;
+        assert_eq!(matched, ["has-powered-by", "php"]);
+        // 多个Set-Cookie分别匹配
+        let session = "JSESSIONID=0123456789abcdef0123456789ABCDEF; Path=/";
+        let matched = names(&[("set-cookie", "lang=zh"), ("set-cookie", session)]).await;
+        assert_eq!(matched, ["java-session"]);
+        let matched = names(&[("set-cookie", "JSESSIONID=short")]).await;
+        assert!(matched.is_empty());
+        assert!(fingerprint_lib(
+            r#"[{"name":"bad-header","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"~(unclosed"},"keyword":[]}]"#,
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cert_match() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"FortiGate","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"cert_subject":["CN=FortiGate"],"cert_issuer":["O=Fortinet"]}]"#,
+        )
+        .unwrap();
+        let matched = check(&raw_data(""), &lib, &NoopObserver).await;
+        assert!(matched.is_empty());
+        let cert_data = raw_data_with(|raw_data| {
+            raw_data.cert = Some(CertInfo {
+                subject: String::from("C=US, O=Fortinet, CN=FortiGate"),
+                issuer: String::from("C=US, O=Fortinet Ltd., CN=support"),
+                subject_alt_names: vec![],
+                not_before: String::new(),
+                not_after: String::new(),
+                serial: String::from("01"),
+                verification: None,
+            });
+        });
+        let matched = check(&cert_data, &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_matcher() {
+        let lib = fingerprint_lib(
+            r#"[{"name":"thinkphp","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["Powered By","by ThinkPHP"]},
+            {"name":"thinkphp-v5","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["thinkphp"],"not_keyword":["v3.2"]},
+            {"name":"laravel","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["laravel","thinkphp"]}]"#,
+        )
+        .unwrap();
+        // 重叠的关键词也要都能匹配到
+        let matched = check(&raw_data("<p>powered by thinkphp</p>"), &lib, &NoopObserver).await;
+        let mut names: Vec<&str> = matched.iter().map(|m| m.name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["thinkphp", "thinkphp-v5"]);
+        let matched = check(&raw_data("Powered by ThinkPHP V3.2"), &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "thinkphp");
+        // 非ASCII字母的大小写同样忽略
+        let lib = fingerprint_lib(
+            r#"[{"name":"bitrix","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["Битрикс"]}]"#,
+        )
+        .unwrap();
+        let matched = check(&raw_data("<p>1С-БИТРИКС</p>"), &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_merge_fingerprint_lib() {
+        let mut lib = fingerprint_lib(
+            r#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]}]"#,
+        )
+        .unwrap();
+        // 同名同规则去重，同名不同规则都保留
+        let other = fingerprint_lib(
+            r#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]},
+            {"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["welcome to nginx"]},
+            {"name":"tomcat","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["apache tomcat"]}]"#,
+        )
+        .unwrap();
+        lib.merge(other);
+        assert_eq!(lib.index.len(), 3);
+        // 合并后的关键词也能匹配
+        let matched = check(&raw_data("<h1>apache tomcat</h1>"), &lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "tomcat");
+    }
+
+    #[test]
+    fn test_parse_web_fingerprint() {
+        let json = br#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{"server":"nginx"},"keyword":[]}]"#;
+        let lib = WebFingerPrintLib::from_reader(&json[..]).unwrap();
+        assert_eq!(lib.index.len(), 1);
+        // 没写优先级的和代码里构造的一样
+        assert_eq!(lib.index[0].priority, WebFingerPrint::default().priority);
+        let err = parse_web_fingerprint(&b"[{\"name\":\"nginx\",\n}"[..]).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+        let json = br#"[{"name":"nginx","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[]},
+            {"name":"tomcat","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":"2oo","headers":{},"keyword":[]}]"#;
+        let err = parse_web_fingerprint(&json[..]).unwrap_err();
+        assert!(err.to_string().contains("#1 `tomcat`"), "{}", err);
+    }
+
+    #[test]
+    fn test_reject_unsendable_request() {
+        let load = |field: &str| {
+            let json = format!(
+                r#"[{{"name":"broken","path":"/","request_method":"get","request_headers":{{}},"request_data":"","status_code":0,"headers":{{}},"keyword":[],{}}}]"#,
+                field
+            );
+            WebFingerPrintLib::from_reader(json.as_bytes())
+        };
+        assert!(load(r#""timeout_secs":3"#).is_ok());
+        for (field, name) in [
+            (r#""request_data":"not base64!""#, "request_data"),
+            (r#""request_headers":{"bad header":"1"}"#, "request_headers"),
+            (r#""request_headers":{"X-Scan":"a\nb"}"#, "request_headers"),
+            (r#""request_method":"GET /""#, "request_method"),
+        ] {
+            match load(field) {
+                Err(WardError::FingerprintLoad(message)) => {
+                    assert!(
+                        message.contains(name) && message.contains("`broken`"),
+                        "{}",
+                        message
+                    );
+                }
+                other => panic!("{} loaded: {:?}", field, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_request_data_file() {
+        let dir =
+            std::env::temp_dir().join(format!("what_web_request_data_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("bodies")).unwrap();
+        std::fs::write(dir.join("bodies/login.xml"), "<user>{{hostname}}</user>").unwrap();
+        let json = br#"[{"name":"soap","path":"/ws","request_method":"post","request_headers":{},"request_data_file":"bodies/login.xml","status_code":0,"headers":{},"keyword":["soap:Envelope"]}]"#;
+        let mut web_fingerprint = parse_web_fingerprint(&json[..]).unwrap();
+        // 没有读取文件的不能直接加载
+        assert!(WebFingerPrintLib::new(web_fingerprint.clone()).is_err());
+        load_request_data_files(&mut web_fingerprint, &dir).unwrap();
+        let lib = WebFingerPrintLib::new(web_fingerprint).unwrap();
+        assert_eq!(
+            base64::decode(&lib.special[0].request.request_data).unwrap(),
+            b"<user>{{hostname}}</user>"
+        );
+        let json = br#"[{"name":"soap","path":"/ws","request_method":"post","request_headers":{},"request_data":"PHVzZXI+","request_data_file":"bodies/login.xml","status_code":0,"headers":{},"keyword":[]}]"#;
+        let mut web_fingerprint = parse_web_fingerprint(&json[..]).unwrap();
+        let err = load_request_data_files(&mut web_fingerprint, &dir).unwrap_err();
+        assert!(err.to_string().contains("both"), "{}", err);
+        let json = br#"[{"name":"soap","path":"/ws","request_method":"post","request_headers":{},"request_data_file":"missing.xml","status_code":0,"headers":{},"keyword":[]}]"#;
+        let mut web_fingerprint = parse_web_fingerprint(&json[..]).unwrap();
+        assert!(load_request_data_files(&mut web_fingerprint, &dir).is_err());
+        // 不能读取指纹目录外的文件
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let outside = dir.join("secret.txt").display().to_string();
+        let fingerprint = |file: &str| {
+            let json = format!(
+                r#"[{{"name":"soap","path":"/ws","request_method":"post","request_headers":{{}},"request_data_file":{},"status_code":0,"headers":{{}},"keyword":[]}}]"#,
+                serde_json::to_string(file).unwrap()
+            );
+            parse_web_fingerprint(json.as_bytes()).unwrap()
+        };
+        for file in [outside.as_str(), "../secret.txt", "bodies/../../secret.txt"] {
+            let mut web_fingerprint = fingerprint(file);
+            let err =
+                load_request_data_files(&mut web_fingerprint, &dir.join("bodies")).unwrap_err();
+            assert!(
+                err.to_string().contains("fingerprint directory"),
+                "{}: {}",
+                file,
+                err
+            );
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("bodies/link.xml"))
+                .unwrap();
+            let mut web_fingerprint = fingerprint("link.xml");
+            let err =
+                load_request_data_files(&mut web_fingerprint, &dir.join("bodies")).unwrap_err();
+            assert!(err.to_string().contains("outside"), "{}", err);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fixture_headers() {
+        // 和benches共用的40个响应头
+        let mut headers = HeaderMap::new();
+        for line in include_str!("../tests/fixtures/headers.txt")
+            .lines()
+            .skip(1)
+        {
+            if let Some((name, value)) = line.split_once(": ") {
+                headers.append(
+                    HeaderName::from_str(name).unwrap(),
+                    HeaderValue::from_str(value).unwrap(),
+                );
+            }
+        }
+        assert_eq!(headers.len(), 40);
+        let header_string = header_to_string(&headers);
+        assert_eq!(header_string.lines().count(), 40);
+        assert!(header_string.contains("server: nginx/1.20.1\r\n"));
+        assert!(header_string
+            .contains("set-cookie: PHPSESSID=9b1c0e7a6f3d4e2a8b5c; path=/; HttpOnly\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_reason_phrase_rule() {
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"thttpd","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"reason_phrase":["document follows"]},
+            {"name":"goahead","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"reason_phrase":["~^Data follows$"]}]"#,
+        )
+        .unwrap();
+        assert!(fingerprint_lib.has_reason_phrase_rules());
+        let names = |status_line: Option<&str>| {
+            let raw_data = raw_data_with(|_| {});
+            if let Some(status_line) = status_line {
+                raw_data.set_status_line(status_line.to_string());
+            }
+            let fingerprint_lib = &fingerprint_lib;
+            async move {
+                check(&raw_data, fingerprint_lib, &NoopObserver)
+                    .await
+                    .into_iter()
+                    .map(|m| m.name)
+                    .collect::<Vec<String>>()
+            }
+        };
+        assert_eq!(
+            names(Some("HTTP/1.0 200 Document follows")).await,
+            ["thttpd"]
+        );
+        assert_eq!(names(Some("HTTP/1.1 200 Data follows")).await, ["goahead"]);
+        // 没有读取状态行、HTTP/2和标准的原因短语都不匹配
+        assert!(names(None).await.is_empty());
+        assert!(names(Some("HTTP/1.1 200 OK")).await.is_empty());
+        // 状态码和响应不一样的状态行不保存
+        assert!(names(Some("HTTP/1.0 404 Document follows"))
+            .await
+            .is_empty());
+        assert_eq!(reason_phrase("HTTP/1.1 200"), None);
+        assert_eq!(
+            reason_phrase("HTTP/1.1 200 Document  follows "),
+            Some("Document  follows")
+        );
+        assert_eq!(reason_phrase("SSH-2.0-OpenSSH 200 OK"), None);
+    }
+
+    #[tokio::test]
+    async fn test_realm_and_status_codes() {
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"dd-wrt","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":401,"headers":{},"keyword":[],"realm":["dd-wrt"]},
+            {"name":"routeros","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":[401,403],"headers":{},"keyword":[],"realm":["~^RouterOS"]},
+            {"name":"denied","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":[401,403],"headers":{},"keyword":["access denied"]}]"#,
+        )
+        .unwrap();
+        let with_realm = |status_code: StatusCode, realm: Option<&str>, text: &str| {
+            raw_data_with(|raw_data| {
+                raw_data.status_code = status_code;
+                raw_data.auth_realm = realm.map(String::from);
+                raw_data.text = text.to_string();
+            })
+        };
+        let names = |raw_data: Arc<RawData>| {
+            let fingerprint_lib = &fingerprint_lib;
+            async move {
+                let mut names: Vec<String> = check(&raw_data, fingerprint_lib, &NoopObserver)
+                    .await
+                    .into_iter()
+                    .map(|m| m.name)
+                    .collect();
+                names.sort();
+                names
+            }
+        };
+        let raw_data = with_realm(StatusCode::UNAUTHORIZED, Some("DD-WRT"), "");
+        assert_eq!(names(raw_data).await, vec!["dd-wrt"]);
+        let raw_data = with_realm(StatusCode::UNAUTHORIZED, Some("RouterOS router"), "");
+        assert_eq!(names(raw_data).await, vec!["routeros"]);
+        // realm规则不会匹配没有realm的响应
+        let raw_data = with_realm(StatusCode::FORBIDDEN, None, "Access Denied");
+        assert_eq!(names(raw_data).await, vec!["denied"]);
+        let raw_data = with_realm(StatusCode::OK, None, "Access Denied");
+        assert!(names(raw_data).await.is_empty());
+        let routeros = fingerprint_lib
+            .index
+            .iter()
+            .find(|f| f.name == "routeros")
+            .unwrap();
+        assert_eq!(
+            routeros.match_rules.status_code,
+            StatusCodes(vec![StatusCodeRule::Exact(401), StatusCodeRule::Exact(403)])
+        );
+        // 只有一个状态码时还是输出整数
+        let dd_wrt = serde_json::to_value(&fingerprint_lib.index[0].match_rules).unwrap();
+        assert_eq!(dd_wrt["status_code"], serde_json::json!(401));
+        let any_status: WebFingerPrint = serde_json::from_str(
+            r#"{"name":"any","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["any"]}"#,
+        )
+        .unwrap();
+        let lib = WebFingerPrintLib::new(vec![any_status]).unwrap();
+        assert!(lib.index[0].match_rules.status_code.is_any());
+    }
+
+    #[test]
+    fn test_status_codes() {
+        let parse = |json: &str| serde_json::from_str::<StatusCodes>(json);
+        let cases: [(&str, &[u16], &[u16]); 7] = [
+            ("404", &[404], &[200, 403]),
+            ("[301, 302]", &[301, 302], &[300, 303]),
+            (r#""3xx""#, &[300, 302, 399], &[299, 400]),
+            (r#""300-305""#, &[300, 305], &[306]),
+            (r#"[401, "5xx"]"#, &[401, 500, 503], &[403]),
+            (r#""403""#, &[403], &[404]),
+            (r#"["401", 0]"#, &[401], &[200]),
+        ];
+        for (json, hits, misses) in cases {
+            let status_codes = parse(json).unwrap();
+            for code in hits {
+                assert!(status_codes.matches(*code), "{} {}", json, code);
+            }
+            for code in misses {
+                assert!(!status_codes.matches(*code), "{} {}", json, code);
+            }
+        }
+        // 0和空列表不限制状态码
+        for json in ["0", "[]", "[0]"] {
+            let status_codes = parse(json).unwrap();
+            assert!(status_codes.is_any());
+            assert!(status_codes.matches(200) && status_codes.matches(502));
+        }
+        for json in [
+            r#""abc""#,
+            r#""399-300""#,
+            r#""0xx""#,
+            "-1",
+            r#"["4xx", "x"]"#,
+        ] {
+            assert!(parse(json).is_err(), "{}", json);
+        }
+        // 序列化回去还能解析，单个状态码仍然是整数
+        for (json, expected) in [
+            ("0", "0"),
+            ("401", "401"),
+            (r#"[401, "5xx", "300-305"]"#, r#"[401,"5xx","300-305"]"#),
+        ] {
+            let status_codes = parse(json).unwrap();
+            let serialized = serde_json::to_string(&status_codes).unwrap();
+            assert_eq!(serialized, expected);
+            assert_eq!(parse(&serialized).unwrap(), status_codes);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_length_rule() {
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"default-page","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":200,"headers":{},"keyword":[],"length":12},
+            {"name":"parked","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":["parked"],"length":"10-20"}]"#,
+        )
+        .unwrap();
+        let names = |text: &'static str| {
+            let fingerprint_lib = &fingerprint_lib;
+            async move {
+                let raw_data = raw_data_with(|raw_data| {
+                    raw_data.text = text.to_string();
+                    raw_data.body = Bytes::from(text);
+                });
+                let mut names: Vec<String> = check(&raw_data, fingerprint_lib, &NoopObserver)
+                    .await
+                    .into_iter()
+                    .map(|m| m.name)
+                    .collect();
+                names.sort();
+                names
+            }
+        };
+        assert_eq!(names("hello world!").await, vec!["default-page"]);
+        assert_eq!(names("parked domain").await, vec!["parked"]);
+        assert!(names("this domain is parked").await.is_empty());
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.text = String::from("hello world!");
+            raw_data.body = Bytes::from("hello world!");
+        });
+        let matched = check(&raw_data, &fingerprint_lib, &NoopObserver).await;
+        assert!(matched[0]
+            .evidence
+            .rules
+            .contains(&MatchedRule::Length { length: 12 }));
+        // 截断的响应按Content-Length算
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.body = Bytes::from("hello");
+            raw_data.truncated = true;
+            raw_data
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from_static("4096"));
+        });
+        assert_eq!(raw_data.body_length(), 4096);
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.body = Bytes::from("hello world!");
+            raw_data.truncated = true;
+            raw_data.decompressed = vec!["gzip"];
+            raw_data
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from_static("5"));
+        });
+        assert_eq!(raw_data.body_length(), 12);
+        for (rule, expected) in [
+            ("1256", LengthRule::Exact(1256)),
+            ("1000-2000", LengthRule::Range(1000, 2000)),
+        ] {
+            assert_eq!(rule.parse::<LengthRule>().unwrap(), expected);
+            assert_eq!(expected.to_string(), rule);
+        }
+        for rule in ["abc", "2000-1000", "-1", "10-"] {
+            assert!(rule.parse::<LengthRule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_robots_rule() {
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"typo3","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"robots":["/typo3/","/fileadmin/"]}]"#,
+        )
+        .unwrap();
+        assert!(fingerprint_lib.has_robots_rules());
+        let raw_data = raw_data_with(|_| {});
+        // 没有robots.txt时不匹配
+        assert!(check(&raw_data, &fingerprint_lib, &NoopObserver)
+            .await
+            .is_empty());
+        raw_data.set_robots(String::from("User-agent: *\nDisallow: /typo3/\n"));
+        assert!(check(&raw_data, &fingerprint_lib, &NoopObserver)
+            .await
+            .is_empty());
+        let raw_data = raw_data_with(|_| {});
+        raw_data.set_robots(String::from(
+            "User-agent: *\r\nDisallow: /typo3/\r\nDisallow: /FileAdmin/\r\n",
+        ));
+        let matched = check(&raw_data, &fingerprint_lib, &NoopObserver).await;
+        assert_eq!(
+            matched[0].evidence.rules,
+            [
+                MatchedRule::Robots {
+                    keyword: String::from("/typo3/"),
+                    line: String::from("Disallow: /typo3/"),
+                },
+                MatchedRule::Robots {
+                    keyword: String::from("/fileadmin/"),
+                    line: String::from("Disallow: /FileAdmin/"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generator_and_script_src() {
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"wordpress","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"meta_generator":["~^wordpress (\\d+)"],"script_src":["/wp-includes/"]},
+            {"name":"joomla","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"meta_generator":["Joomla!"]}]"#,
+        )
+        .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.generator = vec![String::from("WordPress 6.3")];
+            raw_data.asset_paths = vec![
+                String::from("/wp-content/themes/a/style.css"),
+                String::from("/WP-Includes/js/jquery.js"),
+            ];
+        });
+        let matched = check(&raw_data, &fingerprint_lib, &NoopObserver).await;
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "wordpress");
+        assert_eq!(
+            matched[0].evidence.rules,
+            [
+                MatchedRule::MetaGenerator {
+                    generator: String::from("WordPress 6.3"),
+                },
+                MatchedRule::ScriptSrc {
+                    prefix: String::from("/wp-includes/"),
+                    path: String::from("/WP-Includes/js/jquery.js"),
+                },
+            ]
+        );
+        // 只有generator没有脚本路径时不匹配
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.generator = vec![String::from("WordPress 6.3")];
+        });
+        assert!(check(&raw_data, &fingerprint_lib, &NoopObserver)
+            .await
+            .is_empty());
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.generator = vec![String::from("Joomla! - Open Source Content Management")];
+        });
+        let matched = check(&raw_data, &fingerprint_lib, &NoopObserver).await;
+        assert_eq!(matched[0].name, "joomla");
+    }
+
+    #[tokio::test]
+    async fn test_cookie_rule() {
+        let cookie = SetCookie::parse(
+            "BIGipServerpool_web=1677787402.36895.0000; path=/; Httponly; SameSite = Lax",
+        )
+        .unwrap();
+        assert_eq!(cookie.name, "BIGipServerpool_web");
+        assert_eq!(cookie.value, "1677787402.36895.0000");
+        assert_eq!(cookie.attribute("Path"), Some("/"));
+        assert_eq!(cookie.attribute("httponly"), Some(""));
+        assert_eq!(cookie.attribute("samesite"), Some("Lax"));
+        assert_eq!(cookie.attribute("secure"), None);
+        assert_eq!(SetCookie::parse("ci_session=").unwrap().value, "");
+        for header in ["", "no-value", "=value; path=/"] {
+            assert!(SetCookie::parse(header).is_none(), "{}", header);
+        }
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"laravel","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"cookies":["laravel_session","XSRF-TOKEN"]}]"#,
+        )
+        .unwrap();
+        let names = |cookies: &[&str]| {
+            let raw_data = raw_data_with(|raw_data| {
+                raw_data.cookies = cookies
+                    .iter()
+                    .filter_map(|cookie| SetCookie::parse(cookie))
+                    .collect();
+            });
+            let fingerprint_lib = &fingerprint_lib;
+            async move { check(&raw_data, fingerprint_lib, &NoopObserver).await }
+        };
+        // 名字要完全相等，值里出现不算
+        assert!(names(&["laravel_session_id=1", "XSRF-TOKEN=2"])
+            .await
+            .is_empty());
+        assert!(names(&["XSRF-TOKEN=laravel_session"]).await.is_empty());
+        let matched = names(&["xsrf-token=1; path=/", "laravel_session=2; httponly"]).await;
+        assert_eq!(
+            matched[0].evidence.rules,
+            [
+                MatchedRule::Cookie {
+                    rule: String::from("laravel_session"),
+                    name: String::from("laravel_session"),
+                },
+                MatchedRule::Cookie {
+                    rule: String::from("XSRF-TOKEN"),
+                    name: String::from("xsrf-token"),
+                },
+            ]
+        );
+        // 内置的BIG-IP指纹按前缀匹配会话保持的Cookie
+        let bigip = fingerprint_lib
+            .waf
+            .iter()
+            .find(|fingerprint| fingerprint.name == "f5-bigip")
+            .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.cookies = vec![cookie.clone()];
+        });
+        let matched = what_web(&raw_data, bigip, &HashSet::new(), &NoopObserver).unwrap();
+        assert_eq!(
+            matched.evidence.rules,
+            [MatchedRule::Cookie {
+                rule: String::from("BIGipServer*"),
+                name: String::from("BIGipServerpool_web"),
+            }]
+        );
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.cookies = vec![SetCookie::parse("BIGip=1").unwrap()];
+        });
+        assert!(what_web(&raw_data, bigip, &HashSet::new(), &NoopObserver).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_body_hash_rule() {
+        let hash = body_hash(b"hello", false).unwrap();
+        assert_eq!(hash.md5, "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(
+            hash.sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert!(body_hash(b"hello", true).is_none());
+        for hash in [
+            "5d41402abc4b2a76",
+            "sha1:5d41402abc4b2a76b9719d911017c592",
+            "sha256:5d41402abc4b2a76b9719d911017c592",
+            "md5:5d41402abc4b2a76b9719d911017c59g",
+        ] {
+            let fingerprint = format!(
+                r#"[{{"name":"bad","path":"/","request_method":"get","request_headers":{{}},"request_data":"","status_code":0,"headers":{{}},"keyword":[],"body_hash":["{}"]}}]"#,
+                hash
+            );
+            assert!(fingerprint_lib(&fingerprint).is_err(), "{}", hash);
+        }
+        let fingerprint_lib = fingerprint_lib(
+            r#"[{"name":"md5-page","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"body_hash":["00000000000000000000000000000000","MD5:5D41402ABC4B2A76B9719D911017C592"]},
+            {"name":"sha256-page","path":"/","request_method":"get","request_headers":{},"request_data":"","status_code":0,"headers":{},"keyword":[],"body_hash":["2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"]}]"#,
+        )
+        .unwrap();
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.body = Bytes::from("hello");
+            raw_data.body_hash = body_hash(&raw_data.body, false);
+        });
+        let mut matched = check(&raw_data, &fingerprint_lib, &NoopObserver).await;
+        matched.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            matched[0].evidence.rules,
+            [MatchedRule::BodyHash {
+                hash: String::from("md5:5d41402abc4b2a76b9719d911017c592"),
+            }]
+        );
+        assert_eq!(matched[0].confidence, 100);
+        assert_eq!(
+            matched[1].evidence.rules,
+            [MatchedRule::BodyHash {
+                hash: String::from(
+                    "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+                ),
+            }]
+        );
+        // 截断的响应没有哈希
+        let raw_data = raw_data_with(|raw_data| {
+            raw_data.body = Bytes::from("hello");
+            raw_data.truncated = true;
+        });
+        assert!(check(&raw_data, &fingerprint_lib, &NoopObserver)
+            .await
+            .is_empty());
+    }
+}